@@ -13,6 +13,8 @@ pub enum Error {
     TooManyFailures(#[from] Box<reqwest::Error>),
     #[error("Error parsing header: {0}")]
     InvalidHeader(#[from] ToStrError),
+    #[error("Error reading artifact: {0}")]
+    Io(#[from] std::io::Error),
     #[error("Error parsing URL: {0}")]
     InvalidUrl(#[from] url::ParseError),
     #[error("unknown caching status: {0}")]
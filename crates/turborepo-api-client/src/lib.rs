@@ -2,10 +2,13 @@
 #![feature(provide_any)]
 #![feature(error_generic_member_access)]
 
-use std::env;
+use std::{backtrace::Backtrace, env, fs::File, io, path::Path};
 
-use reqwest::RequestBuilder;
+use hyper::body::Bytes;
+pub use reqwest::Response;
+use reqwest::{Body, Method, RequestBuilder, StatusCode};
 use serde::{Deserialize, Serialize};
+use tokio::io::AsyncReadExt;
 
 pub use crate::error::{Error, Result};
 
@@ -39,6 +42,22 @@ pub struct CachingStatusResponse {
     pub status: CachingStatus,
 }
 
+/// The error body returned by the API, e.g. when remote caching is disabled
+/// for a team.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct APIError {
+    pub code: String,
+    pub message: String,
+}
+
+/// The credentials used to access a team's remote cache.
+#[derive(Debug, Clone, Default)]
+pub struct APIAuth {
+    pub team_id: String,
+    pub token: String,
+    pub team_slug: Option<String>,
+}
+
 /// Membership is the relationship between the logged-in user and a particular
 /// team
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -117,6 +136,36 @@ pub struct APIClient {
     user_agent: String,
 }
 
+const BODY_CHUNK_SIZE: usize = 64 * 1024;
+
+// Streams the file at `path` as a request body, a chunk at a time
+fn file_body(path: &Path) -> io::Result<Body> {
+    let mut file = tokio::fs::File::from_std(File::open(path)?);
+    let (mut sender, body) = hyper::Body::channel();
+    tokio::spawn(async move {
+        let mut buffer = vec![0; BODY_CHUNK_SIZE];
+        loop {
+            match file.read(&mut buffer).await {
+                Ok(0) => break,
+                Ok(read) => {
+                    let chunk = Bytes::copy_from_slice(&buffer[..read]);
+                    // The request was dropped, so there's nobody to send to
+                    if sender.send_data(chunk).await.is_err() {
+                        break;
+                    }
+                }
+                Err(_) => {
+                    // Fails the request rather than sending a truncated artifact
+                    sender.abort();
+                    break;
+                }
+            }
+        }
+    });
+
+    Ok(Body::from(body))
+}
+
 impl APIClient {
     pub async fn get_user(&self, token: &str) -> Result<UserResponse> {
         let url = self.make_url("/v2/user");
@@ -200,6 +249,133 @@ impl APIClient {
         Ok(response.json().await?)
     }
 
+    /// Uploads the artifact at `artifact_path` to the remote cache, streaming
+    /// it from disk. `duration` is the time in milliseconds it took to run
+    /// the task and `tag` is the artifact signature, if signing is enabled.
+    pub async fn put_artifact(
+        &self,
+        hash: &str,
+        artifact_path: &Path,
+        duration: u32,
+        tag: Option<&str>,
+        token: &str,
+        team_id: &str,
+        team_slug: Option<&str>,
+    ) -> Result<()> {
+        let artifact_size = artifact_path.metadata()?.len();
+        let build_request = || {
+            let mut request_builder = self
+                .client
+                .put(self.make_url(&format!("/v8/artifacts/{}", hash)))
+                .header("Content-Type", "application/octet-stream")
+                .header("Content-Length", artifact_size)
+                .header("x-artifact-duration", duration.to_string())
+                .header("User-Agent", self.user_agent.clone())
+                .header("Authorization", format!("Bearer {}", token))
+                .body(file_body(artifact_path)?);
+
+            if let Some(tag) = tag {
+                request_builder = request_builder.header("x-artifact-tag", tag);
+            }
+
+            Ok(Self::add_team_params(request_builder, team_id, team_slug))
+        };
+
+        let response = retry::make_retryable_request_with(build_request).await?;
+
+        if response.status() == StatusCode::FORBIDDEN {
+            return Err(Self::handle_403(response).await);
+        }
+
+        response.error_for_status()?;
+        Ok(())
+    }
+
+    /// Downloads an artifact from the remote cache. Returns `None` if the
+    /// artifact does not exist, otherwise the response so that the body can
+    /// be read as it arrives.
+    pub async fn fetch_artifact(
+        &self,
+        hash: &str,
+        token: &str,
+        team_id: &str,
+        team_slug: Option<&str>,
+    ) -> Result<Option<Response>> {
+        self.get_artifact(hash, token, team_id, team_slug, Method::GET)
+            .await
+    }
+
+    /// Checks whether an artifact exists in the remote cache without
+    /// downloading it. Returns `None` if the artifact does not exist.
+    pub async fn artifact_exists(
+        &self,
+        hash: &str,
+        token: &str,
+        team_id: &str,
+        team_slug: Option<&str>,
+    ) -> Result<Option<Response>> {
+        self.get_artifact(hash, token, team_id, team_slug, Method::HEAD)
+            .await
+    }
+
+    async fn get_artifact(
+        &self,
+        hash: &str,
+        token: &str,
+        team_id: &str,
+        team_slug: Option<&str>,
+        method: Method,
+    ) -> Result<Option<Response>> {
+        let request_builder = self
+            .client
+            .request(method, self.make_url(&format!("/v8/artifacts/{}", hash)))
+            .header("Authorization", format!("Bearer {}", token))
+            .header("User-Agent", self.user_agent.clone());
+
+        let request_builder = Self::add_team_params(request_builder, team_id, team_slug);
+
+        let response = retry::make_retryable_request(request_builder).await?;
+
+        match response.status() {
+            StatusCode::FORBIDDEN => Err(Self::handle_403(response).await),
+            StatusCode::NOT_FOUND => Ok(None),
+            _ => Ok(Some(response.error_for_status()?)),
+        }
+    }
+
+    async fn handle_403(response: Response) -> Error {
+        let api_error: APIError = match response.json().await {
+            Ok(api_error) => api_error,
+            Err(err) => return err.into(),
+        };
+
+        if let Some(status_string) = api_error.code.strip_prefix("remote_caching_") {
+            let status = match status_string {
+                "disabled" => CachingStatus::Disabled,
+                "enabled" => CachingStatus::Enabled,
+                "over_limit" => CachingStatus::OverLimit,
+                "paused" => CachingStatus::Paused,
+                _ => {
+                    return Error::UnknownCachingStatus(
+                        status_string.to_string(),
+                        Backtrace::capture(),
+                    )
+                }
+            };
+
+            Error::CacheDisabled {
+                status,
+                message: api_error.message,
+            }
+        } else {
+            Error::UnknownStatus {
+                code: api_error.code,
+                message: api_error.message,
+                backtrace: Backtrace::capture(),
+            }
+        }
+    }
+
     pub async fn get_spaces(&self, token: &str, team_id: Option<&str>) -> Result<SpacesResponse> {
         // create url with teamId if provided
         let endpoint = match team_id {
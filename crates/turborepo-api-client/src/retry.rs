@@ -15,14 +15,25 @@ const RETRY_MAX: u32 = 2;
 ///
 /// * `request_builder`: The request builder with everything, i.e. headers and
 ///   body already set. NOTE: This must be cloneable, so no streams are allowed.
+///   Use `make_retryable_request_with` for streamed bodies.
 ///
 /// returns: Result<Response, Error>
 pub(crate) async fn make_retryable_request(
     request_builder: RequestBuilder,
+) -> Result<Response, Error> {
+    make_retryable_request_with(|| Ok(request_builder.try_clone().expect("cannot clone request")))
+        .await
+}
+
+/// Like `make_retryable_request`, but calls `build_request` for every attempt.
+/// Requests with streamed bodies can't be cloned, so the body has to be
+/// created again for each retry.
+pub(crate) async fn make_retryable_request_with(
+    build_request: impl Fn() -> Result<RequestBuilder, Error>,
 ) -> Result<Response, Error> {
     let mut last_error = None;
     for retry_count in 0..RETRY_MAX {
-        let builder = request_builder.try_clone().expect("cannot clone request");
+        let builder = build_request()?;
         match builder.send().await {
             Ok(value) => return Ok(value),
             Err(err) => {
@@ -0,0 +1,148 @@
+use std::{
+    backtrace::Backtrace,
+    fs::{self, OpenOptions},
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use tar::{EntryType, Header};
+use turbopath::{AbsoluteSystemPath, AnchoredSystemPathBuf, PathError};
+
+use crate::CacheError;
+
+pub struct CacheWriter<'a> {
+    builder: tar::Builder<Box<dyn Write + 'a>>,
+}
+
+impl<'a> CacheWriter<'a> {
+    /// Creates a new artifact at `path`. The artifact is compressed with zstd
+    /// if `path` has a `.zst` extension.
+    pub fn create(path: &AbsoluteSystemPath) -> Result<Self, CacheError> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path.as_path())?;
+        // Flush to disk in 1mb chunks.
+        let file_buffer = BufWriter::with_capacity(1 << 20, file);
+        let is_compressed = path.as_path().extension().map_or(false, |ext| ext == "zst");
+
+        Self::from_writer(file_buffer, is_compressed)
+    }
+
+    pub fn from_writer(writer: impl Write + 'a, use_compression: bool) -> Result<Self, CacheError> {
+        let writer: Box<dyn Write + 'a> = if use_compression {
+            Box::new(zstd::Encoder::new(writer, 0)?.auto_finish())
+        } else {
+            Box::new(writer)
+        };
+
+        Ok(Self {
+            builder: tar::Builder::new(writer),
+        })
+    }
+
+    /// Adds `file_path`, which is anchored at `anchor`, to the artifact.
+    /// Only regular files, directories and symlinks are supported.
+    pub fn add_file(
+        &mut self,
+        anchor: &AbsoluteSystemPath,
+        file_path: &AnchoredSystemPathBuf,
+    ) -> Result<(), CacheError> {
+        let source_path = anchor.resolve(file_path);
+        let file_info = source_path.symlink_metadata()?;
+
+        // Normalize the path within the cache.
+        let unix_path = file_path.to_unix()?;
+        let cache_destination_name = unix_path.as_str()?;
+
+        let mut header = Self::create_header(&file_info)?;
+
+        if file_info.is_symlink() {
+            let link_target = source_path.read_link()?;
+            let link_target = to_unix_link_target(&link_target)?;
+            self.builder
+                .append_link(&mut header, cache_destination_name, link_target)?;
+        } else if file_info.is_file() && file_info.len() > 0 {
+            let file = fs::File::open(source_path.as_path())?;
+            self.builder
+                .append_data(&mut header, cache_destination_name, file)?;
+        } else {
+            self.builder
+                .append_data(&mut header, cache_destination_name, &[][..])?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the end of the archive and flushes it to the underlying writer.
+    pub fn finish(self) -> Result<(), CacheError> {
+        let mut writer = self.builder.into_inner()?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    fn create_header(file_info: &fs::Metadata) -> Result<Header, CacheError> {
+        let mut header = Header::new_gnu();
+
+        let file_type = file_info.file_type();
+        let entry_type = if file_type.is_symlink() {
+            EntryType::Symlink
+        } else if file_type.is_dir() {
+            EntryType::Directory
+        } else if file_type.is_file() {
+            EntryType::Regular
+        } else {
+            return Err(CacheError::CreateUnsupportedFileType(
+                format!("{:?}", file_type),
+                Backtrace::capture(),
+            ));
+        };
+        header.set_entry_type(entry_type);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            header.set_mode(file_info.permissions().mode() & 0o777);
+        }
+        #[cfg(windows)]
+        {
+            // Windows has no notion of unix permissions, so we match what Go
+            // does and pick sensible defaults.
+            let mode = if file_type.is_dir() || file_type.is_symlink() {
+                0o755
+            } else if file_info.permissions().readonly() {
+                0o444
+            } else {
+                0o644
+            };
+            header.set_mode(mode);
+        }
+
+        if entry_type == EntryType::Regular {
+            header.set_size(file_info.len());
+        } else {
+            header.set_size(0);
+        }
+
+        // Consistent creation: artifacts should only depend on file contents.
+        header.set_uid(0);
+        header.set_gid(0);
+        header.set_mtime(0);
+
+        Ok(header)
+    }
+}
+
+fn to_unix_link_target(link_target: &Path) -> Result<String, CacheError> {
+    let link_target = link_target
+        .to_str()
+        .ok_or_else(|| PathError::InvalidUnicode(link_target.to_string_lossy().to_string()))?;
+
+    #[cfg(windows)]
+    let link_target = link_target.replace('\\', "/");
+    #[cfg(not(windows))]
+    let link_target = link_target.to_string();
+
+    Ok(link_target)
+}
@@ -0,0 +1,12 @@
+//! Reading and writing of cache artifacts.
+//!
+//! An artifact is a tar archive of task outputs, optionally compressed with
+//! zstd. Paths inside the archive are always unix paths relative to the
+//! anchor (usually the repository root). The layout is shared with the Go
+//! implementation, so artifacts written by either can be restored by the
+//! other.
+mod create;
+mod restore;
+
+pub use create::CacheWriter;
+pub use restore::CacheReader;
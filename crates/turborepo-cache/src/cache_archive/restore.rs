@@ -0,0 +1,466 @@
+use std::{
+    backtrace::Backtrace,
+    collections::{HashMap, HashSet},
+    fs::{self, OpenOptions},
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use tar::{Entry, EntryType, Header};
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPathBuf, PathError};
+
+use crate::CacheError;
+
+pub struct CacheReader<'a> {
+    reader: Box<dyn Read + 'a>,
+}
+
+impl<'a> CacheReader<'a> {
+    /// Opens the artifact at `path`. The artifact is assumed to be compressed
+    /// with zstd if `path` has a `.zst` extension.
+    pub fn open(path: &AbsoluteSystemPath) -> Result<Self, CacheError> {
+        let file = fs::File::open(path.as_path())?;
+        let is_compressed = path.as_path().extension().map_or(false, |ext| ext == "zst");
+
+        Self::from_reader(BufReader::new(file), is_compressed)
+    }
+
+    pub fn from_reader(reader: impl Read + 'a, is_compressed: bool) -> Result<Self, CacheError> {
+        let reader: Box<dyn Read + 'a> = if is_compressed {
+            Box::new(zstd::Decoder::new(reader)?)
+        } else {
+            Box::new(reader)
+        };
+
+        Ok(Self { reader })
+    }
+
+    /// Extracts the artifact into `anchor`, returning the paths that were
+    /// restored. We're permissive on creation, but restrictive on
+    /// restoration: if anything looks off we bail and the task simply runs.
+    pub fn restore(
+        &mut self,
+        anchor: &AbsoluteSystemPath,
+    ) -> Result<Vec<AnchoredSystemPathBuf>, CacheError> {
+        fs::create_dir_all(anchor.as_path())?;
+        let mut dir_cache = CachedDirTree::new(anchor)?;
+
+        let mut restored = Vec::new();
+        // On first attempt to restore it's possible that a link target doesn't
+        // exist yet. Save them and restore them once everything else is on disk.
+        let mut symlinks = Vec::new();
+
+        let mut archive = tar::Archive::new(&mut self.reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let name =
+                String::from_utf8(entry.path_bytes().into_owned()).map_err(PathError::from)?;
+            let file = canonicalize_name(&name)?;
+
+            match entry.header().entry_type() {
+                EntryType::Directory => {
+                    restore_directory(&mut dir_cache, &file, entry.header())?;
+                }
+                EntryType::Regular => {
+                    restore_regular(&mut dir_cache, &file, &mut entry)?;
+                }
+                EntryType::Symlink => {
+                    let link_target = read_link_target(&entry)?;
+                    if !restore_symlink(&mut dir_cache, &file, &link_target, false)? {
+                        symlinks.push((file, link_target));
+                        continue;
+                    }
+                }
+                ty => {
+                    return Err(CacheError::RestoreUnsupportedFileType(
+                        ty,
+                        Backtrace::capture(),
+                    ))
+                }
+            }
+
+            restored.push(file);
+        }
+
+        restored.extend(topologically_restore_symlinks(&mut dir_cache, symlinks)?);
+
+        Ok(restored)
+    }
+}
+
+/// Keeps track of the directories under the anchor that we have already
+/// created or verified, so that we don't have to `lstat` every path segment
+/// for every file that we restore.
+struct CachedDirTree {
+    anchor: AbsoluteSystemPathBuf,
+    // The anchor with all symlinks resolved. Used to check that a symlinked
+    // directory does not lead us outside of the anchor.
+    real_anchor: PathBuf,
+    verified: HashSet<PathBuf>,
+}
+
+impl CachedDirTree {
+    fn new(anchor: &AbsoluteSystemPath) -> Result<Self, CacheError> {
+        Ok(Self {
+            anchor: anchor.to_owned(),
+            real_anchor: dunce::canonicalize(anchor.as_path())?,
+            verified: HashSet::new(),
+        })
+    }
+
+    /// Creates `dir` and any missing parents, refusing to traverse any
+    /// symlinks that point outside of the anchor.
+    fn safe_mkdir_all(&mut self, dir: &Path) -> Result<(), CacheError> {
+        if self.verified.contains(dir) {
+            return Ok(());
+        }
+
+        let mut relative = PathBuf::new();
+        for component in dir.components() {
+            relative.push(component);
+            if self.verified.contains(&relative) {
+                continue;
+            }
+
+            let current = self.anchor.as_path().join(&relative);
+            match fs::symlink_metadata(&current) {
+                Ok(metadata) if metadata.is_dir() => {}
+                Ok(metadata) if metadata.is_symlink() => {
+                    let target = dunce::canonicalize(&current)?;
+                    if !target.starts_with(&self.real_anchor) || !target.is_dir() {
+                        return Err(CacheError::LinkOutsideOfDirectory(
+                            current.to_string_lossy().to_string(),
+                            Backtrace::capture(),
+                        ));
+                    }
+                }
+                // Something that isn't a directory is in the way, replace it.
+                Ok(_) => {
+                    fs::remove_file(&current)?;
+                    fs::create_dir(&current)?;
+                }
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    fs::create_dir(&current)?;
+                }
+                Err(err) => return Err(err.into()),
+            }
+
+            self.verified.insert(relative.clone());
+        }
+
+        Ok(())
+    }
+
+    fn safe_mkdir_parent(&mut self, file: &AnchoredSystemPathBuf) -> Result<(), CacheError> {
+        let file = PathBuf::from(file.clone());
+        match file.parent() {
+            Some(parent) => self.safe_mkdir_all(parent),
+            None => Ok(()),
+        }
+    }
+}
+
+fn restore_directory(
+    dir_cache: &mut CachedDirTree,
+    dir: &AnchoredSystemPathBuf,
+    header: &Header,
+) -> Result<(), CacheError> {
+    dir_cache.safe_mkdir_all(&PathBuf::from(dir.clone()))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let resolved = dir_cache.anchor.resolve(dir);
+        fs::set_permissions(
+            resolved.as_path(),
+            fs::Permissions::from_mode(header.mode()?),
+        )?;
+    }
+    #[cfg(not(unix))]
+    let _ = header;
+
+    Ok(())
+}
+
+fn restore_regular<R: Read>(
+    dir_cache: &mut CachedDirTree,
+    file: &AnchoredSystemPathBuf,
+    entry: &mut Entry<R>,
+) -> Result<(), CacheError> {
+    dir_cache.safe_mkdir_parent(file)?;
+
+    let resolved = dir_cache.anchor.resolve(file);
+    // Never write through an existing symlink, it could point anywhere.
+    if let Ok(metadata) = resolved.symlink_metadata() {
+        if metadata.is_symlink() {
+            resolved.remove_file()?;
+        }
+    }
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(entry.header().mode()?);
+    }
+
+    let mut output = options.open(resolved.as_path())?;
+    io::copy(entry, &mut output)?;
+
+    Ok(())
+}
+
+fn read_link_target<R: Read>(entry: &Entry<R>) -> Result<String, CacheError> {
+    let link_target = entry.link_name_bytes().ok_or_else(|| {
+        CacheError::MalformedName(
+            String::from_utf8_lossy(&entry.path_bytes()).to_string(),
+            Backtrace::capture(),
+        )
+    })?;
+
+    Ok(String::from_utf8(link_target.into_owned()).map_err(PathError::from)?)
+}
+
+// Returns the location that a link at `link` with the given target points to.
+fn resolve_link_target(
+    anchor: &AbsoluteSystemPath,
+    link: &AnchoredSystemPathBuf,
+    link_target: &str,
+) -> AbsoluteSystemPathBuf {
+    let resolved_link = anchor.resolve(link);
+    let link_parent = resolved_link.parent().unwrap_or_else(|| anchor.to_owned());
+    #[cfg(windows)]
+    let link_target = link_target.replace('/', "\\");
+
+    AbsoluteSystemPathBuf::from_unknown(&link_parent, link_target)
+}
+
+/// Attempts to restore the symlink at `link`. If the link target does not
+/// exist yet and `allow_missing_target` is false, the link is not created and
+/// `false` is returned so that restoration can be retried later.
+fn restore_symlink(
+    dir_cache: &mut CachedDirTree,
+    link: &AnchoredSystemPathBuf,
+    link_target: &str,
+    allow_missing_target: bool,
+) -> Result<bool, CacheError> {
+    let target_path = resolve_link_target(&dir_cache.anchor, link, link_target);
+    let target_metadata = target_path.stat().ok();
+    if target_metadata.is_none() && !allow_missing_target {
+        return Ok(false);
+    }
+
+    dir_cache.safe_mkdir_parent(link)?;
+
+    let resolved = dir_cache.anchor.resolve(link);
+    match resolved.symlink_metadata() {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir(resolved.as_path())?,
+        Ok(_) => resolved.remove_file()?,
+        Err(_) => {}
+    }
+
+    if target_metadata.map_or(false, |metadata| metadata.is_dir()) {
+        resolved.symlink_to_dir(link_target)?;
+    } else {
+        resolved.symlink_to_file(link_target)?;
+    }
+
+    Ok(true)
+}
+
+/// Restores symlinks whose targets did not exist on the first pass. Links
+/// that point to other links are restored after their targets.
+fn topologically_restore_symlinks(
+    dir_cache: &mut CachedDirTree,
+    symlinks: Vec<(AnchoredSystemPathBuf, String)>,
+) -> Result<Vec<AnchoredSystemPathBuf>, CacheError> {
+    let locations = symlinks
+        .iter()
+        .enumerate()
+        .map(|(idx, (link, _))| (dir_cache.anchor.resolve(link), idx))
+        .collect::<HashMap<_, _>>();
+    let dependencies = symlinks
+        .iter()
+        .map(|(link, link_target)| {
+            let target = resolve_link_target(&dir_cache.anchor, link, link_target);
+            locations.get(&target).copied()
+        })
+        .collect::<Vec<_>>();
+
+    #[derive(Clone, Copy, PartialEq)]
+    enum Visit {
+        Unvisited,
+        InProgress,
+        Done,
+    }
+
+    fn visit(
+        idx: usize,
+        dependencies: &[Option<usize>],
+        state: &mut [Visit],
+        order: &mut Vec<usize>,
+    ) -> Result<(), CacheError> {
+        match state[idx] {
+            Visit::Done => return Ok(()),
+            Visit::InProgress => return Err(CacheError::CycleDetected(Backtrace::capture())),
+            Visit::Unvisited => {}
+        }
+        state[idx] = Visit::InProgress;
+        if let Some(dependency) = dependencies[idx] {
+            visit(dependency, dependencies, state, order)?;
+        }
+        state[idx] = Visit::Done;
+        order.push(idx);
+        Ok(())
+    }
+
+    let mut state = vec![Visit::Unvisited; symlinks.len()];
+    let mut order = Vec::with_capacity(symlinks.len());
+    for idx in 0..symlinks.len() {
+        visit(idx, &dependencies, &mut state, &mut order)?;
+    }
+
+    let mut restored = Vec::with_capacity(symlinks.len());
+    for idx in order {
+        let (link, link_target) = &symlinks[idx];
+        restore_symlink(dir_cache, link, link_target, true)?;
+        restored.push(link.clone());
+    }
+
+    Ok(restored)
+}
+
+// Returns either an AnchoredSystemPathBuf or an error.
+fn canonicalize_name(name: &str) -> Result<AnchoredSystemPathBuf, CacheError> {
+    // Assuming this was a `turbo`-created input, we currently have an
+    // anchored unix path. Assuming this is malicious input we don't really care
+    // if we do the wrong thing.
+    let (well_formed, windows_safe) = check_name(name);
+
+    if !well_formed {
+        return Err(CacheError::MalformedName(
+            name.to_string(),
+            Backtrace::capture(),
+        ));
+    }
+
+    if cfg!(windows) && !windows_safe {
+        return Err(CacheError::WindowsUnsafeName(
+            name.to_string(),
+            Backtrace::capture(),
+        ));
+    }
+
+    // Directories will have a trailing slash. Remove it.
+    let no_trailing_slash = name.strip_suffix('/').unwrap_or(name);
+
+    Ok(AnchoredSystemPathBuf::from_raw(no_trailing_slash)?)
+}
+
+// Returns `(well_formed, windows_safe)` via inspection of separators and
+// traversal.
+fn check_name(name: &str) -> (bool, bool) {
+    if name.is_empty() {
+        return (false, false);
+    }
+
+    let well_formed = !(name == "."
+        || name == ".."
+        || name.starts_with('/')
+        || name.starts_with("./")
+        || name.starts_with("../")
+        || name.ends_with("/.")
+        || name.ends_with("/..")
+        || name.contains("//")
+        || name.contains("/./")
+        || name.contains("/../"));
+
+    let windows_safe = !name.contains('\\');
+
+    (well_formed, windows_safe)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::assert_matches::assert_matches;
+
+    use anyhow::Result;
+    use tar::{EntryType, Header};
+    use tempfile::tempdir;
+    use test_case::test_case;
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::{check_name, CacheReader};
+    use crate::CacheError;
+
+    fn header(entry_type: EntryType, size: u64) -> Header {
+        let mut header = Header::new_gnu();
+        header.set_entry_type(entry_type);
+        header.set_mode(0o755);
+        header.set_size(size);
+        header
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_restore_refuses_to_write_through_escaping_link() -> Result<()> {
+        let outside = tempdir()?;
+        let mut builder = tar::Builder::new(Vec::new());
+        builder.append_link(&mut header(EntryType::Symlink, 0), "escape", outside.path())?;
+        builder.append_data(
+            &mut header(EntryType::Regular, 5),
+            "escape/file.txt",
+            &b"hello"[..],
+        )?;
+        let archive = builder.into_inner()?;
+
+        let anchor = tempdir()?;
+        let anchor = AbsoluteSystemPathBuf::new(anchor.path())?;
+        let mut reader = CacheReader::from_reader(archive.as_slice(), false)?;
+        let result = reader.restore(&anchor);
+
+        assert_matches!(result, Err(CacheError::LinkOutsideOfDirectory(..)));
+        assert!(!outside.path().join("file.txt").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_restore_symlink_cycle() -> Result<()> {
+        let mut builder = tar::Builder::new(Vec::new());
+        builder.append_link(&mut header(EntryType::Symlink, 0), "one", "two")?;
+        builder.append_link(&mut header(EntryType::Symlink, 0), "two", "one")?;
+        let archive = builder.into_inner()?;
+
+        let anchor = tempdir()?;
+        let anchor = AbsoluteSystemPathBuf::new(anchor.path())?;
+        let mut reader = CacheReader::from_reader(archive.as_slice(), false)?;
+
+        assert_matches!(reader.restore(&anchor), Err(CacheError::CycleDetected(..)));
+        Ok(())
+    }
+
+    #[test_case("", (false, false) ; "empty")]
+    #[test_case(".", (false, true) ; "dot")]
+    #[test_case("..", (false, true) ; "dot dot")]
+    #[test_case("/", (false, true) ; "slash")]
+    #[test_case("./", (false, true) ; "dot slash")]
+    #[test_case("../", (false, true) ; "dot dot slash")]
+    #[test_case("/a", (false, true) ; "slash a")]
+    #[test_case("./a", (false, true) ; "dot slash a")]
+    #[test_case("../a", (false, true) ; "dot dot slash a")]
+    #[test_case("/.", (false, true) ; "slash dot")]
+    #[test_case("/..", (false, true) ; "slash dot dot")]
+    #[test_case("a/.", (false, true) ; "a slash dot")]
+    #[test_case("a/..", (false, true) ; "a slash dot dot")]
+    #[test_case("a//b", (false, true) ; "double slash")]
+    #[test_case("a/./b", (false, true) ; "inner dot")]
+    #[test_case("a/../b", (false, true) ; "inner dot dot")]
+    #[test_case("a\\b", (true, false) ; "backslash")]
+    #[test_case("a/b/", (true, true) ; "trailing slash")]
+    #[test_case("a/b", (true, true) ; "normal")]
+    #[test_case(".turbo/turbo-build.log", (true, true) ; "log file")]
+    fn test_check_name(name: &str, expected: (bool, bool)) {
+        assert_eq!(check_name(name), expected);
+    }
+}
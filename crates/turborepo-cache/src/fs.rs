@@ -0,0 +1,315 @@
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+
+use crate::{
+    cache_archive::{CacheReader, CacheWriter},
+    CacheError, CacheResponse, CacheSource,
+};
+
+/// A cache that stores artifacts on the local filesystem, by default in
+/// `node_modules/.cache/turbo`.
+///
+/// For every hash there is an artifact `<hash>.tar.zst` (or an uncompressed
+/// `<hash>.tar` written by older versions) and a `<hash>-meta.json` sidecar
+/// holding the hash and the duration of the task that produced it.
+#[derive(Debug, Clone)]
+pub struct FSCache {
+    cache_directory: AbsoluteSystemPathBuf,
+}
+
+/// Stores duration and hash information for a cache entry so that aggregate
+/// time saved calculations can be made from artifacts from various caches.
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheMetadata {
+    hash: String,
+    duration: u32,
+}
+
+impl CacheMetadata {
+    fn read(path: &AbsoluteSystemPath) -> Result<CacheMetadata, CacheError> {
+        let contents = fs::read(path.as_path())?;
+        Ok(serde_json::from_slice(&contents)?)
+    }
+
+    fn write(&self, path: &AbsoluteSystemPath) -> Result<(), CacheError> {
+        let contents = serde_json::to_vec(self)?;
+        fs::write(path.as_path(), contents)?;
+        Ok(())
+    }
+}
+
+impl FSCache {
+    fn resolve_cache_dir(
+        repo_root: &AbsoluteSystemPath,
+        override_dir: Option<&str>,
+    ) -> AbsoluteSystemPathBuf {
+        if let Some(override_dir) = override_dir {
+            AbsoluteSystemPathBuf::from_unknown(repo_root, override_dir)
+        } else {
+            repo_root.join_components(&["node_modules", ".cache", "turbo"])
+        }
+    }
+
+    pub fn new(
+        override_dir: Option<&str>,
+        repo_root: &AbsoluteSystemPath,
+    ) -> Result<Self, CacheError> {
+        let cache_directory = Self::resolve_cache_dir(repo_root, override_dir);
+        cache_directory.create_dir_all()?;
+
+        Ok(FSCache { cache_directory })
+    }
+
+    pub fn cache_directory(&self) -> &AbsoluteSystemPath {
+        &self.cache_directory
+    }
+
+    fn artifact_path(&self, hash: &str) -> Option<AbsoluteSystemPathBuf> {
+        let uncompressed_cache_path = self.cache_directory.join_component(&format!("{hash}.tar"));
+        let compressed_cache_path = self
+            .cache_directory
+            .join_component(&format!("{hash}.tar.zst"));
+
+        if uncompressed_cache_path.exists() {
+            Some(uncompressed_cache_path)
+        } else if compressed_cache_path.exists() {
+            Some(compressed_cache_path)
+        } else {
+            None
+        }
+    }
+
+    fn metadata_path(&self, hash: &str) -> AbsoluteSystemPathBuf {
+        self.cache_directory
+            .join_component(&format!("{hash}-meta.json"))
+    }
+
+    /// Restores the artifact for `hash` into `anchor`. Returns `None` on a
+    /// cache miss.
+    pub fn fetch(
+        &self,
+        anchor: &AbsoluteSystemPath,
+        hash: &str,
+    ) -> Result<Option<(CacheResponse, Vec<AnchoredSystemPathBuf>)>, CacheError> {
+        let Some(cache_path) = self.artifact_path(hash) else {
+            return Ok(None);
+        };
+
+        let mut cache_reader = CacheReader::open(&cache_path)?;
+        let restored_files = cache_reader.restore(anchor)?;
+
+        let meta = CacheMetadata::read(&self.metadata_path(hash))?;
+
+        Ok(Some((
+            CacheResponse {
+                source: CacheSource::Local,
+                time_saved: meta.duration,
+            },
+            restored_files,
+        )))
+    }
+
+    /// Checks whether an artifact for `hash` exists without restoring it.
+    pub fn exists(&self, hash: &str) -> Result<Option<CacheResponse>, CacheError> {
+        if self.artifact_path(hash).is_none() {
+            return Ok(None);
+        }
+
+        // Missing or corrupt metadata shouldn't turn a hit into a miss, we
+        // just can't report the time saved.
+        let time_saved = CacheMetadata::read(&self.metadata_path(hash))
+            .map(|meta| meta.duration)
+            .unwrap_or(0);
+
+        Ok(Some(CacheResponse {
+            source: CacheSource::Local,
+            time_saved,
+        }))
+    }
+
+    /// Stores `files`, which are anchored at `anchor`, as the artifact for
+    /// `hash`. `duration` is the time in milliseconds the task took to run.
+    pub fn put(
+        &self,
+        anchor: &AbsoluteSystemPath,
+        hash: &str,
+        files: &[AnchoredSystemPathBuf],
+        duration: u32,
+    ) -> Result<(), CacheError> {
+        let cache_path = self
+            .cache_directory
+            .join_component(&format!("{hash}.tar.zst"));
+
+        let mut cache_item = CacheWriter::create(&cache_path)?;
+        for file in files {
+            cache_item.add_file(anchor, file)?;
+        }
+        cache_item.finish()?;
+
+        let metadata = CacheMetadata {
+            hash: hash.to_string(),
+            duration,
+        };
+        metadata.write(&self.metadata_path(hash))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use anyhow::Result;
+    use tempfile::tempdir;
+    use turbopath::{AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+
+    use super::*;
+
+    struct TestFile {
+        path: &'static str,
+        contents: Option<&'static str>,
+        link_target: Option<&'static str>,
+    }
+
+    const TEST_FILES: &[TestFile] = &[
+        TestFile {
+            path: "dist",
+            contents: None,
+            link_target: None,
+        },
+        TestFile {
+            path: "dist/index.js",
+            contents: Some("console.log('hello')"),
+            link_target: None,
+        },
+        TestFile {
+            path: "dist/empty.txt",
+            contents: Some(""),
+            link_target: None,
+        },
+        TestFile {
+            path: "dist/nested",
+            contents: None,
+            link_target: None,
+        },
+        TestFile {
+            path: "dist/nested/deep.js",
+            contents: Some("export default 1"),
+            link_target: None,
+        },
+        TestFile {
+            path: "dist/link.js",
+            contents: None,
+            link_target: Some("index.js"),
+        },
+        TestFile {
+            path: "dist/dangling",
+            contents: None,
+            link_target: Some("does-not-exist"),
+        },
+    ];
+
+    fn create_files(root: &AbsoluteSystemPathBuf) -> Result<Vec<AnchoredSystemPathBuf>> {
+        let mut anchored = Vec::new();
+        for file in TEST_FILES {
+            let path = root.resolve(&AnchoredSystemPathBuf::from_raw(file.path)?);
+            match (file.contents, file.link_target) {
+                (Some(contents), _) => fs::write(path.as_path(), contents)?,
+                (None, Some(target)) => path.symlink_to_file(target)?,
+                (None, None) => path.create_dir_all()?,
+            }
+            anchored.push(AnchoredSystemPathBuf::from_raw(file.path)?);
+        }
+        Ok(anchored)
+    }
+
+    fn assert_files_restored(root: &AbsoluteSystemPathBuf) -> Result<()> {
+        for file in TEST_FILES {
+            let path = root.resolve(&AnchoredSystemPathBuf::from_raw(file.path)?);
+            let metadata = path.symlink_metadata()?;
+            match (file.contents, file.link_target) {
+                (Some(contents), _) => {
+                    assert!(metadata.is_file());
+                    assert_eq!(fs::read_to_string(path.as_path())?, contents);
+                }
+                (None, Some(target)) => {
+                    assert!(metadata.is_symlink());
+                    assert_eq!(path.read_link()?, std::path::PathBuf::from(target));
+                }
+                (None, None) => assert!(metadata.is_dir()),
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_put_and_fetch() -> Result<()> {
+        let repo_root = tempdir()?;
+        let repo_root_path = AbsoluteSystemPathBuf::new(repo_root.path())?;
+        let files = create_files(&repo_root_path)?;
+
+        let cache = FSCache::new(None, &repo_root_path)?;
+        assert_eq!(
+            cache.cache_directory().as_path(),
+            repo_root_path
+                .join_components(&["node_modules", ".cache", "turbo"])
+                .as_path()
+        );
+
+        let hash = "this-is-my-hash";
+        assert!(cache.exists(hash)?.is_none());
+
+        cache.put(&repo_root_path, hash, &files, 1234)?;
+        assert!(repo_root_path
+            .join_components(&["node_modules", ".cache", "turbo", "this-is-my-hash.tar.zst"])
+            .exists());
+
+        let expected_response = CacheResponse {
+            source: CacheSource::Local,
+            time_saved: 1234,
+        };
+        assert_eq!(cache.exists(hash)?, Some(expected_response));
+
+        let metadata = fs::read_to_string(
+            repo_root_path
+                .join_components(&[
+                    "node_modules",
+                    ".cache",
+                    "turbo",
+                    "this-is-my-hash-meta.json",
+                ])
+                .as_path(),
+        )?;
+        assert_eq!(metadata, r#"{"hash":"this-is-my-hash","duration":1234}"#);
+
+        let restore_root = tempdir()?;
+        let restore_root_path = AbsoluteSystemPathBuf::new(restore_root.path())?;
+        let (response, mut restored_files) = cache
+            .fetch(&restore_root_path, hash)?
+            .expect("expected a cache hit");
+        assert_eq!(response, expected_response);
+
+        let mut expected_files = files;
+        expected_files.sort();
+        restored_files.sort();
+        assert_eq!(restored_files, expected_files);
+        assert_files_restored(&restore_root_path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_fetch_miss() -> Result<()> {
+        let repo_root = tempdir()?;
+        let repo_root_path = AbsoluteSystemPathBuf::new(repo_root.path())?;
+        let cache = FSCache::new(Some("my-cache"), &repo_root_path)?;
+
+        assert!(repo_root_path.join_component("my-cache").exists());
+        assert!(cache.fetch(&repo_root_path, "missing")?.is_none());
+
+        Ok(())
+    }
+}
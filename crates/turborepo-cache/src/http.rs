@@ -0,0 +1,439 @@
+use std::{
+    backtrace::Backtrace,
+    fs::File,
+    io::{self, BufReader, Read, Seek, Write},
+};
+
+use bytes::{Buf, Bytes};
+use tokio::sync::mpsc;
+use turbopath::{AbsoluteSystemPath, AnchoredSystemPathBuf};
+use turborepo_api_client::{APIAuth, APIClient, Response};
+
+use crate::{
+    cache_archive::{CacheReader, CacheWriter},
+    signature_authentication::ArtifactSignatureAuthenticator,
+    CacheError, CacheOpts, CacheResponse, CacheSource,
+};
+
+const DOWNLOAD_CHANNEL_CAPACITY: usize = 16;
+
+/// A cache backed by the Vercel Remote Cache API.
+pub struct HttpCache {
+    client: APIClient,
+    signer_verifier: Option<ArtifactSignatureAuthenticator>,
+    api_auth: APIAuth,
+}
+
+impl HttpCache {
+    pub fn new(client: APIClient, opts: &CacheOpts, api_auth: APIAuth) -> HttpCache {
+        let signer_verifier = if opts
+            .remote_cache_opts
+            .as_ref()
+            .map_or(false, |remote_cache_opts| remote_cache_opts.signature)
+        {
+            Some(ArtifactSignatureAuthenticator::new(
+                api_auth.team_id.as_bytes().to_vec(),
+                None,
+            ))
+        } else {
+            None
+        };
+
+        HttpCache {
+            client,
+            signer_verifier,
+            api_auth,
+        }
+    }
+
+    /// Uploads `files`, which are anchored at `anchor`, as the artifact for
+    /// `hash`. If signing is enabled, the artifact is sent with an
+    /// `x-artifact-tag` header so that it can be verified on download.
+    pub async fn put(
+        &self,
+        anchor: &AbsoluteSystemPath,
+        hash: &str,
+        files: &[AnchoredSystemPathBuf],
+        duration: u32,
+    ) -> Result<(), CacheError> {
+        // The artifact is written to disk and uploaded from there, rather than
+        // being held in memory
+        let artifact = tempfile::NamedTempFile::new()?;
+        Self::write(artifact.as_file(), anchor, files)?;
+
+        let tag = self
+            .signer_verifier
+            .as_ref()
+            .map(|signer| {
+                signer.generate_tag_from_reader(hash.as_bytes(), BufReader::new(artifact.reopen()?))
+            })
+            .transpose()?;
+
+        self.client
+            .put_artifact(
+                hash,
+                artifact.path(),
+                duration,
+                tag.as_deref(),
+                &self.api_auth.token,
+                &self.api_auth.team_id,
+                self.api_auth.team_slug.as_deref(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    fn write(
+        writer: impl Write,
+        anchor: &AbsoluteSystemPath,
+        files: &[AnchoredSystemPathBuf],
+    ) -> Result<(), CacheError> {
+        let mut cache_archive = CacheWriter::from_writer(writer, true)?;
+        for file in files {
+            cache_archive.add_file(anchor, file)?;
+        }
+        cache_archive.finish()
+    }
+
+    /// Checks whether an artifact for `hash` exists without downloading it.
+    pub async fn exists(&self, hash: &str) -> Result<Option<CacheResponse>, CacheError> {
+        let Some(response) = self
+            .client
+            .artifact_exists(
+                hash,
+                &self.api_auth.token,
+                &self.api_auth.team_id,
+                self.api_auth.team_slug.as_deref(),
+            )
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let duration = Self::get_duration_from_response(&response)?;
+
+        Ok(Some(CacheResponse {
+            source: CacheSource::Remote,
+            time_saved: duration,
+        }))
+    }
+
+    // Writes the response body to `artifact` as it arrives
+    async fn download(mut response: Response, artifact: &mut File) -> Result<(), CacheError> {
+        while let Some(chunk) = response
+            .chunk()
+            .await
+            .map_err(turborepo_api_client::Error::from)?
+        {
+            artifact.write_all(&chunk)?;
+        }
+
+        Ok(())
+    }
+
+    // Restores the artifact while it's being downloaded. The archive is
+    // decoded on a blocking thread that's fed the body a chunk at a time.
+    // Files are written as they're decoded, so if the download fails the
+    // ones restored before the failure are left in `anchor`.
+    async fn restore_download(
+        mut response: Response,
+        anchor: &AbsoluteSystemPath,
+    ) -> Result<Vec<AnchoredSystemPathBuf>, CacheError> {
+        let (sender, receiver) = mpsc::channel(DOWNLOAD_CHANNEL_CAPACITY);
+        let anchor = anchor.to_owned();
+        let restore = tokio::task::spawn_blocking(move || {
+            CacheReader::from_reader(ChunkReader::new(receiver), true)?.restore(&anchor)
+        });
+
+        loop {
+            let chunk = match response.chunk().await {
+                Ok(Some(chunk)) => Ok(chunk),
+                Ok(None) => break,
+                Err(err) => Err(io::Error::new(io::ErrorKind::Other, err)),
+            };
+            let failed = chunk.is_err();
+            // The restore has already stopped, it will report why
+            if sender.send(chunk).await.is_err() || failed {
+                break;
+            }
+        }
+        drop(sender);
+
+        restore.await.expect("restoring artifact panicked")
+    }
+
+    fn get_duration_from_response(response: &Response) -> Result<u32, CacheError> {
+        let Some(duration_value) = response.headers().get("x-artifact-duration") else {
+            return Ok(0);
+        };
+
+        duration_value
+            .to_str()
+            .ok()
+            .and_then(|duration| duration.parse::<u32>().ok())
+            .ok_or_else(|| {
+                CacheError::InvalidDuration(
+                    String::from_utf8_lossy(duration_value.as_bytes()).to_string(),
+                    Backtrace::capture(),
+                )
+            })
+    }
+
+    /// Downloads the artifact for `hash` and restores it into `anchor`.
+    /// Returns `None` on a cache miss. If signing is enabled, artifacts
+    /// without a valid `x-artifact-tag` are rejected.
+    pub async fn fetch(
+        &self,
+        anchor: &AbsoluteSystemPath,
+        hash: &str,
+    ) -> Result<Option<(CacheResponse, Vec<AnchoredSystemPathBuf>)>, CacheError> {
+        let Some(response) = self
+            .client
+            .fetch_artifact(
+                hash,
+                &self.api_auth.token,
+                &self.api_auth.team_id,
+                self.api_auth.team_slug.as_deref(),
+            )
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let duration = Self::get_duration_from_response(&response)?;
+        let expected_tag = response
+            .headers()
+            .get("x-artifact-tag")
+            .map(|tag| String::from_utf8_lossy(tag.as_bytes()).to_string());
+
+        let files = match &self.signer_verifier {
+            // If the verifier is enabled all incoming artifact downloads must
+            // have a signature. Nothing can be restored until the whole
+            // artifact has been verified, so it's downloaded to disk first.
+            Some(signer_verifier) => {
+                let expected_tag = expected_tag
+                    .ok_or_else(|| CacheError::ArtifactTagMissing(Backtrace::capture()))?;
+                let mut artifact = tempfile::tempfile()?;
+                Self::download(response, &mut artifact).await?;
+
+                artifact.rewind()?;
+                if !signer_verifier.validate_reader(
+                    hash.as_bytes(),
+                    BufReader::new(&artifact),
+                    &expected_tag,
+                )? {
+                    return Err(CacheError::InvalidTag(expected_tag, Backtrace::capture()));
+                }
+
+                artifact.rewind()?;
+                CacheReader::from_reader(BufReader::new(artifact), true)?.restore(anchor)?
+            }
+            None => Self::restore_download(response, anchor).await?,
+        };
+
+        Ok(Some((
+            CacheResponse {
+                source: CacheSource::Remote,
+                time_saved: duration,
+            },
+            files,
+        )))
+    }
+}
+
+// Reads the chunks of a download as they're received. A failed download is
+// reported as a read error, so that a truncated artifact fails the restore
+// rather than being reported as a hit. The fetch is then treated as a miss and
+// the task runs again, overwriting anything that was already restored.
+struct ChunkReader {
+    receiver: mpsc::Receiver<io::Result<Bytes>>,
+    chunk: Bytes,
+}
+
+impl ChunkReader {
+    fn new(receiver: mpsc::Receiver<io::Result<Bytes>>) -> Self {
+        Self {
+            receiver,
+            chunk: Bytes::new(),
+        }
+    }
+}
+
+impl Read for ChunkReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.chunk.is_empty() {
+            match self.receiver.blocking_recv() {
+                Some(chunk) => self.chunk = chunk?,
+                None => return Ok(0),
+            }
+        }
+
+        let read = buf.len().min(self.chunk.len());
+        buf[..read].copy_from_slice(&self.chunk[..read]);
+        self.chunk.advance(read);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        assert_matches::assert_matches,
+        fs,
+        io::{self, Read},
+    };
+
+    use anyhow::Result;
+    use bytes::Bytes;
+    use tempfile::tempdir;
+    use tokio::sync::mpsc;
+    use turbopath::{AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+    use turborepo_api_client::{APIAuth, APIClient};
+    use vercel_api_mock::start_test_server;
+
+    use super::{ArtifactSignatureAuthenticator, ChunkReader, HttpCache};
+    use crate::{CacheError, CacheOpts, CacheResponse, CacheSource, RemoteCacheOpts};
+
+    fn api_auth() -> APIAuth {
+        APIAuth {
+            team_id: "my-team".to_string(),
+            token: "my-token".to_string(),
+            team_slug: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_http_cache() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+
+        let repo_root = tempdir()?;
+        let repo_root_path = AbsoluteSystemPathBuf::new(repo_root.path())?;
+        fs::create_dir(repo_root_path.join_component("dist").as_path())?;
+        fs::write(
+            repo_root_path
+                .join_components(&["dist", "index.js"])
+                .as_path(),
+            "console.log('hello')",
+        )?;
+        let files = vec![
+            AnchoredSystemPathBuf::from_raw("dist")?,
+            AnchoredSystemPathBuf::from_raw("dist/index.js")?,
+        ];
+
+        let api_client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0")?;
+        let cache = HttpCache::new(api_client, &CacheOpts::default(), api_auth());
+
+        let hash = "this-is-my-hash";
+        assert!(cache.exists(hash).await?.is_none());
+
+        cache.put(&repo_root_path, hash, &files, 42).await?;
+
+        let expected_response = CacheResponse {
+            source: CacheSource::Remote,
+            time_saved: 42,
+        };
+        assert_eq!(cache.exists(hash).await?, Some(expected_response));
+
+        let restore_root = tempdir()?;
+        let restore_root_path = AbsoluteSystemPathBuf::new(restore_root.path())?;
+        let (response, restored_files) = cache
+            .fetch(&restore_root_path, hash)
+            .await?
+            .expect("expected a cache hit");
+        assert_eq!(response, expected_response);
+        assert_eq!(restored_files, files);
+        assert_eq!(
+            fs::read_to_string(
+                restore_root_path
+                    .join_components(&["dist", "index.js"])
+                    .as_path()
+            )?,
+            "console.log('hello')"
+        );
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_http_cache_signature() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+
+        let repo_root = tempdir()?;
+        let repo_root_path = AbsoluteSystemPathBuf::new(repo_root.path())?;
+        fs::write(repo_root_path.join_component("out.txt").as_path(), "out")?;
+        let files = vec![AnchoredSystemPathBuf::from_raw("out.txt")?];
+
+        let signed_opts = CacheOpts {
+            remote_cache_opts: Some(RemoteCacheOpts {
+                team_id: "my-team".to_string(),
+                signature: true,
+                enabled: true,
+            }),
+            ..CacheOpts::default()
+        };
+
+        let api_client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0")?;
+        let mut cache = HttpCache::new(api_client, &signed_opts, api_auth());
+        assert!(cache.signer_verifier.is_some());
+        // Override the secret key to avoid depending on the environment
+        cache.signer_verifier = Some(ArtifactSignatureAuthenticator::new(
+            b"my-team".to_vec(),
+            Some(b"secret".to_vec()),
+        ));
+
+        // An artifact uploaded without a signature is rejected
+        let unsigned_client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0")?;
+        let unsigned_cache = HttpCache::new(unsigned_client, &CacheOpts::default(), api_auth());
+        unsigned_cache
+            .put(&repo_root_path, "unsigned", &files, 1)
+            .await?;
+        assert_matches!(
+            cache.fetch(&repo_root_path, "unsigned").await,
+            Err(CacheError::ArtifactTagMissing(..))
+        );
+
+        // A signed artifact round-trips
+        cache.put(&repo_root_path, "signed", &files, 1).await?;
+        assert!(cache.fetch(&repo_root_path, "signed").await?.is_some());
+
+        // A signature made with a different key is rejected
+        cache.signer_verifier = Some(ArtifactSignatureAuthenticator::new(
+            b"my-team".to_vec(),
+            Some(b"another secret".to_vec()),
+        ));
+        assert_matches!(
+            cache.fetch(&repo_root_path, "signed").await,
+            Err(CacheError::InvalidTag(..))
+        );
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[test]
+    fn test_chunk_reader() -> Result<()> {
+        let (sender, receiver) = mpsc::channel(4);
+        sender.try_send(Ok(Bytes::from_static(b"hello ")))?;
+        sender.try_send(Ok(Bytes::new()))?;
+        sender.try_send(Ok(Bytes::from_static(b"world")))?;
+        drop(sender);
+
+        let mut contents = String::new();
+        ChunkReader::new(receiver).read_to_string(&mut contents)?;
+        assert_eq!(contents, "hello world");
+
+        // A failed download is an error rather than the end of the artifact
+        let (sender, receiver) = mpsc::channel(4);
+        sender.try_send(Ok(Bytes::from_static(b"partial")))?;
+        sender.try_send(Err(io::Error::new(io::ErrorKind::Other, "reset")))?;
+        assert!(ChunkReader::new(receiver)
+            .read_to_end(&mut Vec::new())
+            .is_err());
+
+        Ok(())
+    }
+}
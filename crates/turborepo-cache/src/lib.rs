@@ -1 +1,182 @@
+#![feature(error_generic_member_access)]
+#![feature(provide_any)]
+#![feature(assert_matches)]
+
+pub mod cache_archive;
+pub mod fs;
+pub mod http;
+pub mod multiplexer;
 pub mod signature_authentication;
+
+use std::backtrace::Backtrace;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use turbopath::PathError;
+
+use crate::signature_authentication::SignatureError;
+
+#[derive(Debug, Error)]
+pub enum CacheError {
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error, #[backtrace] Backtrace),
+    #[error("path error: {0}")]
+    Path(#[from] PathError, #[backtrace] Backtrace),
+    #[error("invalid cache metadata: {0}")]
+    InvalidMetadata(#[from] serde_json::Error, #[backtrace] Backtrace),
+    #[error("attempted to restore unsupported file type: {0:?}")]
+    RestoreUnsupportedFileType(tar::EntryType, #[backtrace] Backtrace),
+    #[error("attempted to create unsupported file type: {0}")]
+    CreateUnsupportedFileType(String, #[backtrace] Backtrace),
+    #[error("tar attempts to write outside of directory: {0}")]
+    LinkOutsideOfDirectory(String, #[backtrace] Backtrace),
+    #[error("links in the cache are cyclic")]
+    CycleDetected(#[backtrace] Backtrace),
+    #[error("file name is malformed: {0}")]
+    MalformedName(String, #[backtrace] Backtrace),
+    #[error("file name is not Windows-safe: {0}")]
+    WindowsUnsafeName(String, #[backtrace] Backtrace),
+    #[error("api client error: {0}")]
+    ApiClientError(Box<turborepo_api_client::Error>, #[backtrace] Backtrace),
+    #[error("signing artifact failed: {0}")]
+    SignatureError(#[from] SignatureError, #[backtrace] Backtrace),
+    #[error(
+        "artifact verification failed: Downloaded artifact is missing required x-artifact-tag \
+         header"
+    )]
+    ArtifactTagMissing(#[backtrace] Backtrace),
+    #[error("invalid artifact verification tag: {0}")]
+    InvalidTag(String, #[backtrace] Backtrace),
+    #[error("invalid x-artifact-duration header: {0}")]
+    InvalidDuration(String, #[backtrace] Backtrace),
+}
+
+impl From<turborepo_api_client::Error> for CacheError {
+    fn from(value: turborepo_api_client::Error) -> Self {
+        CacheError::ApiClientError(Box::new(value), Backtrace::capture())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheSource {
+    Local,
+    Remote,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheResponse {
+    pub source: CacheSource,
+    // The duration of the original task in milliseconds, i.e. how much time
+    // restoring from cache saves us.
+    pub time_saved: u32,
+}
+
+/// Whether a cache layer may be read from and/or written to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheActions {
+    pub read: bool,
+    pub write: bool,
+}
+
+impl CacheActions {
+    pub const DISABLED: Self = Self {
+        read: false,
+        write: false,
+    };
+    pub const READ_ONLY: Self = Self {
+        read: true,
+        write: false,
+    };
+    pub const WRITE_ONLY: Self = Self {
+        read: false,
+        write: true,
+    };
+
+    pub fn is_disabled(&self) -> bool {
+        !self.read && !self.write
+    }
+}
+
+impl Default for CacheActions {
+    fn default() -> Self {
+        Self {
+            read: true,
+            write: true,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct CacheOpts<'a> {
+    pub override_dir: Option<&'a str>,
+    pub local: CacheActions,
+    pub remote: CacheActions,
+    pub workers: u32,
+    pub remote_cache_opts: Option<RemoteCacheOpts>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteCacheOpts {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    team_id: String,
+    #[serde(default, skip_serializing_if = "is_false")]
+    signature: bool,
+    #[serde(
+        default = "default_remote_cache_enabled",
+        skip_serializing_if = "is_default_remote_cache_enabled"
+    )]
+    enabled: bool,
+}
+
+impl RemoteCacheOpts {
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+}
+
+impl Default for RemoteCacheOpts {
+    fn default() -> Self {
+        Self {
+            team_id: String::new(),
+            signature: false,
+            enabled: default_remote_cache_enabled(),
+        }
+    }
+}
+
+// Matches Go, where a missing `enabled` key means the remote cache is on.
+fn default_remote_cache_enabled() -> bool {
+    true
+}
+
+fn is_default_remote_cache_enabled(value: &bool) -> bool {
+    *value == default_remote_cache_enabled()
+}
+
+// Empty fields are omitted when serializing, as Go does.
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_remote_cache_opts_round_trip() -> serde_json::Result<()> {
+        let default = RemoteCacheOpts::default();
+        assert_eq!(serde_json::to_string(&default)?, "{}");
+        assert!(serde_json::from_str::<RemoteCacheOpts>("{}")?.enabled());
+
+        let disabled = RemoteCacheOpts {
+            enabled: false,
+            ..Default::default()
+        };
+        let serialized = serde_json::to_string(&disabled)?;
+        assert_eq!(serialized, r#"{"enabled":false}"#);
+        assert!(!serde_json::from_str::<RemoteCacheOpts>(&serialized)?.enabled());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,441 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use tracing::{debug, warn};
+use turbopath::{AbsoluteSystemPath, AnchoredSystemPathBuf};
+use turborepo_api_client::{APIAuth, APIClient};
+
+use crate::{fs::FSCache, http::HttpCache, CacheActions, CacheError, CacheOpts, CacheResponse};
+
+/// Combines the local filesystem cache and the remote HTTP cache.
+///
+/// Reads check the local cache first and fall back to the remote cache.
+/// Remote hits are written back to the local cache so that the next read is
+/// served locally. Each layer can independently be made read-only,
+/// write-only or disabled via `CacheOpts`.
+pub struct CacheMultiplexer {
+    fs: Option<FSCache>,
+    local_actions: CacheActions,
+    http: Option<HttpCache>,
+    remote_actions: CacheActions,
+    // Set once the remote cache reports that caching is disabled for this
+    // team, so that we stop hitting it for the rest of the run.
+    remote_disabled: AtomicBool,
+}
+
+impl CacheMultiplexer {
+    /// Creates a new multiplexer. `api_auth` is `None` when the user isn't
+    /// logged in, in which case the remote cache is skipped.
+    pub fn new(
+        opts: &CacheOpts,
+        repo_root: &AbsoluteSystemPath,
+        api_client: APIClient,
+        api_auth: Option<APIAuth>,
+    ) -> Result<Self, CacheError> {
+        let remote_cache_enabled = opts
+            .remote_cache_opts
+            .as_ref()
+            .map_or(true, |remote_cache_opts| remote_cache_opts.enabled());
+        let remote_actions = if remote_cache_enabled {
+            opts.remote
+        } else {
+            CacheActions::DISABLED
+        };
+
+        let fs = (!opts.local.is_disabled())
+            .then(|| FSCache::new(opts.override_dir, repo_root))
+            .transpose()?;
+
+        let http = match api_auth {
+            Some(api_auth) if !remote_actions.is_disabled() => {
+                Some(HttpCache::new(api_client, opts, api_auth))
+            }
+            _ => None,
+        };
+
+        if fs.is_none() && http.is_none() {
+            debug!("no caches are enabled");
+        }
+
+        Ok(CacheMultiplexer {
+            fs,
+            local_actions: opts.local,
+            http,
+            remote_actions,
+            remote_disabled: AtomicBool::new(false),
+        })
+    }
+
+    fn fs(&self, needs: impl Fn(&CacheActions) -> bool) -> Option<&FSCache> {
+        self.fs.as_ref().filter(|_| needs(&self.local_actions))
+    }
+
+    fn http(&self, needs: impl Fn(&CacheActions) -> bool) -> Option<&HttpCache> {
+        self.http.as_ref().filter(|_| {
+            needs(&self.remote_actions) && !self.remote_disabled.load(Ordering::Relaxed)
+        })
+    }
+
+    /// Problems with the remote cache never fail the task, the remote cache is
+    /// treated as a miss instead. If it told us caching is disabled, stop
+    /// using it altogether.
+    fn handle_remote_error(&self, err: CacheError) {
+        match err {
+            CacheError::ApiClientError(err, _)
+                if matches!(*err, turborepo_api_client::Error::CacheDisabled { .. }) =>
+            {
+                warn!("{}, removing remote cache", err);
+                self.remote_disabled.store(true, Ordering::Relaxed);
+            }
+            err => warn!("failed to use remote cache: {}", err),
+        }
+    }
+
+    /// Stores `files`, which are anchored at `anchor`, in every writable
+    /// cache layer.
+    pub async fn put(
+        &self,
+        anchor: &AbsoluteSystemPath,
+        hash: &str,
+        files: &[AnchoredSystemPathBuf],
+        duration: u32,
+    ) -> Result<(), CacheError> {
+        if let Some(fs) = self.fs(|actions| actions.write) {
+            fs.put(anchor, hash, files, duration)?;
+        }
+
+        if let Some(http) = self.http(|actions| actions.write) {
+            if let Err(err) = http.put(anchor, hash, files, duration).await {
+                self.handle_remote_error(err);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Restores the artifact for `hash` into `anchor` from the first readable
+    /// layer that has it. Returns `None` on a cache miss.
+    pub async fn fetch(
+        &self,
+        anchor: &AbsoluteSystemPath,
+        hash: &str,
+    ) -> Result<Option<(CacheResponse, Vec<AnchoredSystemPathBuf>)>, CacheError> {
+        if let Some(fs) = self.fs(|actions| actions.read) {
+            match fs.fetch(anchor, hash) {
+                Ok(Some(hit)) => return Ok(Some(hit)),
+                Ok(None) => {}
+                // A broken local entry shouldn't stop us from trying the
+                // remote cache
+                Err(err) => warn!("failed to fetch {} from local cache: {}", hash, err),
+            }
+        }
+
+        let Some(http) = self.http(|actions| actions.read) else {
+            return Ok(None);
+        };
+
+        let hit = match http.fetch(anchor, hash).await {
+            Ok(hit) => hit,
+            Err(err) => {
+                self.handle_remote_error(err);
+                None
+            }
+        };
+
+        if let Some((response, files)) = &hit {
+            // Back-fill the local cache. Failing to do so shouldn't fail the
+            // fetch, since the outputs have already been restored.
+            if let Some(fs) = self.fs(|actions| actions.write) {
+                if let Err(err) = fs.put(anchor, hash, files, response.time_saved) {
+                    warn!("failed to back-fill local cache for {}: {}", hash, err);
+                }
+            }
+        }
+
+        Ok(hit)
+    }
+
+    /// Checks whether any readable layer has an artifact for `hash`.
+    pub async fn exists(&self, hash: &str) -> Result<Option<CacheResponse>, CacheError> {
+        if let Some(fs) = self.fs(|actions| actions.read) {
+            if let Some(response) = fs.exists(hash)? {
+                return Ok(Some(response));
+            }
+        }
+
+        let Some(http) = self.http(|actions| actions.read) else {
+            return Ok(None);
+        };
+
+        match http.exists(hash).await {
+            Ok(response) => Ok(response),
+            Err(err) => {
+                self.handle_remote_error(err);
+                Ok(None)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use anyhow::Result;
+    use tempfile::tempdir;
+    use turbopath::{AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+    use turborepo_api_client::{APIAuth, APIClient};
+    use vercel_api_mock::start_test_server;
+
+    use super::CacheMultiplexer;
+    use crate::{CacheActions, CacheOpts, CacheResponse, CacheSource, RemoteCacheOpts};
+
+    struct TestCase {
+        local: CacheActions,
+        remote: CacheActions,
+        remote_cache_enabled: bool,
+        // Which layers should have the artifact after a `put`
+        expected_local_put: bool,
+        expected_remote_put: bool,
+    }
+
+    fn api_auth() -> APIAuth {
+        APIAuth {
+            team_id: "my-team".to_string(),
+            token: "my-token".to_string(),
+            team_slug: None,
+        }
+    }
+
+    fn create_output(root: &AbsoluteSystemPathBuf) -> Result<Vec<AnchoredSystemPathBuf>> {
+        fs::write(root.join_component("out.txt").as_path(), "hello")?;
+        Ok(vec![AnchoredSystemPathBuf::from_raw("out.txt")?])
+    }
+
+    #[tokio::test]
+    async fn test_put_respects_actions() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+
+        let test_cases = [
+            TestCase {
+                local: CacheActions::default(),
+                remote: CacheActions::default(),
+                remote_cache_enabled: true,
+                expected_local_put: true,
+                expected_remote_put: true,
+            },
+            TestCase {
+                local: CacheActions::default(),
+                remote: CacheActions::READ_ONLY,
+                remote_cache_enabled: true,
+                expected_local_put: true,
+                expected_remote_put: false,
+            },
+            TestCase {
+                local: CacheActions::DISABLED,
+                remote: CacheActions::default(),
+                remote_cache_enabled: true,
+                expected_local_put: false,
+                expected_remote_put: true,
+            },
+            TestCase {
+                local: CacheActions::WRITE_ONLY,
+                remote: CacheActions::default(),
+                remote_cache_enabled: false,
+                expected_local_put: true,
+                expected_remote_put: false,
+            },
+        ];
+
+        for (i, test_case) in test_cases.into_iter().enumerate() {
+            let repo_root = tempdir()?;
+            let repo_root_path = AbsoluteSystemPathBuf::new(repo_root.path())?;
+            let files = create_output(&repo_root_path)?;
+
+            let opts = CacheOpts {
+                local: test_case.local,
+                remote: test_case.remote,
+                remote_cache_opts: Some(RemoteCacheOpts {
+                    enabled: test_case.remote_cache_enabled,
+                    ..RemoteCacheOpts::default()
+                }),
+                ..CacheOpts::default()
+            };
+            let api_client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0")?;
+            let cache =
+                CacheMultiplexer::new(&opts, &repo_root_path, api_client, Some(api_auth()))?;
+
+            let hash = format!("put-hash-{}", i);
+            cache.put(&repo_root_path, &hash, &files, 10).await?;
+
+            let local_cache = crate::fs::FSCache::new(None, &repo_root_path)?;
+            assert_eq!(
+                local_cache.exists(&hash)?.is_some(),
+                test_case.expected_local_put
+            );
+
+            let remote_client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0")?;
+            let remote_cache =
+                crate::http::HttpCache::new(remote_client, &CacheOpts::default(), api_auth());
+            assert_eq!(
+                remote_cache.exists(&hash).await?.is_some(),
+                test_case.expected_remote_put
+            );
+        }
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_fetch_backfills_local() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+
+        let repo_root = tempdir()?;
+        let repo_root_path = AbsoluteSystemPathBuf::new(repo_root.path())?;
+        let files = create_output(&repo_root_path)?;
+        let hash = "remote-only-hash";
+
+        // Seed only the remote cache
+        let remote_client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0")?;
+        let remote_cache =
+            crate::http::HttpCache::new(remote_client, &CacheOpts::default(), api_auth());
+        remote_cache.put(&repo_root_path, hash, &files, 25).await?;
+
+        let api_client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0")?;
+        let cache = CacheMultiplexer::new(
+            &CacheOpts::default(),
+            &repo_root_path,
+            api_client,
+            Some(api_auth()),
+        )?;
+
+        let restore_root = tempdir()?;
+        let restore_root_path = AbsoluteSystemPathBuf::new(restore_root.path())?;
+        let (response, restored_files) = cache
+            .fetch(&restore_root_path, hash)
+            .await?
+            .expect("expected a remote cache hit");
+        assert_eq!(
+            response,
+            CacheResponse {
+                source: CacheSource::Remote,
+                time_saved: 25,
+            }
+        );
+        assert_eq!(restored_files, files);
+
+        // The second fetch is served by the back-filled local cache
+        let (response, _) = cache
+            .fetch(&restore_root_path, hash)
+            .await?
+            .expect("expected a local cache hit");
+        assert_eq!(
+            response,
+            CacheResponse {
+                source: CacheSource::Local,
+                time_saved: 25,
+            }
+        );
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_no_auth_skips_remote() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+
+        let repo_root = tempdir()?;
+        let repo_root_path = AbsoluteSystemPathBuf::new(repo_root.path())?;
+        let files = create_output(&repo_root_path)?;
+        let hash = "logged-out-hash";
+
+        let api_client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0")?;
+        let cache = CacheMultiplexer::new(
+            &CacheOpts {
+                local: CacheActions::DISABLED,
+                ..CacheOpts::default()
+            },
+            &repo_root_path,
+            api_client,
+            None,
+        )?;
+
+        cache.put(&repo_root_path, hash, &files, 1).await?;
+        assert!(cache.exists(hash).await?.is_none());
+        assert!(cache.fetch(&repo_root_path, hash).await?.is_none());
+
+        handle.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cache_errors_are_misses() -> Result<()> {
+        let port = port_scanner::request_open_port().unwrap();
+        let handle = tokio::spawn(start_test_server(port));
+
+        let repo_root = tempdir()?;
+        let repo_root_path = AbsoluteSystemPathBuf::new(repo_root.path())?;
+        let files = create_output(&repo_root_path)?;
+        let hash = "broken-local-hash";
+
+        let remote_client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0")?;
+        let remote_cache =
+            crate::http::HttpCache::new(remote_client, &CacheOpts::default(), api_auth());
+        remote_cache.put(&repo_root_path, hash, &files, 5).await?;
+
+        // A corrupt local artifact falls through to the remote cache
+        let local_cache = crate::fs::FSCache::new(None, &repo_root_path)?;
+        local_cache.put(&repo_root_path, hash, &files, 5)?;
+        fs::write(
+            repo_root_path
+                .join_components(&[
+                    "node_modules",
+                    ".cache",
+                    "turbo",
+                    "broken-local-hash.tar.zst",
+                ])
+                .as_path(),
+            "not an artifact",
+        )?;
+
+        let api_client = APIClient::new(format!("http://localhost:{}", port), 200, "2.0.0")?;
+        let cache = CacheMultiplexer::new(
+            &CacheOpts::default(),
+            &repo_root_path,
+            api_client,
+            Some(api_auth()),
+        )?;
+        let (response, _) = cache
+            .fetch(&repo_root_path, hash)
+            .await?
+            .expect("expected a remote cache hit");
+        assert_eq!(response.source, CacheSource::Remote);
+
+        // An unreachable remote cache is a miss rather than an error
+        let unreachable_port = port_scanner::request_open_port().unwrap();
+        let api_client = APIClient::new(
+            format!("http://localhost:{}", unreachable_port),
+            200,
+            "2.0.0",
+        )?;
+        let cache = CacheMultiplexer::new(
+            &CacheOpts {
+                local: CacheActions::DISABLED,
+                ..CacheOpts::default()
+            },
+            &repo_root_path,
+            api_client,
+            Some(api_auth()),
+        )?;
+        cache.put(&repo_root_path, hash, &files, 5).await?;
+        assert!(cache.exists(hash).await?.is_none());
+        assert!(cache.fetch(&repo_root_path, hash).await?.is_none());
+
+        handle.abort();
+        Ok(())
+    }
+}
@@ -1,9 +1,9 @@
-use std::env;
+use std::{env, io, io::Read};
 
 use base64::{prelude::BASE64_STANDARD, Engine};
 use os_str_bytes::OsStringBytes;
 use ring::{
-    hmac,
+    constant_time, hmac,
     hmac::{Algorithm, Tag, HMAC_SHA256},
 };
 use thiserror::Error;
@@ -19,6 +19,8 @@ pub enum SignatureError {
     SerializationError(#[from] serde_json::Error),
     #[error("base64 encoding error: {0}")]
     Base64EncodingError(#[from] base64::DecodeError),
+    #[error("error reading artifact: {0}")]
+    Io(#[from] io::Error),
 }
 
 static TURBO_HMAC_ALGORITHM: Algorithm = HMAC_SHA256;
@@ -93,6 +95,43 @@ impl ArtifactSignatureAuthenticator {
         Ok(BASE64_STANDARD.encode(hmac_output))
     }
 
+    // Signs the artifact a chunk at a time, so that it doesn't have to be held
+    // in memory
+    fn sign_reader(&self, hash: &[u8], mut artifact: impl Read) -> Result<Tag, SignatureError> {
+        let mut hmac_ctx = self.get_tag_generator(hash)?;
+        let mut buffer = [0; 8192];
+        loop {
+            let read = artifact.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hmac_ctx.update(&buffer[..read]);
+        }
+
+        Ok(hmac_ctx.sign())
+    }
+
+    /// Like `generate_tag`, but reads the artifact from `artifact`.
+    pub fn generate_tag_from_reader(
+        &self,
+        hash: &[u8],
+        artifact: impl Read,
+    ) -> Result<String, SignatureError> {
+        Ok(BASE64_STANDARD.encode(self.sign_reader(hash, artifact)?))
+    }
+
+    /// Like `validate`, but reads the artifact from `artifact`.
+    pub fn validate_reader(
+        &self,
+        hash: &[u8],
+        artifact: impl Read,
+        expected_tag: &str,
+    ) -> Result<bool, SignatureError> {
+        let expected_bytes = BASE64_STANDARD.decode(expected_tag)?;
+        let tag = self.sign_reader(hash, artifact)?;
+        Ok(constant_time::verify_slices_are_equal(tag.as_ref(), &expected_bytes).is_ok())
+    }
+
     pub fn validate(
         &self,
         hash: &[u8],
@@ -265,6 +304,14 @@ mod tests {
 
         // Confirm it's valid
         assert!(signature.validate(hash, artifact_body, &tag)?);
+
+        // Signing a reader gives the same tag as signing the bytes
+        assert_eq!(
+            signature.generate_tag_from_reader(hash, *artifact_body)?,
+            tag
+        );
+        assert!(signature.validate_reader(hash, *artifact_body, &tag)?);
+        assert!(!signature.validate_reader(hash, *artifact_body, &bad_tag)?);
         Ok(())
     }
 }
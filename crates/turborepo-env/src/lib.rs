@@ -7,6 +7,7 @@ use std::{
 
 use regex::Regex;
 use serde::Serialize;
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 
 const DEFAULT_ENV_VARS: [&str; 1] = ["VERCEL_ANALYTICS_ID"];
@@ -23,7 +24,7 @@ pub enum Error {
 pub struct EnvironmentVariableMap(HashMap<String, String>);
 
 // BySource contains a map of environment variables broken down by the source
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize)]
 pub struct BySource {
     pub explicit: EnvironmentVariableMap,
     pub matching: EnvironmentVariableMap,
@@ -32,7 +33,7 @@ pub struct BySource {
 // DetailedMap contains the composite and the detailed maps of environment
 // variables All is used as a taskhash input (taskhash.CalculateTaskHash)
 // BySource is used by dry runs and run summaries
-#[derive(Debug, Serialize)]
+#[derive(Clone, Debug, Serialize)]
 pub struct DetailedMap {
     pub all: EnvironmentVariableMap,
     pub by_source: BySource,
@@ -83,6 +84,36 @@ impl EnvironmentVariableMap {
         self.0
     }
 
+    // Returns a deterministically sorted list of `KEY=value` pairs. This is used
+    // as a task hash input, so it needs to be stable.
+    pub fn to_hashable(&self) -> Vec<String> {
+        let mut pairs: Vec<String> = self
+            .0
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect();
+        pairs.sort();
+        pairs
+    }
+
+    // Returns a deterministically sorted list of `KEY=<sha256 of value>` pairs,
+    // so that env var values can be displayed without leaking secrets.
+    pub fn to_secret_hashable(&self) -> Vec<String> {
+        let mut pairs: Vec<String> = self
+            .0
+            .iter()
+            .map(|(key, value)| {
+                if value.is_empty() {
+                    format!("{}=", key)
+                } else {
+                    format!("{}={}", key, hex::encode(Sha256::digest(value)))
+                }
+            })
+            .collect();
+        pairs.sort();
+        pairs
+    }
+
     // Takes another EnvironmentVariableMap and adds it into `self`
     // Overwrites values if they already exist.
     pub fn union(&mut self, another: &EnvironmentVariableMap) {
@@ -262,4 +293,33 @@ mod tests {
         let actual = super::wildcard_to_regex_pattern(pattern);
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_to_hashable() {
+        let env = super::EnvironmentVariableMap(
+            [("B", "2"), ("A", "1"), ("C", "")]
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        );
+        assert_eq!(env.to_hashable(), vec!["A=1", "B=2", "C="]);
+    }
+
+    #[test]
+    fn test_to_secret_hashable() {
+        let env = super::EnvironmentVariableMap(
+            [("B", "2"), ("A", "1"), ("C", "")]
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        );
+        assert_eq!(
+            env.to_secret_hashable(),
+            vec![
+                "A=6b86b273ff34fce19d6b804eff5a3f5747ada4eaa22f1d49c01e52ddb7875b4b",
+                "B=d4735e3a265e16eee03f59718b9b5d03019c07d8b6c51f90da3a666eec13ab35",
+                "C="
+            ]
+        );
+    }
 }
@@ -5,7 +5,7 @@ use std::{
 
 use thiserror::Error;
 use turborepo_lockfiles::{
-    self, BerryLockfile, LockfileData, NpmLockfile, Package, PnpmLockfile, Yarn1Lockfile,
+    self, BerryLockfile, Lockfile, LockfileData, NpmLockfile, Package, PnpmLockfile, Yarn1Lockfile,
 };
 
 use super::{proto, Buffer};
@@ -184,15 +184,7 @@ fn patches_internal(buf: Buffer) -> Result<proto::Patches, Error> {
         proto::PackageManager::Berry => {
             let data = LockfileData::from_bytes(&request.contents)?;
             let lockfile = BerryLockfile::new(&data, None)?;
-            Ok(lockfile
-                .patches()
-                .into_iter()
-                .map(|p| {
-                    p.to_str()
-                        .expect("patch coming from yarn.lock isn't valid utf8")
-                        .to_string()
-                })
-                .collect::<Vec<_>>())
+            Ok(lockfile.patches())
         }
         proto::PackageManager::Pnpm => {
             let lockfile = PnpmLockfile::from_bytes(&request.contents)?;
@@ -14,7 +14,9 @@ use turbopath::AbsoluteSystemPathBuf;
 #[cfg(feature = "run-stub")]
 use crate::commands::run;
 use crate::{
-    commands::{bin, daemon, generate, link, login, logout, unlink, CommandBase},
+    commands::{
+        bin, daemon, generate, link, lockfile, login, logout, prune, schema, unlink, CommandBase,
+    },
     get_version,
     shim::{RepoMode, RepoState},
     tracing::TurboSubscriber,
@@ -78,6 +80,17 @@ pub enum EnvMode {
     Strict,
 }
 
+#[derive(Copy, Clone, Debug, Default, PartialEq, Serialize, ValueEnum)]
+pub enum ShallowCloneMode {
+    #[default]
+    #[serde(rename = "all-changed")]
+    AllChanged,
+    #[serde(rename = "deepen")]
+    Deepen,
+    #[serde(rename = "fail")]
+    Fail,
+}
+
 #[derive(Parser, Clone, Default, Debug, PartialEq, Serialize)]
 #[clap(author, about = "The build system that makes ship happen", long_about = None)]
 #[clap(disable_help_subcommand = true)]
@@ -199,6 +212,15 @@ pub enum DaemonCommand {
     Clean,
 }
 
+#[derive(Subcommand, Copy, Clone, Debug, Serialize, PartialEq)]
+#[serde(tag = "command")]
+pub enum LockfileCommand {
+    /// Checks that the lockfile resolves every workspace dependency and
+    /// doesn't contain unused packages. Exits with a non-zero code if the
+    /// lockfile is out of sync.
+    Check,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Serialize, ValueEnum)]
 pub enum LinkTarget {
     RemoteCache,
@@ -317,6 +339,12 @@ pub enum Command {
         #[serde(skip)]
         command: Option<Box<GenerateCommand>>,
     },
+    /// Inspect the lockfile of your monorepo
+    Lockfile {
+        #[clap(subcommand)]
+        #[serde(flatten)]
+        command: LockfileCommand,
+    },
     /// Login to your Vercel account
     Login {
         #[clap(long = "sso-team")]
@@ -344,6 +372,8 @@ pub enum Command {
     ///
     /// Arguments passed after '--' will be passed through to the named tasks.
     Run(Box<RunArgs>),
+    /// Print the JSON Schema for turbo.json
+    Schema {},
     /// Unlink the current directory from your Vercel organization and disable
     /// Remote Caching
     Unlink {
@@ -413,6 +443,12 @@ pub enum GenerateCommand {
 
 #[derive(Parser, Clone, Debug, Default, Serialize, PartialEq)]
 pub struct RunArgs {
+    /// Set which cache layers are read from and written to, as a comma
+    /// separated list such as "local:r,remote:rw". Layers that aren't listed
+    /// are disabled. Takes precedence over --remote-only and
+    /// --remote-cache-read-only. (default local:rw,remote:rw)
+    #[clap(long, env = "TURBO_CACHE")]
+    pub cache: Option<String>,
     /// Override the filesystem cache directory.
     #[clap(long)]
     pub cache_dir: Option<String>,
@@ -453,6 +489,10 @@ pub struct RunArgs {
     /// .html). Outputs dot graph to stdout when if no filename is provided
     #[clap(long, num_args = 0..=1, default_missing_value = "")]
     pub graph: Option<String>,
+    /// How long tasks get to exit after turbo is asked to stop before they
+    /// are killed, such as "10s" or "1m30s". (default 10s)
+    #[clap(long)]
+    pub grace_period: Option<String>,
     /// Environment variable mode.
     /// Loose passes the entire environment.
     /// Strict uses an allowlist specified in turbo.json.
@@ -506,6 +546,10 @@ pub struct RunArgs {
     /// allow reading and caching artifacts using the remote cache.
     #[clap(long, env = "TURBO_REMOTE_ONLY", value_name = "BOOL", action = ArgAction::Set, default_value = "false", default_missing_value = "true", num_args = 0..=1)]
     pub remote_only: bool,
+    /// Read artifacts from the remote cache, but never upload to it. Useful
+    /// for CI runs on untrusted branches.
+    #[clap(long, env = "TURBO_REMOTE_CACHE_READ_ONLY", value_name = "BOOL", action = ArgAction::Set, default_value = "false", default_missing_value = "true", num_args = 0..=1)]
+    pub remote_cache_read_only: bool,
     /// Specify package(s) to act as entry points for task execution.
     /// Supports globs.
     #[clap(long)]
@@ -515,6 +559,13 @@ pub struct RunArgs {
     /// to identify which packages have changed.
     #[clap(long)]
     pub since: Option<String>,
+    /// What to do when comparing refs in a shallow clone that doesn't have
+    /// their merge base, such as with --filter=[main]. Use "all-changed" to
+    /// consider every package changed. Use "deepen" to fetch more history
+    /// until the merge base is found. Use "fail" to exit with an error.
+    /// (default all-changed)
+    #[clap(long, value_enum, default_value_t = ShallowCloneMode::AllChanged)]
+    pub shallow_clone: ShallowCloneMode,
     /// Generate a summary of the turbo run
     #[clap(long, env = "TURBO_RUN_SUMMARY", default_missing_value = "true")]
     pub summarize: Option<Option<bool>>,
@@ -650,6 +701,11 @@ pub async fn run(
 
             Ok(Payload::Rust(Ok(0)))
         }
+        Command::Schema { .. } => {
+            schema::run()?;
+
+            Ok(Payload::Rust(Ok(0)))
+        }
         Command::Logout { .. } => {
             let mut base = CommandBase::new(cli_args, repo_root, version, ui)?;
             logout::logout(&mut base)?;
@@ -746,9 +802,9 @@ pub async fn run(
         #[cfg(feature = "run-stub")]
         Command::Run(args) => {
             let base = CommandBase::new(cli_args, repo_root, version, ui)?;
-            run::run(base).await?;
+            let exit_code = run::run(base).await?;
 
-            Ok(Payload::Rust(Ok(0)))
+            Ok(Payload::Rust(Ok(exit_code)))
         }
         #[cfg(not(feature = "run-stub"))]
         Command::Run(args) => {
@@ -758,9 +814,27 @@ pub async fn run(
             let base = CommandBase::new(cli_args, repo_root, version, UI::new(true))?;
             Ok(Payload::Go(Box::new(base)))
         }
-        Command::Prune { .. } => {
-            let base = CommandBase::new(cli_args, repo_root, version, UI::new(true))?;
-            Ok(Payload::Go(Box::new(base)))
+        Command::Prune {
+            scope,
+            docker,
+            output_dir,
+        } => {
+            let scope = scope.clone();
+            let docker = *docker;
+            let output_dir = output_dir.clone();
+            let base = CommandBase::new(cli_args, repo_root, version, ui)?;
+            prune::prune(&base, &scope, docker, &output_dir)?;
+
+            Ok(Payload::Rust(Ok(0)))
+        }
+        Command::Lockfile { command } => {
+            let command = *command;
+            let base = CommandBase::new(cli_args, repo_root, version, ui)?;
+            let exit_code = match command {
+                LockfileCommand::Check => lockfile::check(&base)?,
+            };
+
+            Ok(Payload::Rust(Ok(exit_code)))
         }
         Command::Completion { shell } => {
             generate(*shell, &mut Args::command(), "turbo", &mut io::stdout());
@@ -830,7 +904,8 @@ mod test {
     use anyhow::Result;
 
     use crate::cli::{
-        Args, Command, DryRunMode, EnvMode, LogOrder, LogPrefix, OutputLogsMode, RunArgs, Verbosity,
+        Args, Command, DryRunMode, EnvMode, LockfileCommand, LogOrder, LogPrefix, OutputLogsMode,
+        RunArgs, ShallowCloneMode, Verbosity,
     };
 
     #[test]
@@ -1357,6 +1432,31 @@ mod test {
             }
         );
 
+        assert_eq!(
+            Args::try_parse_from(["turbo", "run", "build", "--cache", "local:r,remote:rw"])
+                .unwrap(),
+            Args {
+                command: Some(Command::Run(Box::new(RunArgs {
+                    tasks: vec!["build".to_string()],
+                    cache: Some("local:r,remote:rw".to_string()),
+                    ..get_default_run_args()
+                }))),
+                ..Args::default()
+            }
+        );
+
+        assert_eq!(
+            Args::try_parse_from(["turbo", "run", "build", "--shallow-clone", "deepen"]).unwrap(),
+            Args {
+                command: Some(Command::Run(Box::new(RunArgs {
+                    tasks: vec!["build".to_string()],
+                    shallow_clone: ShallowCloneMode::Deepen,
+                    ..get_default_run_args()
+                }))),
+                ..Args::default()
+            }
+        );
+
         assert_eq!(
             Args::try_parse_from(["turbo", "run", "build"]).unwrap(),
             Args {
@@ -1446,6 +1546,19 @@ mod test {
             "remote_only=false works"
         );
 
+        assert_eq!(
+            Args::try_parse_from(["turbo", "run", "build", "--remote-cache-read-only"]).unwrap(),
+            Args {
+                command: Some(Command::Run(Box::new(RunArgs {
+                    tasks: vec!["build".to_string()],
+                    remote_cache_read_only: true,
+                    ..get_default_run_args()
+                }))),
+                ..Args::default()
+            },
+            "remote_cache_read_only with no value, means true"
+        );
+
         assert_eq!(
             Args::try_parse_from(["turbo", "run", "build", "--scope", "foo", "--scope", "bar"])
                 .unwrap(),
@@ -1721,6 +1834,31 @@ mod test {
         .test();
     }
 
+    #[test]
+    fn test_parse_schema() {
+        assert_eq!(
+            Args::try_parse_from(["turbo", "schema"]).unwrap(),
+            Args {
+                command: Some(Command::Schema {}),
+                ..Args::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_lockfile() {
+        assert_eq!(
+            Args::try_parse_from(["turbo", "lockfile", "check"]).unwrap(),
+            Args {
+                command: Some(Command::Lockfile {
+                    command: LockfileCommand::Check,
+                }),
+                ..Args::default()
+            }
+        );
+        assert!(Args::try_parse_from(["turbo", "lockfile"]).is_err());
+    }
+
     #[test]
     fn test_pass_through_args() {
         assert_eq!(
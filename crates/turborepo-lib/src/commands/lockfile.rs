@@ -0,0 +1,105 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, Context, Result};
+use turborepo_lockfiles::Report;
+
+use crate::{
+    commands::CommandBase,
+    package_graph::PackageGraph,
+    package_json::PackageJson,
+    ui::{BOLD, BOLD_RED, GREY, UI},
+};
+
+/// Audits the repository's lockfile against the dependencies declared by each
+/// workspace. Returns a non-zero exit code if the lockfile has drifted from the
+/// package.json files.
+pub fn check(base: &CommandBase) -> Result<i32> {
+    let repo_root = &base.repo_root;
+    let root_package_json = PackageJson::load(&repo_root.join_component("package.json"))
+        .context("failed to read package.json")?;
+    let package_graph = PackageGraph::build_multi_package_graph(repo_root, &root_package_json)
+        .context("could not construct graph")?;
+    let lockfile_name = package_graph.package_manager().lockfile_name();
+    let lockfile = package_graph
+        .lockfile()
+        .ok_or_else(|| anyhow!("Unable to parse {lockfile_name}"))?;
+
+    let mut workspace_names = HashMap::new();
+    let mut workspaces = BTreeMap::new();
+    for (name, info) in package_graph.workspace_infos().iter() {
+        let path = info.lockfile_path()?;
+        workspaces.insert(path.clone(), info.unresolved_external_dependencies.clone());
+        workspace_names.insert(path, name.clone());
+    }
+
+    let report = turborepo_lockfiles::check(lockfile, &workspaces)?;
+    print_report(&base.ui, lockfile_name, &report, |path| {
+        workspace_names.get(path).map_or(path, |name| name.as_str())
+    });
+
+    Ok(match report.has_drift() {
+        true => 1,
+        false => 0,
+    })
+}
+
+fn print_report<'a>(
+    ui: &UI,
+    lockfile_name: &str,
+    report: &'a Report,
+    workspace_name: impl Fn(&'a str) -> &'a str,
+) {
+    for unresolved in &report.unresolved {
+        let mut line = format!(
+            "{} depends on {}@{}, which isn't resolved by {lockfile_name}",
+            ui.apply(BOLD.apply_to(workspace_name(&unresolved.workspace))),
+            unresolved.name,
+            unresolved.specifier,
+        );
+        if let Some(error) = &unresolved.error {
+            line.push_str(&format!(": {error}"));
+        }
+        println!("{line}");
+    }
+
+    for duplicate in &report.duplicates {
+        let versions = duplicate
+            .versions
+            .iter()
+            .map(|(version, workspaces)| {
+                let workspaces = workspaces
+                    .iter()
+                    .map(|path| workspace_name(path))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("{version} ({workspaces})")
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!(
+            "{} is resolved to multiple versions: {versions}",
+            ui.apply(BOLD.apply_to(&duplicate.name))
+        );
+    }
+
+    for key in &report.stale {
+        println!(
+            "{} is in {lockfile_name} but isn't used by any workspace",
+            ui.apply(BOLD.apply_to(key))
+        );
+    }
+
+    if report.has_drift() {
+        println!(
+            "{}",
+            ui.apply(BOLD_RED.apply_to(format!(
+                "{lockfile_name} is out of sync with the workspaces' package.json files"
+            )))
+        );
+    } else {
+        println!(
+            "{}",
+            ui.apply(GREY.apply_to(format!("{lockfile_name} is in sync")))
+        );
+    }
+}
@@ -19,9 +19,12 @@ pub(crate) mod bin;
 pub(crate) mod daemon;
 pub(crate) mod generate;
 pub(crate) mod link;
+pub(crate) mod lockfile;
 pub(crate) mod login;
 pub(crate) mod logout;
+pub(crate) mod prune;
 pub(crate) mod run;
+pub(crate) mod schema;
 pub(crate) mod unlink;
 
 #[derive(Debug)]
@@ -141,7 +144,7 @@ impl CommandBase {
         &self.args
     }
 
-    pub fn api_client(&mut self) -> Result<APIClient> {
+    pub fn api_client(&self) -> Result<APIClient> {
         let repo_config = self.repo_config()?;
         let client_config = self.client_config()?;
 
@@ -0,0 +1,722 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde::Serialize;
+use tracing::trace;
+use turbopath::{AbsoluteSystemPathBuf, RelativeUnixPathBuf};
+use turborepo_cache::RemoteCacheOpts;
+use turborepo_lockfiles::{Lockfile, PnpmLockfile};
+
+use crate::{
+    commands::CommandBase,
+    config::{SpacesJson, TurboJson},
+    package_graph::{PackageGraph, WorkspaceInfo},
+    package_json::PackageJson,
+    package_manager::PackageManager,
+    run::{
+        summary::TaskSummaryTaskDefinition,
+        task_id::{is_task_in_package, ROOT_PKG_NAME},
+    },
+    ui::BOLD,
+};
+
+/// Creates a subset of the monorepo in `output_dir` containing only the
+/// given workspaces, their internal dependencies and the parts of the
+/// lockfile they need. With `docker`, the package.json files are also copied
+/// into `json/` so that dependencies can be installed in a separate layer.
+pub fn prune(base: &CommandBase, scope: &[String], docker: bool, output_dir: &str) -> Result<()> {
+    if scope.is_empty() {
+        bail!("at least one target must be specified");
+    }
+
+    let repo_root = &base.repo_root;
+    let root_package_json_path = repo_root.join_component("package.json");
+    let root_package_json =
+        PackageJson::load(&root_package_json_path).context("failed to read package.json")?;
+    let package_graph = PackageGraph::build_multi_package_graph(repo_root, &root_package_json)
+        .context("could not construct graph")?;
+
+    let out_dir = AbsoluteSystemPathBuf::from_unknown(repo_root, output_dir);
+    let full_dir = if docker {
+        out_dir.join_component("full")
+    } else {
+        out_dir.clone()
+    };
+
+    trace!("scope: {}", scope.join(", "));
+    trace!("docker: {}", docker);
+    trace!("out dir: {}", out_dir);
+
+    for workspace in scope {
+        let info = package_graph
+            .workspace_info(workspace)
+            .ok_or_else(|| anyhow!("invalid scope: package {} not found", workspace))?;
+        trace!("target: {} at {:?}", workspace, info.package_path());
+    }
+
+    let package_manager = package_graph.package_manager();
+    let reread_lockfile;
+    let lockfile = match package_graph.lockfile() {
+        Some(lockfile) => lockfile,
+        // Building the graph only warns when the lockfile can't be used, so
+        // read it again to report why
+        None => {
+            reread_lockfile = package_manager
+                .read_lockfile(repo_root, &root_package_json)
+                .map_err(|err| anyhow!("Cannot prune without parsed lockfile: {}", err))?;
+            reread_lockfile.as_ref()
+        }
+    };
+
+    println!(
+        "Generating pruned monorepo for {} in {}",
+        base.ui.apply(BOLD.apply_to(scope.join(", "))),
+        base.ui.apply(BOLD.apply_to(&out_dir)),
+    );
+
+    out_dir
+        .create_dir_all()
+        .context("could not create output directory")?;
+    if let Some(workspace_config) = workspace_config_name(package_manager) {
+        let workspace_config_path = repo_root.join_component(workspace_config);
+        if workspace_config_path.exists() {
+            let mut destinations = vec![
+                out_dir.join_component(workspace_config),
+                full_dir.join_component(workspace_config),
+            ];
+            if docker {
+                destinations.push(out_dir.join_components(&["json", workspace_config]));
+            }
+            for destination in destinations {
+                turborepo_fs::copy_file(&workspace_config_path, destination)
+                    .with_context(|| format!("could not copy {workspace_config}"))?;
+            }
+        }
+    }
+
+    let targets = package_graph
+        .transitive_internal_dependencies(scope.iter().map(String::as_str).chain([ROOT_PKG_NAME]));
+    trace!("targets: {:?}", targets);
+
+    let mut workspaces = vec![workspace_info(&package_graph, ROOT_PKG_NAME)?];
+    for target in targets.iter().filter(|target| **target != ROOT_PKG_NAME) {
+        let info = workspace_info(&package_graph, target)?;
+        let package_path = info.package_path();
+        let target_dir = full_dir.resolve(&package_path);
+        turborepo_fs::recursive_copy(repo_root.resolve(&package_path), &target_dir)
+            .with_context(|| format!("failed to copy {target} into {target_dir}"))?;
+        if docker {
+            let json_path = out_dir
+                .join_component("json")
+                .resolve(&info.package_json_path);
+            turborepo_fs::copy_file(repo_root.resolve(&info.package_json_path), &json_path)
+                .with_context(|| format!("failed to copy {target} into {json_path}"))?;
+        }
+
+        workspaces.push(info);
+        println!(" - Added {target}");
+    }
+
+    let pruned_lockfile =
+        prune_lockfile(lockfile, &workspaces).context("Failed creating pruned lockfile")?;
+    std::fs::write(
+        out_dir.join_component(pruned_lockfile_name(package_manager)),
+        &pruned_lockfile.contents,
+    )
+    .context("Failed to create lockfile")?;
+
+    let gitignore_path = repo_root.join_component(".gitignore");
+    if gitignore_path.exists() {
+        turborepo_fs::copy_file(&gitignore_path, full_dir.join_component(".gitignore"))
+            .context("failed to copy root .gitignore")?;
+    }
+
+    let npmrc_path = repo_root.join_component(".npmrc");
+    if npmrc_path.exists() {
+        turborepo_fs::copy_file(&npmrc_path, full_dir.join_component(".npmrc"))
+            .context("failed to copy root .npmrc")?;
+        if docker {
+            turborepo_fs::copy_file(&npmrc_path, out_dir.join_components(&["json", ".npmrc"]))
+                .context("failed to copy root .npmrc")?;
+        }
+    }
+
+    if repo_root.join_component("turbo.json").exists() {
+        let turbo_json = TurboJson::load(repo_root, &root_package_json, false)
+            .context("failed to read turbo.json")?;
+        let pruned_turbo_json = PrunedTurboJson::new(&turbo_json, |task_id| {
+            targets
+                .iter()
+                .any(|target| is_task_in_package(task_id, target))
+        });
+        let mut contents = serde_json::to_string_pretty(&pruned_turbo_json)
+            .context("failed to write turbo.json")?;
+        contents.push('\n');
+        std::fs::write(full_dir.join_component("turbo.json"), contents)
+            .context("failed to prune workspace tasks from turbo.json")?;
+    }
+
+    let new_package_json_path = full_dir.join_component("package.json");
+    // If the original lockfile uses any patches or overrides, the package.json
+    // is rewritten so that it doesn't reference any that were pruned.
+    if pruned_lockfile.original_patches.is_empty() && pruned_lockfile.overrides.is_none() {
+        turborepo_fs::copy_file(&root_package_json_path, &new_package_json_path)
+            .context("failed to copy root package.json")?;
+    } else {
+        let contents = std::fs::read_to_string(&root_package_json_path)
+            .context("failed to read package.json")?;
+        let mut package_json: serde_json::Value = serde_json::from_str(&contents)?;
+        if !pruned_lockfile.original_patches.is_empty() {
+            prune_patched_packages(package_manager, &mut package_json, &pruned_lockfile.patches)
+                .with_context(|| {
+                    format!("Unable to prune patches section of {root_package_json_path}")
+                })?;
+        }
+        if let Some(overrides) = &pruned_lockfile.overrides {
+            prune_overrides(&mut package_json, overrides).with_context(|| {
+                format!("Unable to prune overrides section of {root_package_json_path}")
+            })?;
+        }
+        let mut contents = serde_json::to_string_pretty(&package_json)?;
+        contents.push('\n');
+        std::fs::write(&new_package_json_path, contents)?;
+        std::fs::set_permissions(
+            &new_package_json_path,
+            root_package_json_path.symlink_metadata()?.permissions(),
+        )?;
+
+        for patch in &pruned_lockfile.patches {
+            let patch = RelativeUnixPathBuf::new(patch.as_str())?;
+            let patch_path = repo_root.join_unix_path(&patch)?;
+            turborepo_fs::copy_file(&patch_path, full_dir.join_unix_path(&patch)?)
+                .context("Failed copying patch file")?;
+            if docker {
+                let json_dir = out_dir.join_component("json");
+                turborepo_fs::copy_file(&patch_path, json_dir.join_unix_path(&patch)?)
+                    .context("Failed copying patch file")?;
+            }
+        }
+    }
+
+    if docker {
+        // Copy from the full directory so that we get the pruned package.json
+        turborepo_fs::copy_file(
+            &new_package_json_path,
+            out_dir.join_components(&["json", "package.json"]),
+        )
+        .context("failed to copy root package.json")?;
+    }
+
+    Ok(())
+}
+
+fn workspace_info<'a>(package_graph: &'a PackageGraph, name: &str) -> Result<&'a WorkspaceInfo> {
+    package_graph
+        .workspace_info(name)
+        .ok_or_else(|| anyhow!("workspace {} is missing from the package graph", name))
+}
+
+// The file, other than package.json, that declares the workspaces.
+fn workspace_config_name(package_manager: &PackageManager) -> Option<&'static str> {
+    match package_manager {
+        PackageManager::Pnpm | PackageManager::Pnpm6 => Some("pnpm-workspace.yaml"),
+        PackageManager::Npm
+        | PackageManager::Yarn
+        | PackageManager::Berry
+        | PackageManager::Bun => None,
+    }
+}
+
+// A binary bun.lockb can't be written without running Bun, so pruned Bun
+// lockfiles are written in the yarn v1 format that Bun prints them in.
+fn pruned_lockfile_name(package_manager: &PackageManager) -> &'static str {
+    match package_manager {
+        PackageManager::Bun => "yarn.lock",
+        package_manager => package_manager.lockfile_name(),
+    }
+}
+
+struct PrunedLockfile {
+    contents: Vec<u8>,
+    // Patch files referenced by the original lockfile
+    original_patches: Vec<String>,
+    // Patch files that are still referenced after pruning
+    patches: Vec<String>,
+    // pnpm overrides that are still referenced after pruning, pnpm refuses a
+    // frozen install if these differ from the ones in package.json
+    overrides: Option<Vec<String>>,
+}
+
+// Produces a lockfile containing only the given workspaces and the external
+// packages they depend on.
+fn prune_lockfile(
+    lockfile: &dyn Lockfile,
+    workspaces: &[&WorkspaceInfo],
+) -> Result<PrunedLockfile> {
+    let mut workspace_paths = Vec::new();
+    let mut packages = Vec::new();
+    for info in workspaces {
+        let path = info.lockfile_path()?;
+        // The root workspace is always part of the pruned lockfile
+        if !path.is_empty() {
+            workspace_paths.push(path);
+        }
+        packages.extend(
+            info.transitive_dependencies
+                .iter()
+                .flatten()
+                .map(|package| package.key.clone()),
+        );
+    }
+    packages.sort();
+    packages.dedup();
+
+    let pruned = lockfile.subgraph(&workspace_paths, &packages)?;
+    Ok(PrunedLockfile {
+        contents: pruned.encode()?,
+        original_patches: lockfile.patches(),
+        patches: pruned.patches(),
+        overrides: pruned
+            .as_any()
+            .downcast_ref::<PnpmLockfile>()
+            .and_then(PnpmLockfile::overrides),
+    })
+}
+
+// Removes any patches that were pruned from the lockfile from package.json,
+// since package managers error on patches for packages that aren't installed.
+fn prune_patched_packages(
+    package_manager: &PackageManager,
+    package_json: &mut serde_json::Value,
+    patches: &[String],
+) -> Result<()> {
+    match package_manager {
+        PackageManager::Berry => {
+            let resolutions = package_json
+                .get_mut("resolutions")
+                .and_then(|resolutions| resolutions.as_object_mut())
+                .ok_or_else(|| {
+                    anyhow!("Invalid structure for resolutions field in package.json")
+                })?;
+            let mut unused = Vec::new();
+            for (dependency, patch) in resolutions.iter() {
+                let patch = patch.as_str().ok_or_else(|| {
+                    anyhow!(
+                        "Expected value of {} in package.json to be a string, got {}",
+                        dependency,
+                        patch
+                    )
+                })?;
+                // Only patches are removed, as those are the only resolutions
+                // that error if unused.
+                if patch.ends_with(".patch") && !patches.iter().any(|kept| patch.ends_with(kept)) {
+                    unused.push(dependency.clone());
+                }
+            }
+            for dependency in unused {
+                resolutions.remove(&dependency);
+            }
+        }
+        PackageManager::Pnpm | PackageManager::Pnpm6 => {
+            let patched_dependencies = package_json
+                .get_mut("pnpm")
+                .and_then(|pnpm| pnpm.as_object_mut())
+                .ok_or_else(|| anyhow!("Invalid structure for pnpm field in package.json"))?
+                .get_mut("patchedDependencies")
+                .and_then(|patched| patched.as_object_mut())
+                .ok_or_else(|| {
+                    anyhow!("Invalid structure for patchedDependencies field in package.json")
+                })?;
+            let mut unused = Vec::new();
+            for (dependency, patch) in patched_dependencies.iter() {
+                let patch = patch.as_str().ok_or_else(|| {
+                    anyhow!(
+                        "Expected only strings in patchedDependencies. Got {}",
+                        patch
+                    )
+                })?;
+                if !patches.iter().any(|kept| kept == patch) {
+                    unused.push(dependency.clone());
+                }
+            }
+            for dependency in unused {
+                patched_dependencies.remove(&dependency);
+            }
+        }
+        PackageManager::Npm | PackageManager::Yarn | PackageManager::Bun => {}
+    }
+
+    Ok(())
+}
+
+// Removes any pnpm overrides that were pruned from the lockfile from
+// package.json, pnpm treats the lockfile as outdated if they don't match.
+fn prune_overrides(package_json: &mut serde_json::Value, overrides: &[String]) -> Result<()> {
+    let Some(pnpm_overrides) = package_json
+        .get_mut("pnpm")
+        .and_then(|pnpm| pnpm.get_mut("overrides"))
+    else {
+        return Ok(());
+    };
+    let pnpm_overrides = pnpm_overrides
+        .as_object_mut()
+        .ok_or_else(|| anyhow!("Invalid structure for overrides field in package.json"))?;
+    pnpm_overrides.retain(|selector, _| overrides.contains(selector));
+
+    Ok(())
+}
+
+// The turbo.json written to the pruned monorepo. Tasks are written out with
+// all of their fields resolved, which matches the Go implementation.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct PrunedTurboJson<'a> {
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    global_dependencies: &'a [String],
+    #[serde(skip_serializing_if = "<[String]>::is_empty")]
+    global_env: &'a [String],
+    global_pass_through_env: &'a Option<Vec<String>>,
+    global_dot_env: &'a Option<Vec<String>>,
+    pipeline: BTreeMap<&'a str, TaskSummaryTaskDefinition>,
+    remote_cache: RemoteCacheOpts,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    experimental_spaces: Option<SpacesJson>,
+}
+
+impl<'a> PrunedTurboJson<'a> {
+    fn new(turbo_json: &'a TurboJson, include_task: impl Fn(&str) -> bool) -> Self {
+        let pipeline = turbo_json
+            .pipeline
+            .iter()
+            .filter(|(task_id, _)| include_task(task_id))
+            .map(|(task_id, task_definition)| {
+                (
+                    task_id.as_str(),
+                    TaskSummaryTaskDefinition::from(&task_definition.task_definition()),
+                )
+            })
+            .collect();
+
+        Self {
+            global_dependencies: &turbo_json.global_dependencies,
+            global_env: &turbo_json.global_env,
+            global_pass_through_env: &turbo_json.global_pass_through_env,
+            global_dot_env: &turbo_json.global_dot_env,
+            pipeline,
+            remote_cache: turbo_json.remote_cache_opts.clone().unwrap_or_default(),
+            experimental_spaces: turbo_json.space_id.clone().map(|id| SpacesJson {
+                id: Some(id),
+                other: None,
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use serde_json::json;
+    use tempfile::tempdir;
+    use turbopath::AbsoluteSystemPath;
+
+    use super::*;
+    use crate::{ui::UI, Args};
+
+    fn write_json(path: &AbsoluteSystemPath, value: serde_json::Value) {
+        fs::create_dir_all(path.as_path().parent().unwrap()).unwrap();
+        fs::write(
+            path.as_path(),
+            serde_json::to_string_pretty(&value).unwrap(),
+        )
+        .unwrap();
+    }
+
+    fn read_json(path: &AbsoluteSystemPath) -> serde_json::Value {
+        serde_json::from_str(&fs::read_to_string(path.as_path()).unwrap()).unwrap()
+    }
+
+    fn setup_repo(repo_root: &AbsoluteSystemPath) {
+        write_json(
+            &repo_root.join_component("package.json"),
+            json!({
+                "name": "monorepo",
+                "packageManager": "npm@8.19.4",
+                "workspaces": ["packages/*"],
+                "devDependencies": {"turbo": "^1.9.0"},
+            }),
+        );
+        write_json(
+            &repo_root.join_components(&["packages", "a", "package.json"]),
+            json!({"name": "a", "dependencies": {"b": "*", "lodash": "^4.0.0"}}),
+        );
+        write_json(
+            &repo_root.join_components(&["packages", "b", "package.json"]),
+            json!({"name": "b"}),
+        );
+        write_json(
+            &repo_root.join_components(&["packages", "c", "package.json"]),
+            json!({"name": "c", "dependencies": {"react": "^18.0.0"}}),
+        );
+        write_json(
+            &repo_root.join_component("package-lock.json"),
+            json!({
+                "name": "monorepo",
+                "lockfileVersion": 3,
+                "requires": true,
+                "packages": {
+                    "": {"name": "monorepo", "workspaces": ["packages/*"], "devDependencies": {"turbo": "^1.9.0"}},
+                    "packages/a": {"name": "a", "dependencies": {"b": "*", "lodash": "^4.0.0"}},
+                    "packages/b": {"name": "b"},
+                    "packages/c": {"name": "c", "dependencies": {"react": "^18.0.0"}},
+                    "node_modules/a": {"resolved": "packages/a", "link": true},
+                    "node_modules/b": {"resolved": "packages/b", "link": true},
+                    "node_modules/c": {"resolved": "packages/c", "link": true},
+                    "node_modules/lodash": {"version": "4.17.21"},
+                    "node_modules/react": {"version": "18.2.0"},
+                    "node_modules/turbo": {"version": "1.9.3", "dev": true},
+                }
+            }),
+        );
+        write_json(
+            &repo_root.join_component("turbo.json"),
+            json!({
+                "pipeline": {
+                    "build": {"outputs": ["dist/**"]},
+                    "c#build": {"dependsOn": ["^build"]},
+                    "//#lint": {},
+                }
+            }),
+        );
+    }
+
+    fn command_base(repo_root: AbsoluteSystemPathBuf) -> CommandBase {
+        CommandBase::new(Args::default(), repo_root, "1.0.0", UI::new(true)).unwrap()
+    }
+
+    #[test]
+    fn test_prune() {
+        let tmp = tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::new(tmp.path()).unwrap();
+        setup_repo(&repo_root);
+
+        prune(
+            &command_base(repo_root.clone()),
+            &["a".to_string()],
+            false,
+            "out",
+        )
+        .unwrap();
+
+        let out_dir = repo_root.join_component("out");
+        assert!(out_dir
+            .join_components(&["packages", "a", "package.json"])
+            .exists());
+        assert!(out_dir
+            .join_components(&["packages", "b", "package.json"])
+            .exists());
+        assert!(!out_dir.join_components(&["packages", "c"]).exists());
+        assert_eq!(
+            read_json(&out_dir.join_component("package.json")),
+            read_json(&repo_root.join_component("package.json"))
+        );
+
+        let lockfile = read_json(&out_dir.join_component("package-lock.json"));
+        let mut packages = lockfile["packages"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        packages.sort();
+        assert_eq!(
+            packages,
+            vec![
+                "",
+                "node_modules/a",
+                "node_modules/b",
+                "node_modules/lodash",
+                "node_modules/turbo",
+                "packages/a",
+                "packages/b",
+            ]
+        );
+
+        let turbo_json = read_json(&out_dir.join_component("turbo.json"));
+        let mut tasks = turbo_json["pipeline"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        tasks.sort();
+        assert_eq!(tasks, vec!["//#lint", "build"]);
+        assert_eq!(
+            turbo_json["pipeline"]["build"]["outputs"],
+            json!(["dist/**"])
+        );
+        assert_eq!(turbo_json["remoteCache"], json!({}));
+    }
+
+    #[test]
+    fn test_prune_docker() {
+        let tmp = tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::new(tmp.path()).unwrap();
+        setup_repo(&repo_root);
+        let out_dir = tmp.path().join("docker-out");
+
+        prune(
+            &command_base(repo_root),
+            &["c".to_string()],
+            true,
+            out_dir.to_str().unwrap(),
+        )
+        .unwrap();
+
+        let out_dir = AbsoluteSystemPathBuf::new(out_dir).unwrap();
+        assert!(out_dir.join_component("package-lock.json").exists());
+        assert!(out_dir.join_components(&["json", "package.json"]).exists());
+        assert!(out_dir
+            .join_components(&["json", "packages", "c", "package.json"])
+            .exists());
+        assert!(!out_dir.join_components(&["json", "packages", "a"]).exists());
+        assert!(out_dir.join_components(&["full", "package.json"]).exists());
+        assert!(out_dir.join_components(&["full", "turbo.json"]).exists());
+        assert!(out_dir
+            .join_components(&["full", "packages", "c", "package.json"])
+            .exists());
+    }
+
+    #[test]
+    fn test_prune_invalid_scope() {
+        let tmp = tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::new(tmp.path()).unwrap();
+        setup_repo(&repo_root);
+
+        let base = command_base(repo_root);
+        let err = prune(&base, &["missing".to_string()], false, "out").unwrap_err();
+        assert_eq!(err.to_string(), "invalid scope: package missing not found");
+        let err = prune(&base, &[], false, "out").unwrap_err();
+        assert_eq!(err.to_string(), "at least one target must be specified");
+    }
+
+    #[test]
+    fn test_prune_pnpm_patches() {
+        let mut package_json = json!({
+            "pnpm": {
+                "patchedDependencies": {
+                    "is-odd@3.0.1": "patches/is-odd@3.0.1.patch",
+                    "is-number@7.0.0": "patches/is-number@7.0.0.patch",
+                }
+            }
+        });
+        prune_patched_packages(
+            &PackageManager::Pnpm,
+            &mut package_json,
+            &["patches/is-number@7.0.0.patch".to_string()],
+        )
+        .unwrap();
+        assert_eq!(
+            package_json,
+            json!({
+                "pnpm": {
+                    "patchedDependencies": {
+                        "is-number@7.0.0": "patches/is-number@7.0.0.patch",
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_prune_pnpm_overrides() {
+        let mut package_json = json!({
+            "pnpm": {
+                "overrides": {
+                    "js-tokens": "4.0.0",
+                    "is-odd>is-number": "6.0.0",
+                }
+            }
+        });
+        prune_overrides(&mut package_json, &["js-tokens".to_string()]).unwrap();
+        assert_eq!(
+            package_json,
+            json!({
+                "pnpm": {
+                    "overrides": {
+                        "js-tokens": "4.0.0",
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn test_prune_bun() {
+        let tmp = tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::new(tmp.path()).unwrap();
+        write_json(
+            &repo_root.join_component("package.json"),
+            json!({
+                "name": "monorepo",
+                "packageManager": "bun@1.0.1",
+                "workspaces": ["packages/*"],
+            }),
+        );
+        write_json(
+            &repo_root.join_components(&["packages", "a", "package.json"]),
+            json!({"name": "a", "dependencies": {"lodash": "^4.0.0"}}),
+        );
+        write_json(
+            &repo_root.join_components(&["packages", "c", "package.json"]),
+            json!({"name": "c", "dependencies": {"react": "^18.0.0"}}),
+        );
+        // The text format bun prints bun.lockb in
+        fs::write(
+            repo_root.join_component("bun.lockb").as_path(),
+            "# yarn lockfile v1\n\n\nlodash@^4.0.0:\n  version \"4.17.21\"\n\nreact@^18.0.0:\n  \
+             version \"18.2.0\"\n",
+        )
+        .unwrap();
+
+        prune(
+            &command_base(repo_root.clone()),
+            &["a".to_string()],
+            false,
+            "out",
+        )
+        .unwrap();
+
+        let out_dir = repo_root.join_component("out");
+        assert!(!out_dir.join_component("bun.lockb").exists());
+        let lockfile = fs::read_to_string(out_dir.join_component("yarn.lock").as_path()).unwrap();
+        assert!(lockfile.contains("lodash@^4.0.0"));
+        assert!(!lockfile.contains("react"));
+    }
+
+    #[test]
+    fn test_prune_berry_patches() {
+        let mut package_json = json!({
+            "resolutions": {
+                "is-odd@3.0.1": "patch:is-odd@npm:3.0.1#.yarn/patches/is-odd-npm-3.0.1.patch",
+                "is-even": "patch:is-even@npm:1.0.0#.yarn/patches/is-even-npm-1.0.0.patch",
+                "lodash": "4.17.21",
+            }
+        });
+        prune_patched_packages(
+            &PackageManager::Berry,
+            &mut package_json,
+            &[".yarn/patches/is-odd-npm-3.0.1.patch".to_string()],
+        )
+        .unwrap();
+        assert_eq!(
+            package_json,
+            json!({
+                "resolutions": {
+                    "is-odd@3.0.1": "patch:is-odd@npm:3.0.1#.yarn/patches/is-odd-npm-3.0.1.patch",
+                    "lodash": "4.17.21",
+                }
+            })
+        );
+    }
+}
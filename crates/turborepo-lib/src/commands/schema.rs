@@ -0,0 +1,10 @@
+use anyhow::Result;
+
+use crate::config::turbo_json_schema;
+
+/// Prints the JSON Schema for turbo.json, for editors to validate against.
+pub fn run() -> Result<()> {
+    println!("{}", serde_json::to_string_pretty(&turbo_json_schema())?);
+
+    Ok(())
+}
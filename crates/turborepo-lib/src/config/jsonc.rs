@@ -0,0 +1,445 @@
+//! A parser for JSON with comments and trailing commas, as accepted in
+//! turbo.json. Parsed values keep their location in the source so that
+//! configuration errors can point at the offending part of the file.
+//!
+//! Deserialization is still done by serde: comments and trailing commas are
+//! blanked out of the source, which leaves plain JSON with every value at its
+//! original line and column.
+
+use std::{fmt, ops::Range};
+
+use thiserror::Error;
+
+#[derive(Debug)]
+pub struct Document {
+    pub root: Node,
+    json: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Node {
+    pub span: Range<usize>,
+    pub value: Value,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number,
+    String(String),
+    Array(Vec<Node>),
+    Object(Vec<Member>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Member {
+    pub key: String,
+    pub key_span: Range<usize>,
+    pub value: Node,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("{reason}")]
+pub struct ParseError {
+    pub offset: usize,
+    pub reason: String,
+}
+
+/// An error in a configuration file, rendered with the offending line and a
+/// caret under the column it refers to.
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error(
+    "{file}:{line}:{column}: {message}\n  {source_line}\n  {}^{}",
+    " ".repeat(*.caret),
+    .help.as_ref().map(|help| format!("\n  {help}")).unwrap_or_default()
+)]
+pub struct Diagnostic {
+    file: String,
+    line: usize,
+    column: usize,
+    message: String,
+    source_line: String,
+    // the number of characters before the column in `source_line`
+    caret: usize,
+    help: Option<String>,
+}
+
+impl Document {
+    /// The source as plain JSON, with all values at their original offsets.
+    pub fn json(&self) -> &str {
+        &self.json
+    }
+}
+
+impl Node {
+    /// Returns the value of `key` if this node is an object that contains it.
+    pub fn get(&self, key: &str) -> Option<&Node> {
+        self.members()
+            .iter()
+            .find(|member| member.key == key)
+            .map(|member| &member.value)
+    }
+
+    /// The members of this node if it's an object, and nothing otherwise.
+    pub fn members(&self) -> &[Member] {
+        match &self.value {
+            Value::Object(members) => members,
+            _ => &[],
+        }
+    }
+}
+
+impl Diagnostic {
+    pub fn at_offset(
+        file: impl fmt::Display,
+        source: &str,
+        offset: usize,
+        message: impl Into<String>,
+    ) -> Self {
+        let offset = offset.min(source.len());
+        let line_start = source[..offset].rfind('\n').map_or(0, |index| index + 1);
+        let line = source[..line_start].matches('\n').count() + 1;
+        Self::new(file, source, line, offset - line_start + 1, message)
+    }
+
+    /// Creates a diagnostic from a 1-based line and byte column, as reported
+    /// by serde_json.
+    pub fn at_line_column(
+        file: impl fmt::Display,
+        source: &str,
+        line: usize,
+        column: usize,
+        message: impl Into<String>,
+    ) -> Self {
+        Self::new(file, source, line.max(1), column.max(1), message)
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    fn new(
+        file: impl fmt::Display,
+        source: &str,
+        line: usize,
+        column: usize,
+        message: impl Into<String>,
+    ) -> Self {
+        let source_line = source
+            .lines()
+            .nth(line - 1)
+            .unwrap_or_default()
+            .trim_end()
+            .to_string();
+        let caret = source_line
+            .get(..column - 1)
+            .map_or(column - 1, |prefix| prefix.chars().count());
+        Self {
+            file: file.to_string(),
+            line,
+            column,
+            message: message.into(),
+            source_line,
+            caret,
+            help: None,
+        }
+    }
+}
+
+pub fn parse(source: &str) -> Result<Document, ParseError> {
+    let mut parser = Parser {
+        source,
+        pos: 0,
+        blanks: Vec::new(),
+    };
+    let root = parser.parse_value()?;
+    parser.skip_trivia()?;
+    if parser.pos < source.len() {
+        return Err(parser.error("unexpected content after the end of the document"));
+    }
+
+    let mut json = source.as_bytes().to_vec();
+    for range in parser.blanks {
+        for byte in &mut json[range] {
+            if !matches!(byte, b'\n' | b'\r') {
+                *byte = b' ';
+            }
+        }
+    }
+    let json = String::from_utf8(json).expect("only ascii bytes are replaced with ascii");
+
+    Ok(Document { root, json })
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    pos: usize,
+    // comments and trailing commas, which aren't valid JSON
+    blanks: Vec<Range<usize>>,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.source.as_bytes().get(self.pos).copied()
+    }
+
+    fn error(&self, reason: impl Into<String>) -> ParseError {
+        ParseError {
+            offset: self.pos,
+            reason: reason.into(),
+        }
+    }
+
+    fn unexpected(&self) -> ParseError {
+        match self.source[self.pos..].chars().next() {
+            Some(c) => self.error(format!("unexpected character '{c}'")),
+            None => self.error("unexpected end of file"),
+        }
+    }
+
+    fn skip_trivia(&mut self) -> Result<(), ParseError> {
+        loop {
+            let rest = &self.source[self.pos..];
+            if rest.starts_with("//") {
+                let end = rest.find('\n').unwrap_or(rest.len());
+                self.blanks.push(self.pos..self.pos + end);
+                self.pos += end;
+            } else if let Some(comment) = rest.strip_prefix("/*") {
+                let end = comment
+                    .find("*/")
+                    .ok_or_else(|| self.error("unterminated comment"))?;
+                self.blanks.push(self.pos..self.pos + end + 4);
+                self.pos += end + 4;
+            } else if let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+                self.pos += 1;
+            } else {
+                return Ok(());
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Node, ParseError> {
+        self.skip_trivia()?;
+        let start = self.pos;
+        let value = match self.peek() {
+            Some(b'{') => self.parse_object()?,
+            Some(b'[') => self.parse_array()?,
+            Some(b'"') => Value::String(self.parse_string()?),
+            Some(b'-' | b'0'..=b'9') => self.parse_number()?,
+            Some(b't') => self.parse_literal("true", Value::Bool(true))?,
+            Some(b'f') => self.parse_literal("false", Value::Bool(false))?,
+            Some(b'n') => self.parse_literal("null", Value::Null)?,
+            _ => return Err(self.unexpected()),
+        };
+
+        Ok(Node {
+            span: start..self.pos,
+            value,
+        })
+    }
+
+    fn parse_object(&mut self) -> Result<Value, ParseError> {
+        self.pos += 1;
+        let mut members = Vec::new();
+        loop {
+            self.skip_trivia()?;
+            match self.peek() {
+                Some(b'}') => break,
+                Some(b'"') => {}
+                _ => return Err(self.unexpected()),
+            }
+
+            let key_start = self.pos;
+            let key = self.parse_string()?;
+            let key_span = key_start..self.pos;
+            self.skip_trivia()?;
+            if self.peek() != Some(b':') {
+                return Err(self.error(format!("expected ':' after \"{key}\"")));
+            }
+            self.pos += 1;
+            let value = self.parse_value()?;
+            members.push(Member {
+                key,
+                key_span,
+                value,
+            });
+
+            if !self.parse_separator(b'}')? {
+                break;
+            }
+        }
+        self.pos += 1;
+
+        Ok(Value::Object(members))
+    }
+
+    fn parse_array(&mut self) -> Result<Value, ParseError> {
+        self.pos += 1;
+        let mut elements = Vec::new();
+        loop {
+            self.skip_trivia()?;
+            if self.peek() == Some(b']') {
+                break;
+            }
+            elements.push(self.parse_value()?);
+            if !self.parse_separator(b']')? {
+                break;
+            }
+        }
+        self.pos += 1;
+
+        Ok(Value::Array(elements))
+    }
+
+    // Consumes the comma after an element, returning whether more elements
+    // can follow. A comma directly before `close` is allowed and blanked out.
+    fn parse_separator(&mut self, close: u8) -> Result<bool, ParseError> {
+        self.skip_trivia()?;
+        match self.peek() {
+            Some(b',') => {
+                let comma = self.pos;
+                self.pos += 1;
+                self.skip_trivia()?;
+                if self.peek() == Some(close) {
+                    self.blanks.push(comma..comma + 1);
+                }
+                Ok(true)
+            }
+            Some(c) if c == close => Ok(false),
+            _ => Err(self.error(format!("expected ',' or '{}'", close as char))),
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, ParseError> {
+        let start = self.pos;
+        let bytes = self.source.as_bytes();
+        let mut end = start + 1;
+        loop {
+            match bytes.get(end) {
+                Some(b'"') => break,
+                Some(b'\\') => end += 2,
+                Some(b'\n') | None => {
+                    return Err(ParseError {
+                        offset: start,
+                        reason: "unterminated string".to_string(),
+                    })
+                }
+                Some(_) => end += 1,
+            }
+        }
+        self.pos = end + 1;
+
+        // serde_json takes care of escapes
+        serde_json::from_str(&self.source[start..self.pos]).map_err(|err| ParseError {
+            offset: start + err.column().saturating_sub(1),
+            reason: "invalid string".to_string(),
+        })
+    }
+
+    fn parse_number(&mut self) -> Result<Value, ParseError> {
+        let start = self.pos;
+        let len = self.source[start..]
+            .find(|c: char| !matches!(c, '0'..='9' | '-' | '+' | '.' | 'e' | 'E'))
+            .unwrap_or(self.source.len() - start);
+        serde_json::from_str::<serde_json::Number>(&self.source[start..start + len])
+            .map_err(|_| self.error("invalid number"))?;
+        self.pos += len;
+
+        Ok(Value::Number)
+    }
+
+    fn parse_literal(&mut self, literal: &str, value: Value) -> Result<Value, ParseError> {
+        let rest = &self.source[self.pos..];
+        let is_literal = rest.starts_with(literal)
+            && !rest[literal.len()..].starts_with(|c: char| c.is_alphanumeric());
+        if !is_literal {
+            return Err(self.unexpected());
+        }
+        self.pos += literal.len();
+
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_jsonc() {
+        let source = r#"{
+            // the tasks
+            "pipeline": {
+                "build": {"outputs": ["dist/**",], /* cached */ "cache": true},
+            },
+            "n": -1.5e3, "empty": {}, "list": [null, false],
+        }"#;
+
+        let document = parse(source).unwrap();
+        let value: serde_json::Value = serde_json::from_str(document.json()).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "pipeline": {"build": {"outputs": ["dist/**"], "cache": true}},
+                "n": -1.5e3, "empty": {}, "list": [null, false]
+            })
+        );
+        // Blanking keeps every value at the same offset
+        assert_eq!(document.json().len(), source.len());
+        assert_eq!(document.json().lines().count(), source.lines().count());
+
+        let pipeline = document.root.get("pipeline").unwrap();
+        let member = &pipeline.members()[0];
+        assert_eq!(member.key, "build");
+        assert_eq!(&source[member.key_span.clone()], r#""build""#);
+        assert_eq!(
+            &source[member.value.get("cache").unwrap().span.clone()],
+            "true"
+        );
+    }
+
+    #[test_case("", 0, "unexpected end of file" ; "empty")]
+    #[test_case(r#"{"a": 1"#, 7, "expected ',' or '}'" ; "unclosed object")]
+    #[test_case(r#"{"a" 1}"#, 5, "expected ':' after \"a\"" ; "missing colon")]
+    #[test_case(r#"{"a": [1,, 2]}"#, 9, "unexpected character ','" ; "double comma")]
+    #[test_case(r#"{"a": tru}"#, 6, "unexpected character 't'" ; "bad literal")]
+    #[test_case(r#"{"a": "b}"#, 6, "unterminated string" ; "unterminated string")]
+    #[test_case(r#"{"a": "\x"}"#, 8, "invalid string" ; "bad escape")]
+    #[test_case(r#"{"a": 1.}"#, 6, "invalid number" ; "bad number")]
+    #[test_case("{} /* trailing", 3, "unterminated comment" ; "unterminated comment")]
+    #[test_case("{} {}", 3, "unexpected content after the end of the document" ; "trailing content")]
+    fn test_parse_errors(source: &str, offset: usize, reason: &str) {
+        assert_eq!(
+            parse(source).unwrap_err(),
+            ParseError {
+                offset,
+                reason: reason.to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_diagnostic() {
+        let source = "{\n  \"pipeline\": {\"build\": {\"dependOn\": []}}\n}";
+        let offset = source.find("\"dependOn\"").unwrap();
+
+        let diagnostic = Diagnostic::at_offset("turbo.json", source, offset, "unknown key")
+            .with_help("did you mean \"dependsOn\"?");
+        assert_eq!(
+            diagnostic.to_string(),
+            format!(
+                "turbo.json:2:26: unknown key\n    \"pipeline\": {{\"build\": {{\"dependOn\": \
+                 []}}}}\n  {}^\n  did you mean \"dependsOn\"?",
+                " ".repeat(25)
+            )
+        );
+        assert_eq!(
+            Diagnostic::at_line_column("turbo.json", source, 2, 26, "unknown key"),
+            Diagnostic::at_offset("turbo.json", source, offset, "unknown key")
+        );
+    }
+}
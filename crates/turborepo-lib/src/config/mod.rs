@@ -1,6 +1,8 @@
 mod client;
 mod env;
+mod jsonc;
 mod repo;
+mod schema;
 mod turbo;
 mod user;
 
@@ -18,6 +20,10 @@ use dirs_next::config_dir;
 use dirs_next::data_local_dir as config_dir;
 pub use env::MappedEnvironment;
 pub use repo::{get_repo_config_path, RepoConfig, RepoConfigLoader};
+#[cfg(test)]
+pub use schema::assert_matches_schema;
+pub(crate) use schema::properties;
+pub use schema::{camel_case, field_schema, object_schema, turbo_json_schema, JsonSchema};
 use serde::Serialize;
 pub use turbo::{SpacesJson, TurboJson};
 pub use user::{UserConfig, UserConfigLoader};
@@ -0,0 +1,192 @@
+//! JSON Schema for turbo.json, so that editors can validate and complete it.
+//! The schema is built from the same types that turbo.json is deserialized
+//! into: each property is described by the type of the field it's read into.
+
+use std::collections::HashMap;
+
+use serde_json::{json, Map, Value};
+
+use super::TurboJson;
+
+/// A type that can describe the JSON it is deserialized from.
+pub trait JsonSchema {
+    fn json_schema() -> Value;
+}
+
+impl JsonSchema for String {
+    fn json_schema() -> Value {
+        json!({"type": "string"})
+    }
+}
+
+impl JsonSchema for bool {
+    fn json_schema() -> Value {
+        json!({"type": "boolean"})
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Vec<T> {
+    fn json_schema() -> Value {
+        json!({"type": "array", "items": T::json_schema()})
+    }
+}
+
+// Optional fields are described by leaving them out of "required"
+impl<T: JsonSchema> JsonSchema for Option<T> {
+    fn json_schema() -> Value {
+        T::json_schema()
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for HashMap<String, T> {
+    fn json_schema() -> Value {
+        json!({"type": "object", "additionalProperties": T::json_schema()})
+    }
+}
+
+/// Describes an object with the given properties, each as a key, its schema
+/// and a description for editors to show. Unless `closed`, keys other than
+/// these are allowed.
+pub fn object_schema<'a>(
+    properties: impl IntoIterator<Item = (String, Value, &'a str)>,
+    closed: bool,
+) -> Value {
+    let properties = properties
+        .into_iter()
+        .map(|(key, mut schema, description)| {
+            schema["description"] = description.into();
+            (key, schema)
+        })
+        .collect::<Map<_, _>>();
+
+    let mut schema = json!({
+        "type": "object",
+        "properties": properties,
+    });
+    if closed {
+        schema["additionalProperties"] = false.into();
+    }
+    schema
+}
+
+/// Describes the type of a struct field, given an accessor for it.
+pub fn field_schema<S, T: JsonSchema>(_field: impl Fn(&S) -> &T) -> Value {
+    T::json_schema()
+}
+
+/// The key that serde reads a field from with `rename_all = "camelCase"`.
+pub fn camel_case(field: &str) -> String {
+    let mut key = String::with_capacity(field.len());
+    let mut upper = false;
+    for c in field.chars() {
+        match c {
+            '_' => upper = true,
+            c if upper => {
+                key.extend(c.to_uppercase());
+                upper = false;
+            }
+            c => key.push(c),
+        }
+    }
+    key
+}
+
+/// Lists the properties of a `rename_all = "camelCase"` struct for
+/// `object_schema`, with each schema taken from the field's type. Fields
+/// that serde renames give their key with `as`.
+macro_rules! properties {
+    ($ty:ty { $($field:ident $(as $key:literal)? => $description:expr),* $(,)? }) => {
+        [$((
+            None$(.or(Some($key)))?
+                .map_or_else(|| $crate::config::camel_case(stringify!($field)), str::to_string),
+            $crate::config::field_schema(|value: &$ty| &value.$field),
+            $description,
+        )),*]
+    };
+}
+pub(crate) use properties;
+
+/// The schema for a turbo.json file, as printed by `turbo schema`.
+pub fn turbo_json_schema() -> Value {
+    let mut schema = json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "turbo.json",
+    });
+    if let (Value::Object(schema), Value::Object(turbo_json)) =
+        (&mut schema, TurboJson::json_schema())
+    {
+        schema.extend(turbo_json);
+    }
+
+    schema
+}
+
+/// Checks that `value` has the types that `schema` describes.
+#[cfg(test)]
+pub fn assert_matches_schema(value: &Value, schema: &Value) {
+    if let Some(variants) = schema.get("enum") {
+        assert!(
+            variants.as_array().unwrap().contains(value),
+            "{value} isn't one of {variants}"
+        );
+    }
+    match (schema["type"].as_str(), value) {
+        (Some("string"), Value::String(_)) | (Some("boolean"), Value::Bool(_)) => {}
+        (Some("array"), Value::Array(items)) => {
+            for item in items {
+                assert_matches_schema(item, &schema["items"]);
+            }
+        }
+        (Some("object"), Value::Object(members)) => {
+            for (key, member) in members {
+                let member_schema = schema["properties"]
+                    .get(key)
+                    .or_else(|| schema.get("additionalProperties").filter(|s| s.is_object()));
+                match member_schema {
+                    Some(member_schema) => assert_matches_schema(member, member_schema),
+                    None => assert_ne!(
+                        schema["additionalProperties"], false,
+                        "unexpected key \"{key}\""
+                    ),
+                }
+            }
+        }
+        _ => panic!("{value} doesn't match {schema}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_turbo_json_schema() {
+        let schema = turbo_json_schema();
+        assert_eq!(schema["title"], "turbo.json");
+        // Unknown top level keys are allowed, unknown task keys aren't
+        assert_eq!(schema.get("additionalProperties"), None);
+
+        let build = &schema["properties"]["pipeline"]["additionalProperties"];
+        assert_eq!(
+            build["properties"]["dependsOn"]["type"],
+            json!("array"),
+            "{build:#}"
+        );
+        assert_eq!(
+            build["properties"]["outputMode"]["enum"],
+            json!(["full", "none", "hash-only", "new-only", "errors-only"])
+        );
+        assert_eq!(build["additionalProperties"], false);
+    }
+
+    #[test]
+    fn test_camel_case() {
+        assert_eq!(camel_case("extends"), "extends");
+        assert_eq!(
+            camel_case("global_pass_through_env"),
+            "globalPassThroughEnv"
+        );
+    }
+}
@@ -1,6 +1,23 @@
+use std::{collections::BTreeSet, fmt};
+
+use anyhow::{anyhow, Context, Result};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+use turbopath::{AbsoluteSystemPath, RelativeUnixPathBuf};
+use turborepo_cache::RemoteCacheOpts;
+
+use super::{
+    jsonc::{self, Diagnostic},
+    object_schema, properties, JsonSchema,
+};
+use crate::{
+    package_json::PackageJson,
+    run::task_id::{is_package_task, root_task_id, strip_package_name, ROOT_PKG_NAME},
+    task_graph::{gather_env_vars, BookkeepingTaskDefinition, Pipeline, ENV_PIPELINE_DELIMITER},
+};
 
-use crate::{opts::RemoteCacheOpts, task_graph::Pipeline};
+const CONFIG_FILE: &str = "turbo.json";
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 #[serde(rename_all = "camelCase")]
@@ -15,10 +32,644 @@ pub struct SpacesJson {
 pub struct TurboJson {
     #[serde(flatten)]
     other: serde_json::Value,
+    // Workspace turbo.json files must extend from the root turbo.json
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub extends: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub global_dependencies: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub global_env: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub global_pass_through_env: Option<Vec<String>>,
+    // These are _explicitly_ not sorted, the order is the load order.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub global_dot_env: Option<Vec<String>>,
+    #[serde(rename = "remoteCache", skip_serializing_if = "Option::is_none")]
     pub(crate) remote_cache_opts: Option<RemoteCacheOpts>,
     pub space_id: Option<String>,
-    #[allow(dead_code)]
+    #[serde(default)]
     pub pipeline: Pipeline,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub experimental_spaces: Option<SpacesJson>,
 }
+
+impl TurboJson {
+    fn read(path: &AbsoluteSystemPath) -> Result<Option<TurboJson>> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err).with_context(|| format!("unable to read {}", path)),
+        };
+        let mut turbo_json = Self::parse(&contents, path)?;
+        turbo_json.process_global_fields()?;
+        Ok(Some(turbo_json))
+    }
+
+    // turbo.json may contain comments and trailing commas. Errors point at the
+    // line and column in `file` that caused them.
+    fn parse(contents: &str, file: impl fmt::Display) -> Result<TurboJson, Diagnostic> {
+        let document = jsonc::parse(contents)
+            .map_err(|err| Diagnostic::at_offset(&file, contents, err.offset, err.reason))?;
+        validate_tasks(&document, contents, &file)?;
+
+        serde_json::from_str(document.json()).map_err(|err| {
+            Diagnostic::at_line_column(
+                &file,
+                contents,
+                err.line(),
+                err.column(),
+                serde_message(&err),
+            )
+        })
+    }
+
+    // Env vars declared in `globalDependencies` are moved to `globalEnv`, and
+    // the rest of the global fields are sorted and deduplicated.
+    fn process_global_fields(&mut self) -> Result<()> {
+        let mut global_env = BTreeSet::new();
+        gather_env_vars(
+            std::mem::take(&mut self.global_env),
+            "globalEnv",
+            &mut global_env,
+        )?;
+
+        if let Some(pass_through_env) = self.global_pass_through_env.take() {
+            let mut global_pass_through_env = BTreeSet::new();
+            gather_env_vars(
+                pass_through_env,
+                "globalPassThroughEnv",
+                &mut global_pass_through_env,
+            )?;
+            self.global_pass_through_env = Some(global_pass_through_env.into_iter().collect());
+        }
+
+        let mut global_dependencies = BTreeSet::new();
+        for value in std::mem::take(&mut self.global_dependencies) {
+            if let Some(env_var) = value.strip_prefix(ENV_PIPELINE_DELIMITER) {
+                warn!(
+                    "[DEPRECATED] Declaring an environment variable in \"globalDependencies\" is \
+                     deprecated, found {}. Use the \"globalEnv\" key or use `npx @turbo/codemod \
+                     migrate-env-var-dependencies`.",
+                    value
+                );
+                global_env.insert(env_var.to_string());
+            } else {
+                if std::path::Path::new(&value).is_absolute() {
+                    warn!(
+                        "Using an absolute path in \"globalDependencies\" ({}) will not work and \
+                         will be an error in a future version",
+                        value
+                    );
+                }
+                global_dependencies.insert(value);
+            }
+        }
+
+        self.global_env = global_env.into_iter().collect();
+        self.global_dependencies = global_dependencies.into_iter().collect();
+
+        if let Some(global_dot_env) = &self.global_dot_env {
+            for path in global_dot_env {
+                RelativeUnixPathBuf::new(path.as_str())?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Loads the turbo.json in `dir`. In single package mode, a pipeline entry
+    /// is synthesized for every script in the root package.json that doesn't
+    /// have one, and turbo.json doesn't need to exist.
+    pub fn load(
+        dir: &AbsoluteSystemPath,
+        root_package_json: &PackageJson,
+        include_synthesized_from_root_package_json: bool,
+    ) -> Result<TurboJson> {
+        let turbo_from_files = Self::read(&dir.join_component(CONFIG_FILE))?;
+
+        if !include_synthesized_from_root_package_json {
+            return turbo_from_files.ok_or_else(|| {
+                anyhow!(
+                    "Could not find {}. Follow directions at https://turbo.build/repo/docs to \
+                     create one",
+                    CONFIG_FILE
+                )
+            });
+        }
+
+        let mut turbo_json = match turbo_from_files {
+            Some(mut turbo_json) => {
+                // For now, we error on any package tasks and turn regular tasks into
+                // root tasks
+                let mut pipeline = Pipeline::new();
+                for (task_id, task_definition) in turbo_json.pipeline.drain() {
+                    if is_package_task(&task_id) {
+                        return Err(anyhow!(
+                            "Package tasks (<package>#<task>) are not allowed in single-package \
+                             repositories: found {}",
+                            task_id
+                        ));
+                    }
+                    pipeline.insert(root_task_id(&task_id), task_definition);
+                }
+                turbo_json.pipeline = pipeline;
+                turbo_json
+            }
+            None => TurboJson::default(),
+        };
+
+        for script_name in root_package_json.scripts.keys() {
+            turbo_json
+                .pipeline
+                .entry(root_task_id(script_name))
+                .or_insert_with(BookkeepingTaskDefinition::synthesized);
+        }
+
+        Ok(turbo_json)
+    }
+
+    /// Loads the turbo.json of a non-root workspace, returning `None` if the
+    /// workspace doesn't have one. Its tasks are merged onto the root
+    /// pipeline, so it must extend from the root and can only configure
+    /// tasks by name.
+    pub fn load_workspace(dir: &AbsoluteSystemPath) -> Result<Option<TurboJson>> {
+        let path = dir.join_component(CONFIG_FILE);
+        let Some(turbo_json) = Self::read(&path)? else {
+            return Ok(None);
+        };
+
+        let errors = turbo_json.validate_workspace();
+        if !errors.is_empty() {
+            let errors = errors
+                .iter()
+                .map(|error| format!("\n - {error}"))
+                .collect::<String>();
+            return Err(anyhow!("Invalid turbo.json at {}{}", path, errors));
+        }
+
+        Ok(Some(turbo_json))
+    }
+
+    fn validate_workspace(&self) -> Vec<String> {
+        let mut errors = Vec::new();
+
+        let mut package_tasks = self
+            .pipeline
+            .keys()
+            .filter(|task_id| is_package_task(task_id))
+            .collect::<Vec<_>>();
+        package_tasks.sort();
+        for task_id in package_tasks {
+            errors.push(format!(
+                "\"{}\". Use \"{}\" instead",
+                task_id,
+                strip_package_name(task_id)
+            ));
+        }
+
+        // TODO: Allow extending from workspaces other than the root
+        match self.extends.as_slice() {
+            [] => errors.push("No \"extends\" key found".to_string()),
+            [root] if root == ROOT_PKG_NAME => {}
+            _ => errors.push("You can only extend from the root workspace".to_string()),
+        }
+
+        errors
+    }
+}
+
+// Task definitions are checked key by key, so that errors point at the
+// offending value. serde would also silently ignore unknown keys, dropping a
+// misspelled key from a task.
+fn validate_tasks(
+    document: &jsonc::Document,
+    contents: &str,
+    file: impl fmt::Display,
+) -> Result<(), Diagnostic> {
+    let schema = BookkeepingTaskDefinition::json_schema();
+    let task_keys = schema["properties"]
+        .as_object()
+        .expect("task definitions are described as objects");
+    let json = document.json();
+
+    let Some(pipeline) = document.root.get("pipeline") else {
+        return Ok(());
+    };
+    for task in pipeline.members() {
+        for member in task.value.members() {
+            if !task_keys.contains_key(&member.key) {
+                let diagnostic = Diagnostic::at_offset(
+                    &file,
+                    contents,
+                    member.key_span.start,
+                    format!("unknown key \"{}\" in task \"{}\"", member.key, task.key),
+                );
+                return Err(
+                    match did_you_mean(&member.key, task_keys.keys().map(|key| key.as_str())) {
+                        Some(suggestion) => {
+                            diagnostic.with_help(format!("did you mean \"{suggestion}\"?"))
+                        }
+                        None => diagnostic,
+                    },
+                );
+            }
+
+            let field = format!(
+                "{{{}:{}}}",
+                &json[member.key_span.clone()],
+                &json[member.value.span.clone()]
+            );
+            if let Err(err) = serde_json::from_str::<BookkeepingTaskDefinition>(&field) {
+                return Err(Diagnostic::at_offset(
+                    &file,
+                    contents,
+                    member.value.span.start,
+                    serde_message(&err),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// serde_json appends the location to its messages, which diagnostics already
+// show.
+fn serde_message(err: &serde_json::Error) -> String {
+    let message = err.to_string();
+    let location = format!(" at line {} column {}", err.line(), err.column());
+    match message.strip_suffix(&location) {
+        Some(message) => message.to_string(),
+        None => message,
+    }
+}
+
+// Suggests the closest of `candidates` if it's within a couple of edits.
+fn did_you_mean<'a>(key: &str, candidates: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let max_distance = (key.chars().count() / 3).max(1);
+    candidates
+        .map(|candidate| (edit_distance(key, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+// Levenshtein distance, ignoring case
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.to_lowercase().chars().collect::<Vec<_>>();
+    let b = b.to_lowercase().chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a_char) in a.iter().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+impl JsonSchema for TurboJson {
+    fn json_schema() -> Value {
+        let properties = properties!(TurboJson {
+            extends => "The workspaces that this turbo.json extends. Only workspace turbo.json \
+                        files can extend, and only from the root: [\"//\"].",
+            global_dependencies => "Globs of files that every task's hash depends on.",
+            global_env => "Environment variables that every task's hash depends on.",
+            global_pass_through_env => "Environment variables that are available to every task \
+                                        without affecting hashes.",
+            global_dot_env => "`.env` files that every task's hash depends on, in the order they \
+                               are loaded.",
+            remote_cache_opts as "remoteCache" => "Configuration for the remote cache.",
+            space_id => "The Vercel Space that runs are reported to.",
+            pipeline => "The tasks that can be run, by task name or `<workspace>#<task>`.",
+            experimental_spaces => "Experimental configuration for Vercel Spaces.",
+        });
+        // `$schema` and any unknown keys are kept in `other`
+        let schema = (
+            "$schema".to_string(),
+            String::json_schema(),
+            "The schema that this file is validated against.",
+        );
+        object_schema(std::iter::once(schema).chain(properties), false)
+    }
+}
+
+impl JsonSchema for SpacesJson {
+    fn json_schema() -> Value {
+        // Unknown keys are kept in `other`
+        object_schema(
+            properties!(SpacesJson {
+                id => "The id of the Space.",
+            }),
+            false,
+        )
+    }
+}
+
+// The fields of `RemoteCacheOpts` belong to the cache crate, so they're
+// listed by key. Unknown keys are ignored when reading them.
+impl JsonSchema for RemoteCacheOpts {
+    fn json_schema() -> Value {
+        object_schema(
+            [
+                (
+                    "teamId".to_string(),
+                    String::json_schema(),
+                    "The team that owns the remote cache.",
+                ),
+                (
+                    "signature".to_string(),
+                    bool::json_schema(),
+                    "Whether artifacts are signed with `TURBO_REMOTE_CACHE_SIGNATURE_KEY`.",
+                ),
+                (
+                    "enabled".to_string(),
+                    bool::json_schema(),
+                    "Whether the remote cache is used. Defaults to true.",
+                ),
+            ],
+            false,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::BTreeSet, fs};
+
+    use anyhow::Result;
+    use tempfile::tempdir;
+    use test_case::test_case;
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::*;
+    use crate::config::assert_matches_schema;
+
+    #[test]
+    fn test_load_turbo_json() -> Result<()> {
+        let dir = tempdir()?;
+        let repo_root = AbsoluteSystemPathBuf::new(dir.path())?;
+        fs::write(
+            repo_root.join_component("turbo.json"),
+            r#"{
+                "pipeline": {
+                    "build": {"dependsOn": ["^build"], "outputs": ["dist/**"]},
+                    "//#lint": {}
+                },
+                "remoteCache": {"signature": true}
+            }"#,
+        )?;
+
+        let turbo_json = TurboJson::load(&repo_root, &PackageJson::default(), false)?;
+        assert_eq!(turbo_json.pipeline.len(), 2);
+        assert_eq!(
+            turbo_json.pipeline["build"]
+                .task_definition()
+                .topological_dependencies,
+            vec!["build".to_string()]
+        );
+        assert!(turbo_json.remote_cache_opts.unwrap().enabled());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_global_fields() -> Result<()> {
+        let dir = tempdir()?;
+        let repo_root = AbsoluteSystemPathBuf::new(dir.path())?;
+        fs::write(
+            repo_root.join_component("turbo.json"),
+            r#"{
+                "globalDependencies": ["tsconfig.json", "$GLOBAL_TOKEN", ".env"],
+                "globalEnv": ["NODE_ENV", "CI"],
+                "globalDotEnv": [".env.local", ".env"],
+                "pipeline": {}
+            }"#,
+        )?;
+
+        let turbo_json = TurboJson::load(&repo_root, &PackageJson::default(), false)?;
+        assert_eq!(
+            turbo_json.global_dependencies,
+            vec![".env", "tsconfig.json"]
+        );
+        assert_eq!(
+            turbo_json.global_env,
+            vec!["CI", "GLOBAL_TOKEN", "NODE_ENV"]
+        );
+        assert_eq!(turbo_json.global_pass_through_env, None);
+        assert_eq!(
+            turbo_json.global_dot_env,
+            Some(vec![".env.local".to_string(), ".env".to_string()])
+        );
+
+        fs::write(
+            repo_root.join_component("turbo.json"),
+            r#"{"globalPassThroughEnv": ["$AWS_SECRET"], "pipeline": {}}"#,
+        )?;
+        assert!(TurboJson::load(&repo_root, &PackageJson::default(), false).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_missing_turbo_json() -> Result<()> {
+        let dir = tempdir()?;
+        let repo_root = AbsoluteSystemPathBuf::new(dir.path())?;
+
+        let err = TurboJson::load(&repo_root, &PackageJson::default(), false).unwrap_err();
+        assert!(err.to_string().starts_with("Could not find turbo.json"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_jsonc_turbo_json() -> Result<()> {
+        let turbo_json = TurboJson::parse(
+            r#"{
+                // Comments and trailing commas are allowed
+                "$schema": "https://turbo.build/schema.json",
+                "pipeline": {
+                    "build": {"outputs": ["dist/**",]}, /* lint everything */
+                    "lint": {},
+                },
+            }"#,
+            "turbo.json",
+        )?;
+        assert_eq!(turbo_json.pipeline.len(), 2);
+        assert!(turbo_json.pipeline["build"].has_field("Outputs"));
+
+        Ok(())
+    }
+
+    #[test_case(
+        "{\"pipeline\": {\n  \"build\": {\"dependOn\": [\"^build\"]}\n}}",
+        (2, 13),
+        "unknown key \"dependOn\" in task \"build\""
+        ; "misspelled task key"
+    )]
+    #[test_case(
+        "{\"pipeline\": {\n  \"build\": {\"env\": [\"$FOO\"]}\n}}",
+        (2, 20),
+        "You specified \"$FOO\" in the \"env\" key. You should not prefix your environment \
+         variables with \"$\""
+        ; "invalid task definition"
+    )]
+    #[test_case(
+        "{\"pipeline\": {\n  \"build\": {\"cache\": \"no\"}\n}}",
+        (2, 22),
+        "invalid type: string \"no\", expected a boolean"
+        ; "wrong type"
+    )]
+    #[test_case(
+        "{\"pipeline\": {\n  \"build\": {}\n  \n}",
+        (4, 2),
+        "expected ',' or '}'"
+        ; "syntax error"
+    )]
+    #[test_case(
+        "{\n  \"globalEnv\": \"FOO\"\n}",
+        (2, 20),
+        "invalid type: string \"FOO\", expected a sequence"
+        ; "wrong type outside of a task"
+    )]
+    fn test_parse_errors(contents: &str, (line, column): (usize, usize), message: &str) {
+        let err = TurboJson::parse(contents, "turbo.json").unwrap_err();
+        let rendered = err.to_string();
+        assert!(
+            rendered.starts_with(&format!("turbo.json:{line}:{column}: {message}\n")),
+            "{rendered}"
+        );
+    }
+
+    #[test]
+    fn test_unknown_task_key_suggestion() {
+        let err = TurboJson::parse(
+            r#"{"pipeline": {"build": {"output": ["dist/**"]}}}"#,
+            "turbo.json",
+        )
+        .unwrap_err();
+        assert!(err.to_string().ends_with("\n  did you mean \"outputs\"?"));
+
+        let err = TurboJson::parse(r#"{"pipeline": {"build": {"bogus": true}}}"#, "turbo.json")
+            .unwrap_err();
+        assert!(!err.to_string().contains("did you mean"));
+    }
+
+    #[test]
+    fn test_did_you_mean() {
+        let candidates = ["outputs", "dependsOn", "env", "inputs"];
+        assert_eq!(
+            did_you_mean("output", candidates.into_iter()),
+            Some("outputs")
+        );
+        assert_eq!(
+            did_you_mean("dependson", candidates.into_iter()),
+            Some("dependsOn")
+        );
+        assert_eq!(did_you_mean("cache", candidates.into_iter()), None);
+    }
+
+    #[test]
+    fn test_schema_describes_every_key() -> Result<()> {
+        let all_fields = TurboJson {
+            other: serde_json::json!({"$schema": ""}),
+            extends: vec!["//".to_string()],
+            global_dependencies: vec![String::new()],
+            global_env: vec![String::new()],
+            global_pass_through_env: Some(vec![]),
+            global_dot_env: Some(vec![]),
+            remote_cache_opts: Some(serde_json::from_str(r#"{"teamId": "team"}"#)?),
+            space_id: Some(String::new()),
+            pipeline: serde_json::from_str(
+                r#"{"build": {"outputs": ["dist/**"], "cache": false, "outputMode": "new-only"}}"#,
+            )?,
+            experimental_spaces: Some(SpacesJson {
+                id: Some("space".to_string()),
+                other: None,
+            }),
+        };
+        let value = serde_json::to_value(all_fields)?;
+        let keys = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        let schema = TurboJson::json_schema();
+        let properties = schema["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        assert_eq!(keys, properties);
+        assert_matches_schema(&value, &schema);
+
+        // unknown keys are kept rather than rejected
+        let unknown = serde_json::json!({"unknownKey": 1});
+        serde_json::from_value::<TurboJson>(unknown.clone())?;
+        assert_matches_schema(&unknown, &schema);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_workspace_turbo_json() -> Result<()> {
+        let dir = tempdir()?;
+        let workspace_dir = AbsoluteSystemPathBuf::new(dir.path())?;
+        assert!(TurboJson::load_workspace(&workspace_dir)?.is_none());
+
+        fs::write(
+            workspace_dir.join_component("turbo.json"),
+            r#"{"extends": ["//"], "pipeline": {"build": {"outputs": ["lib/**"]}}}"#,
+        )?;
+        let turbo_json = TurboJson::load_workspace(&workspace_dir)?.unwrap();
+        assert_eq!(turbo_json.extends, vec!["//".to_string()]);
+        assert!(turbo_json.pipeline["build"].has_field("Outputs"));
+
+        fs::write(
+            workspace_dir.join_component("turbo.json"),
+            r#"{"pipeline": {"//#build": {}}}"#,
+        )?;
+        let err = TurboJson::load_workspace(&workspace_dir).unwrap_err();
+        assert!(err
+            .to_string()
+            .ends_with("\n - \"//#build\". Use \"build\" instead\n - No \"extends\" key found"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_synthesize_single_package_pipeline() -> Result<()> {
+        let dir = tempdir()?;
+        let repo_root = AbsoluteSystemPathBuf::new(dir.path())?;
+        let root_package_json: PackageJson =
+            serde_json::from_str(r#"{"scripts": {"build": "tsc", "test": "jest"}}"#)?;
+        fs::write(
+            repo_root.join_component("turbo.json"),
+            r#"{"pipeline": {"build": {"outputs": ["dist/**"]}}}"#,
+        )?;
+
+        let turbo_json = TurboJson::load(&repo_root, &root_package_json, true)?;
+        let build = turbo_json.pipeline["//#build"].task_definition();
+        assert!(build.should_cache);
+        assert_eq!(build.outputs.inclusions, vec!["dist/**".to_string()]);
+        // Scripts without a pipeline entry aren't cached
+        assert!(
+            !turbo_json.pipeline["//#test"]
+                .task_definition()
+                .should_cache
+        );
+
+        // Package tasks can't be used in single package mode
+        fs::write(
+            repo_root.join_component("turbo.json"),
+            r#"{"pipeline": {"a#build": {}}}"#,
+        )?;
+        assert!(TurboJson::load(&repo_root, &root_package_json, true).is_err());
+
+        Ok(())
+    }
+}
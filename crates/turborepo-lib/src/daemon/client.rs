@@ -1,17 +1,19 @@
+use std::collections::{HashMap, HashSet};
+
 use thiserror::Error;
 use tonic::{Code, Status};
-use tracing::info;
+use tracing::{debug, info};
+use turbopath::AbsoluteSystemPath;
+use turborepo_scm::git::ShallowCloneMode;
 
 use self::proto::turbod_client::TurbodClient;
+pub(crate) use super::proto;
 use super::{
     connector::{DaemonConnector, DaemonConnectorError},
     endpoint::SocketOpenError,
+    graph::package_graph_from_proto,
 };
-use crate::get_version;
-
-pub mod proto {
-    tonic::include_proto!("turbodprotocol");
-}
+use crate::{get_version, package_graph::PackageGraph, package_json::PackageJson, run::scope};
 
 #[derive(Debug)]
 pub struct DaemonClient<T> {
@@ -104,6 +106,67 @@ impl DaemonClient<DaemonConnector> {
         Ok(())
     }
 
+    /// Get the hashes of the files in the package at `package_path` that
+    /// match `inputs`, keyed by their unix path relative to the package.
+    pub async fn get_package_file_hashes(
+        &mut self,
+        package_path: String,
+        inputs: Vec<String>,
+    ) -> Result<HashMap<String, String>, DaemonError> {
+        Ok(self
+            .client
+            .get_package_file_hashes(proto::GetPackageFileHashesRequest {
+                package_path,
+                inputs,
+            })
+            .await?
+            .into_inner()
+            .file_hashes)
+    }
+
+    /// Get the package graph of the repository. Workspaces have their
+    /// dependencies resolved, but the lockfile itself is not sent.
+    pub async fn get_package_graph(&mut self) -> Result<PackageGraph, DaemonError> {
+        let graph = self
+            .client
+            .get_package_graph(proto::GetPackageGraphRequest {})
+            .await?
+            .into_inner()
+            .package_graph
+            .ok_or(DaemonError::MalformedResponse)?;
+        package_graph_from_proto(graph).map_err(|_| DaemonError::MalformedResponse)
+    }
+
+    /// Get the workspaces that changed between `from_ref` and `to_ref`.
+    pub async fn get_changed_packages(
+        &mut self,
+        from_ref: String,
+        to_ref: String,
+        global_deps: Vec<String>,
+        ignore_patterns: Vec<String>,
+        shallow_clone_mode: ShallowCloneMode,
+    ) -> Result<HashSet<String>, DaemonError> {
+        let shallow_clone_mode = match shallow_clone_mode {
+            ShallowCloneMode::AllChanged => proto::ShallowCloneMode::AllChanged,
+            ShallowCloneMode::Deepen => proto::ShallowCloneMode::Deepen,
+            ShallowCloneMode::Fail => proto::ShallowCloneMode::Fail,
+        };
+        Ok(self
+            .client
+            .get_changed_packages(proto::GetChangedPackagesRequest {
+                from_ref,
+                to_ref,
+                global_deps,
+                ignore_patterns,
+                shallow_clone_mode: shallow_clone_mode.into(),
+            })
+            .await?
+            .into_inner()
+            .packages
+            .into_iter()
+            .collect())
+    }
+
     /// Get the status of the daemon.
     pub async fn status(&mut self) -> Result<proto::DaemonStatus, DaemonError> {
         self.client
@@ -123,6 +186,60 @@ impl DaemonClient<DaemonConnector> {
     }
 }
 
+/// Gets the package graph from the daemon if there is one, otherwise builds
+/// it from the repository.
+pub async fn package_graph(
+    daemon: Option<&mut DaemonClient<DaemonConnector>>,
+    repo_root: &AbsoluteSystemPath,
+    root_package_json: &PackageJson,
+) -> Result<PackageGraph, crate::package_graph::Error> {
+    if let Some(daemon) = daemon {
+        match daemon.get_package_graph().await {
+            Ok(graph) => return Ok(graph),
+            Err(e) => debug!("failed to get package graph from daemon: {}", e),
+        }
+    }
+    PackageGraph::build_multi_package_graph(repo_root, root_package_json)
+}
+
+/// Gets the workspaces that changed between `from_ref` and `to_ref` from the
+/// daemon if there is one, otherwise calculates them from the repository.
+pub async fn changed_packages(
+    daemon: Option<&mut DaemonClient<DaemonConnector>>,
+    repo_root: &AbsoluteSystemPath,
+    package_graph: &PackageGraph,
+    from_ref: &str,
+    to_ref: &str,
+    global_deps: &[String],
+    ignore_patterns: &[String],
+    shallow_clone_mode: ShallowCloneMode,
+) -> anyhow::Result<HashSet<String>> {
+    if let Some(daemon) = daemon {
+        match daemon
+            .get_changed_packages(
+                from_ref.to_string(),
+                to_ref.to_string(),
+                global_deps.to_vec(),
+                ignore_patterns.to_vec(),
+                shallow_clone_mode,
+            )
+            .await
+        {
+            Ok(packages) => return Ok(packages),
+            Err(e) => debug!("failed to get changed packages from daemon: {}", e),
+        }
+    }
+    scope::changed_packages(
+        repo_root,
+        package_graph,
+        from_ref,
+        to_ref,
+        global_deps,
+        ignore_patterns,
+        shallow_clone_mode,
+    )
+}
+
 #[derive(Error, Debug)]
 pub enum DaemonError {
     /// The server was connected but is now unavailable.
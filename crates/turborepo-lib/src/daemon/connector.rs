@@ -629,6 +629,27 @@ mod test {
         ) -> tonic::Result<tonic::Response<proto::GetChangedOutputsResponse>> {
             unimplemented!()
         }
+
+        async fn get_package_file_hashes(
+            &self,
+            _req: tonic::Request<proto::GetPackageFileHashesRequest>,
+        ) -> tonic::Result<tonic::Response<proto::GetPackageFileHashesResponse>> {
+            unimplemented!()
+        }
+
+        async fn get_package_graph(
+            &self,
+            _req: tonic::Request<proto::GetPackageGraphRequest>,
+        ) -> tonic::Result<tonic::Response<proto::GetPackageGraphResponse>> {
+            unimplemented!()
+        }
+
+        async fn get_changed_packages(
+            &self,
+            _req: tonic::Request<proto::GetChangedPackagesRequest>,
+        ) -> tonic::Result<tonic::Response<proto::GetChangedPackagesResponse>> {
+            unimplemented!()
+        }
     }
 
     #[tokio::test]
@@ -0,0 +1,148 @@
+//! Conversions between the package graph and the form it is sent in by the
+//! daemon. Workspaces are sent with their dependencies already resolved, so
+//! that clients don't need to read the lockfile.
+
+use std::str::FromStr;
+
+use anyhow::Result;
+use turbopath::AnchoredSystemPathBuf;
+use turborepo_lockfiles::Package;
+
+use super::proto;
+use crate::{
+    package_graph::{PackageGraph, WorkspaceInfo},
+    package_manager::PackageManager,
+};
+
+pub(crate) fn package_graph_to_proto(graph: &PackageGraph) -> Result<proto::PackageGraph> {
+    let workspaces = graph
+        .workspace_infos()
+        .iter()
+        .map(|(name, info)| {
+            Ok(proto::Workspace {
+                name: name.clone(),
+                package_json_path: info.package_json_path.to_unix()?.as_str()?.to_string(),
+                package_json: serde_json::to_string(&info.package_json)?,
+                internal_dependencies: info.internal_dependencies.iter().cloned().collect(),
+                unresolved_external_dependencies: info
+                    .unresolved_external_dependencies
+                    .clone()
+                    .into_iter()
+                    .collect(),
+                transitive_dependencies: info.transitive_dependencies.as_ref().map(|packages| {
+                    proto::TransitiveDependencies {
+                        packages: packages
+                            .iter()
+                            .map(|Package { key, version }| proto::LockfilePackage {
+                                key: key.clone(),
+                                version: version.clone(),
+                            })
+                            .collect(),
+                    }
+                }),
+            })
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(proto::PackageGraph {
+        package_manager: graph.package_manager().to_string(),
+        workspaces,
+        has_lockfile: graph.has_lockfile(),
+    })
+}
+
+pub(crate) fn package_graph_from_proto(graph: proto::PackageGraph) -> Result<PackageGraph> {
+    let package_manager = PackageManager::from_str(&graph.package_manager)?;
+    let workspaces = graph
+        .workspaces
+        .into_iter()
+        .map(|workspace| {
+            let info = WorkspaceInfo {
+                package_json: serde_json::from_str(&workspace.package_json)?,
+                package_json_path: AnchoredSystemPathBuf::from_raw(&workspace.package_json_path)?,
+                internal_dependencies: workspace.internal_dependencies.into_iter().collect(),
+                unresolved_external_dependencies: workspace
+                    .unresolved_external_dependencies
+                    .into_iter()
+                    .collect(),
+                transitive_dependencies: workspace.transitive_dependencies.map(|dependencies| {
+                    dependencies
+                        .packages
+                        .into_iter()
+                        .map(|proto::LockfilePackage { key, version }| Package { key, version })
+                        .collect()
+                }),
+            };
+            Ok((workspace.name, info))
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(PackageGraph::from_resolved_workspaces(
+        workspaces,
+        package_manager,
+        graph.has_lockfile,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use serde_json::json;
+    use tempfile::tempdir;
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::{package_graph_from_proto, package_graph_to_proto};
+    use crate::{package_graph::PackageGraph, package_json::PackageJson};
+
+    #[test]
+    fn test_package_graph_roundtrip() {
+        let tmp = tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::new(tmp.path()).unwrap();
+        let write = |path: &[&str], value: serde_json::Value| {
+            let path = repo_root.join_components(path);
+            fs::create_dir_all(path.as_path().parent().unwrap()).unwrap();
+            fs::write(path, value.to_string()).unwrap();
+        };
+        write(
+            &["package.json"],
+            json!({"name": "monorepo", "packageManager": "npm@8.19.4", "workspaces": ["packages/*"]}),
+        );
+        write(
+            &["packages", "a", "package.json"],
+            json!({"name": "a", "dependencies": {"b": "*", "lodash": "^4.0.0"}}),
+        );
+        write(&["packages", "b", "package.json"], json!({"name": "b"}));
+        write(
+            &["package-lock.json"],
+            json!({
+                "lockfileVersion": 3,
+                "packages": {
+                    "": {"workspaces": ["packages/*"]},
+                    "packages/a": {"dependencies": {"b": "*", "lodash": "^4.0.0"}},
+                    "packages/b": {},
+                    "node_modules/b": {"resolved": "packages/b", "link": true},
+                    "node_modules/lodash": {"version": "4.17.21"},
+                }
+            }),
+        );
+
+        let root_package_json =
+            PackageJson::load(&repo_root.join_component("package.json")).unwrap();
+        let graph =
+            PackageGraph::build_multi_package_graph(&repo_root, &root_package_json).unwrap();
+        let received = package_graph_from_proto(package_graph_to_proto(&graph).unwrap()).unwrap();
+
+        assert_eq!(received.package_manager(), graph.package_manager());
+        assert!(received.has_lockfile());
+        assert!(received.lockfile().is_none());
+        assert_eq!(
+            received.workspace_infos().iter().collect::<Vec<_>>(),
+            graph.workspace_infos().iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            received.dependencies("a").unwrap().collect::<Vec<_>>(),
+            vec!["b"]
+        );
+    }
+}
@@ -3,9 +3,10 @@ mod bump_timeout_layer;
 mod client;
 mod connector;
 pub(crate) mod endpoint;
+mod graph;
 mod server;
 
-pub use client::{DaemonClient, DaemonError};
+pub use client::{changed_packages, package_graph, DaemonClient, DaemonError};
 pub use connector::DaemonConnector;
 pub use server::{CloseReason, DaemonServer};
 
@@ -11,6 +11,11 @@
 //! that hash, and files that have been updated for that hash. In addition, this
 //! server can be interrogated over grpc to register interest in particular
 //! globs, and to query for changes for those globs.
+//!
+//! The watcher also keeps the input file hashes of each package that has been
+//! requested, so that they are only recalculated once a file in that package
+//! changes. Similarly, the package graph is only rebuilt once a package.json,
+//! lockfile or workspace config changes.
 
 use std::{
     collections::{HashMap, HashSet},
@@ -33,22 +38,26 @@ use tokio::{
 use tonic::transport::{NamedService, Server};
 use tower::ServiceBuilder;
 use tracing::{error, trace};
-use turbopath::AbsoluteSystemPathBuf;
+use turbopath::{AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+use turborepo_scm::git::ShallowCloneMode;
 
 use super::{
     bump_timeout::BumpTimeout,
     endpoint::SocketOpenError,
+    graph::package_graph_to_proto,
     proto::{self},
     DaemonError,
 };
 use crate::{
     commands::CommandBase, daemon::bump_timeout_layer::BumpTimeoutLayer, get_version,
-    globwatcher::HashGlobWatcher,
+    globwatcher::HashGlobWatcher, package_graph::PackageGraph, package_json::PackageJson,
+    package_manager::PackageManager, run::scope,
 };
 
 pub struct DaemonServer<T: Watcher> {
     #[allow(dead_code)]
     daemon_root: AbsoluteSystemPathBuf,
+    repo_root: AbsoluteSystemPathBuf,
     log_file: AbsoluteSystemPathBuf,
 
     start_time: Instant,
@@ -63,6 +72,10 @@ pub struct DaemonServer<T: Watcher> {
     running: Arc<AtomicBool>,
 
     times_saved: Arc<std::sync::Mutex<HashMap<String, u64>>>,
+
+    /// the last package graph built, with the workspace generation it was
+    /// built at
+    package_graph: StdMutux<Option<(u64, Arc<PackageGraph>)>>,
 }
 
 #[derive(Debug)]
@@ -84,9 +97,11 @@ impl DaemonServer<notify::RecommendedWatcher> {
         log_file: AbsoluteSystemPathBuf,
     ) -> Result<Self, DaemonError> {
         let daemon_root = base.daemon_file_root();
+        let repo_root =
+            AbsoluteSystemPathBuf::new(base.repo_root.clone()).expect("valid repo root");
 
         let watcher = Arc::new(HashGlobWatcher::new(
-            AbsoluteSystemPathBuf::new(base.repo_root.clone()).expect("valid repo root"),
+            repo_root.clone(),
             daemon_root.join_component("flush").as_path().to_owned(),
         )?);
 
@@ -94,6 +109,7 @@ impl DaemonServer<notify::RecommendedWatcher> {
 
         Ok(Self {
             daemon_root,
+            repo_root,
             log_file,
 
             start_time: Instant::now(),
@@ -105,6 +121,7 @@ impl DaemonServer<notify::RecommendedWatcher> {
 
             running: Arc::new(AtomicBool::new(true)),
             times_saved: Arc::new(StdMutux::new(HashMap::new())),
+            package_graph: StdMutux::new(None),
         })
     }
 }
@@ -221,6 +238,87 @@ impl<T: Watcher + Send + 'static> DaemonServer<T> {
     }
 }
 
+impl<T: Watcher + Send + 'static> DaemonServer<T> {
+    /// Returns the package graph, rebuilding it if any of the files it was
+    /// built from have changed since it was last built.
+    async fn package_graph(&self) -> Result<Arc<PackageGraph>, tonic::Status> {
+        // read the generation before building, so that anything that changes
+        // while building causes the next request to rebuild. if the watcher
+        // hasn't caught up, writes may be missing from the generation so the
+        // stored graph can't be trusted
+        let generation = self.watcher.workspace_generation().await.map_err(|e| {
+            error!("failed to flush watcher: {:?}", e);
+            tonic::Status::internal("failed to flush watcher")
+        })?;
+
+        if let (Some(generation), Some((built_at, graph))) = (
+            generation,
+            self.package_graph
+                .lock()
+                .expect("package graph lock poisoned")
+                .as_ref(),
+        ) {
+            if *built_at == generation {
+                return Ok(graph.clone());
+            }
+        }
+
+        let repo_root = self.repo_root.clone();
+        let (root_package_json, workspace_globs) = tokio::task::spawn_blocking(move || {
+            let root_package_json = PackageJson::load(&repo_root.join_component("package.json"))?;
+            let package_manager =
+                PackageManager::get_package_manager(&repo_root, Some(&root_package_json))?;
+            let globs = package_manager.get_workspace_globs(&repo_root)?;
+            let workspace_globs = globs
+                .package_json_inclusions()
+                .iter()
+                .map(|glob| {
+                    glob.strip_suffix("/package.json")
+                        .unwrap_or(glob)
+                        .to_string()
+                })
+                .collect::<Vec<_>>();
+            Ok::<_, anyhow::Error>((root_package_json, workspace_globs))
+        })
+        .await
+        .map_err(|e| tonic::Status::internal(e.to_string()))?
+        .map_err(|e| {
+            error!("failed to read workspace config: {:?}", e);
+            tonic::Status::internal(format!("failed to read workspace config: {e}"))
+        })?;
+
+        // watch before building, so that anything written while we build
+        // invalidates the result
+        self.watcher
+            .watch_workspaces(workspace_globs.iter().map(String::as_str))
+            .await
+            .map_err(|e| {
+                error!("failed to watch workspaces: {:?}", e);
+                tonic::Status::internal("failed to watch workspaces")
+            })?;
+
+        let repo_root = self.repo_root.clone();
+        let graph = tokio::task::spawn_blocking(move || {
+            PackageGraph::build_multi_package_graph(&repo_root, &root_package_json)
+        })
+        .await
+        .map_err(|e| tonic::Status::internal(e.to_string()))?
+        .map_err(|e| {
+            error!("failed to build package graph: {:?}", e);
+            tonic::Status::internal(format!("failed to build package graph: {e}"))
+        })?;
+
+        let graph = Arc::new(graph);
+        if let Some(generation) = generation {
+            *self
+                .package_graph
+                .lock()
+                .expect("package graph lock poisoned") = Some((generation, graph.clone()));
+        }
+        Ok(graph)
+    }
+}
+
 #[tonic::async_trait]
 impl<T: Watcher + Send + 'static> proto::turbod_server::Turbod for DaemonServer<T> {
     async fn hello(
@@ -317,6 +415,88 @@ impl<T: Watcher + Send + 'static> proto::turbod_server::Turbod for DaemonServer<
             }
         }
     }
+
+    async fn get_package_file_hashes(
+        &self,
+        request: tonic::Request<proto::GetPackageFileHashesRequest>,
+    ) -> Result<tonic::Response<proto::GetPackageFileHashesResponse>, tonic::Status> {
+        let inner = request.into_inner();
+        let package_path = AnchoredSystemPathBuf::from_raw(&inner.package_path)
+            .map_err(|e| tonic::Status::invalid_argument(e.to_string()))?;
+
+        let hashes = match self
+            .watcher
+            .package_file_hashes(package_path, inner.inputs)
+            .await
+        {
+            Ok(hashes) => hashes,
+            Err(e) => {
+                error!("failed to hash package files: {:?}", e);
+                return Err(tonic::Status::internal("failed to hash package files"));
+            }
+        };
+
+        let file_hashes = hashes
+            .into_iter()
+            .map(|(path, hash)| Ok((path.as_str()?.to_string(), hash)))
+            .collect::<Result<_, turbopath::PathError>>()
+            .map_err(|e| tonic::Status::internal(e.to_string()))?;
+        Ok(tonic::Response::new(proto::GetPackageFileHashesResponse {
+            file_hashes,
+        }))
+    }
+
+    async fn get_package_graph(
+        &self,
+        _request: tonic::Request<proto::GetPackageGraphRequest>,
+    ) -> Result<tonic::Response<proto::GetPackageGraphResponse>, tonic::Status> {
+        let graph = self.package_graph().await?;
+        let package_graph =
+            package_graph_to_proto(&graph).map_err(|e| tonic::Status::internal(e.to_string()))?;
+        Ok(tonic::Response::new(proto::GetPackageGraphResponse {
+            package_graph: Some(package_graph),
+        }))
+    }
+
+    async fn get_changed_packages(
+        &self,
+        request: tonic::Request<proto::GetChangedPackagesRequest>,
+    ) -> Result<tonic::Response<proto::GetChangedPackagesResponse>, tonic::Status> {
+        let inner = request.into_inner();
+        let graph = self.package_graph().await?;
+        let repo_root = self.repo_root.clone();
+        let shallow_clone_mode = match inner.shallow_clone_mode() {
+            proto::ShallowCloneMode::AllChanged => ShallowCloneMode::AllChanged,
+            proto::ShallowCloneMode::Deepen => ShallowCloneMode::Deepen,
+            proto::ShallowCloneMode::Fail => ShallowCloneMode::Fail,
+        };
+        let to_ref = match inner.to_ref.as_str() {
+            "" => "HEAD".to_string(),
+            _ => inner.to_ref,
+        };
+
+        let changed = tokio::task::spawn_blocking(move || {
+            scope::changed_packages(
+                &repo_root,
+                &graph,
+                &inner.from_ref,
+                &to_ref,
+                &inner.global_deps,
+                &inner.ignore_patterns,
+                shallow_clone_mode,
+            )
+        })
+        .await
+        .map_err(|e| tonic::Status::internal(e.to_string()))?
+        .map_err(|e| {
+            error!("failed to get changed packages: {:?}", e);
+            tonic::Status::internal(format!("failed to get changed packages: {e}"))
+        })?;
+
+        Ok(tonic::Response::new(proto::GetChangedPackagesResponse {
+            packages: changed.into_iter().collect(),
+        }))
+    }
 }
 
 impl<T: Watcher> NamedService for DaemonServer<T> {
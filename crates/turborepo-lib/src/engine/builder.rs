@@ -0,0 +1,634 @@
+use std::collections::{BTreeSet, HashSet, VecDeque};
+
+use thiserror::Error;
+
+use super::{Engine, TaskNode};
+use crate::{
+    package_graph::{PackageGraph, ROOT_NODE_NAME},
+    run::{
+        graph::CompleteGraph,
+        task_id::{get_package_task_from_id, get_task_id, is_package_task, ROOT_PKG_NAME},
+    },
+    task_graph::{BookkeepingTaskDefinition, Pipeline, TaskDefinition},
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Could not find the following tasks in project: {0}")]
+    MissingTasks(String),
+    #[error(
+        "{0} needs an entry in turbo.json before it can be depended on because it is a task run \
+         from the root package"
+    )]
+    MissingRootTaskInTurboJson(String),
+    #[error("Could not find workspace \"{workspace}\" from task \"{task_id}\" in project")]
+    MissingWorkspace { workspace: String, task_id: String },
+    #[error("Could not find \"{0}\" in root turbo.json")]
+    MissingTaskDefinition(String),
+    #[error("Could not find \"{task_id}\" in root turbo.json or \"{workspace}\" workspace")]
+    MissingWorkspaceTaskDefinition { task_id: String, workspace: String },
+    #[error("{0:#}")]
+    WorkspaceTurboJson(anyhow::Error),
+    #[error("Invalid task dependency graph:\n{0}")]
+    CyclicDependencies(String),
+    #[error("Invalid task dependency graph:\n{0} depends on itself")]
+    SelfDependency(String),
+    #[error("Cannot find task definition for {task_id} in package {package}")]
+    MissingPackageTask { task_id: String, package: String },
+    #[error("Cannot find package {0}")]
+    MissingPackage(String),
+    #[error(
+        "Invalid persistent task configuration:\n\"{persistent_task}\" is a persistent task, \
+         \"{dependant}\" cannot depend on it"
+    )]
+    PersistentDependency {
+        persistent_task: String,
+        dependant: String,
+    },
+    #[error(
+        "Invalid persistent task configuration:\nYou have {persistent_count} persistent tasks but \
+         `turbo` is configured for concurrency of {concurrency}. Set --concurrency to at least {}",
+        persistent_count + 1
+    )]
+    PersistentConcurrency {
+        persistent_count: u32,
+        concurrency: u32,
+    },
+}
+
+/// Builds the task graph for a set of workspaces and tasks, following the
+/// `dependsOn` configuration in turbo.json.
+pub struct EngineBuilder<'a> {
+    package_graph: &'a PackageGraph,
+    pipeline: &'a Pipeline,
+    complete_graph: Option<&'a CompleteGraph<'a>>,
+    is_single: bool,
+    workspaces: Vec<String>,
+    tasks: Vec<String>,
+    tasks_only: bool,
+    workspace_dependencies: bool,
+}
+
+impl<'a> EngineBuilder<'a> {
+    pub fn new(package_graph: &'a PackageGraph, pipeline: &'a Pipeline, is_single: bool) -> Self {
+        Self {
+            package_graph,
+            pipeline,
+            complete_graph: None,
+            is_single,
+            workspaces: Vec::new(),
+            tasks: Vec::new(),
+            tasks_only: false,
+            workspace_dependencies: true,
+        }
+    }
+
+    /// Merge task definitions from workspace turbo.json files, loaded through
+    /// `complete_graph`, onto the root pipeline.
+    pub fn with_workspace_turbo_jsons(mut self, complete_graph: &'a CompleteGraph<'a>) -> Self {
+        self.complete_graph = Some(complete_graph);
+        self
+    }
+
+    /// The workspaces in scope for this run.
+    pub fn with_workspaces(mut self, workspaces: Vec<String>) -> Self {
+        self.workspaces = workspaces;
+        self
+    }
+
+    /// The tasks requested on the command line.
+    pub fn with_tasks(mut self, tasks: Vec<String>) -> Self {
+        self.tasks = tasks;
+        self
+    }
+
+    /// Restrict dependencies to the requested tasks, as with `--only`.
+    pub fn with_tasks_only(mut self, tasks_only: bool) -> Self {
+        self.tasks_only = tasks_only;
+        self
+    }
+
+    /// Whether `^` dependencies follow the edges between workspaces.
+    /// `--parallel` turns these off so that only dependencies between tasks
+    /// of the same workspace are kept.
+    pub fn with_workspace_dependencies(mut self, workspace_dependencies: bool) -> Self {
+        self.workspace_dependencies = workspace_dependencies;
+        self
+    }
+
+    // Tasks from the root workspace only run if they're explicitly configured
+    // with `//#<task>`
+    fn root_enabled_tasks(&self) -> HashSet<String> {
+        self.pipeline
+            .keys()
+            .filter(|task_id| is_package_task(task_id))
+            .filter_map(|task_id| {
+                let (package, task) = get_package_task_from_id(task_id);
+                (package == ROOT_PKG_NAME).then_some(task)
+            })
+            .collect()
+    }
+
+    // The definition from the root pipeline, either for this task in this
+    // workspace or for the task in all workspaces, is overridden field by
+    // field by the definition from the workspace's own turbo.json.
+    fn get_task_definition(
+        &self,
+        task_id: &str,
+        task_name: &str,
+    ) -> Result<Option<TaskDefinition>, Error> {
+        let mut chain = Vec::new();
+        if let Some(definition) = self
+            .pipeline
+            .get(task_id)
+            .or_else(|| self.pipeline.get(task_name))
+        {
+            chain.push(definition.clone());
+        }
+
+        let (workspace, _) = get_package_task_from_id(task_id);
+        if let Some(complete_graph) = self.complete_graph {
+            // Root tasks can only be configured in the root turbo.json
+            if !self.is_single && workspace != ROOT_PKG_NAME && workspace != ROOT_NODE_NAME {
+                let turbo_json = complete_graph
+                    .workspace_turbo_json(&workspace)
+                    .map_err(Error::WorkspaceTurboJson)?;
+                if let Some(definition) = turbo_json
+                    .as_ref()
+                    .and_then(|turbo_json| turbo_json.pipeline.get(task_name))
+                {
+                    chain.push(definition.clone());
+                }
+            }
+        }
+
+        Ok((!chain.is_empty()).then(|| BookkeepingTaskDefinition::merge(&chain)))
+    }
+
+    pub fn build(self) -> Result<Engine, Error> {
+        let mut engine = Engine::new();
+
+        // If there are no affected packages, we don't need to go through all this
+        // work
+        if self.workspaces.is_empty() {
+            return Ok(engine);
+        }
+
+        let root_enabled_tasks = self.root_enabled_tasks();
+        let mut missing_tasks = self.tasks.iter().collect::<BTreeSet<_>>();
+        let mut traversal_queue = VecDeque::with_capacity(self.workspaces.len() * self.tasks.len());
+
+        // Get the entry points into the task graph by pairing every workspace
+        // with every requested task
+        for workspace in &self.workspaces {
+            for task_name in &self.tasks {
+                let task_id = get_task_id(workspace, task_name);
+                // Non-package tasks don't need to exist for every workspace, as long
+                // as some workspace defines it. Dependencies *are* required to
+                // have a definition.
+                if self.get_task_definition(&task_id, task_name)?.is_none() {
+                    continue;
+                }
+
+                missing_tasks.remove(task_name);
+
+                // Tasks from the root workspace are only entry points if they're
+                // enabled for the root
+                if workspace != ROOT_PKG_NAME || root_enabled_tasks.contains(task_name) {
+                    traversal_queue.push_back(task_id);
+                }
+            }
+        }
+
+        if !missing_tasks.is_empty() {
+            let missing_tasks = missing_tasks
+                .into_iter()
+                .map(|task| task.as_str())
+                .collect::<Vec<_>>();
+            return Err(Error::MissingTasks(missing_tasks.join(", ")));
+        }
+
+        let mut visited = HashSet::new();
+        while let Some(task_id) = traversal_queue.pop_front() {
+            let (workspace, task_name) = get_package_task_from_id(&task_id);
+
+            if workspace == ROOT_PKG_NAME && !root_enabled_tasks.contains(&task_name) {
+                return Err(Error::MissingRootTaskInTurboJson(task_id));
+            }
+
+            if workspace != ROOT_NODE_NAME
+                && self.package_graph.workspace_info(&workspace).is_none()
+            {
+                // If this happens, either building the workspace catalog went wrong, or
+                // turbo.json references a workspace that doesn't exist
+                return Err(Error::MissingWorkspace { workspace, task_id });
+            }
+
+            let task_definition =
+                self.get_task_definition(&task_id, &task_name)?
+                    .ok_or_else(|| {
+                        if self.is_single {
+                            Error::MissingTaskDefinition(task_id.clone())
+                        } else {
+                            Error::MissingWorkspaceTaskDefinition {
+                                task_id: task_id.clone(),
+                                workspace: workspace.clone(),
+                            }
+                        }
+                    })?;
+
+            if !visited.insert(task_id.clone()) {
+                continue;
+            }
+
+            let keep = |dependency: &String| !self.tasks_only || self.tasks.contains(dependency);
+            let topological_dependencies = task_definition
+                .topological_dependencies
+                .iter()
+                .filter(|dependency| keep(dependency))
+                .collect::<Vec<_>>();
+            let task_dependencies = task_definition
+                .task_dependencies
+                .iter()
+                .filter(|dependency| keep(dependency))
+                .collect::<Vec<_>>();
+
+            let dependency_workspaces = match self.package_graph.dependencies(&workspace) {
+                Some(dependencies) if self.workspace_dependencies => dependencies.collect(),
+                _ => Vec::new(),
+            };
+
+            let mut has_dependencies = false;
+
+            // Tasks from the workspace's dependencies, e.g. `^build`
+            for dependency in &topological_dependencies {
+                for dependency_workspace in &dependency_workspaces {
+                    let dependency_id = get_task_id(dependency_workspace, dependency);
+                    engine.connect(&task_id, &TaskNode::Task(dependency_id.clone()));
+                    traversal_queue.push_back(dependency_id);
+                    has_dependencies = true;
+                }
+            }
+
+            // Tasks from the same workspace, e.g. `prebuild`, or a specific
+            // workspace's task, e.g. `//#codegen`
+            for dependency in &task_dependencies {
+                let dependency_id = get_task_id(&workspace, dependency);
+                engine.connect(&task_id, &TaskNode::Task(dependency_id.clone()));
+                traversal_queue.push_back(dependency_id);
+                has_dependencies = true;
+            }
+
+            if !has_dependencies {
+                engine.connect(&task_id, &TaskNode::Root);
+            }
+
+            engine.task_definitions.insert(task_id, task_definition);
+        }
+
+        Self::validate(&engine)?;
+
+        Ok(engine)
+    }
+
+    fn validate(engine: &Engine) -> Result<(), Error> {
+        let cycles = petgraph::algo::tarjan_scc(&engine.task_graph)
+            .into_iter()
+            .filter(|component| component.len() > 1)
+            .map(|component| {
+                let mut names = component
+                    .into_iter()
+                    .map(|index| engine.task_graph[index].to_string())
+                    .collect::<Vec<_>>();
+                names.sort();
+                format!("\t{}", names.join(", "))
+            })
+            .collect::<Vec<_>>();
+        if !cycles.is_empty() {
+            return Err(Error::CyclicDependencies(cycles.join("\n")));
+        }
+
+        for edge in engine.task_graph.raw_edges() {
+            if edge.source() == edge.target() {
+                return Err(Error::SelfDependency(
+                    engine.task_graph[edge.source()].to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{assert_matches::assert_matches, collections::HashSet, fs};
+
+    use anyhow::Result;
+    use tempfile::TempDir;
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::*;
+    use crate::package_json::PackageJson;
+
+    // Creates an npm workspace where `a` depends on `b` and `b` depends on `c`
+    fn package_graph() -> Result<(TempDir, PackageGraph)> {
+        let dir = tempfile::tempdir()?;
+        let repo_root = AbsoluteSystemPathBuf::new(dir.path())?;
+        let root_package_json = r#"{
+            "name": "root",
+            "packageManager": "npm@8.19.4",
+            "workspaces": ["packages/*"],
+            "scripts": {"lint": "eslint"}
+        }"#;
+        fs::write(repo_root.join_component("package.json"), root_package_json)?;
+        for (name, dependency) in [("a", Some("b")), ("b", Some("c")), ("c", None)] {
+            let workspace_dir = repo_root.join_components(&["packages", name]);
+            workspace_dir.create_dir_all()?;
+            let dependencies = dependency
+                .map(|dependency| format!(r#""dependencies": {{"{dependency}": "*"}},"#))
+                .unwrap_or_default();
+            fs::write(
+                workspace_dir.join_component("package.json"),
+                format!(
+                    r#"{{"name": "{name}", {dependencies} "scripts": {{"build": "tsc", "dev": "tsc -w"}}}}"#
+                ),
+            )?;
+        }
+
+        let root_package_json: PackageJson = serde_json::from_str(root_package_json)?;
+        let package_graph =
+            PackageGraph::build_multi_package_graph(&repo_root, &root_package_json)?;
+        Ok((dir, package_graph))
+    }
+
+    fn pipeline(config: &str) -> Pipeline {
+        serde_json::from_str(config).unwrap()
+    }
+
+    fn dependencies(engine: &Engine, task_id: &str) -> HashSet<String> {
+        engine
+            .dependencies(task_id)
+            .unwrap()
+            .into_iter()
+            .map(|node| node.to_string())
+            .collect()
+    }
+
+    fn set(items: &[&str]) -> HashSet<String> {
+        items.iter().map(|item| item.to_string()).collect()
+    }
+
+    #[test]
+    fn test_topological_dependencies() -> Result<()> {
+        let (_dir, package_graph) = package_graph()?;
+        let pipeline = pipeline(
+            r#"{
+                "build": {"dependsOn": ["^build", "prebuild"]},
+                "prebuild": {},
+                "//#lint": {}
+            }"#,
+        );
+
+        let engine = EngineBuilder::new(&package_graph, &pipeline, false)
+            .with_workspaces(vec!["a".into(), "b".into(), "c".into()])
+            .with_tasks(vec!["build".into()])
+            .build()?;
+
+        assert_eq!(
+            engine.tasks().collect::<HashSet<_>>(),
+            HashSet::from([
+                "a#build",
+                "a#prebuild",
+                "b#build",
+                "b#prebuild",
+                "c#build",
+                "c#prebuild",
+            ])
+        );
+        assert_eq!(
+            dependencies(&engine, "a#build"),
+            set(&["b#build", "a#prebuild"])
+        );
+        assert_eq!(dependencies(&engine, "c#build"), set(&["c#prebuild"]));
+        assert_eq!(dependencies(&engine, "c#prebuild"), set(&[ROOT_NODE_NAME]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_without_workspace_dependencies() -> Result<()> {
+        let (_dir, package_graph) = package_graph()?;
+        let pipeline = pipeline(r#"{"build": {"dependsOn": ["^build"]}}"#);
+
+        let engine = EngineBuilder::new(&package_graph, &pipeline, false)
+            .with_workspaces(vec!["a".into(), "b".into()])
+            .with_tasks(vec!["build".into()])
+            .with_workspace_dependencies(false)
+            .build()?;
+
+        assert_eq!(engine.tasks().count(), 2);
+        assert_eq!(dependencies(&engine, "a#build"), set(&[ROOT_NODE_NAME]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_tasks_only() -> Result<()> {
+        let (_dir, package_graph) = package_graph()?;
+        let pipeline = pipeline(
+            r#"{
+                "build": {"dependsOn": ["^build", "prebuild"]},
+                "prebuild": {}
+            }"#,
+        );
+
+        let engine = EngineBuilder::new(&package_graph, &pipeline, false)
+            .with_workspaces(vec!["a".into()])
+            .with_tasks(vec!["build".into()])
+            .with_tasks_only(true)
+            .build()?;
+
+        assert_eq!(
+            engine.tasks().collect::<HashSet<_>>(),
+            HashSet::from(["a#build", "b#build", "c#build"])
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_root_tasks() -> Result<()> {
+        let (_dir, package_graph) = package_graph()?;
+        let pipeline = pipeline(
+            r#"{
+                "build": {"dependsOn": ["//#lint"]},
+                "//#lint": {},
+                "test": {"dependsOn": ["//#typecheck"]}
+            }"#,
+        );
+
+        let engine = EngineBuilder::new(&package_graph, &pipeline, false)
+            .with_workspaces(vec!["c".into(), ROOT_PKG_NAME.into()])
+            .with_tasks(vec!["build".into(), "lint".into()])
+            .build()?;
+        assert_eq!(dependencies(&engine, "c#build"), set(&["//#lint"]));
+        assert!(engine.task_definition("//#lint").is_some());
+
+        let result = EngineBuilder::new(&package_graph, &pipeline, false)
+            .with_workspaces(vec!["c".into()])
+            .with_tasks(vec!["test".into()])
+            .build();
+        assert_matches!(result, Err(Error::MissingRootTaskInTurboJson(task_id)) if task_id == "//#typecheck");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_tasks() -> Result<()> {
+        let (_dir, package_graph) = package_graph()?;
+        let pipeline = pipeline(r#"{"build": {}}"#);
+
+        let result = EngineBuilder::new(&package_graph, &pipeline, false)
+            .with_workspaces(vec!["a".into()])
+            .with_tasks(vec!["test".into(), "build".into(), "lint".into()])
+            .build();
+        assert_matches!(result, Err(Error::MissingTasks(tasks)) if tasks == "lint, test");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_cycles() -> Result<()> {
+        let (_dir, package_graph) = package_graph()?;
+        let pipeline = pipeline(
+            r#"{
+                "build": {"dependsOn": ["test"]},
+                "test": {"dependsOn": ["build"]}
+            }"#,
+        );
+
+        let result = EngineBuilder::new(&package_graph, &pipeline, false)
+            .with_workspaces(vec!["c".into()])
+            .with_tasks(vec!["build".into()])
+            .build();
+        assert_matches!(result, Err(Error::CyclicDependencies(cycles)) if cycles == "\tc#build, c#test");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_persistent_dependencies() -> Result<()> {
+        let (_dir, package_graph) = package_graph()?;
+        let pipeline = pipeline(
+            r#"{
+                "dev": {"dependsOn": ["^dev"], "persistent": true}
+            }"#,
+        );
+
+        let engine = EngineBuilder::new(&package_graph, &pipeline, false)
+            .with_workspaces(vec!["b".into()])
+            .with_tasks(vec!["dev".into()])
+            .build()?;
+        assert_matches!(
+            engine.validate_persistent_dependencies(&package_graph, 10),
+            Err(Error::PersistentDependency { persistent_task, dependant })
+                if persistent_task == "c#dev" && dependant == "b#dev"
+        );
+
+        let engine = EngineBuilder::new(&package_graph, &pipeline, false)
+            .with_workspaces(vec!["a".into(), "b".into()])
+            .with_tasks(vec!["dev".into()])
+            .with_workspace_dependencies(false)
+            .build()?;
+        assert_matches!(
+            engine.validate_persistent_dependencies(&package_graph, 10),
+            Ok(())
+        );
+        assert_matches!(
+            engine.validate_persistent_dependencies(&package_graph, 2),
+            Err(Error::PersistentConcurrency {
+                persistent_count: 2,
+                concurrency: 2
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_workspace_turbo_json() -> Result<()> {
+        let (dir, package_graph) = package_graph()?;
+        let repo_root = AbsoluteSystemPathBuf::new(dir.path())?;
+        let pipeline = pipeline(
+            r#"{
+                "build": {"dependsOn": ["^build"], "outputs": ["dist/**"]},
+                "test": {}
+            }"#,
+        );
+        fs::write(
+            repo_root.join_components(&["packages", "b", "turbo.json"]),
+            r#"{
+                "extends": ["//"],
+                "pipeline": {
+                    "build": {"outputs": ["lib/**"]},
+                    "codegen": {"cache": false}
+                }
+            }"#,
+        )?;
+
+        let complete_graph = CompleteGraph::new(&package_graph, &repo_root);
+        let engine = EngineBuilder::new(&package_graph, &pipeline, false)
+            .with_workspace_turbo_jsons(&complete_graph)
+            .with_workspaces(vec!["a".into()])
+            .with_tasks(vec!["build".into()])
+            .build()?;
+
+        // Only the fields set in the workspace turbo.json are overridden
+        let build = engine.task_definition("b#build").unwrap();
+        assert_eq!(build.outputs.inclusions, vec!["lib/**".to_string()]);
+        assert_eq!(build.topological_dependencies, vec!["build".to_string()]);
+        let build = engine.task_definition("a#build").unwrap();
+        assert_eq!(build.outputs.inclusions, vec!["dist/**".to_string()]);
+
+        // Tasks can be defined only in a workspace
+        let engine = EngineBuilder::new(&package_graph, &pipeline, false)
+            .with_workspace_turbo_jsons(&complete_graph)
+            .with_workspaces(vec!["a".into(), "b".into()])
+            .with_tasks(vec!["codegen".into()])
+            .build()?;
+        assert_eq!(engine.tasks().collect::<Vec<_>>(), vec!["b#codegen"]);
+        assert!(!engine.task_definition("b#codegen").unwrap().should_cache);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_workspace_turbo_json() -> Result<()> {
+        let (dir, package_graph) = package_graph()?;
+        let repo_root = AbsoluteSystemPathBuf::new(dir.path())?;
+        let pipeline = pipeline(r#"{"build": {}}"#);
+        fs::write(
+            repo_root.join_components(&["packages", "c", "turbo.json"]),
+            r#"{"extends": ["a"], "pipeline": {"c#build": {}}}"#,
+        )?;
+
+        let complete_graph = CompleteGraph::new(&package_graph, &repo_root);
+        let result = EngineBuilder::new(&package_graph, &pipeline, false)
+            .with_workspace_turbo_jsons(&complete_graph)
+            .with_workspaces(vec!["c".into()])
+            .with_tasks(vec!["build".into()])
+            .build();
+        let Err(Error::WorkspaceTurboJson(err)) = result else {
+            panic!("expected an invalid turbo.json error");
+        };
+        let message = err.to_string();
+        assert!(message.starts_with("Invalid turbo.json at "));
+        assert!(message.ends_with(
+            "\n - \"c#build\". Use \"build\" instead\n - You can only extend from the root \
+             workspace"
+        ));
+
+        Ok(())
+    }
+}
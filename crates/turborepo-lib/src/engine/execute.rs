@@ -0,0 +1,231 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    future::Future,
+};
+
+use futures::{stream::FuturesUnordered, StreamExt};
+use petgraph::{graph::NodeIndex, Direction};
+use thiserror::Error;
+
+use super::{Engine, TaskNode};
+
+/// Controls a single walk of the task graph.
+#[derive(Debug, Clone, Copy)]
+pub struct ExecutionOptions {
+    /// Run every task as soon as its dependencies are done, ignoring
+    /// `concurrency`
+    pub parallel: bool,
+    /// The maximum number of tasks that can run at once
+    pub concurrency: u32,
+}
+
+impl ExecutionOptions {
+    pub fn new(parallel: bool, concurrency: u32) -> Self {
+        Self {
+            parallel,
+            concurrency,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum VisitorError {
+    /// The task failed, but other tasks can keep running
+    #[error(transparent)]
+    Task(anyhow::Error),
+    /// The task failed and no further tasks should be started. Tasks that are
+    /// already running are allowed to finish.
+    #[error("Execution stopped due to error: {0}")]
+    StopExecution(anyhow::Error),
+}
+
+impl Engine {
+    /// Walks the task graph, calling `visitor` with each task ID once all of
+    /// its dependencies have been visited successfully. Returns the errors
+    /// from every failed task.
+    pub async fn execute<V, F>(&self, opts: ExecutionOptions, visitor: V) -> Vec<anyhow::Error>
+    where
+        V: Fn(String) -> F,
+        F: Future<Output = Result<(), VisitorError>>,
+    {
+        let mut remaining_dependencies = self
+            .task_graph
+            .node_indices()
+            .map(|index| {
+                let count = self
+                    .task_graph
+                    .neighbors_directed(index, Direction::Outgoing)
+                    .count();
+                (index, count)
+            })
+            .collect::<HashMap<_, _>>();
+        let mut ready = remaining_dependencies
+            .iter()
+            .filter(|(_, count)| **count == 0)
+            .map(|(index, _)| *index)
+            .collect::<VecDeque<_>>();
+
+        let mut running = FuturesUnordered::new();
+        let mut errors = Vec::new();
+        let mut stopped = false;
+
+        // Marks a node as done and queues up the dependents that were waiting on it
+        let mut complete = |index: NodeIndex, ready: &mut VecDeque<NodeIndex>| {
+            for dependent in self
+                .task_graph
+                .neighbors_directed(index, Direction::Incoming)
+            {
+                let count = remaining_dependencies
+                    .get_mut(&dependent)
+                    .expect("every node has a dependency count");
+                *count -= 1;
+                if *count == 0 {
+                    ready.push_back(dependent);
+                }
+            }
+        };
+
+        loop {
+            while !stopped && (opts.parallel || running.len() < opts.concurrency.max(1) as usize) {
+                let Some(index) = ready.pop_front() else {
+                    break;
+                };
+                match &self.task_graph[index] {
+                    TaskNode::Root => complete(index, &mut ready),
+                    TaskNode::Task(task_id) => {
+                        let visit = visitor(task_id.clone());
+                        running.push(async move { (index, visit.await) });
+                    }
+                }
+            }
+
+            let Some((index, result)) = running.next().await else {
+                break;
+            };
+
+            match result {
+                Ok(()) => complete(index, &mut ready),
+                // The dependents of a failed task are never marked ready, so
+                // only tasks that don't depend on it keep running
+                Err(VisitorError::Task(err)) => errors.push(err),
+                Err(VisitorError::StopExecution(err)) => {
+                    stopped = true;
+                    errors.push(err);
+                }
+            }
+        }
+
+        errors
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        cell::RefCell,
+        sync::atomic::{AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use anyhow::anyhow;
+
+    use super::*;
+
+    // a#build -> b#build -> ___ROOT___
+    //         -> c#build -> ___ROOT___
+    fn engine() -> Engine {
+        let mut engine = Engine::new();
+        engine.connect("a#build", &TaskNode::Task("b#build".into()));
+        engine.connect("a#build", &TaskNode::Task("c#build".into()));
+        engine.connect("b#build", &TaskNode::Root);
+        engine.connect("c#build", &TaskNode::Root);
+        engine
+    }
+
+    #[tokio::test]
+    async fn test_dependencies_run_first() {
+        let engine = engine();
+        let visited = RefCell::new(Vec::new());
+
+        let errors = engine
+            .execute(ExecutionOptions::new(false, 10), |task_id| {
+                visited.borrow_mut().push(task_id);
+                async { Ok(()) }
+            })
+            .await;
+
+        assert!(errors.is_empty());
+        let visited = visited.into_inner();
+        assert_eq!(visited.len(), 3);
+        assert_eq!(visited.last().unwrap(), "a#build");
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit() {
+        let engine = engine();
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+
+        let visitor = |_| async {
+            let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+            max_running.fetch_max(now_running, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            running.fetch_sub(1, Ordering::SeqCst);
+            Ok(())
+        };
+
+        engine
+            .execute(ExecutionOptions::new(false, 1), visitor)
+            .await;
+        assert_eq!(max_running.swap(0, Ordering::SeqCst), 1);
+
+        engine
+            .execute(ExecutionOptions::new(true, 1), visitor)
+            .await;
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_stop_execution() {
+        let engine = engine();
+        let visited = RefCell::new(Vec::new());
+
+        let errors = engine
+            .execute(ExecutionOptions::new(false, 1), |task_id| {
+                visited.borrow_mut().push(task_id.clone());
+                async move { Err(VisitorError::StopExecution(anyhow!("{task_id} failed"))) }
+            })
+            .await;
+
+        // Only the first task runs, nothing is started after it fails
+        assert_eq!(errors.len(), 1);
+        assert_eq!(visited.into_inner().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_continue_on_error() {
+        let engine = engine();
+        let visited = RefCell::new(Vec::new());
+
+        let errors = engine
+            .execute(ExecutionOptions::new(false, 1), |task_id| {
+                visited.borrow_mut().push(task_id.clone());
+                async move {
+                    if task_id == "b#build" {
+                        Err(VisitorError::Task(anyhow!("{task_id} failed")))
+                    } else {
+                        Ok(())
+                    }
+                }
+            })
+            .await;
+
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].to_string(), "b#build failed");
+        // c#build doesn't depend on b#build so it still runs, but a#build is
+        // skipped
+        let mut visited = visited.into_inner();
+        visited.sort();
+        assert_eq!(visited, vec!["b#build", "c#build"]);
+    }
+}
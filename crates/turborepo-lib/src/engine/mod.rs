@@ -0,0 +1,203 @@
+mod builder;
+mod execute;
+
+use std::collections::{HashMap, HashSet};
+
+pub use builder::{EngineBuilder, Error as BuilderError};
+pub use execute::{ExecutionOptions, VisitorError};
+use petgraph::{graph::NodeIndex, Direction};
+
+use crate::{
+    package_graph::PackageGraph,
+    run::{
+        graph_visualizer::GraphVisualizer,
+        task_id::{get_package_task_from_id, get_task_id, strip_package_name},
+    },
+    task_graph::TaskDefinition,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TaskNode {
+    Root,
+    Task(String),
+}
+
+impl std::fmt::Display for TaskNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TaskNode::Root => f.write_str(crate::package_graph::ROOT_NODE_NAME),
+            TaskNode::Task(task_id) => f.write_str(task_id),
+        }
+    }
+}
+
+/// The graph of tasks for a run. Edges point from a task to the tasks it
+/// depends on, tasks without any dependencies point at the root node.
+#[derive(Debug)]
+pub struct Engine {
+    task_graph: petgraph::Graph<TaskNode, ()>,
+    root_index: NodeIndex,
+    task_lookup: HashMap<String, NodeIndex>,
+    task_definitions: HashMap<String, TaskDefinition>,
+}
+
+impl Engine {
+    fn new() -> Self {
+        let mut task_graph = petgraph::Graph::default();
+        let root_index = task_graph.add_node(TaskNode::Root);
+        Self {
+            task_graph,
+            root_index,
+            task_lookup: HashMap::default(),
+            task_definitions: HashMap::default(),
+        }
+    }
+
+    fn get_index(&mut self, task_id: &str) -> NodeIndex {
+        if let Some(index) = self.task_lookup.get(task_id) {
+            return *index;
+        }
+        let index = self
+            .task_graph
+            .add_node(TaskNode::Task(task_id.to_string()));
+        self.task_lookup.insert(task_id.to_string(), index);
+        index
+    }
+
+    fn connect(&mut self, task_id: &str, dependency: &TaskNode) {
+        let from = self.get_index(task_id);
+        let to = match dependency {
+            TaskNode::Root => self.root_index,
+            TaskNode::Task(dependency_id) => self.get_index(dependency_id),
+        };
+        self.task_graph.update_edge(from, to, ());
+    }
+
+    /// Prepares the task graph for rendering with `--graph`. Single package
+    /// repos don't have packages, so the package names are dropped from the
+    /// task IDs.
+    pub fn visualizer(&self, single_package: bool) -> GraphVisualizer {
+        GraphVisualizer::new(&self.task_graph, self.root_index, |node| match node {
+            TaskNode::Task(task_id) if single_package => strip_package_name(task_id),
+            node => node.to_string(),
+        })
+    }
+
+    /// All task IDs in the graph, not including the root node.
+    pub fn tasks(&self) -> impl Iterator<Item = &str> {
+        self.task_lookup.keys().map(|task_id| task_id.as_str())
+    }
+
+    pub fn task_definition(&self, task_id: &str) -> Option<&TaskDefinition> {
+        self.task_definitions.get(task_id)
+    }
+
+    pub fn task_definitions(&self) -> &HashMap<String, TaskDefinition> {
+        &self.task_definitions
+    }
+
+    /// Returns the tasks that `task_id` directly depends on.
+    pub fn dependencies(&self, task_id: &str) -> Option<HashSet<&TaskNode>> {
+        self.neighbors(task_id, Direction::Outgoing)
+    }
+
+    /// Returns every task that `task_id` depends on, directly or
+    /// transitively.
+    pub fn transitive_dependencies(&self, task_id: &str) -> Option<HashSet<&TaskNode>> {
+        self.transitive_neighbors(task_id, Direction::Outgoing)
+    }
+
+    /// Returns every task that depends on `task_id`, directly or
+    /// transitively.
+    pub fn transitive_dependents(&self, task_id: &str) -> Option<HashSet<&TaskNode>> {
+        self.transitive_neighbors(task_id, Direction::Incoming)
+    }
+
+    fn transitive_neighbors(
+        &self,
+        task_id: &str,
+        direction: Direction,
+    ) -> Option<HashSet<&TaskNode>> {
+        let index = self.task_lookup.get(task_id)?;
+        let mut visited = HashSet::new();
+        let mut stack = vec![*index];
+        while let Some(index) = stack.pop() {
+            for neighbor in self.task_graph.neighbors_directed(index, direction) {
+                if visited.insert(neighbor) {
+                    stack.push(neighbor);
+                }
+            }
+        }
+        Some(
+            visited
+                .into_iter()
+                .map(|index| &self.task_graph[index])
+                .collect(),
+        )
+    }
+
+    fn neighbors(&self, task_id: &str, direction: Direction) -> Option<HashSet<&TaskNode>> {
+        let index = self.task_lookup.get(task_id)?;
+        Some(
+            self.task_graph
+                .neighbors_directed(*index, direction)
+                .map(|index| &self.task_graph[index])
+                .collect(),
+        )
+    }
+
+    /// Checks that no task depends on a persistent task that is actually
+    /// implemented, and that there's enough concurrency to run every
+    /// persistent task at once.
+    pub fn validate_persistent_dependencies(
+        &self,
+        package_graph: &PackageGraph,
+        concurrency: u32,
+    ) -> Result<(), BuilderError> {
+        let mut persistent_count = 0;
+        for (task_id, index) in &self.task_lookup {
+            if self
+                .task_definitions
+                .get(task_id)
+                .map_or(false, |definition| definition.persistent)
+            {
+                persistent_count += 1;
+            }
+
+            for dependency in self.task_graph.neighbors(*index) {
+                let TaskNode::Task(dependency_id) = &self.task_graph[dependency] else {
+                    continue;
+                };
+                let (package_name, task_name) = get_package_task_from_id(dependency_id);
+                let dependency_definition =
+                    self.task_definitions.get(dependency_id).ok_or_else(|| {
+                        BuilderError::MissingPackageTask {
+                            task_id: dependency_id.clone(),
+                            package: package_name.clone(),
+                        }
+                    })?;
+                let workspace = package_graph
+                    .workspace_info(&package_name)
+                    .ok_or_else(|| BuilderError::MissingPackage(package_name.clone()))?;
+                let has_script = workspace.package_json.scripts.contains_key(&task_name);
+
+                if dependency_definition.persistent && has_script {
+                    let (current_package, current_task) = get_package_task_from_id(task_id);
+                    return Err(BuilderError::PersistentDependency {
+                        persistent_task: get_task_id(package_name, &task_name),
+                        dependant: get_task_id(current_package, &current_task),
+                    });
+                }
+            }
+        }
+
+        if persistent_count >= concurrency {
+            return Err(BuilderError::PersistentConcurrency {
+                persistent_count,
+                concurrency,
+            });
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,178 @@
+use crate::package_graph::WorkspaceInfo;
+
+/// A framework that we can detect from a workspace's dependencies. Env vars
+/// matching `env_wildcards` are automatically included in task hashes for
+/// workspaces using the framework.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Framework {
+    pub slug: &'static str,
+    pub env_wildcards: &'static [&'static str],
+    dependency_match: Matcher,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Strategy {
+    All,
+    Some,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Matcher {
+    strategy: Strategy,
+    dependencies: &'static [&'static str],
+}
+
+impl Matcher {
+    fn test(&self, workspace: &WorkspaceInfo) -> bool {
+        let deps = &workspace.unresolved_external_dependencies;
+        match self.strategy {
+            Strategy::All => self
+                .dependencies
+                .iter()
+                .all(|dependency| deps.contains_key(*dependency)),
+            Strategy::Some => self
+                .dependencies
+                .iter()
+                .any(|dependency| deps.contains_key(*dependency)),
+        }
+    }
+}
+
+// The order matters, the first framework that matches is used
+static FRAMEWORKS: [Framework; 12] = [
+    Framework {
+        slug: "blitzjs",
+        env_wildcards: &["NEXT_PUBLIC_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["blitz"],
+        },
+    },
+    Framework {
+        slug: "nextjs",
+        env_wildcards: &["NEXT_PUBLIC_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["next"],
+        },
+    },
+    Framework {
+        slug: "gatsby",
+        env_wildcards: &["GATSBY_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["gatsby"],
+        },
+    },
+    Framework {
+        slug: "astro",
+        env_wildcards: &["PUBLIC_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["astro"],
+        },
+    },
+    Framework {
+        slug: "solidstart",
+        env_wildcards: &["VITE_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["solid-js", "solid-start"],
+        },
+    },
+    Framework {
+        slug: "vue",
+        env_wildcards: &["VUE_APP_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["@vue/cli-service"],
+        },
+    },
+    Framework {
+        slug: "sveltekit",
+        env_wildcards: &["VITE_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["@sveltejs/kit"],
+        },
+    },
+    Framework {
+        slug: "create-react-app",
+        env_wildcards: &["REACT_APP_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::Some,
+            dependencies: &["react-scripts", "react-dev-utils"],
+        },
+    },
+    Framework {
+        slug: "nuxtjs",
+        env_wildcards: &["NUXT_ENV_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::Some,
+            dependencies: &["nuxt", "nuxt-edge", "nuxt3", "nuxt3-edge"],
+        },
+    },
+    Framework {
+        slug: "redwoodjs",
+        env_wildcards: &["REDWOOD_ENV_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["@redwoodjs/core"],
+        },
+    },
+    Framework {
+        slug: "vite",
+        env_wildcards: &["VITE_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["vite"],
+        },
+    },
+    Framework {
+        slug: "sanity",
+        env_wildcards: &["SANITY_STUDIO_*"],
+        dependency_match: Matcher {
+            strategy: Strategy::All,
+            dependencies: &["@sanity/cli"],
+        },
+    },
+];
+
+/// Returns the first framework whose dependencies are used by `workspace`.
+pub fn infer_framework(workspace: &WorkspaceInfo) -> Option<&'static Framework> {
+    FRAMEWORKS
+        .iter()
+        .find(|framework| framework.dependency_match.test(workspace))
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::infer_framework;
+    use crate::package_graph::WorkspaceInfo;
+
+    fn workspace(dependencies: &[&str]) -> WorkspaceInfo {
+        WorkspaceInfo {
+            package_json: Default::default(),
+            package_json_path: turbopath::AnchoredSystemPathBuf::from_raw("package.json").unwrap(),
+            internal_dependencies: Default::default(),
+            unresolved_external_dependencies: dependencies
+                .iter()
+                .map(|dependency| (dependency.to_string(), "*".to_string()))
+                .collect(),
+            transitive_dependencies: None,
+        }
+    }
+
+    #[test_case(&[], None ; "no dependencies")]
+    #[test_case(&["blitz", "next"], Some("blitzjs") ; "first match wins")]
+    #[test_case(&["next"], Some("nextjs") ; "single dependency")]
+    #[test_case(&["solid-js"], None ; "partial all match")]
+    #[test_case(&["solid-js", "solid-start"], Some("solidstart") ; "full all match")]
+    #[test_case(&["react-dev-utils"], Some("create-react-app") ; "some match")]
+    fn test_infer_framework(dependencies: &[&str], expected: Option<&str>) {
+        let framework = infer_framework(&workspace(dependencies));
+        assert_eq!(framework.map(|framework| framework.slug), expected);
+    }
+}
@@ -1,7 +1,10 @@
 use std::{
     collections::{hash_map::Entry, HashMap, HashSet},
     path::{Path, PathBuf},
-    sync::{Arc, Mutex, MutexGuard},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
     time::Duration,
 };
 
@@ -9,11 +12,17 @@ use futures::{stream::iter, StreamExt};
 use globwatch::{ConfigError, GlobWatcher, StopToken, WatchConfig, Watcher};
 use itertools::Itertools;
 use notify::{EventKind, RecommendedWatcher};
+use thiserror::Error;
 use tokio::time::timeout;
 use tracing::{trace, warn};
-use turbopath::AbsoluteSystemPathBuf;
+use turbopath::{AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+use turborepo_scm::package_deps::{get_package_file_hashes, GitHashes};
 use wax::{Glob as WaxGlob, Pattern};
 
+use self::package_hashes::PackageHashes;
+
+mod package_hashes;
+
 // these aliases are for readability, but they're just strings. it may make
 // sense to use a newtype wrapper for these types in the future.
 type Glob = Arc<String>;
@@ -22,6 +31,18 @@ type Hash = Arc<String>;
 /// timeout for flushing the watcher
 const FLUSH_TIMEOUT: Duration = Duration::from_millis(500);
 
+/// files that determine the workspaces in the repo and their dependencies
+// only the rust daemon watches files, which isn't built alongside the go one
+#[cfg_attr(feature = "go-daemon", allow(dead_code))]
+const WORKSPACE_FILES: &[&str] = &[
+    "package.json",
+    "pnpm-workspace.yaml",
+    "package-lock.json",
+    "pnpm-lock.yaml",
+    "yarn.lock",
+    "bun.lockb",
+];
+
 /// Tracks changes for a given hash. A hash is a unique identifier for a set of
 /// files. Given a hash and a set of globs to track, this will watch for file
 /// changes and allow the user to query for changes. Once all globs for a
@@ -36,6 +57,12 @@ pub struct HashGlobWatcher<T: Watcher> {
     /// maps a glob to the hashes for which this glob hasn't changed
     glob_statuses: Arc<Mutex<HashMap<Glob, HashSet<Hash>>>>,
 
+    /// input file hashes for packages whose files haven't changed
+    package_hashes: Arc<Mutex<PackageHashes>>,
+
+    /// incremented whenever one of the `WORKSPACE_FILES` changes
+    workspace_generation: Arc<AtomicU64>,
+
     #[allow(dead_code)]
     watcher: Arc<Mutex<Option<GlobWatcher>>>,
     config: WatchConfig<T>,
@@ -58,6 +85,8 @@ impl HashGlobWatcher<RecommendedWatcher> {
             relative_to: relative_to.as_path().canonicalize()?,
             hash_globs: Default::default(),
             glob_statuses: Default::default(),
+            package_hashes: Default::default(),
+            workspace_generation: Default::default(),
             watcher: Arc::new(Mutex::new(Some(watcher))),
             config,
         })
@@ -108,6 +137,15 @@ impl<T: Watcher> HashGlobWatcher<T> {
                 .iter()
                 .filter_map(|path| path.strip_prefix(&self.relative_to).ok());
 
+            self.package_hashes
+                .lock()
+                .expect("only fails if poisoned")
+                .invalidate(repo_relative_paths.clone());
+
+            if repo_relative_paths.clone().any(is_workspace_file) {
+                self.workspace_generation.fetch_add(1, Ordering::SeqCst);
+            }
+
             // put these in a block so we can drop the locks before we await
             let globs_to_exclude = {
                 let glob_statuses = self.glob_statuses.lock().expect("only fails if poisoned");
@@ -123,6 +161,17 @@ impl<T: Watcher> HashGlobWatcher<T> {
                 globs_to_exclude
             };
 
+            // unwatching a glob can unwatch the paths that package hashes and
+            // workspace files are watched through, so forget everything that
+            // relies on them
+            if !globs_to_exclude.is_empty() {
+                self.package_hashes
+                    .lock()
+                    .expect("only fails if poisoned")
+                    .invalidate_all();
+                self.workspace_generation.fetch_add(1, Ordering::SeqCst);
+            }
+
             for glob in globs_to_exclude {
                 self.config.exclude(&self.relative_to, &glob).await;
             }
@@ -259,6 +308,119 @@ impl<T: Watcher> HashGlobWatcher<T> {
             None => candidates,
         })
     }
+
+    /// returns the hashes of the files in the package at `package_path`
+    /// matching `inputs`. the package is watched from the first request, and
+    /// its files are only hashed again once one of them changes.
+    pub async fn package_file_hashes(
+        &self,
+        package_path: AnchoredSystemPathBuf,
+        inputs: Vec<String>,
+    ) -> Result<GitHashes, PackageHashError> {
+        // make sure that we've seen every write made before this request.
+        // if we haven't, the stored hashes may be stale so we hash again
+        let flushed = match timeout(FLUSH_TIMEOUT, self.config.flush()).await {
+            Ok(Ok(())) => true,
+            Ok(Err(e)) => return Err(PackageHashError::Watch(e)),
+            Err(_) => {
+                trace!("timed out waiting for flush, not using stored hashes");
+                false
+            }
+        };
+
+        let generation = {
+            let mut package_hashes = self.package_hashes.lock().expect("only fails if poisoned");
+            if flushed {
+                if let Some(hashes) = package_hashes.get(&package_path, &inputs) {
+                    return Ok(hashes);
+                }
+            }
+            package_hashes.generation(&package_path)
+        };
+
+        // watch before hashing, so that anything written while we hash
+        // invalidates the result
+        let glob = match package_path.to_unix()?.as_str()? {
+            "" => "**".to_string(),
+            path => format!("{path}/**"),
+        };
+        self.config
+            .include(&self.relative_to, &glob)
+            .await
+            .map_err(PackageHashError::Watch)?;
+
+        let repo_root = AbsoluteSystemPathBuf::new(self.relative_to.clone())?;
+        let hashes = {
+            let package_path = package_path.clone();
+            let inputs = inputs.clone();
+            tokio::task::spawn_blocking(move || {
+                get_package_file_hashes(&repo_root, &package_path, &inputs)
+            })
+            .await??
+        };
+
+        let stored = self
+            .package_hashes
+            .lock()
+            .expect("only fails if poisoned")
+            .insert(&package_path, inputs, generation, hashes.clone());
+        if !stored {
+            trace!("{:?} changed while hashing", package_path);
+        }
+
+        Ok(hashes)
+    }
+
+    /// returns a counter that changes whenever a file that affects the
+    /// package graph changes, once the watcher has caught up with any
+    /// pending writes. returns `None` if the watcher couldn't catch up, in
+    /// which case the counter may not reflect the latest writes.
+    pub async fn workspace_generation(&self) -> Result<Option<u64>, ConfigError> {
+        match timeout(FLUSH_TIMEOUT, self.config.flush()).await {
+            Ok(Ok(())) => Ok(Some(self.workspace_generation.load(Ordering::SeqCst))),
+            Ok(Err(e)) => Err(e),
+            Err(_) => {
+                trace!("timed out waiting for flush");
+                Ok(None)
+            }
+        }
+    }
+
+    /// watches the repo root and the workspace directories matching
+    /// `workspace_globs`, so that changes to the workspace files in them
+    /// update the `workspace_generation`.
+    pub async fn watch_workspaces(
+        &self,
+        workspace_globs: impl Iterator<Item = &str>,
+    ) -> Result<(), ConfigError> {
+        self.config.include_path(&self.relative_to).await?;
+        for glob in workspace_globs {
+            // a missing workspace shouldn't stop us from watching the rest
+            if let Err(e) = self.config.include(&self.relative_to, glob).await {
+                trace!("unable to watch {}: {:?}", glob, e);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "go-daemon", allow(dead_code))]
+fn is_workspace_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .map_or(false, |name| WORKSPACE_FILES.contains(&name))
+}
+
+#[derive(Debug, Error)]
+pub enum PackageHashError {
+    #[error("unable to watch package: {0:?}")]
+    Watch(ConfigError),
+    #[error(transparent)]
+    Scm(#[from] turborepo_scm::Error),
+    #[error(transparent)]
+    Path(#[from] turbopath::PathError),
+    #[error("hashing task failed: {0}")]
+    Join(#[from] tokio::task::JoinError),
 }
 
 /// iterate each path-glob pair and stop tracking globs whose files have
@@ -360,7 +522,7 @@ mod test {
 
     use globwatch::StopSource;
     use tokio::time::timeout;
-    use turbopath::AbsoluteSystemPathBuf;
+    use turbopath::{AbsoluteSystemPathBuf, AnchoredSystemPathBuf, RelativeUnixPathBuf};
 
     fn setup() -> tempdir::TempDir {
         let tmp = tempdir::TempDir::new("globwatch").unwrap();
@@ -723,4 +885,93 @@ mod test {
             _ => (),
         }
     }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn package_file_hashes() {
+        let dir = setup();
+        let flush = tempdir::TempDir::new("globwatch-flush").unwrap();
+        let watcher = Arc::new(
+            super::HashGlobWatcher::new(
+                AbsoluteSystemPathBuf::new(dir.path()).unwrap(),
+                flush.path().to_path_buf(),
+            )
+            .unwrap(),
+        );
+
+        let stop = StopSource::new();
+
+        let task_watcher = watcher.clone();
+        let token = stop.token();
+
+        // dropped when the test ends
+        let _s = tokio::task::spawn(async move { task_watcher.watch(token).await });
+
+        let package = AnchoredSystemPathBuf::from_raw("my-pkg").unwrap();
+        let irrelevant = RelativeUnixPathBuf::new("irrelevant").unwrap();
+
+        let hashes = watcher
+            .package_file_hashes(package.clone(), vec![])
+            .await
+            .unwrap();
+        assert_eq!(hashes.len(), 4);
+        assert!(
+            watcher
+                .package_hashes
+                .lock()
+                .unwrap()
+                .get(&package, &[])
+                .is_some(),
+            "hashes should be kept until the package changes"
+        );
+
+        // a change in the package invalidates its hashes
+        std::fs::write(dir.path().join("my-pkg/irrelevant"), "changed").unwrap();
+        let changed = watcher
+            .package_file_hashes(package.clone(), vec![])
+            .await
+            .unwrap();
+        assert_ne!(hashes.get(&irrelevant), changed.get(&irrelevant));
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn workspace_generation() {
+        let dir = setup();
+        let flush = tempdir::TempDir::new("globwatch-flush").unwrap();
+        let watcher = Arc::new(
+            super::HashGlobWatcher::new(
+                AbsoluteSystemPathBuf::new(dir.path()).unwrap(),
+                flush.path().to_path_buf(),
+            )
+            .unwrap(),
+        );
+
+        let stop = StopSource::new();
+
+        let task_watcher = watcher.clone();
+        let token = stop.token();
+
+        // dropped when the test ends
+        let _s = tokio::task::spawn(async move { task_watcher.watch(token).await });
+
+        watcher
+            .watch_workspaces(["my-pkg"].into_iter())
+            .await
+            .unwrap();
+        let generation = watcher.workspace_generation().await.unwrap().unwrap();
+
+        // other files don't affect the package graph
+        std::fs::write(dir.path().join("my-pkg/irrelevant"), "changed").unwrap();
+        assert_eq!(
+            watcher.workspace_generation().await.unwrap().unwrap(),
+            generation
+        );
+
+        std::fs::write(dir.path().join("my-pkg/package.json"), "{}").unwrap();
+        assert_ne!(
+            watcher.workspace_generation().await.unwrap().unwrap(),
+            generation
+        );
+    }
 }
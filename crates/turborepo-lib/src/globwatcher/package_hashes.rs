@@ -0,0 +1,135 @@
+use std::{collections::HashMap, path::Path};
+
+use turbopath::AnchoredSystemPathBuf;
+use turborepo_scm::package_deps::GitHashes;
+
+/// File hashes for packages, keyed by package path and then by the inputs
+/// the hashes were calculated for. Entries are dropped as soon as any file in
+/// their package changes.
+///
+/// Each package has a generation, which is bumped whenever the package is
+/// invalidated. Hashes are only stored if the generation hasn't changed since
+/// hashing started, so a write that happens while hashing can't leave stale
+/// hashes behind.
+#[derive(Debug, Default)]
+pub struct PackageHashes {
+    packages: HashMap<AnchoredSystemPathBuf, PackageEntry>,
+}
+
+#[derive(Debug, Default)]
+struct PackageEntry {
+    generation: u64,
+    hashes: HashMap<Vec<String>, GitHashes>,
+}
+
+impl PackageHashes {
+    pub fn get(
+        &self,
+        package_path: &AnchoredSystemPathBuf,
+        inputs: &[String],
+    ) -> Option<GitHashes> {
+        self.packages.get(package_path)?.hashes.get(inputs).cloned()
+    }
+
+    pub fn generation(&mut self, package_path: &AnchoredSystemPathBuf) -> u64 {
+        self.packages
+            .entry(package_path.clone())
+            .or_default()
+            .generation
+    }
+
+    /// Stores `hashes`, unless the package was invalidated after
+    /// `generation` was read.
+    pub fn insert(
+        &mut self,
+        package_path: &AnchoredSystemPathBuf,
+        inputs: Vec<String>,
+        generation: u64,
+        hashes: GitHashes,
+    ) -> bool {
+        let entry = self.packages.entry(package_path.clone()).or_default();
+        if entry.generation != generation {
+            return false;
+        }
+        entry.hashes.insert(inputs, hashes);
+        true
+    }
+
+    /// Invalidates every package containing one of the repo relative `paths`.
+    /// A change to a .gitignore can affect which files are hashed anywhere
+    /// below it, so that invalidates everything.
+    // only the rust daemon watches files, which isn't built alongside the go one
+    #[cfg_attr(feature = "go-daemon", allow(dead_code))]
+    pub fn invalidate<'a>(&mut self, paths: impl Iterator<Item = &'a Path>) {
+        for path in paths {
+            if path.file_name().map_or(false, |name| name == ".gitignore") {
+                self.invalidate_all();
+                return;
+            }
+            for (package_path, entry) in self.packages.iter_mut() {
+                // package paths are only ever created from valid utf8
+                if package_path
+                    .to_str()
+                    .map_or(true, |package_path| path.starts_with(package_path))
+                {
+                    entry.invalidate();
+                }
+            }
+        }
+    }
+
+    #[cfg_attr(feature = "go-daemon", allow(dead_code))]
+    pub fn invalidate_all(&mut self) {
+        for entry in self.packages.values_mut() {
+            entry.invalidate();
+        }
+    }
+}
+
+impl PackageEntry {
+    fn invalidate(&mut self) {
+        self.generation += 1;
+        self.hashes.clear();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use turbopath::{AnchoredSystemPathBuf, RelativeUnixPathBuf};
+    use turborepo_scm::package_deps::GitHashes;
+
+    use super::PackageHashes;
+
+    #[test]
+    fn test_invalidation() {
+        let a = AnchoredSystemPathBuf::from_raw("packages/a").unwrap();
+        let b = AnchoredSystemPathBuf::from_raw("packages/b").unwrap();
+        let hashes = GitHashes::from([(
+            RelativeUnixPathBuf::new("index.js").unwrap(),
+            "abc".to_string(),
+        )]);
+
+        let mut package_hashes = PackageHashes::default();
+        for package in [&a, &b] {
+            let generation = package_hashes.generation(package);
+            assert!(package_hashes.insert(package, vec![], generation, hashes.clone()));
+        }
+
+        package_hashes.invalidate([Path::new("packages/a/src/index.js")].into_iter());
+        assert_eq!(package_hashes.get(&a, &[]), None);
+        assert_eq!(package_hashes.get(&b, &[]), Some(hashes.clone()));
+
+        // hashes calculated before an invalidation are discarded
+        let generation = package_hashes.generation(&b);
+        package_hashes.invalidate([Path::new("packages/b/index.js")].into_iter());
+        assert!(!package_hashes.insert(&b, vec![], generation, hashes.clone()));
+        assert_eq!(package_hashes.get(&b, &[]), None);
+
+        let generation = package_hashes.generation(&b);
+        assert!(package_hashes.insert(&b, vec![], generation, hashes));
+        package_hashes.invalidate([Path::new(".gitignore")].into_iter());
+        assert_eq!(package_hashes.get(&b, &[]), None);
+    }
+}
@@ -1,11 +1,427 @@
-// Manager is a wrapper around child processes executed by turbo
-#[derive(Debug)]
+use std::{
+    io,
+    process::{Command, ExitStatus},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use shared_child::SharedChild;
+use thiserror::Error;
+use tracing::debug;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("process manager is already closing")]
+    Closing,
+    #[error("command {command} exited ({exit_code})")]
+    ChildExit { exit_code: i32, command: String },
+    #[error(transparent)]
+    Io(#[from] io::Error),
+}
+
+// Matches the kill timeout Go uses for child processes
+pub(crate) const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(10);
+const SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The signals turbo passes on to its children when it's asked to stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Interrupt,
+    Terminate,
+}
+
+// Manager is a wrapper around child processes executed by turbo. Every child
+// leads its own process group, so that stopping a child also stops everything
+// it started.
+#[derive(Debug, Clone)]
 pub struct Manager {
-    // TODO
+    state: Arc<Mutex<ManagerState>>,
+    grace_period: Duration,
+}
+
+#[derive(Debug, Default)]
+struct ManagerState {
+    is_closing: bool,
+    children: Vec<Arc<SharedChild>>,
+    // Process groups that are still running after their leader exited
+    orphaned_groups: Vec<u32>,
+    shutdown: Option<JoinHandle<()>>,
+}
+
+/// A child process spawned by the `Manager`. It's tracked by the manager
+/// until it has been waited on.
+#[derive(Debug)]
+pub struct Child {
+    child: Arc<SharedChild>,
+    command: String,
+    manager: Manager,
+}
+
+impl Default for Manager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Manager {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            state: Arc::default(),
+            grace_period: DEFAULT_GRACE_PERIOD,
+        }
+    }
+
+    /// Sets how long stopped children get to exit before they're killed.
+    pub fn with_grace_period(mut self, grace_period: Duration) -> Self {
+        self.grace_period = grace_period;
+        self
+    }
+
+    /// Spawns `command`, unless the manager is shutting down.
+    pub fn spawn(&self, mut command: Command) -> Result<Child, Error> {
+        let mut state = self.state.lock().expect("manager lock poisoned");
+        if state.is_closing {
+            return Err(Error::Closing);
+        }
+
+        let description = label(&command);
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+        let child = Arc::new(SharedChild::spawn(&mut command)?);
+        state.children.push(child.clone());
+
+        Ok(Child {
+            child,
+            command: description,
+            manager: self.clone(),
+        })
+    }
+
+    /// Stops all running children and prevents any new ones from being
+    /// spawned.
+    pub fn stop(&self) {
+        self.stop_with_signal(Signal::Interrupt);
+    }
+
+    /// Sends `signal` to the process group of every child, and prevents any
+    /// new children from being spawned. Process groups that are still running
+    /// once the grace period is up are killed.
+    pub fn stop_with_signal(&self, signal: Signal) {
+        let mut state = self.state.lock().expect("manager lock poisoned");
+        state.is_closing = true;
+        for child in &state.children {
+            debug!("stopping child process {} with {:?}", child.id(), signal);
+            // on windows, we can't send signals so just kill
+            #[cfg(windows)]
+            child.kill().ok();
+
+            #[cfg(unix)]
+            signal_group(child.id(), signal.as_raw());
+        }
+        #[cfg(unix)]
+        for group in &state.orphaned_groups {
+            debug!(
+                "stopping orphaned process group {} with {:?}",
+                group, signal
+            );
+            signal_group(*group, signal.as_raw());
+        }
+
+        if state.shutdown.is_none() {
+            let manager = self.clone();
+            state.shutdown = Some(thread::spawn(move || manager.kill_after_grace_period()));
+        }
+    }
+
+    /// Waits until everything stopped by `stop` has exited. Returns
+    /// immediately if the manager hasn't been stopped.
+    pub async fn wait_for_shutdown(&self) {
+        let shutdown = self
+            .state
+            .lock()
+            .expect("manager lock poisoned")
+            .shutdown
+            .take();
+        if let Some(shutdown) = shutdown {
+            tokio::task::spawn_blocking(move || shutdown.join())
+                .await
+                .ok();
+        }
+    }
+
+    // Runs on its own thread once the manager is stopped, so that children
+    // are killed even if nothing is waiting on them
+    fn kill_after_grace_period(&self) {
+        let deadline = Instant::now() + self.grace_period;
+        loop {
+            let mut state = self.state.lock().expect("manager lock poisoned");
+            #[cfg(unix)]
+            state.orphaned_groups.retain(|group| group_exists(*group));
+            if state.children.is_empty() && state.orphaned_groups.is_empty() {
+                return;
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                for child in &state.children {
+                    debug!("killing child process {}", child.id());
+                    #[cfg(windows)]
+                    child.kill().ok();
+
+                    #[cfg(unix)]
+                    signal_group(child.id(), libc::SIGKILL);
+                }
+                #[cfg(unix)]
+                for group in state.orphaned_groups.drain(..) {
+                    debug!("killing orphaned process group {}", group);
+                    signal_group(group, libc::SIGKILL);
+                }
+                return;
+            }
+
+            drop(state);
+            thread::sleep(SHUTDOWN_POLL_INTERVAL.min(deadline - now));
+        }
+    }
+
+    fn remove(&self, child: &Arc<SharedChild>) {
+        let mut state = self.state.lock().expect("manager lock poisoned");
+        state
+            .children
+            .retain(|tracked| !Arc::ptr_eq(tracked, child));
+
+        // Processes started by the child can outlive it. Keep track of them so
+        // that they're stopped along with everything else.
+        #[cfg(unix)]
+        {
+            state.orphaned_groups.retain(|group| group_exists(*group));
+            if group_exists(child.id()) {
+                debug!("process group {} outlived its leader", child.id());
+                state.orphaned_groups.push(child.id());
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Signal {
+    fn as_raw(self) -> libc::c_int {
+        match self {
+            Signal::Interrupt => libc::SIGINT,
+            Signal::Terminate => libc::SIGTERM,
+        }
+    }
+}
+
+/// Waits until turbo is asked to stop, returning the signal it received.
+#[cfg(unix)]
+pub async fn wait_for_signal() -> Option<Signal> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate()).ok()?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result.ok().map(|()| Signal::Interrupt),
+        result = terminate.recv() => result.map(|()| Signal::Terminate),
+    }
+}
+
+/// Waits until turbo is asked to stop, returning the signal it received.
+#[cfg(windows)]
+pub async fn wait_for_signal() -> Option<Signal> {
+    tokio::signal::ctrl_c()
+        .await
+        .ok()
+        .map(|()| Signal::Interrupt)
+}
+
+// Sends `signal` to every process in the group led by `pgid`, returning
+// whether there was anything to signal
+#[cfg(unix)]
+fn signal_group(pgid: u32, signal: libc::c_int) -> bool {
+    // A negative pid refers to the whole process group
+    // SAFETY: libc::kill has no memory safety requirements
+    unsafe { libc::kill(-(pgid as i32), signal) == 0 }
+}
+
+#[cfg(unix)]
+fn group_exists(pgid: u32) -> bool {
+    // Signal 0 only checks whether the group can be signalled
+    signal_group(pgid, 0)
+}
+
+// Matches the label Go uses for child processes: `(<dir>) <program> <args>`
+fn label(command: &Command) -> String {
+    let dir = command
+        .get_current_dir()
+        .map(|dir| dir.display().to_string())
+        .unwrap_or_default();
+    let args = std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| arg.to_string_lossy())
+        .collect::<Vec<_>>();
+    format!("({}) {}", dir, args.join(" "))
+}
+
+impl Child {
+    pub fn take_stdout(&self) -> Option<std::process::ChildStdout> {
+        self.child.take_stdout()
+    }
+
+    pub fn take_stderr(&self) -> Option<std::process::ChildStderr> {
+        self.child.take_stderr()
+    }
+
+    /// Waits for the child to exit, returning an error if it exited
+    /// unsuccessfully.
+    pub async fn wait(self) -> Result<(), Error> {
+        let child = self.child.clone();
+        let status = tokio::task::spawn_blocking(move || child.wait())
+            .await
+            .expect("wait task panicked");
+        self.manager.remove(&self.child);
+
+        let status: ExitStatus = status?;
+        if status.success() {
+            return Ok(());
+        }
+
+        Err(Error::ChildExit {
+            // A child killed by a signal doesn't have an exit code
+            exit_code: status.code().unwrap_or(-1),
+            command: self.command,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        assert_matches::assert_matches,
+        io::{BufRead, BufReader},
+        process::{Command, Stdio},
+        time::{Duration, Instant},
+    };
+
+    use super::{Error, Manager, Signal};
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_exit_codes() {
+        let manager = Manager::new();
+
+        let child = manager.spawn(Command::new("true")).unwrap();
+        assert_matches!(child.wait().await, Ok(()));
+
+        let child = manager.spawn(Command::new("false")).unwrap();
+        assert_matches!(
+            child.wait().await,
+            Err(Error::ChildExit { exit_code: 1, .. })
+        );
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stop() {
+        let manager = Manager::new();
+
+        let mut command = Command::new("sleep");
+        command.arg("10");
+        let child = manager.spawn(command).unwrap();
+        manager.stop();
+        assert_matches!(
+            child.wait().await,
+            Err(Error::ChildExit { exit_code: -1, .. })
+        );
+
+        assert_matches!(manager.spawn(Command::new("true")), Err(Error::Closing));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stop_with_signal() {
+        let manager = Manager::new();
+
+        let mut command = Command::new("sleep");
+        command.arg("10");
+        let child = manager.spawn(command).unwrap();
+        manager.stop_with_signal(Signal::Terminate);
+        assert_matches!(
+            child.wait().await,
+            Err(Error::ChildExit { exit_code: -1, .. })
+        );
+        manager.wait_for_shutdown().await;
+    }
+
+    // Whether `pid` is still running, rather than exited and waiting to be
+    // reaped
+    #[cfg(target_os = "linux")]
+    fn is_running(pid: &str) -> bool {
+        std::fs::read_to_string(format!("/proc/{pid}/stat"))
+            .map(|stat| !stat.contains(") Z "))
+            .unwrap_or(false)
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_stop_kills_process_group() {
+        let manager = Manager::new().with_grace_period(Duration::from_millis(200));
+
+        // Background jobs of a non-interactive shell ignore SIGINT, so the
+        // sleep outlives the shell and has to be killed
+        let mut command = Command::new("sh");
+        command
+            .args(["-c", "sleep 30 & echo $!; wait"])
+            .stdout(Stdio::piped());
+        let child = manager.spawn(command).unwrap();
+        let mut pid = String::new();
+        BufReader::new(child.take_stdout().unwrap())
+            .read_line(&mut pid)
+            .unwrap();
+        let pid = pid.trim();
+        assert!(is_running(pid));
+
+        let start = Instant::now();
+        manager.stop();
+        assert_matches!(child.wait().await, Err(Error::ChildExit { .. }));
+        manager.wait_for_shutdown().await;
+        assert!(start.elapsed() >= Duration::from_millis(200));
+
+        // Give the kernel a moment to deliver the SIGKILL
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while is_running(pid) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!is_running(pid));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_stop_orphaned_process_group() {
+        let manager = Manager::new().with_grace_period(Duration::from_millis(200));
+
+        let mut command = Command::new("sh");
+        command
+            .args(["-c", "sleep 30 & echo $!"])
+            .stdout(Stdio::piped());
+        let child = manager.spawn(command).unwrap();
+        let mut pid = String::new();
+        BufReader::new(child.take_stdout().unwrap())
+            .read_line(&mut pid)
+            .unwrap();
+        let pid = pid.trim();
+        assert_matches!(child.wait().await, Ok(()));
+        assert!(is_running(pid));
+
+        manager.stop();
+        manager.wait_for_shutdown().await;
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while is_running(pid) && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!is_running(pid));
     }
 }
@@ -1,10 +1,16 @@
 #![allow(dead_code)]
-use anyhow::{anyhow, Result};
-use serde::{Deserialize, Serialize};
+use std::{path::Path, time::Duration};
+
+use anyhow::{anyhow, Context, Result};
+use turbopath::AnchoredSystemPathBuf;
+use turborepo_cache::{CacheActions, CacheOpts};
+use turborepo_scm::git::ShallowCloneMode;
 
 use crate::{
-    cli::{Command, DryRunMode, EnvMode, LogPrefix, RunArgs},
+    cli::{self, Command, DryRunMode, EnvMode, LogOrder, LogPrefix, RunArgs},
     daemon::{DaemonClient, DaemonConnector},
+    manager,
+    task_graph::TaskOutputMode,
     Args,
 };
 
@@ -16,30 +22,44 @@ pub struct Opts<'a> {
     pub scope_opts: ScopeOpts,
 }
 
-#[derive(Debug, Default)]
-pub struct CacheOpts<'a> {
-    override_dir: Option<&'a str>,
-    skip_remote: bool,
-    skip_filesystem: bool,
-    workers: u32,
-    pub(crate) remote_cache_opts: Option<RemoteCacheOpts>,
-}
+impl<'a> TryFrom<&'a RunArgs> for CacheOpts<'a> {
+    type Error = anyhow::Error;
 
-impl<'a> From<&'a RunArgs> for CacheOpts<'a> {
-    fn from(run_args: &'a RunArgs) -> Self {
-        CacheOpts {
+    fn try_from(run_args: &'a RunArgs) -> Result<Self> {
+        let (local, remote) = match run_args.cache.as_deref() {
+            Some(cache) => parse_cache(cache)?,
+            None => (
+                if run_args.remote_only {
+                    CacheActions::DISABLED
+                } else {
+                    CacheActions::default()
+                },
+                if run_args.remote_cache_read_only {
+                    CacheActions::READ_ONLY
+                } else {
+                    CacheActions::default()
+                },
+            ),
+        };
+
+        Ok(CacheOpts {
             override_dir: run_args.cache_dir.as_deref(),
-            skip_filesystem: run_args.remote_only,
+            local,
+            remote,
             workers: run_args.cache_workers,
             ..CacheOpts::default()
-        }
+        })
     }
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
-pub struct RemoteCacheOpts {
-    team_id: String,
-    signature: bool,
+impl From<cli::ShallowCloneMode> for ShallowCloneMode {
+    fn from(mode: cli::ShallowCloneMode) -> Self {
+        match mode {
+            cli::ShallowCloneMode::AllChanged => ShallowCloneMode::AllChanged,
+            cli::ShallowCloneMode::Deepen => ShallowCloneMode::Deepen,
+            cli::ShallowCloneMode::Fail => ShallowCloneMode::Fail,
+        }
+    }
 }
 
 impl<'a> TryFrom<&'a Args> for Opts<'a> {
@@ -50,42 +70,61 @@ impl<'a> TryFrom<&'a Args> for Opts<'a> {
           return Err(anyhow!("Expected run command"))
         };
         let run_opts = RunOpts::try_from(run_args.as_ref())?;
-        let cache_opts = CacheOpts::from(run_args.as_ref());
+        let cache_opts = CacheOpts::try_from(run_args.as_ref())?;
 
         Ok(Self {
             run_opts,
             cache_opts,
-            scope_opts: ScopeOpts::default(),
-            runcache_opts: RunCacheOpts::default(),
+            scope_opts: ScopeOpts::try_from(run_args.as_ref())?,
+            runcache_opts: RunCacheOpts::from(run_args.as_ref()),
         })
     }
 }
 
 #[derive(Debug, Default)]
 pub struct RunCacheOpts {
+    // --force ignores existing cache entries, but still writes new ones
+    pub(crate) skip_reads: bool,
+    // --no-cache runs tasks without writing their outputs to the cache
+    pub(crate) skip_writes: bool,
+    // --output-logs overrides the output mode of every task
+    pub(crate) output_mode_override: Option<TaskOutputMode>,
     pub(crate) output_watcher: Option<DaemonClient<DaemonConnector>>,
 }
 
+impl<'a> From<&'a RunArgs> for RunCacheOpts {
+    fn from(args: &'a RunArgs) -> Self {
+        RunCacheOpts {
+            skip_reads: args.force.flatten().unwrap_or_default(),
+            skip_writes: args.no_cache,
+            output_mode_override: args.output_logs.map(TaskOutputMode::from),
+            ..RunCacheOpts::default()
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct RunOpts<'a> {
-    tasks: &'a [String],
-    concurrency: u32,
-    parallel: bool,
+    pub(crate) tasks: &'a [String],
+    pub(crate) concurrency: u32,
+    pub(crate) parallel: bool,
     pub(crate) env_mode: EnvMode,
     // Whether or not to infer the framework for each workspace.
     pub(crate) framework_inference: bool,
     profile: Option<&'a str>,
-    continue_on_error: bool,
-    passthrough_args: &'a [String],
-    only: bool,
-    dry_run: bool,
+    pub(crate) continue_on_error: bool,
+    pub(crate) grace_period: Duration,
+    pub(crate) passthrough_args: &'a [String],
+    pub(crate) only: bool,
+    pub(crate) dry_run: bool,
     pub(crate) dry_run_json: bool,
     pub graph_dot: bool,
-    graph_file: Option<&'a str>,
+    pub(crate) graph_file: Option<&'a str>,
     pub(crate) no_daemon: bool,
     pub(crate) single_package: bool,
-    log_prefix: LogPrefix,
-    summarize: Option<Option<bool>>,
+    pub(crate) log_prefix: LogPrefix,
+    pub(crate) log_order: LogOrder,
+    pub(crate) summarize: bool,
     pub(crate) experimental_space_id: Option<String>,
 }
 
@@ -102,6 +141,13 @@ impl<'a> TryFrom<&'a RunArgs> for RunOpts<'a> {
             .transpose()?
             .unwrap_or(DEFAULT_CONCURRENCY);
 
+        let grace_period = args
+            .grace_period
+            .as_deref()
+            .map(parse_grace_period)
+            .transpose()?
+            .unwrap_or(manager::DEFAULT_GRACE_PERIOD);
+
         let (graph_dot, graph_file) = match &args.graph {
             Some(file) if file.is_empty() => (true, None),
             Some(file) => (false, Some(file.as_str())),
@@ -111,7 +157,8 @@ impl<'a> TryFrom<&'a RunArgs> for RunOpts<'a> {
         Ok(Self {
             tasks: args.tasks.as_slice(),
             log_prefix: args.log_prefix,
-            summarize: args.summarize,
+            log_order: args.log_order,
+            summarize: args.summarize.flatten().unwrap_or_default(),
             experimental_space_id: args.experimental_space_id.clone(),
             framework_inference: args.framework_inference,
             env_mode: args.env_mode,
@@ -119,6 +166,7 @@ impl<'a> TryFrom<&'a RunArgs> for RunOpts<'a> {
             parallel: args.parallel,
             profile: args.profile.as_deref(),
             continue_on_error: args.continue_execution,
+            grace_period,
             passthrough_args: args.pass_through_args.as_ref(),
             only: args.only,
             no_daemon: args.no_daemon,
@@ -131,6 +179,35 @@ impl<'a> TryFrom<&'a RunArgs> for RunOpts<'a> {
     }
 }
 
+impl<'a> RunOpts<'a> {
+    /// Produces a command that selects the same tasks and task arguments as
+    /// these opts.
+    pub fn synthesize_command(&self) -> String {
+        let mut cmd = format!("turbo run {}", self.tasks.join(" "));
+        if self.parallel {
+            cmd.push_str(" --parallel");
+        }
+        if self.continue_on_error {
+            cmd.push_str(" --continue");
+        }
+        if self.dry_run {
+            if self.dry_run_json {
+                cmd.push_str(" --dry=json");
+            } else {
+                cmd.push_str(" --dry");
+            }
+        }
+        if self.only {
+            cmd.push_str(" --only");
+        }
+        if !self.passthrough_args.is_empty() {
+            cmd.push_str(" -- ");
+            cmd.push_str(&self.passthrough_args.join(" "));
+        }
+        cmd
+    }
+}
+
 fn parse_concurrency(concurrency_raw: &str) -> Result<u32> {
     if let Some(percent) = concurrency_raw.strip_suffix('%') {
         let percent = percent.parse::<f64>()?;
@@ -145,7 +222,7 @@ fn parse_concurrency(concurrency_raw: &str) -> Result<u32> {
         };
     }
     match concurrency_raw.parse::<u32>() {
-        Ok(concurrency) if concurrency > 1 => Ok(concurrency),
+        Ok(concurrency) if concurrency >= 1 => Ok(concurrency),
         Ok(_) | Err(_) => Err(anyhow!(
             "invalid value for --concurrency CLI flag. This should be a positive integer greater \
              than or equal to 1: {}",
@@ -154,5 +231,200 @@ fn parse_concurrency(concurrency_raw: &str) -> Result<u32> {
     }
 }
 
+fn parse_grace_period(grace_period: &str) -> Result<Duration> {
+    go_parse_duration::parse_duration(grace_period)
+        .ok()
+        .and_then(|nanos| u64::try_from(nanos).ok())
+        .map(Duration::from_nanos)
+        .ok_or_else(|| {
+            anyhow!(
+                "invalid value for --grace-period CLI flag. This should be a duration, such as \
+                 10s: {}",
+                grace_period
+            )
+        })
+}
+
+// Parses the local and remote cache actions from a --cache value
+fn parse_cache(cache: &str) -> Result<(CacheActions, CacheActions)> {
+    let invalid = || {
+        anyhow!(
+            "invalid value for --cache CLI flag. This should be a comma separated list of cache \
+             layers and the actions allowed on them, such as local:r,remote:rw: {}",
+            cache
+        )
+    };
+
+    let mut local = CacheActions::DISABLED;
+    let mut remote = CacheActions::DISABLED;
+    for layer in cache.split(',') {
+        let (name, allowed) = layer.split_once(':').ok_or_else(invalid)?;
+        let actions = match name {
+            "local" => &mut local,
+            "remote" => &mut remote,
+            _ => return Err(invalid()),
+        };
+        for action in allowed.chars() {
+            match action {
+                'r' => actions.read = true,
+                'w' => actions.write = true,
+                _ => return Err(invalid()),
+            }
+        }
+    }
+
+    Ok((local, remote))
+}
+
+/// The flags that select workspaces from before the `--filter` syntax, which
+/// are translated into filter patterns.
 #[derive(Debug, Default)]
-pub struct ScopeOpts {}
+pub struct LegacyFilter {
+    // --include-dependencies
+    pub(crate) include_dependencies: bool,
+    // --no-deps
+    pub(crate) skip_dependents: bool,
+    // --scope
+    pub(crate) entrypoints: Vec<String>,
+    // --since
+    pub(crate) since: Option<String>,
+}
+
+impl LegacyFilter {
+    pub fn as_filter_patterns(&self) -> Vec<String> {
+        let prefix = if self.skip_dependents { "" } else { "..." };
+        let suffix = if self.include_dependencies { "..." } else { "" };
+        let since = self
+            .since
+            .as_ref()
+            .map(|since| format!("[{since}]"))
+            .unwrap_or_default();
+
+        if !self.entrypoints.is_empty() {
+            // --scope implies our tweaked syntax to see if any dependency matches
+            let since = if since.is_empty() {
+                since
+            } else {
+                format!("...{since}")
+            };
+            self.entrypoints
+                .iter()
+                .map(|pattern| {
+                    if pattern.starts_with('!') {
+                        pattern.clone()
+                    } else {
+                        format!("{prefix}{pattern}{since}{suffix}")
+                    }
+                })
+                .collect()
+        } else if !since.is_empty() {
+            // no scopes specified, but --since was provided
+            vec![format!("{prefix}{since}{suffix}")]
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ScopeOpts {
+    // The directory turbo was invoked from, used to infer which workspaces
+    // filters without a workspace refer to. `None` if invoked from the root.
+    pub(crate) pkg_inference_root: Option<AnchoredSystemPathBuf>,
+    pub(crate) legacy_filter: LegacyFilter,
+    pub(crate) global_deps: Vec<String>,
+    pub(crate) filter_patterns: Vec<String>,
+    pub(crate) ignore_patterns: Vec<String>,
+    // --shallow-clone, for comparing refs without a merge base
+    pub(crate) shallow_clone_mode: ShallowCloneMode,
+}
+
+impl<'a> TryFrom<&'a RunArgs> for ScopeOpts {
+    type Error = anyhow::Error;
+
+    fn try_from(args: &'a RunArgs) -> Result<Self> {
+        let pkg_inference_root = args
+            .pkg_inference_root
+            .as_deref()
+            .map(|root| {
+                if Path::new(root).is_absolute() {
+                    return Err(anyhow!("invalid package inference root {root}"));
+                }
+                AnchoredSystemPathBuf::from_raw(root)
+                    .with_context(|| format!("invalid package inference root {root}"))
+            })
+            .transpose()?
+            .filter(|root| !matches!(root.to_str(), Ok("" | ".")));
+
+        Ok(Self {
+            pkg_inference_root,
+            legacy_filter: LegacyFilter {
+                include_dependencies: args.include_dependencies,
+                skip_dependents: args.no_deps,
+                entrypoints: args.scope.clone(),
+                since: args.since.clone(),
+            },
+            global_deps: args.global_deps.clone(),
+            filter_patterns: args.filter.clone(),
+            ignore_patterns: args.ignore.clone(),
+            shallow_clone_mode: args.shallow_clone.into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use test_case::test_case;
+    use turborepo_cache::CacheActions;
+
+    use super::{parse_cache, parse_grace_period, LegacyFilter};
+
+    const RW: CacheActions = CacheActions {
+        read: true,
+        write: true,
+    };
+
+    #[test_case("local:rw,remote:rw", Some((RW, RW)) ; "everything")]
+    #[test_case("local:r,remote:rw", Some((CacheActions::READ_ONLY, RW)) ; "local read only")]
+    #[test_case("remote:w", Some((CacheActions::DISABLED, CacheActions::WRITE_ONLY)) ; "remote write only")]
+    #[test_case("local:,remote:r", Some((CacheActions::DISABLED, CacheActions::READ_ONLY)) ; "local disabled")]
+    #[test_case("local:rx", None ; "unknown action")]
+    #[test_case("disk:rw", None ; "unknown layer")]
+    #[test_case("local", None ; "missing actions")]
+    fn test_parse_cache(cache: &str, expected: Option<(CacheActions, CacheActions)>) {
+        assert_eq!(parse_cache(cache).ok(), expected);
+    }
+
+    #[test_case("10s", Some(Duration::from_secs(10)) ; "seconds")]
+    #[test_case("1m30s", Some(Duration::from_secs(90)) ; "minutes and seconds")]
+    #[test_case("0s", Some(Duration::ZERO) ; "zero")]
+    #[test_case("-1s", None ; "negative")]
+    #[test_case("soon", None ; "invalid")]
+    fn test_parse_grace_period(grace_period: &str, expected: Option<Duration>) {
+        assert_eq!(parse_grace_period(grace_period).ok(), expected);
+    }
+
+    #[test_case(&[], None, false, false, &[] ; "no filter")]
+    #[test_case(&["foo"], None, false, false, &["...foo"] ; "scope")]
+    #[test_case(&["foo"], None, true, true, &["foo..."] ; "scope without dependents")]
+    #[test_case(&["foo", "!bar"], Some("main"), true, false, &["...foo...[main]...", "!bar"] ; "scope since")]
+    #[test_case(&[], Some("main"), false, false, &["...[main]"] ; "since")]
+    #[test_case(&[], Some("main"), false, true, &["[main]"] ; "since without dependents")]
+    fn test_legacy_filter(
+        entrypoints: &[&str],
+        since: Option<&str>,
+        include_dependencies: bool,
+        skip_dependents: bool,
+        expected: &[&str],
+    ) {
+        let filter = LegacyFilter {
+            include_dependencies,
+            skip_dependents,
+            entrypoints: entrypoints.iter().map(|s| s.to_string()).collect(),
+            since: since.map(str::to_string),
+        };
+        assert_eq!(filter.as_filter_patterns(), expected);
+    }
+}
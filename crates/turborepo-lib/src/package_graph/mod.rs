@@ -1,59 +1,766 @@
-use std::rc::Rc;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    sync::Arc,
+};
 
-use anyhow::Result;
-use turbopath::AbsoluteSystemPathBuf;
-use turborepo_lockfiles::Lockfile;
+use node_semver::{Range, Version};
+use petgraph::graph::NodeIndex;
+use thiserror::Error;
+use tracing::warn;
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+use turborepo_lockfiles::{Lockfile, Package};
 
-use crate::{package_json::PackageJson, package_manager::PackageManager};
+use crate::{
+    package_json::PackageJson,
+    package_manager::{self, PackageManager},
+    run::{hash::hash_external_dependencies, task_id::ROOT_PKG_NAME},
+};
 
-#[derive(Default)]
-pub struct WorkspaceCatalog {}
+/// A sigil identifying the root of the workspace graph. Every workspace
+/// without internal dependencies depends on it.
+pub const ROOT_NODE_NAME: &str = "___ROOT___";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    PackageManager(#[from] package_manager::Error),
+    #[error("unable to read {0}: {1}")]
+    PackageJson(AbsoluteSystemPathBuf, String),
+    #[error("package.json at {0} is missing a name")]
+    MissingName(AbsoluteSystemPathBuf),
+    #[error(
+        "Failed to add workspace \"{name}\" from {path}, it already exists at {existing_path}"
+    )]
+    DuplicateWorkspace {
+        name: String,
+        path: String,
+        existing_path: String,
+    },
+    #[error("cyclic dependency detected:\n{0}")]
+    CyclicDependencies(String),
+    #[error("{0} depends on itself")]
+    SelfDependency(String),
+    #[error(transparent)]
+    Path(#[from] turbopath::PathError),
+    #[error(transparent)]
+    Lockfile(#[from] turborepo_lockfiles::Error),
+    #[error("Cannot detect changed packages without previous and current lockfile")]
+    MissingLockfile,
+}
+
+/// Information about a single workspace, keyed by its name in
+/// `WorkspaceCatalog`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceInfo {
+    pub package_json: PackageJson,
+    /// Path to the workspace's package.json, anchored at the repo root
+    pub package_json_path: AnchoredSystemPathBuf,
+    /// Names of workspaces that this workspace depends on
+    pub internal_dependencies: BTreeSet<String>,
+    /// Dependencies that aren't satisfied by workspaces, as specified in
+    /// package.json
+    pub unresolved_external_dependencies: BTreeMap<String, String>,
+    /// All external packages this workspace depends on, as resolved by the
+    /// lockfile. `None` if the lockfile couldn't be read.
+    pub transitive_dependencies: Option<HashSet<Package>>,
+}
+
+impl WorkspaceInfo {
+    /// The directory containing the workspace, anchored at the repo root.
+    pub fn package_path(&self) -> AnchoredSystemPathBuf {
+        let path = std::path::PathBuf::from(self.package_json_path.clone());
+        let dir = path.parent().unwrap_or_else(|| std::path::Path::new(""));
+        AnchoredSystemPathBuf::from_raw(dir).expect("parent of anchored path is anchored")
+    }
+
+    /// Hash of the external packages this workspace depends on, empty if they
+    /// couldn't be resolved from the lockfile.
+    pub fn external_deps_hash(&self) -> String {
+        self.transitive_dependencies
+            .as_ref()
+            .map(|dependencies| hash_external_dependencies(dependencies.iter()))
+            .unwrap_or_default()
+    }
+
+    // Lockfiles identify workspaces by their unix path, with the root being ""
+    pub(crate) fn lockfile_path(&self) -> Result<String, Error> {
+        Ok(self.package_path().to_unix()?.as_str()?.to_string())
+    }
+}
+
+/// The package.json contents of every workspace, keyed by workspace name.
+/// The root workspace is stored under `ROOT_PKG_NAME`.
+#[derive(Debug, Default)]
+pub struct WorkspaceCatalog {
+    workspaces: BTreeMap<String, WorkspaceInfo>,
+}
+
+impl WorkspaceCatalog {
+    pub fn get(&self, name: &str) -> Option<&WorkspaceInfo> {
+        self.workspaces.get(name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &WorkspaceInfo)> {
+        self.workspaces.iter()
+    }
+}
 
 pub struct PackageGraph {
-    workspace_graph: Rc<petgraph::Graph<String, String>>,
-    workspace_infos: Rc<WorkspaceCatalog>,
+    // Edges point from a workspace to the workspaces it depends on
+    workspace_graph: Arc<petgraph::Graph<String, ()>>,
+    node_lookup: HashMap<String, NodeIndex>,
+    workspace_infos: Arc<WorkspaceCatalog>,
     package_manager: PackageManager,
-    lockfile: Box<dyn Lockfile>,
+    lockfile: Option<Box<dyn Lockfile>>,
+    has_lockfile: bool,
 }
 
 impl PackageGraph {
-    pub fn build_single_package_graph(_root_package_json: &PackageJson) -> Result<PackageGraph> {
-        // TODO
-        Ok(PackageGraph {
-            workspace_graph: Rc::new(petgraph::Graph::new()),
-            workspace_infos: Rc::new(WorkspaceCatalog::default()),
-            package_manager: PackageManager::Npm,
-            lockfile: Box::<turborepo_lockfiles::NpmLockfile>::default(),
-        })
+    pub fn build_single_package_graph(
+        root_package_json: &PackageJson,
+    ) -> Result<PackageGraph, Error> {
+        let package_manager =
+            PackageManager::read_package_manager(root_package_json)?.unwrap_or(PackageManager::Npm);
+
+        let mut workspaces = BTreeMap::new();
+        workspaces.insert(
+            ROOT_PKG_NAME.to_string(),
+            WorkspaceInfo {
+                package_json: root_package_json.clone(),
+                package_json_path: AnchoredSystemPathBuf::from_raw("package.json")?,
+                internal_dependencies: BTreeSet::new(),
+                unresolved_external_dependencies: root_package_json.all_dependencies(),
+                transitive_dependencies: None,
+            },
+        );
+
+        Ok(Self::from_workspaces(
+            WorkspaceCatalog { workspaces },
+            package_manager,
+            None,
+        ))
     }
 
     pub fn build_multi_package_graph(
-        _repo_root: &AbsoluteSystemPathBuf,
-        _root_package_json: &PackageJson,
-    ) -> Result<PackageGraph> {
-        // TODO
-        Ok(PackageGraph {
-            workspace_graph: Rc::new(petgraph::Graph::new()),
-            workspace_infos: Rc::new(WorkspaceCatalog::default()),
-            package_manager: PackageManager::Npm,
-            lockfile: Box::<turborepo_lockfiles::NpmLockfile>::default(),
-        })
-    }
-
-    pub fn validate(&self) -> Result<()> {
-        // TODO
+        repo_root: &AbsoluteSystemPath,
+        root_package_json: &PackageJson,
+    ) -> Result<PackageGraph, Error> {
+        let package_manager =
+            PackageManager::get_package_manager(repo_root, Some(root_package_json))?;
+
+        let mut workspaces: BTreeMap<String, WorkspaceInfo> = BTreeMap::new();
+        for package_json_path in package_manager.get_package_jsons(repo_root)? {
+            let package_json = PackageJson::load(&package_json_path)
+                .map_err(|err| Error::PackageJson(package_json_path.clone(), err.to_string()))?;
+            let name = package_json
+                .name
+                .clone()
+                .ok_or_else(|| Error::MissingName(package_json_path.clone()))?;
+            let info = WorkspaceInfo {
+                package_json,
+                package_json_path: repo_root.anchor(&package_json_path)?,
+                internal_dependencies: BTreeSet::new(),
+                unresolved_external_dependencies: BTreeMap::new(),
+                transitive_dependencies: None,
+            };
+
+            if let Some(existing) = workspaces.get(&name) {
+                return Err(Error::DuplicateWorkspace {
+                    name,
+                    path: info.package_path().to_str()?.to_string(),
+                    existing_path: existing.package_path().to_str()?.to_string(),
+                });
+            }
+            workspaces.insert(name, info);
+        }
+
+        // The root package.json can have an arbitrary name, so we always store
+        // it under the root package name.
+        workspaces.insert(
+            ROOT_PKG_NAME.to_string(),
+            WorkspaceInfo {
+                package_json: root_package_json.clone(),
+                package_json_path: AnchoredSystemPathBuf::from_raw("package.json")?,
+                internal_dependencies: BTreeSet::new(),
+                unresolved_external_dependencies: BTreeMap::new(),
+                transitive_dependencies: None,
+            },
+        );
+
+        Self::split_dependencies(repo_root, &mut workspaces);
+
+        let lockfile = match package_manager.read_lockfile(repo_root, root_package_json) {
+            Ok(lockfile) => {
+                if let Err(err) =
+                    Self::populate_transitive_dependencies(lockfile.as_ref(), &mut workspaces)
+                {
+                    warn!(
+                        "Issues occurred when constructing package graph. Turbo will function, \
+                         but some features may not be available: {}",
+                        err
+                    );
+                }
+                Some(lockfile)
+            }
+            Err(err) => {
+                warn!(
+                    "Issues occurred when constructing package graph. Turbo will function, but \
+                     some features may not be available: {}",
+                    err
+                );
+                None
+            }
+        };
+
+        Ok(Self::from_workspaces(
+            WorkspaceCatalog { workspaces },
+            package_manager,
+            lockfile,
+        ))
+    }
+
+    // Splits each workspace's dependencies into dependencies on other
+    // workspaces and external dependencies.
+    fn split_dependencies(
+        repo_root: &AbsoluteSystemPath,
+        workspaces: &mut BTreeMap<String, WorkspaceInfo>,
+    ) {
+        let workspace_versions: HashMap<String, Option<String>> = workspaces
+            .iter()
+            .filter(|(name, _)| name.as_str() != ROOT_PKG_NAME)
+            .map(|(name, info)| (name.clone(), info.package_json.version.clone()))
+            .collect();
+
+        for info in workspaces.values_mut() {
+            let package_dir = repo_root.resolve(&info.package_path());
+            for (name, version) in info.package_json.all_dependencies() {
+                match workspace_versions.get(&name) {
+                    Some(workspace_version)
+                        if is_workspace_reference(
+                            workspace_version.as_deref(),
+                            &version,
+                            &package_dir,
+                            repo_root,
+                        ) =>
+                    {
+                        info.internal_dependencies.insert(name);
+                    }
+                    _ => {
+                        info.unresolved_external_dependencies.insert(name, version);
+                    }
+                }
+            }
+        }
+    }
+
+    fn populate_transitive_dependencies(
+        lockfile: &dyn Lockfile,
+        workspaces: &mut BTreeMap<String, WorkspaceInfo>,
+    ) -> Result<(), Error> {
+        for info in workspaces.values_mut() {
+            let unresolved_deps = info
+                .unresolved_external_dependencies
+                .iter()
+                .map(|(name, version)| (name.clone(), version.clone()))
+                .collect();
+            let closure = turborepo_lockfiles::transitive_closure(
+                lockfile,
+                &info.lockfile_path()?,
+                unresolved_deps,
+            )?;
+            info.transitive_dependencies = Some(closure);
+        }
+
         Ok(())
     }
 
-    pub fn len(&self) -> usize {
-        self.workspace_graph.node_count()
+    /// Reassembles a graph from workspaces whose dependencies have already
+    /// been resolved, such as a graph received from the daemon. The lockfile
+    /// itself isn't available, only whether it was used to resolve them.
+    pub(crate) fn from_resolved_workspaces(
+        workspaces: BTreeMap<String, WorkspaceInfo>,
+        package_manager: PackageManager,
+        has_lockfile: bool,
+    ) -> PackageGraph {
+        PackageGraph {
+            has_lockfile,
+            ..Self::from_workspaces(WorkspaceCatalog { workspaces }, package_manager, None)
+        }
+    }
+
+    fn from_workspaces(
+        workspace_infos: WorkspaceCatalog,
+        package_manager: PackageManager,
+        lockfile: Option<Box<dyn Lockfile>>,
+    ) -> PackageGraph {
+        let mut workspace_graph = petgraph::Graph::new();
+        let mut node_lookup = HashMap::new();
+
+        let root_index = workspace_graph.add_node(ROOT_NODE_NAME.to_string());
+        node_lookup.insert(ROOT_NODE_NAME.to_string(), root_index);
+        for name in workspace_infos.workspaces.keys() {
+            let index = workspace_graph.add_node(name.clone());
+            node_lookup.insert(name.clone(), index);
+        }
+
+        for (name, info) in &workspace_infos.workspaces {
+            let index = node_lookup[name];
+            if info.internal_dependencies.is_empty() {
+                workspace_graph.add_edge(index, root_index, ());
+            }
+            for dependency in &info.internal_dependencies {
+                workspace_graph.add_edge(index, node_lookup[dependency], ());
+            }
+        }
+
+        PackageGraph {
+            workspace_graph: Arc::new(workspace_graph),
+            node_lookup,
+            workspace_infos: Arc::new(workspace_infos),
+            package_manager,
+            has_lockfile: lockfile.is_some(),
+            lockfile,
+        }
+    }
+
+    /// Checks that the workspace graph is acyclic and that no workspace
+    /// depends on itself.
+    pub fn validate(&self) -> Result<(), Error> {
+        let cycles = petgraph::algo::tarjan_scc(self.workspace_graph.as_ref())
+            .into_iter()
+            .filter(|component| component.len() > 1)
+            .map(|component| {
+                let mut names = component
+                    .into_iter()
+                    .map(|index| self.workspace_graph[index].as_str())
+                    .collect::<Vec<_>>();
+                names.sort();
+                format!("\t{}", names.join(", "))
+            })
+            .collect::<Vec<_>>();
+        if !cycles.is_empty() {
+            return Err(Error::CyclicDependencies(cycles.join("\n")));
+        }
+
+        for edge in self.workspace_graph.raw_edges() {
+            if edge.source() == edge.target() {
+                return Err(Error::SelfDependency(
+                    self.workspace_graph[edge.source()].clone(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn workspace_infos(&self) -> Arc<WorkspaceCatalog> {
+        self.workspace_infos.clone()
+    }
+
+    pub fn workspace_info(&self, name: &str) -> Option<&WorkspaceInfo> {
+        self.workspace_infos.get(name)
+    }
+
+    /// Returns the workspaces that `workspace` directly depends on.
+    pub fn dependencies<'a>(&'a self, workspace: &str) -> Option<impl Iterator<Item = &'a str>> {
+        let index = *self.node_lookup.get(workspace)?;
+        Some(
+            self.workspace_graph
+                .neighbors_directed(index, petgraph::Direction::Outgoing)
+                .map(|index| self.workspace_graph[index].as_str())
+                .filter(|name| *name != ROOT_NODE_NAME),
+        )
+    }
+
+    /// Returns the workspaces that directly depend on `workspace`.
+    pub fn dependents<'a>(&'a self, workspace: &str) -> Option<impl Iterator<Item = &'a str>> {
+        let index = *self.node_lookup.get(workspace)?;
+        Some(
+            self.workspace_graph
+                .neighbors_directed(index, petgraph::Direction::Incoming)
+                .map(|index| self.workspace_graph[index].as_str()),
+        )
+    }
+
+    /// Returns the given workspaces along with every workspace they
+    /// transitively depend on.
+    pub fn transitive_internal_dependencies<'a, I: IntoIterator<Item = &'a str>>(
+        &'a self,
+        workspaces: I,
+    ) -> BTreeSet<&'a str> {
+        let mut visited = BTreeSet::new();
+        let mut stack: Vec<&str> = workspaces.into_iter().collect();
+        while let Some(workspace) = stack.pop() {
+            if !visited.insert(workspace) {
+                continue;
+            }
+            if let Some(dependencies) = self.dependencies(workspace) {
+                stack.extend(dependencies);
+            }
+        }
+        visited
+    }
+
+    /// Returns the given workspaces along with every workspace that
+    /// transitively depends on them.
+    pub fn transitive_dependents<'a, I: IntoIterator<Item = &'a str>>(
+        &'a self,
+        workspaces: I,
+    ) -> BTreeSet<&'a str> {
+        let mut visited = BTreeSet::new();
+        let mut stack: Vec<&str> = workspaces.into_iter().collect();
+        while let Some(workspace) = stack.pop() {
+            if !visited.insert(workspace) {
+                continue;
+            }
+            if let Some(dependents) = self.dependents(workspace) {
+                stack.extend(dependents);
+            }
+        }
+        visited
     }
 
     pub fn package_manager(&self) -> &PackageManager {
         &self.package_manager
     }
 
-    pub fn lockfile(&self) -> &dyn Lockfile {
-        self.lockfile.as_ref()
+    pub fn lockfile(&self) -> Option<&dyn Lockfile> {
+        self.lockfile.as_deref()
+    }
+
+    /// Whether the lockfile could be read when building the graph. Unlike
+    /// `lockfile`, this is also set for graphs received from the daemon.
+    pub fn has_lockfile(&self) -> bool {
+        self.has_lockfile
+    }
+
+    /// Returns the workspaces whose external dependencies differ between the
+    /// current lockfile and `previous`. If the lockfiles differ in a way that
+    /// affects every workspace, such as a lockfile version bump or a change
+    /// to the root workspace's dependencies, every workspace is returned.
+    pub fn changed_packages(&self, previous: &dyn Lockfile) -> Result<Vec<String>, Error> {
+        let current = self.lockfile().ok_or(Error::MissingLockfile)?;
+        let all_workspaces = || {
+            self.workspace_infos
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect()
+        };
+
+        if current.global_change(previous) {
+            return Ok(all_workspaces());
+        }
+
+        let mut changed = Vec::new();
+        for (name, info) in self.workspace_infos.iter() {
+            let unresolved_deps = info
+                .unresolved_external_dependencies
+                .iter()
+                .map(|(name, version)| (name.clone(), version.clone()))
+                .collect();
+            let previous_deps = turborepo_lockfiles::transitive_closure(
+                previous,
+                &info.lockfile_path()?,
+                unresolved_deps,
+            )?;
+            if info.transitive_dependencies.as_ref() != Some(&previous_deps) {
+                if name == ROOT_PKG_NAME {
+                    return Ok(all_workspaces());
+                }
+                changed.push(name.clone());
+            }
+        }
+
+        Ok(changed)
+    }
+}
+
+// Splits "npm:^1.2.3" and "github:foo/bar.git" into a protocol part and a
+// version part.
+fn parse_dependency_protocol(version: &str) -> (Option<&str>, &str) {
+    match version.split_once(':') {
+        Some((protocol, version)) => (Some(protocol), version),
+        None => (None, version),
+    }
+}
+
+/// Port of Go's `isWorkspaceReference`, which determines whether a dependency
+/// on a package with the same name as a workspace refers to that workspace.
+fn is_workspace_reference(
+    package_version: Option<&str>,
+    dependency_version: &str,
+    cwd: &AbsoluteSystemPath,
+    repo_root: &AbsoluteSystemPath,
+) -> bool {
+    let (protocol, dependency_version) = parse_dependency_protocol(dependency_version);
+
+    match protocol {
+        // Multiple workspaces with the same name aren't supported, so any
+        // `workspace:` range is assumed to match.
+        Some("workspace") => true,
+        Some("file") | Some("link") => {
+            AbsoluteSystemPathBuf::from_unknown(cwd, dependency_version).starts_with(repo_root)
+        }
+        // The npm protocol still uses the workspace package if the version
+        // matches, all other protocols are external references.
+        Some(protocol) if protocol != "npm" => false,
+        _ if dependency_version == "*" => true,
+        _ => {
+            let (Ok(range), Some(Ok(version))) = (
+                Range::parse(dependency_version),
+                package_version.map(Version::parse),
+            ) else {
+                // For backwards compatibility, if we can't parse the version we
+                // treat the dependency as an internal reference.
+                return true;
+            };
+            range.satisfies(&version)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use serde_json::json;
+    use tempfile::tempdir;
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case(Some("1.2.3"), "workspace:*", true ; "workspace protocol")]
+    #[test_case(Some("1.2.3"), "workspace:../other", true ; "workspace protocol with path")]
+    #[test_case(Some("1.2.3"), "*", true ; "wildcard")]
+    #[test_case(Some("1.2.3"), "^1.0.0", true ; "matching range")]
+    #[test_case(Some("2.0.0"), "^1.0.0", false ; "mismatched range")]
+    #[test_case(Some("1.2.3"), "npm:^1.0.0", true ; "npm protocol matching range")]
+    #[test_case(Some("1.2.3"), "github:foo/bar", false ; "external protocol")]
+    #[test_case(None, "^1.0.0", true ; "unversioned workspace")]
+    #[test_case(Some("1.2.3"), "latest", true ; "unparseable range")]
+    #[test_case(Some("1.2.3"), "file:../lib", true ; "file protocol within repo")]
+    #[test_case(Some("1.2.3"), "link:../../../../outside", false ; "link protocol outside repo")]
+    fn test_is_workspace_reference(
+        package_version: Option<&str>,
+        dependency_version: &str,
+        expected: bool,
+    ) {
+        let repo_root =
+            AbsoluteSystemPathBuf::new(if cfg!(windows) { "C:\\repo" } else { "/repo" }).unwrap();
+        let cwd = repo_root.join_components(&["packages", "app"]);
+        assert_eq!(
+            is_workspace_reference(package_version, dependency_version, &cwd, &repo_root),
+            expected
+        );
+    }
+
+    fn write_json(path: &AbsoluteSystemPath, value: serde_json::Value) {
+        fs::create_dir_all(path.as_path().parent().unwrap()).unwrap();
+        fs::write(
+            path.as_path(),
+            serde_json::to_string_pretty(&value).unwrap(),
+        )
+        .unwrap();
+    }
+
+    fn setup_repo(
+        repo_root: &AbsoluteSystemPath,
+        workspaces: &[(&str, serde_json::Value)],
+    ) -> PackageJson {
+        let root_package_json = json!({
+            "name": "monorepo",
+            "packageManager": "npm@8.19.4",
+            "workspaces": ["packages/*"],
+            "devDependencies": {"turbo": "^1.9.0"},
+        });
+        write_json(
+            &repo_root.join_component("package.json"),
+            root_package_json.clone(),
+        );
+        for (dir, package_json) in workspaces {
+            write_json(
+                &repo_root.join_components(&["packages", dir, "package.json"]),
+                package_json.clone(),
+            );
+        }
+        serde_json::from_value(root_package_json).unwrap()
+    }
+
+    #[test]
+    fn test_build_multi_package_graph() {
+        let tmp = tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::new(tmp.path()).unwrap();
+        let root_package_json = setup_repo(
+            &repo_root,
+            &[
+                (
+                    "a",
+                    json!({"name": "a", "version": "1.0.0", "dependencies": {"b": "workspace:*", "lodash": "^4.0.0"}}),
+                ),
+                (
+                    "b",
+                    json!({"name": "b", "version": "1.0.0", "devDependencies": {"c": "^1.0.0"}}),
+                ),
+                ("c", json!({"name": "c", "version": "2.0.0"})),
+            ],
+        );
+        write_json(
+            &repo_root.join_component("package-lock.json"),
+            json!({
+                "lockfileVersion": 3,
+                "packages": {
+                    "": {"devDependencies": {"turbo": "^1.9.0"}},
+                    "packages/a": {"dependencies": {"b": "workspace:*", "lodash": "^4.0.0"}},
+                    "packages/b": {"devDependencies": {"c": "^1.0.0"}},
+                    "packages/c": {"version": "2.0.0"},
+                    "node_modules/lodash": {"version": "4.17.21"},
+                    "node_modules/turbo": {"version": "1.9.3"},
+                    "packages/b/node_modules/c": {"version": "1.0.0"},
+                }
+            }),
+        );
+
+        let graph =
+            PackageGraph::build_multi_package_graph(&repo_root, &root_package_json).unwrap();
+        graph.validate().unwrap();
+
+        assert_eq!(graph.workspace_infos().iter().count(), 4);
+        assert_eq!(graph.package_manager(), &PackageManager::Npm);
+        assert!(graph.lockfile().is_some());
+
+        let deps = |name| graph.dependencies(name).unwrap().collect::<Vec<_>>();
+        assert_eq!(deps("a"), vec!["b"]);
+        // c doesn't satisfy ^1.0.0, so b depends on the published version
+        assert!(deps("b").is_empty());
+        assert!(deps(ROOT_PKG_NAME).is_empty());
+        assert_eq!(
+            graph.dependents("b").unwrap().collect::<Vec<_>>(),
+            vec!["a"]
+        );
+        assert_eq!(
+            graph.transitive_internal_dependencies(["a", ROOT_PKG_NAME]),
+            BTreeSet::from(["a", "b", ROOT_PKG_NAME])
+        );
+
+        let b = graph.workspace_info("b").unwrap();
+        assert_eq!(
+            b.package_path(),
+            AnchoredSystemPathBuf::from_raw(["packages", "b"].join(std::path::MAIN_SEPARATOR_STR))
+                .unwrap()
+        );
+        assert_eq!(
+            b.transitive_dependencies,
+            Some(HashSet::from([Package::new(
+                "packages/b/node_modules/c",
+                "1.0.0"
+            )]))
+        );
+        assert_eq!(
+            graph.workspace_info("a").unwrap().transitive_dependencies,
+            Some(HashSet::from([Package::new(
+                "node_modules/lodash",
+                "4.17.21"
+            )]))
+        );
+        assert_eq!(
+            graph
+                .workspace_info(ROOT_PKG_NAME)
+                .unwrap()
+                .transitive_dependencies,
+            Some(HashSet::from([Package::new("node_modules/turbo", "1.9.3")]))
+        );
+    }
+
+    #[test]
+    fn test_missing_lockfile_is_not_fatal() {
+        let tmp = tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::new(tmp.path()).unwrap();
+        let root_package_json = setup_repo(
+            &repo_root,
+            &[("a", json!({"name": "a", "version": "1.0.0"}))],
+        );
+
+        let graph =
+            PackageGraph::build_multi_package_graph(&repo_root, &root_package_json).unwrap();
+        assert!(graph.lockfile().is_none());
+        assert_eq!(
+            graph.workspace_info("a").unwrap().transitive_dependencies,
+            None
+        );
+    }
+
+    #[test]
+    fn test_duplicate_workspace_names() {
+        let tmp = tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::new(tmp.path()).unwrap();
+        let root_package_json = setup_repo(
+            &repo_root,
+            &[
+                ("a", json!({"name": "a"})),
+                ("also-a", json!({"name": "a"})),
+            ],
+        );
+
+        let result = PackageGraph::build_multi_package_graph(&repo_root, &root_package_json);
+        assert!(matches!(
+            result,
+            Err(Error::DuplicateWorkspace { name, .. }) if name == "a"
+        ));
+    }
+
+    #[test]
+    fn test_validate_cycle() {
+        let tmp = tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::new(tmp.path()).unwrap();
+        let root_package_json = setup_repo(
+            &repo_root,
+            &[
+                ("a", json!({"name": "a", "dependencies": {"b": "*"}})),
+                ("b", json!({"name": "b", "dependencies": {"a": "*"}})),
+                ("c", json!({"name": "c", "dependencies": {"c": "*"}})),
+            ],
+        );
+
+        let graph =
+            PackageGraph::build_multi_package_graph(&repo_root, &root_package_json).unwrap();
+        let err = graph.validate().unwrap_err();
+        assert!(matches!(&err, Error::CyclicDependencies(cycles) if cycles == "\ta, b"));
+    }
+
+    #[test]
+    fn test_validate_self_dependency() {
+        let tmp = tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::new(tmp.path()).unwrap();
+        let root_package_json = setup_repo(
+            &repo_root,
+            &[("c", json!({"name": "c", "dependencies": {"c": "*"}}))],
+        );
+
+        let graph =
+            PackageGraph::build_multi_package_graph(&repo_root, &root_package_json).unwrap();
+        assert!(matches!(
+            graph.validate(),
+            Err(Error::SelfDependency(name)) if name == "c"
+        ));
+    }
+
+    #[test]
+    fn test_single_package_graph() {
+        let root_package_json = PackageJson {
+            name: Some("single".to_string()),
+            package_manager: Some("yarn@1.22.19".to_string()),
+            dependencies: Some(BTreeMap::from([("react".to_string(), "^18".to_string())])),
+            ..Default::default()
+        };
+
+        let graph = PackageGraph::build_single_package_graph(&root_package_json).unwrap();
+        graph.validate().unwrap();
+        assert_eq!(graph.workspace_infos().iter().count(), 1);
+        assert_eq!(graph.package_manager(), &PackageManager::Yarn);
+        let root = graph.workspace_info(ROOT_PKG_NAME).unwrap();
+        assert_eq!(
+            root.unresolved_external_dependencies,
+            BTreeMap::from([("react".to_string(), "^18".to_string())])
+        );
     }
 }
@@ -1,11 +1,30 @@
+use std::collections::BTreeMap;
+
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use turbopath::AbsoluteSystemPath;
 
-#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct PackageJson {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub package_manager: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dependencies: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dev_dependencies: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub optional_dependencies: Option<BTreeMap<String, String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peer_dependencies: Option<BTreeMap<String, String>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub scripts: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resolutions: Option<BTreeMap<String, String>>,
 }
 
 impl PackageJson {
@@ -14,4 +33,20 @@ impl PackageJson {
         let package_json: PackageJson = serde_json::from_str(&contents)?;
         Ok(package_json)
     }
+
+    /// Returns the dependencies that turbo considers when building the package
+    /// graph. As in Go, `dependencies` take precedence over
+    /// `optionalDependencies`, which take precedence over `devDependencies`.
+    pub fn all_dependencies(&self) -> BTreeMap<String, String> {
+        [
+            &self.dev_dependencies,
+            &self.optional_dependencies,
+            &self.dependencies,
+        ]
+        .into_iter()
+        .flatten()
+        .flat_map(|deps| deps.iter())
+        .map(|(name, version)| (name.clone(), version.clone()))
+        .collect()
+    }
 }
@@ -0,0 +1,96 @@
+use std::{io::Write, process::Command};
+
+use turbopath::AbsoluteSystemPath;
+use which::which;
+
+use crate::package_manager::{Error, PackageManager};
+
+pub const LOCKFILE: &str = "bun.lockb";
+
+// bun.lockb files start with this header before switching to binary
+const BINARY_LOCKFILE_HEADER: &[u8] = b"#!/usr/bin/env bun\nbun-lockfile-format-v0";
+
+pub struct BunDetector<'a> {
+    repo_root: &'a AbsoluteSystemPath,
+    found: bool,
+}
+
+impl<'a> BunDetector<'a> {
+    pub fn new(repo_root: &'a AbsoluteSystemPath) -> Self {
+        Self {
+            repo_root,
+            found: false,
+        }
+    }
+}
+
+impl<'a> Iterator for BunDetector<'a> {
+    type Item = Result<PackageManager, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.found {
+            return None;
+        }
+        self.found = true;
+
+        let bun_lockfile = self.repo_root.join_component(LOCKFILE);
+
+        bun_lockfile.exists().then(|| Ok(PackageManager::Bun))
+    }
+}
+
+/// Converts the contents of a binary bun.lockb into the yarn v1 format that
+/// `bun bun.lockb` prints. Contents that are already text are returned as is.
+pub(crate) fn print_lockfile(contents: &[u8]) -> Result<Vec<u8>, Error> {
+    if !contents.starts_with(BINARY_LOCKFILE_HEADER) {
+        return Ok(contents.to_vec());
+    }
+
+    // Bun only prints lockfiles from disk and relies on the extension to
+    // recognize them
+    let mut lockfile = tempfile::Builder::new().suffix(".lockb").tempfile()?;
+    lockfile.write_all(contents)?;
+    lockfile.flush()?;
+
+    let bun_binary = which("bun")?;
+    let output = Command::new(bun_binary).arg(lockfile.path()).output()?;
+    if !output.status.success() {
+        return Err(Error::BunLockfile(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+
+    use anyhow::Result;
+    use tempfile::tempdir;
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::{print_lockfile, LOCKFILE};
+    use crate::package_manager::PackageManager;
+
+    #[test]
+    fn test_detect_bun() -> Result<()> {
+        let repo_root = tempdir()?;
+        let repo_root_path = AbsoluteSystemPathBuf::new(repo_root.path())?;
+
+        let lockfile_path = repo_root.path().join(LOCKFILE);
+        File::create(lockfile_path)?;
+        let package_manager = PackageManager::detect_package_manager(&repo_root_path)?;
+        assert_eq!(package_manager, PackageManager::Bun);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_text_lockfile_is_unchanged() -> Result<()> {
+        let contents = b"# yarn lockfile v1\n";
+        assert_eq!(print_lockfile(contents)?, contents);
+        Ok(())
+    }
+}
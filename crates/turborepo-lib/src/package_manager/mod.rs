@@ -1,3 +1,4 @@
+mod bun;
 mod npm;
 mod pnpm;
 mod yarn;
@@ -6,6 +7,7 @@ use std::{
     backtrace,
     fmt::{self, Display},
     fs,
+    str::FromStr,
 };
 
 use itertools::{Either, Itertools};
@@ -14,11 +16,15 @@ use regex::Regex;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf};
+use turborepo_lockfiles::{
+    BerryLockfile, BerryManifest, BunLockfile, Lockfile, LockfileData, NpmLockfile, PnpmLockfile,
+    Yarn1Lockfile,
+};
 use wax::{Any, Glob, Pattern};
 
 use crate::{
     package_json::PackageJson,
-    package_manager::{npm::NpmDetector, pnpm::PnpmDetector, yarn::YarnDetector},
+    package_manager::{bun::BunDetector, npm::NpmDetector, pnpm::PnpmDetector, yarn::YarnDetector},
     ui::{UI, UNDERLINE},
 };
 
@@ -61,6 +67,7 @@ impl From<Workspaces> for Vec<String> {
 #[serde(rename_all = "lowercase")]
 pub enum PackageManager {
     Berry,
+    Bun,
     Npm,
     Pnpm,
     Pnpm6,
@@ -73,6 +80,7 @@ impl fmt::Display for PackageManager {
         // packagemanager.go
         match self {
             PackageManager::Berry => write!(f, "berry"),
+            PackageManager::Bun => write!(f, "bun"),
             PackageManager::Npm => write!(f, "npm"),
             PackageManager::Pnpm => write!(f, "pnpm"),
             PackageManager::Pnpm6 => write!(f, "pnpm6"),
@@ -81,6 +89,23 @@ impl fmt::Display for PackageManager {
     }
 }
 
+impl FromStr for PackageManager {
+    type Err = Error;
+
+    // The inverse of `Display`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "berry" => Ok(PackageManager::Berry),
+            "bun" => Ok(PackageManager::Bun),
+            "npm" => Ok(PackageManager::Npm),
+            "pnpm" => Ok(PackageManager::Pnpm),
+            "pnpm6" => Ok(PackageManager::Pnpm6),
+            "yarn" => Ok(PackageManager::Yarn),
+            _ => Err(Error::UnknownPackageManager(s.to_string())),
+        }
+    }
+}
+
 // WorkspaceGlobs is suitable for finding package.json files via globwalk
 #[derive(Debug)]
 pub struct WorkspaceGlobs {
@@ -138,6 +163,11 @@ impl WorkspaceGlobs {
         })
     }
 
+    /// Globs matching the package.json of every workspace.
+    pub fn package_json_inclusions(&self) -> &[String] {
+        &self.package_json_inclusions
+    }
+
     pub fn target_is_workspace(
         &self,
         root: &AbsoluteSystemPath,
@@ -198,6 +228,10 @@ impl Display for MissingWorkspaceError {
                 "package.json: no workspaces found. Turborepo requires npm workspaces to be \
                  defined in the root package.json"
             }
+            PackageManager::Bun => {
+                "package.json: no workspaces found. Turborepo requires bun workspaces to be \
+                 defined in the root package.json"
+            }
         };
         write!(f, "{}", err)
     }
@@ -248,12 +282,21 @@ pub enum Error {
         "We could not parse the packageManager field in package.json, expected: {0}, received: {1}"
     )]
     InvalidPackageManager(String, String),
+    #[error("unknown package manager: {0}")]
+    UnknownPackageManager(String),
     #[error(transparent)]
     WalkError(#[from] globwalk::WalkError),
+    #[error("unable to parse lockfile: {0}")]
+    Lockfile(
+        #[from] turborepo_lockfiles::Error,
+        #[backtrace] backtrace::Backtrace,
+    ),
+    #[error("unable to read bun lockfile: {0}")]
+    BunLockfile(String),
 }
 
 static PACKAGE_MANAGER_PATTERN: Lazy<Regex> =
-    lazy_regex!(r"(?P<manager>npm|pnpm|yarn)@(?P<version>\d+\.\d+\.\d+(-.+)?)");
+    lazy_regex!(r"(?P<manager>npm|pnpm|yarn|bun)@(?P<version>\d+\.\d+\.\d+(-.+)?)");
 
 impl PackageManager {
     /// Returns the set of globs for the workspace.
@@ -274,12 +317,44 @@ impl PackageManager {
         Ok(globs)
     }
 
+    /// Returns the globs for files that are never part of a workspace, such as
+    /// installed dependencies.
+    pub fn get_workspace_ignores(
+        &self,
+        root_path: &AbsoluteSystemPath,
+    ) -> Result<Vec<String>, Error> {
+        match self {
+            PackageManager::Npm | PackageManager::Berry | PackageManager::Bun => {
+                Ok(self.get_default_exclusions().collect())
+            }
+            PackageManager::Pnpm | PackageManager::Pnpm6 => {
+                let mut ignores = self.get_default_exclusions().collect::<Vec<_>>();
+                match self.get_configured_workspace_globs(root_path) {
+                    Ok((_, exclusions)) => ignores.extend(exclusions),
+                    // Single package repos don't have a pnpm-workspace.yaml
+                    Err(Error::Io(err, _)) if err.kind() == std::io::ErrorKind::NotFound => {}
+                    Err(err) => return Err(err),
+                }
+                Ok(ignores)
+            }
+            // Yarn only ignores node_modules within each workspace
+            PackageManager::Yarn => match self.get_configured_workspace_globs(root_path) {
+                Ok((inclusions, _)) => Ok(inclusions
+                    .into_iter()
+                    .map(|inclusion| format!("{inclusion}/node_modules/**"))
+                    .collect()),
+                Err(Error::Workspace(_)) => Ok(vec!["node_modules/**".to_string()]),
+                Err(err) => Err(err),
+            },
+        }
+    }
+
     fn get_default_exclusions(&self) -> impl Iterator<Item = String> {
         let ignores = match self {
             PackageManager::Pnpm | PackageManager::Pnpm6 => {
                 ["**/node_modules/**", "**/bower_components/**"].as_slice()
             }
-            PackageManager::Npm => ["**/node_modules/**"].as_slice(),
+            PackageManager::Npm | PackageManager::Bun => ["**/node_modules/**"].as_slice(),
             PackageManager::Berry => ["**/node_modules", "**/.git", "**/.yarn"].as_slice(),
             PackageManager::Yarn => [].as_slice(), // yarn does its own handling above
         };
@@ -301,7 +376,10 @@ impl PackageManager {
                     pnpm_workspace.packages
                 }
             }
-            PackageManager::Berry | PackageManager::Npm | PackageManager::Yarn => {
+            PackageManager::Berry
+            | PackageManager::Bun
+            | PackageManager::Npm
+            | PackageManager::Yarn => {
                 let package_json_text =
                     fs::read_to_string(root_path.join_component("package.json"))?;
                 let package_json: PackageJsonWorkspaces = serde_json::from_str(&package_json_text)?;
@@ -341,7 +419,7 @@ impl PackageManager {
     }
 
     // Attempts to read the package manager from the package.json
-    fn read_package_manager(pkg: &PackageJson) -> Result<Option<Self>, Error> {
+    pub(crate) fn read_package_manager(pkg: &PackageJson) -> Result<Option<Self>, Error> {
         let Some(package_manager) = &pkg.package_manager else {
             return Ok(None)
         };
@@ -350,6 +428,7 @@ impl PackageManager {
         let version = version.parse()?;
         let manager = match manager {
             "npm" => Some(PackageManager::Npm),
+            "bun" => Some(PackageManager::Bun),
             "yarn" => Some(YarnDetector::detect_berry_or_yarn(&version)?),
             "pnpm" => Some(PnpmDetector::detect_pnpm6_or_pnpm(&version)?),
             _ => None,
@@ -362,6 +441,7 @@ impl PackageManager {
         let mut detected_package_managers = PnpmDetector::new(repo_root)
             .chain(NpmDetector::new(repo_root))
             .chain(YarnDetector::new(repo_root))
+            .chain(BunDetector::new(repo_root))
             .collect::<Result<Vec<_>, Error>>()?;
 
         match detected_package_managers.len() {
@@ -390,7 +470,83 @@ impl PackageManager {
         }
     }
 
-    #[allow(dead_code)]
+    pub fn lockfile_name(&self) -> &'static str {
+        match self {
+            PackageManager::Npm => "package-lock.json",
+            PackageManager::Pnpm | PackageManager::Pnpm6 => "pnpm-lock.yaml",
+            PackageManager::Yarn | PackageManager::Berry => "yarn.lock",
+            PackageManager::Bun => bun::LOCKFILE,
+        }
+    }
+
+    /// The binary used to run package.json scripts.
+    pub fn command(&self) -> &'static str {
+        match self {
+            PackageManager::Npm => "npm",
+            PackageManager::Pnpm | PackageManager::Pnpm6 => "pnpm",
+            PackageManager::Yarn | PackageManager::Berry => "yarn",
+            PackageManager::Bun => "bun",
+        }
+    }
+
+    /// The separator needed between a script's name and the arguments
+    /// passed through to it, if any.
+    pub fn arg_separator(&self) -> Option<&'static str> {
+        match self {
+            PackageManager::Npm | PackageManager::Pnpm6 | PackageManager::Yarn => Some("--"),
+            PackageManager::Pnpm | PackageManager::Berry | PackageManager::Bun => None,
+        }
+    }
+
+    pub fn lockfile_path(&self, root_path: &AbsoluteSystemPath) -> AbsoluteSystemPathBuf {
+        root_path.join_component(self.lockfile_name())
+    }
+
+    /// Reads and parses the lockfile at the root of the repository.
+    /// The root package.json is needed to apply any berry resolutions.
+    pub fn read_lockfile(
+        &self,
+        root_path: &AbsoluteSystemPath,
+        root_package_json: &PackageJson,
+    ) -> Result<Box<dyn Lockfile>, Error> {
+        let contents = fs::read(self.lockfile_path(root_path))?;
+        self.parse_lockfile(root_package_json, &contents)
+    }
+
+    /// Parses lockfile contents, e.g. the lockfile as it was at a previous
+    /// commit. Binary bun lockfiles are converted to text using `bun`.
+    pub fn parse_lockfile(
+        &self,
+        root_package_json: &PackageJson,
+        contents: &[u8],
+    ) -> Result<Box<dyn Lockfile>, Error> {
+        let lockfile: Box<dyn Lockfile> = match self {
+            PackageManager::Npm => Box::new(NpmLockfile::load(contents)?),
+            PackageManager::Pnpm | PackageManager::Pnpm6 => {
+                Box::new(PnpmLockfile::from_bytes(contents)?)
+            }
+            PackageManager::Yarn => Box::new(
+                Yarn1Lockfile::from_bytes(contents).map_err(turborepo_lockfiles::Error::from)?,
+            ),
+            PackageManager::Bun => {
+                Box::new(BunLockfile::from_bytes(&bun::print_lockfile(contents)?)?)
+            }
+            PackageManager::Berry => {
+                let data =
+                    LockfileData::from_bytes(contents).map_err(turborepo_lockfiles::Error::from)?;
+                let manifest = root_package_json
+                    .resolutions
+                    .clone()
+                    .map(BerryManifest::with_resolutions);
+                Box::new(
+                    BerryLockfile::new(&data, manifest.as_ref())
+                        .map_err(turborepo_lockfiles::Error::from)?,
+                )
+            }
+        };
+        Ok(lockfile)
+    }
+
     pub fn get_package_jsons(
         &self,
         repo_root: &AbsoluteSystemPath,
@@ -492,13 +648,14 @@ mod tests {
             PackageManager::Npm,
             PackageManager::Yarn,
             PackageManager::Berry,
+            PackageManager::Bun,
             PackageManager::Pnpm,
             PackageManager::Pnpm6,
         ] {
             let globs = mgr.get_workspace_globs(&fixtures).unwrap();
             let ignores: HashSet<String> = HashSet::from_iter(globs.raw_exclusions.into_iter());
             let expected: &[&str] = match mgr {
-                PackageManager::Npm => &["**/node_modules/**"],
+                PackageManager::Npm | PackageManager::Bun => &["**/node_modules/**"],
                 PackageManager::Berry => &["**/node_modules", "**/.git", "**/.yarn"],
                 PackageManager::Yarn => &["apps/*/node_modules/**", "packages/*/node_modules/**"],
                 PackageManager::Pnpm | PackageManager::Pnpm6 => &[
@@ -579,6 +736,13 @@ mod tests {
                 expected_version: "111.0.1".to_owned(),
                 expected_error: false,
             },
+            TestCase {
+                name: "supports bun".to_owned(),
+                package_manager: "bun@1.0.1".to_owned(),
+                expected_manager: "bun".to_owned(),
+                expected_version: "1.0.1".to_owned(),
+                expected_error: false,
+            },
         ];
 
         for case in tests {
@@ -597,6 +761,7 @@ mod tests {
     fn test_read_package_manager() -> Result<(), Error> {
         let mut package_json = PackageJson {
             package_manager: Some("npm@8.19.4".to_string()),
+            ..Default::default()
         };
         let package_manager = PackageManager::read_package_manager(&package_json)?;
         assert_eq!(package_manager, Some(PackageManager::Npm));
@@ -617,6 +782,10 @@ mod tests {
         let package_manager = PackageManager::read_package_manager(&package_json)?;
         assert_eq!(package_manager, Some(PackageManager::Pnpm));
 
+        package_json.package_manager = Some("bun@1.0.1".to_string());
+        let package_manager = PackageManager::read_package_manager(&package_json)?;
+        assert_eq!(package_manager, Some(PackageManager::Bun));
+
         Ok(())
     }
 
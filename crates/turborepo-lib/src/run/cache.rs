@@ -0,0 +1,247 @@
+use std::{io, sync::Arc, time::Duration};
+
+use globwalk::WalkType;
+use thiserror::Error;
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPathBuf, PathError};
+use turborepo_cache::{multiplexer::CacheMultiplexer, CacheError, CacheResponse};
+
+use crate::{
+    opts::RunCacheOpts,
+    run::{
+        output::{replay_log_file, OutputWriter, TaskOutput},
+        task_hash::hashable_outputs,
+    },
+    task_graph::{TaskDefinition, TaskOutputMode},
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("error finding outputs to save: {0}")]
+    Globwalk(#[from] globwalk::WalkError),
+    #[error(transparent)]
+    Cache(#[from] CacheError),
+    #[error(transparent)]
+    Path(#[from] PathError),
+}
+
+/// Wraps the cache with the cache settings for the whole run.
+pub struct RunCache {
+    cache: CacheMultiplexer,
+    reads_disabled: bool,
+    writes_disabled: bool,
+    repo_root: AbsoluteSystemPathBuf,
+    // --output-logs takes precedence over the output mode of every task
+    output_mode_override: Option<TaskOutputMode>,
+}
+
+impl RunCache {
+    pub fn new(
+        cache: CacheMultiplexer,
+        repo_root: &AbsoluteSystemPath,
+        opts: &RunCacheOpts,
+    ) -> Self {
+        Self {
+            cache,
+            reads_disabled: opts.skip_reads,
+            writes_disabled: opts.skip_writes,
+            repo_root: repo_root.to_owned(),
+            output_mode_override: opts.output_mode_override,
+        }
+    }
+
+    /// Checks whether the outputs for `hash` are cached, without restoring
+    /// them.
+    pub async fn exists(&self, hash: &str) -> Result<Option<CacheResponse>, Error> {
+        Ok(self.cache.exists(hash).await?)
+    }
+
+    pub fn task_cache(
+        &self,
+        task_definition: &TaskDefinition,
+        package_dir: &AnchoredSystemPathBuf,
+        task: &str,
+        hash: &str,
+    ) -> TaskCache<'_> {
+        let outputs = hashable_outputs(task_definition, task);
+        let package_dir = self.repo_root.resolve(package_dir);
+        let log_file = package_dir.join_components(&[".turbo", &format!("turbo-{task}.log")]);
+        TaskCache {
+            run_cache: self,
+            package_dir,
+            log_file,
+            inclusions: outputs.inclusions,
+            exclusions: outputs.exclusions,
+            hash: hash.to_string(),
+            caching_disabled: !task_definition.should_cache,
+            output_mode: self
+                .output_mode_override
+                .unwrap_or(task_definition.output_mode),
+        }
+    }
+}
+
+/// The cache for the outputs of a single task.
+pub struct TaskCache<'a> {
+    run_cache: &'a RunCache,
+    package_dir: AbsoluteSystemPathBuf,
+    log_file: AbsoluteSystemPathBuf,
+    inclusions: Vec<String>,
+    exclusions: Vec<String>,
+    hash: String,
+    caching_disabled: bool,
+    output_mode: TaskOutputMode,
+}
+
+impl TaskCache<'_> {
+    /// Whether we'll try to restore this task from the cache at all.
+    pub fn reads_enabled(&self) -> bool {
+        !self.caching_disabled && !self.run_cache.reads_disabled
+    }
+
+    fn writes_enabled(&self) -> bool {
+        !self.caching_disabled && !self.run_cache.writes_disabled
+    }
+
+    /// Reports that this task is about to run because it wasn't restored from
+    /// the cache.
+    pub fn on_cache_miss(&self, output: &TaskOutput) {
+        if shows_cache_status(self.output_mode) {
+            let status = if self.reads_enabled() {
+                "cache miss, executing"
+            } else {
+                "cache bypass, force executing"
+            };
+            output.info(format_args!("{status} {}", output.dim(&self.hash)));
+        }
+    }
+
+    /// Reports that this task was restored from the cache, replaying its logs
+    /// when the output mode shows them.
+    pub fn on_cache_hit(&self, output: &TaskOutput) {
+        let hash = output.dim(&self.hash);
+        match self.output_mode {
+            TaskOutputMode::Hash | TaskOutputMode::New => {
+                output.info(format_args!("cache hit, suppressing logs {hash}"));
+            }
+            TaskOutputMode::Full => {
+                output.info(format_args!("cache hit, replaying logs {hash}"));
+                self.replay_log_file(output);
+            }
+            // Nothing went wrong, so there's nothing to show
+            TaskOutputMode::Error | TaskOutputMode::None => {}
+        }
+    }
+
+    /// Shows the output of a failed task that was hidden while it ran, when
+    /// only errors are shown.
+    pub fn on_error(&self, output: &TaskOutput) {
+        if self.output_mode == TaskOutputMode::Error {
+            output.info(format_args!(
+                "cache miss, executing {}",
+                output.dim(&self.hash)
+            ));
+            self.replay_log_file(output);
+        }
+    }
+
+    fn replay_log_file(&self, output: &TaskOutput) {
+        if !self.log_file.exists() {
+            return;
+        }
+        if let Err(err) = replay_log_file(&self.log_file, output) {
+            output.error(format_args!("error reading logs: {err}"));
+        }
+    }
+
+    /// Creates the sink for the output of this task while it runs. Output is
+    /// saved to the task's log file whenever it will be cached, and shown on
+    /// the terminal unless the output mode hides it.
+    pub fn output_writer(&self, output: &Arc<TaskOutput>) -> io::Result<OutputWriter> {
+        let writer = OutputWriter::default();
+        if !self.writes_enabled() {
+            return Ok(writer.with_terminal(output.clone()));
+        }
+
+        let writer = writer.with_log_file(&self.log_file)?;
+        Ok(if streams_output(self.output_mode) {
+            writer.with_terminal(output.clone())
+        } else {
+            writer
+        })
+    }
+
+    /// Restores the outputs of this task into the repository. Returns `None` on
+    /// a cache miss, or when reading from the cache is disabled.
+    pub async fn restore_outputs(
+        &self,
+    ) -> Result<Option<(CacheResponse, Vec<AnchoredSystemPathBuf>)>, Error> {
+        if !self.reads_enabled() {
+            return Ok(None);
+        }
+
+        Ok(self
+            .run_cache
+            .cache
+            .fetch(&self.run_cache.repo_root, &self.hash)
+            .await?)
+    }
+
+    /// Saves the outputs of this task to the cache, returning the files that
+    /// were saved.
+    pub async fn save_outputs(
+        &self,
+        duration: Duration,
+    ) -> Result<Vec<AnchoredSystemPathBuf>, Error> {
+        if !self.writes_enabled() {
+            return Ok(Vec::new());
+        }
+
+        let outputs = globwalk::globwalk(
+            &self.package_dir,
+            &self.inclusions,
+            &self.exclusions,
+            WalkType::All,
+        )?;
+        let repo_root = &self.run_cache.repo_root;
+        let mut files = outputs
+            .iter()
+            .map(|output| repo_root.anchor(output))
+            .collect::<Result<Vec<_>, _>>()?;
+        files.sort();
+
+        let duration = u32::try_from(duration.as_millis()).unwrap_or(u32::MAX);
+        self.run_cache
+            .cache
+            .put(repo_root, &self.hash, &files, duration)
+            .await?;
+
+        Ok(files)
+    }
+}
+
+// Whether the cache miss and bypass messages are shown
+fn shows_cache_status(output_mode: TaskOutputMode) -> bool {
+    !matches!(output_mode, TaskOutputMode::None | TaskOutputMode::Error)
+}
+
+// Whether the output of a running task is shown as it's written
+fn streams_output(output_mode: TaskOutputMode) -> bool {
+    matches!(output_mode, TaskOutputMode::Full | TaskOutputMode::New)
+}
+
+#[cfg(test)]
+mod test {
+    use test_case::test_case;
+
+    use super::*;
+
+    #[test_case(TaskOutputMode::Full, true, true)]
+    #[test_case(TaskOutputMode::None, false, false)]
+    #[test_case(TaskOutputMode::Hash, true, false)]
+    #[test_case(TaskOutputMode::New, true, true)]
+    #[test_case(TaskOutputMode::Error, false, false)]
+    fn test_output_modes(output_mode: TaskOutputMode, shows_status: bool, streams: bool) {
+        assert_eq!(shows_cache_status(output_mode), shows_status);
+        assert_eq!(streams_output(output_mode), streams);
+    }
+}
@@ -0,0 +1,423 @@
+use std::{
+    collections::HashSet,
+    io::{BufRead, BufReader, Read},
+    process::{Command, Stdio},
+    sync::Arc,
+    time::Instant,
+};
+
+use anyhow::anyhow;
+use tokio::task::JoinHandle;
+use tracing::debug;
+use turbopath::AbsoluteSystemPath;
+use turborepo_env::EnvironmentVariableMap;
+
+use crate::{
+    cli::{EnvMode, LogOrder, LogPrefix},
+    engine::{Engine, TaskNode, VisitorError},
+    manager::{self, Manager},
+    package_graph::{PackageGraph, WorkspaceInfo},
+    run::{
+        cache::RunCache,
+        output::{ColorSelector, OutputStream, OutputWriter, TaskOutput},
+        summary::{
+            ExecutionTracker, TaskCacheSummary, TaskEnvConfiguration, TaskEnvVarSummary,
+            TaskSummary, FRAMEWORK_DETECTION_SKIPPED, MISSING_TASK_LABEL, NO_FRAMEWORK_DETECTED,
+        },
+        task_hash::{task_env_mode, TaskHasher},
+        task_id::get_package_task_from_id,
+    },
+    task_graph::TaskDefinition,
+    ui::{BOLD_RED, UI},
+};
+
+/// Everything needed to run a single task of the task graph.
+pub struct ExecContext<'a> {
+    pub engine: &'a Engine,
+    pub task_hasher: &'a TaskHasher<'a>,
+    pub global_env_mode: EnvMode,
+    pub framework_inference: bool,
+    pub env_at_execution_start: &'a EnvironmentVariableMap,
+    pub run_cache: &'a RunCache,
+    pub execution_tracker: &'a ExecutionTracker,
+    pub repo_root: &'a AbsoluteSystemPath,
+    pub package_graph: &'a PackageGraph,
+    pub ui: &'a UI,
+    pub manager: Manager,
+    pub targets: &'a [String],
+    pub passthrough_args: &'a [String],
+    pub continue_on_error: bool,
+    pub is_single_package: bool,
+    pub dry_run: bool,
+    pub log_prefix: LogPrefix,
+    pub log_order: LogOrder,
+    pub color_selector: ColorSelector,
+}
+
+impl<'a> ExecContext<'a> {
+    // Arguments after `--` are only passed to the tasks that were requested
+    // on the command line, not to their dependencies.
+    fn args_for_task(&self, task: &str) -> &[String] {
+        if self.targets.iter().any(|target| target == task) {
+            self.passthrough_args
+        } else {
+            &[]
+        }
+    }
+
+    fn output_prefix(&self, workspace: &str, task: &str) -> String {
+        match self.log_prefix {
+            LogPrefix::None => String::new(),
+            _ if self.is_single_package => format!("{task}: "),
+            _ => format!("{workspace}:{task}: "),
+        }
+    }
+
+    /// Runs the script for `task_id`, if its workspace has one.
+    pub async fn exec(&self, task_id: String) -> Result<(), VisitorError> {
+        let (workspace, task) = get_package_task_from_id(&task_id);
+        let workspace_info = self
+            .package_graph
+            .workspace_info(&workspace)
+            .ok_or_else(|| {
+                VisitorError::StopExecution(anyhow!(
+                    "Could not find workspace \"{}\" from task \"{}\" in project",
+                    workspace,
+                    task_id
+                ))
+            })?;
+
+        let task_definition = self.engine.task_definition(&task_id).ok_or_else(|| {
+            VisitorError::StopExecution(anyhow!("Could not find definition for task"))
+        })?;
+        let hash = self
+            .task_hasher
+            .calculate_task_hash(
+                &task_id,
+                task_definition,
+                workspace_info,
+                task_env_mode(self.global_env_mode, task_definition),
+                self.engine.dependencies(&task_id).unwrap_or_default(),
+                self.framework_inference,
+                self.args_for_task(&task),
+            )
+            // Not being able to construct the task hash is a hard error
+            .map_err(|err| VisitorError::StopExecution(anyhow!("Hashing error: {}", err)))?;
+        debug!("task {} hash is {}", task_id, hash);
+
+        if self.dry_run {
+            return self.dry_run(&task_id, &task, workspace_info, task_definition, hash);
+        }
+
+        let Some(script) = workspace_info.package_json.scripts.get(&task) else {
+            debug!("no task in package, skipping {}", task_id);
+            // The task is still summarized, but without an execution
+            let task_summary = self
+                .task_summary(
+                    &task_id,
+                    &task,
+                    workspace_info,
+                    task_definition,
+                    hash,
+                    MISSING_TASK_LABEL.to_string(),
+                )
+                .map_err(|err| VisitorError::StopExecution(anyhow!("Summary error: {}", err)))?;
+            self.execution_tracker.add_task(task_summary);
+            return Ok(());
+        };
+        let mut task_summary = self
+            .task_summary(
+                &task_id,
+                &task,
+                workspace_info,
+                task_definition,
+                hash.clone(),
+                script.clone(),
+            )
+            .map_err(|err| VisitorError::StopExecution(anyhow!("Summary error: {}", err)))?;
+        let tracker = self.execution_tracker.track_task();
+
+        let prefix = self.color_selector.prefix_with_color(
+            self.ui,
+            &workspace,
+            &self.output_prefix(&workspace, &task),
+        );
+        // Dropping the output at the end of the task prints it, when logs are
+        // grouped
+        let output = Arc::new(TaskOutput::new(prefix, *self.ui, self.log_order));
+        let task_cache = self.run_cache.task_cache(
+            task_definition,
+            &workspace_info.package_path(),
+            &task,
+            &hash,
+        );
+        match task_cache.restore_outputs().await {
+            Ok(Some((response, files))) => {
+                task_cache.on_cache_hit(&output);
+                task_summary.cache_summary = TaskCacheSummary::from(response);
+                task_summary.expanded_outputs = files;
+                task_summary.execution = Some(tracker.cached());
+                self.execution_tracker.add_task(task_summary);
+                return Ok(());
+            }
+            Ok(None) => task_cache.on_cache_miss(&output),
+            Err(err) => {
+                output.error(format_args!("error fetching from cache: {err}"));
+                task_cache.on_cache_miss(&output);
+            }
+        }
+
+        let writer = match task_cache.output_writer(&output) {
+            Ok(writer) => writer,
+            Err(err) => {
+                output.error(format_args!("error creating log file: {err}"));
+                if !self.continue_on_error {
+                    return Err(VisitorError::StopExecution(anyhow!(
+                        "failed to capture outputs for \"{}\": {}",
+                        task_id,
+                        err
+                    )));
+                }
+                OutputWriter::default().with_terminal(output.clone())
+            }
+        };
+        let writer = Arc::new(writer);
+
+        let package_manager = self.package_graph.package_manager();
+        let mut command = Command::new(package_manager.command());
+        command.arg("run").arg(&task);
+        let args = self.args_for_task(&task);
+        if !args.is_empty() {
+            command.args(package_manager.arg_separator());
+            command.args(args);
+        }
+        command
+            .current_dir(self.repo_root.resolve(&workspace_info.package_path()))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        let start = Instant::now();
+        let result = match self.manager.spawn(command) {
+            Ok(child) => {
+                let stdout = child
+                    .take_stdout()
+                    .map(|stdout| forward_output(stdout, OutputStream::Stdout, writer.clone()));
+                let stderr = child
+                    .take_stderr()
+                    .map(|stderr| forward_output(stderr, OutputStream::Stderr, writer.clone()));
+                let result = child.wait().await;
+                for handle in [stdout, stderr].into_iter().flatten() {
+                    handle.await.ok();
+                }
+                if let Err(err) = writer.flush() {
+                    output.error(format_args!("error writing log file: {err}"));
+                }
+                result
+            }
+            Err(err) => Err(err),
+        };
+
+        let execution = match &result {
+            Ok(()) => {
+                match task_cache.save_outputs(start.elapsed()).await {
+                    Ok(files) => task_summary.expanded_outputs = files,
+                    Err(err) => output.error(format_args!("error caching output: {err}")),
+                }
+                tracker.built()
+            }
+            Err(manager::Error::Closing) => tracker.stopped(),
+            Err(err) => {
+                task_cache.on_error(&output);
+                let exit_code = match err {
+                    manager::Error::ChildExit { exit_code, .. } => Some(*exit_code),
+                    _ => None,
+                };
+                tracker.failed(exit_code, err.to_string())
+            }
+        };
+        task_summary.execution = Some(execution);
+        self.execution_tracker.add_task(task_summary);
+
+        match result {
+            Ok(()) => Ok(()),
+            // We're already shutting down, there's no need to record an error for this
+            Err(manager::Error::Closing) => Ok(()),
+            Err(err) if self.continue_on_error => {
+                output.error("command finished with error, but continuing...");
+                Err(VisitorError::Task(err.into()))
+            }
+            Err(err) => {
+                output.error(self.ui.apply(
+                    BOLD_RED.apply_to(format!("ERROR: command finished with error: {err}")),
+                ));
+                self.manager.stop();
+                Err(VisitorError::StopExecution(err.into()))
+            }
+        }
+    }
+
+    // Records what would be run for the task, without running it. The cache
+    // status is filled in once all tasks have been visited.
+    fn dry_run(
+        &self,
+        task_id: &str,
+        task: &str,
+        workspace_info: &WorkspaceInfo,
+        task_definition: &TaskDefinition,
+        hash: String,
+    ) -> Result<(), VisitorError> {
+        let command = workspace_info
+            .package_json
+            .scripts
+            .get(task)
+            .cloned()
+            .unwrap_or_else(|| MISSING_TASK_LABEL.to_string());
+        let mut task_summary = self
+            .task_summary(
+                task_id,
+                task,
+                workspace_info,
+                task_definition,
+                hash,
+                command,
+            )
+            .map_err(|err| VisitorError::StopExecution(anyhow!("Summary error: {}", err)))?;
+        if task_summary.framework.is_empty() {
+            task_summary.framework = if self.framework_inference {
+                NO_FRAMEWORK_DETECTED
+            } else {
+                FRAMEWORK_DETECTION_SKIPPED
+            }
+            .to_string();
+        }
+        self.execution_tracker.add_task(task_summary);
+
+        Ok(())
+    }
+
+    // Summarizes everything we know about a task before it runs. The cache
+    // status and outcome are filled in once the task finishes.
+    fn task_summary(
+        &self,
+        task_id: &str,
+        task: &str,
+        workspace_info: &WorkspaceInfo,
+        task_definition: &TaskDefinition,
+        hash: String,
+        command: String,
+    ) -> anyhow::Result<TaskSummary> {
+        let (workspace, _) = get_package_task_from_id(task_id);
+        let package_dir = self.repo_root.resolve(&workspace_info.package_path());
+        let log_file = package_dir.join_components(&[".turbo", &format!("turbo-{task}.log")]);
+
+        let expanded_inputs = self
+            .task_hasher
+            .expanded_inputs(task_id)
+            .into_iter()
+            .flatten()
+            .map(|(path, hash)| Ok((path.as_str()?.to_string(), hash.clone())))
+            .collect::<Result<_, turbopath::PathError>>()?;
+
+        let env_vars = self.task_hasher.env_vars(task_id);
+        let pass_through_env = task_definition
+            .pass_through_env
+            .as_ref()
+            .map(|pass_through_env| {
+                self.env_at_execution_start
+                    .from_wildcards(pass_through_env)
+                    .map(|env_vars| env_vars.to_secret_hashable())
+            })
+            .transpose()?;
+        let dot_env = task_definition
+            .dot_env
+            .as_ref()
+            .map(|dot_env| {
+                dot_env
+                    .iter()
+                    .map(|path| Ok(path.as_str()?.to_string()))
+                    .collect::<Result<Vec<_>, turbopath::PathError>>()
+            })
+            .transpose()?;
+        let outputs = &task_definition.outputs;
+        let dir = workspace_info.package_path().to_str()?.to_string();
+
+        Ok(TaskSummary {
+            task_id: task_id.to_string(),
+            task: task.to_string(),
+            package: Some(workspace),
+            hash,
+            expanded_inputs,
+            external_deps_hash: workspace_info.external_deps_hash(),
+            cache_summary: TaskCacheSummary::miss(),
+            command,
+            command_arguments: self.args_for_task(task).to_vec(),
+            outputs: (!outputs.inclusions.is_empty()).then(|| outputs.inclusions.clone()),
+            excluded_outputs: (!outputs.exclusions.is_empty()).then(|| outputs.exclusions.clone()),
+            log_file_relative_path: self.repo_root.anchor(&log_file)?.to_str()?.to_string(),
+            dir: (!dir.is_empty()).then_some(dir),
+            dependencies: task_ids(self.engine.transitive_dependencies(task_id)),
+            dependents: task_ids(self.engine.transitive_dependents(task_id)),
+            resolved_task_definition: task_definition.into(),
+            expanded_outputs: Vec::new(),
+            framework: self
+                .task_hasher
+                .framework(task_id)
+                .unwrap_or_default()
+                .to_string(),
+            env_mode: task_env_mode(self.global_env_mode, task_definition),
+            env_vars: TaskEnvVarSummary {
+                specified: TaskEnvConfiguration {
+                    env: task_definition.env_var_dependencies.clone(),
+                    pass_through_env: task_definition.pass_through_env.clone(),
+                },
+                configured: env_vars
+                    .as_ref()
+                    .map(|env_vars| env_vars.by_source.explicit.to_secret_hashable())
+                    .unwrap_or_default(),
+                inferred: env_vars
+                    .as_ref()
+                    .map(|env_vars| env_vars.by_source.matching.to_secret_hashable())
+                    .unwrap_or_default(),
+                passthrough: pass_through_env,
+            },
+            dot_env,
+            execution: None,
+        })
+    }
+}
+
+// Sorted task IDs, without the root node
+fn task_ids(nodes: Option<HashSet<&TaskNode>>) -> Vec<String> {
+    let mut task_ids = nodes
+        .into_iter()
+        .flatten()
+        .filter_map(|node| match node {
+            TaskNode::Task(task_id) => Some(task_id.clone()),
+            TaskNode::Root => None,
+        })
+        .collect::<Vec<_>>();
+    task_ids.sort();
+    task_ids
+}
+
+// Copies output from a child process line by line into the task's output
+fn forward_output<R>(reader: R, stream: OutputStream, writer: Arc<OutputWriter>) -> JoinHandle<()>
+where
+    R: Read + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        let mut reader = BufReader::new(reader);
+        let mut line = Vec::new();
+        loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {
+                    // The task keeps running even if its log can't be written
+                    writer.write_line(stream, &line).ok();
+                }
+            }
+        }
+    })
+}
@@ -1,46 +1,57 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use anyhow::Result;
-use turbopath::{AbsoluteSystemPath, RelativeUnixPathBuf};
+use anyhow::{Context, Result};
+use globwalk::WalkType;
+use turbopath::{AbsoluteSystemPath, AnchoredSystemPathBuf, RelativeUnixPathBuf};
 use turborepo_env::{BySource, DetailedMap, EnvironmentVariableMap};
-use turborepo_lockfiles::Lockfile;
+use turborepo_scm::manual::hash_files;
 
-use crate::{cli::EnvMode, package_json::PackageJson, package_manager::PackageManager, ui::UI};
+use crate::{
+    cli::EnvMode,
+    package_graph::WorkspaceInfo,
+    package_manager::PackageManager,
+    run::hash::{format_file_hashes, format_paths, format_slice, hash_object},
+    ui::UI,
+};
 
 static DEFAULT_ENV_VARS: [&str; 1] = ["VERCEL_ANALYTICS_ID"];
 
+pub const GLOBAL_CACHE_KEY: &str = "You don't understand! I coulda had class. I coulda been a \
+                                    contender. I could've been somebody, instead of a bum, which \
+                                    is what I am.";
+
 #[derive(Default)]
 pub struct GlobalHashableInputs {
-    global_cache_key: &'static str,
-    global_file_hash_map: HashMap<RelativeUnixPathBuf, String>,
-    root_external_deps_hash: String,
-    env: Vec<String>,
+    pub global_cache_key: &'static str,
+    pub global_file_hash_map: HashMap<RelativeUnixPathBuf, String>,
+    pub root_external_deps_hash: String,
+    pub env: Vec<String>,
     // Only Option to allow #[derive(Default)]
-    resolved_env_vars: Option<DetailedMap>,
-    pass_through_env: Vec<String>,
-    env_mode: EnvMode,
-    framework_inference: bool,
-    dot_env: Vec<RelativeUnixPathBuf>,
+    pub resolved_env_vars: Option<DetailedMap>,
+    pub pass_through_env: Option<Vec<String>>,
+    pub env_mode: EnvMode,
+    pub framework_inference: bool,
+    pub dot_env: Option<Vec<RelativeUnixPathBuf>>,
 }
 
-pub fn get_global_hash_inputs<L: ?Sized + Lockfile>(
+pub fn get_global_hash_inputs(
     _ui: &UI,
-    _root_path: &AbsoluteSystemPath,
-    _root_package_json: &PackageJson,
-    _package_manager: &PackageManager,
-    _lockfile: &L,
-    _global_file_dependencies: Vec<String>,
+    root_path: &AbsoluteSystemPath,
+    root_workspace: &WorkspaceInfo,
+    package_manager: &PackageManager,
+    has_lockfile: bool,
+    global_file_dependencies: &[String],
     env_at_execution_start: &EnvironmentVariableMap,
-    global_env: Vec<String>,
-    _global_pass_through_env: Vec<String>,
-    _env_mode: EnvMode,
-    _framework_inference: bool,
-    _dot_env: Vec<RelativeUnixPathBuf>,
+    global_env: &[String],
+    global_pass_through_env: Option<&[String]>,
+    env_mode: EnvMode,
+    framework_inference: bool,
+    dot_env: Option<&[String]>,
 ) -> Result<GlobalHashableInputs> {
     let default_env_var_map = env_at_execution_start.from_wildcards(&DEFAULT_ENV_VARS[..])?;
 
     let user_env_var_set =
-        env_at_execution_start.wildcard_map_from_wildcards_unresolved(&global_env)?;
+        env_at_execution_start.wildcard_map_from_wildcards_unresolved(global_env)?;
 
     let mut all_env_var_map = EnvironmentVariableMap::default();
     all_env_var_map.union(&user_env_var_set.inclusions);
@@ -63,8 +74,101 @@ pub fn get_global_hash_inputs<L: ?Sized + Lockfile>(
         },
     };
 
+    // Calculate global file dependencies
+    let mut global_deps = HashSet::new();
+    if !global_file_dependencies.is_empty() {
+        let ignores = package_manager.get_workspace_ignores(root_path)?;
+        let files = globwalk::globwalk(
+            root_path,
+            global_file_dependencies,
+            &ignores,
+            WalkType::Files,
+        )?;
+        for file in files {
+            global_deps.insert(root_path.anchor(&file)?);
+        }
+    }
+
+    if !has_lockfile {
+        // If we don't have lockfile information available, add the specfile and
+        // lockfile to global deps
+        global_deps.insert(AnchoredSystemPathBuf::from_raw("package.json")?);
+        let lockfile_path = root_path.join_component(package_manager.lockfile_name());
+        if lockfile_path.exists() {
+            global_deps.insert(root_path.anchor(&lockfile_path)?);
+        }
+    }
+
+    let mut global_file_hash_map =
+        hash_files(root_path, global_deps.into_iter(), false).context("error hashing files")?;
+
+    // Make sure we include specified .env files in the file hash.
+    // Handled separately because these are not globs!
+    let dot_env = dot_env
+        .map(|dot_env| {
+            dot_env
+                .iter()
+                .map(|path| RelativeUnixPathBuf::new(path.as_str()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?;
+    if let Some(dot_env) = &dot_env {
+        let dot_env_files = dot_env
+            .iter()
+            .map(|path| AnchoredSystemPathBuf::from_raw(path.as_str()?))
+            .collect::<Result<Vec<_>, _>>()?;
+        let dot_env_object = hash_files(root_path, dot_env_files.into_iter(), true)
+            .context("error hashing files")?;
+        global_file_hash_map.extend(dot_env_object);
+    }
+
     Ok(GlobalHashableInputs {
+        global_cache_key: GLOBAL_CACHE_KEY,
+        global_file_hash_map,
+        root_external_deps_hash: root_workspace.external_deps_hash(),
+        env: global_env.to_vec(),
         resolved_env_vars: Some(global_hashable_env_vars),
-        ..GlobalHashableInputs::default()
+        pass_through_env: global_pass_through_env.map(|env| env.to_vec()),
+        env_mode,
+        framework_inference,
+        dot_env,
     })
 }
+
+impl GlobalHashableInputs {
+    pub fn calculate_global_hash(&self) -> Result<String> {
+        let env_mode = match self.env_mode {
+            // In infer mode, if there is any pass through config (even if it is an
+            // empty array) we'll hash the whole object, so we can detect changes
+            // to that config. Further, resolve the env mode to the concrete value.
+            EnvMode::Infer if self.pass_through_env.is_some() => "Strict",
+            EnvMode::Infer => "Infer",
+            EnvMode::Loose => "Loose",
+            EnvMode::Strict => "Strict",
+        };
+        // Pass through env vars are removed from consideration in loose mode, but
+        // Go formats them the same as an empty list either way.
+        let pass_through_env = match self.env_mode {
+            EnvMode::Loose => format_slice::<String>(&[]),
+            _ => format_slice(self.pass_through_env.as_deref().unwrap_or_default()),
+        };
+        let resolved_env_vars = self
+            .resolved_env_vars
+            .as_ref()
+            .map(|env_vars| env_vars.all.to_hashable())
+            .unwrap_or_default();
+
+        Ok(hash_object(&format!(
+            "{{{} {} {} {} {} {} {} {} {}}}",
+            self.global_cache_key,
+            format_file_hashes(&self.global_file_hash_map)?,
+            self.root_external_deps_hash,
+            format_slice(&self.env),
+            format_slice(&resolved_env_vars),
+            pass_through_env,
+            env_mode,
+            self.framework_inference,
+            format_paths(self.dot_env.as_deref().unwrap_or_default())?,
+        )))
+    }
+}
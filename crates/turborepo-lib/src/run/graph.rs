@@ -1,11 +1,15 @@
-use std::{collections::BTreeMap, rc::Rc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use turbopath::AbsoluteSystemPath;
 
 use crate::{
     config::TurboJson,
-    package_graph::{self, PackageGraph, WorkspaceCatalog},
+    package_graph::{PackageGraph, WorkspaceCatalog},
+    run::task_id::ROOT_PKG_NAME,
     task_graph::{Pipeline, TaskDefinition},
 };
 
@@ -16,12 +20,15 @@ pub struct CompleteGraph<'run> {
     // Config from turbo.json
     pipeline: Pipeline,
     // Stores the package.json contents by package name
-    workspace_infos: Rc<WorkspaceCatalog>,
+    workspace_infos: Arc<WorkspaceCatalog>,
     // Hash of all global dependencies
     global_hash: Option<String>,
 
     task_definitions: BTreeMap<String, TaskDefinition>,
     repo_root: &'run AbsoluteSystemPath,
+    // Workspace turbo.json files, loaded the first time one of their tasks is
+    // looked up. `None` means the workspace doesn't have a turbo.json.
+    workspace_turbo_jsons: Mutex<HashMap<String, Option<Arc<TurboJson>>>>,
 
     task_hash_tracker: TaskHashTracker,
 }
@@ -31,22 +38,55 @@ impl<'run> CompleteGraph<'run> {
         Self {
             package_graph,
             pipeline: Pipeline::default(),
-            // TODO: build during construction by querying package graph
-            workspace_infos: Default::default(),
+            workspace_infos: package_graph.workspace_infos(),
             repo_root,
             global_hash: None,
             task_definitions: BTreeMap::new(),
+            workspace_turbo_jsons: Mutex::default(),
             task_hash_tracker: TaskHashTracker::default(),
         }
     }
 
     pub fn get_turbo_config_from_workspace(
         &self,
-        _workspace_name: &str,
-        _is_single_package: bool,
+        workspace_name: &str,
+        is_single_package: bool,
     ) -> Result<TurboJson> {
-        // TODO
-        Ok(TurboJson::default())
+        let workspace = self
+            .workspace_infos
+            .get(workspace_name)
+            .ok_or_else(|| anyhow!("could not find workspace {}", workspace_name))?;
+        let workspace_dir = self.repo_root.resolve(&workspace.package_path());
+
+        // Only the root turbo.json can be synthesized from package.json
+        if workspace_name == ROOT_PKG_NAME {
+            TurboJson::load(&workspace_dir, &workspace.package_json, is_single_package)
+        } else {
+            TurboJson::load_workspace(&workspace_dir)?
+                .ok_or_else(|| anyhow!("Could not find turbo.json in workspace {}", workspace_name))
+        }
+    }
+
+    /// Returns the turbo.json of a non-root workspace, if it has one. Each
+    /// workspace's turbo.json is only read and validated once per run.
+    pub fn workspace_turbo_json(&self, workspace_name: &str) -> Result<Option<Arc<TurboJson>>> {
+        let mut turbo_jsons = self
+            .workspace_turbo_jsons
+            .lock()
+            .expect("only fails if poisoned");
+        if let Some(turbo_json) = turbo_jsons.get(workspace_name) {
+            return Ok(turbo_json.clone());
+        }
+
+        let workspace = self
+            .workspace_infos
+            .get(workspace_name)
+            .ok_or_else(|| anyhow!("could not find workspace {}", workspace_name))?;
+        let workspace_dir = self.repo_root.resolve(&workspace.package_path());
+        let turbo_json = TurboJson::load_workspace(&workspace_dir)?.map(Arc::new);
+        turbo_jsons.insert(workspace_name.to_string(), turbo_json.clone());
+
+        Ok(turbo_json)
     }
 }
 
@@ -0,0 +1,571 @@
+//! Renders graphs for `--graph`. The DOT output matches Go so that existing
+//! tooling keeps working, Mermaid and HTML output additionally distinguish
+//! edges to the root node and transitive edges, i.e. edges that are already
+//! implied by a longer path through the graph.
+
+use std::{
+    collections::HashSet,
+    fmt::Write as _,
+    io::Write as _,
+    process::{Command, Stdio},
+};
+
+use petgraph::{
+    algo::{
+        toposort,
+        tred::{dag_to_toposorted_adjacency_list, dag_transitive_reduction_closure},
+    },
+    graph::{DefaultIx, NodeIndex},
+    visit::EdgeRef,
+    Graph,
+};
+use thiserror::Error;
+use tracing::debug;
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf};
+
+use crate::ui::{BOLD, UI};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("error writing graph to {0}: {1}")]
+    Io(AbsoluteSystemPathBuf, #[source] std::io::Error),
+    #[error("could not generate task graph file {0}: {1}")]
+    Graphviz(AbsoluteSystemPathBuf, String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EdgeKind {
+    Direct,
+    // The edge points at the root node
+    Root,
+    // The edge is implied by a longer path between the same nodes
+    Transitive,
+}
+
+impl EdgeKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            EdgeKind::Direct => "direct",
+            EdgeKind::Root => "root",
+            EdgeKind::Transitive => "transitive",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Edge {
+    from: usize,
+    to: usize,
+    kind: EdgeKind,
+}
+
+/// A graph prepared for rendering. Edges are sorted by the names of their
+/// endpoints so that the output is the same on every run.
+#[derive(Debug)]
+pub struct GraphVisualizer {
+    names: Vec<String>,
+    edges: Vec<Edge>,
+    // Distance of every node from the root, following the longest path
+    layers: Vec<usize>,
+}
+
+impl GraphVisualizer {
+    /// Prepares `graph` for rendering. Edges point from a node to its
+    /// dependencies, and everything eventually depends on `root`.
+    pub fn new<N>(graph: &Graph<N, ()>, root: NodeIndex, name: impl Fn(&N) -> String) -> Self {
+        let names = graph.node_weights().map(name).collect::<Vec<_>>();
+        let reduced_edges = transitive_reduction(graph);
+
+        let mut edges = graph
+            .edge_references()
+            .map(|edge| {
+                let (from, to) = (edge.source(), edge.target());
+                let kind = if to == root {
+                    EdgeKind::Root
+                } else if reduced_edges
+                    .as_ref()
+                    .map_or(false, |reduced| !reduced.contains(&(from, to)))
+                {
+                    EdgeKind::Transitive
+                } else {
+                    EdgeKind::Direct
+                };
+                Edge {
+                    from: from.index(),
+                    to: to.index(),
+                    kind,
+                }
+            })
+            .collect::<Vec<_>>();
+        edges.sort_by(|a, b| (&names[a.from], &names[a.to]).cmp(&(&names[b.from], &names[b.to])));
+
+        let mut layers = vec![0; graph.node_count()];
+        if let Ok(sorted) = toposort(graph, None) {
+            // Dependencies come after their dependents, so walking backwards
+            // visits every dependency before the nodes that depend on it
+            for index in sorted.into_iter().rev() {
+                layers[index.index()] = graph
+                    .neighbors(index)
+                    .map(|dependency| layers[dependency.index()] + 1)
+                    .max()
+                    .unwrap_or_default();
+            }
+        }
+
+        Self {
+            names,
+            edges,
+            layers,
+        }
+    }
+
+    /// Renders the graph in the same format as Go's `dag` package.
+    pub fn dot(&self) -> String {
+        let mut out = String::new();
+        out.push_str("digraph {\n");
+        out.push_str("\tcompound = \"true\"\n");
+        out.push_str("\tnewrank = \"true\"\n");
+        out.push_str("\tsubgraph \"root\" {\n");
+        for edge in &self.edges {
+            writeln!(
+                out,
+                "\t\t\"[root] {}\" -> \"[root] {}\"",
+                self.names[edge.from], self.names[edge.to]
+            )
+            .ok();
+        }
+        out.push_str("\t}\n");
+        out.push_str("}\n");
+        out
+    }
+
+    /// Renders the graph as a Mermaid flowchart. Edges to the root are drawn
+    /// in grey and transitive edges are dashed.
+    pub fn mermaid(&self) -> String {
+        let mut out = String::from("graph TD\n");
+        for edge in &self.edges {
+            writeln!(
+                out,
+                "\tN{}(\"{}\") --> N{}(\"{}\")",
+                edge.from, self.names[edge.from], edge.to, self.names[edge.to]
+            )
+            .ok();
+        }
+
+        for (kind, style) in [
+            (EdgeKind::Root, "stroke:#999"),
+            (EdgeKind::Transitive, "stroke-dasharray:5 5"),
+        ] {
+            let indices = self
+                .edges
+                .iter()
+                .enumerate()
+                .filter(|(_, edge)| edge.kind == kind)
+                .map(|(i, _)| i.to_string())
+                .collect::<Vec<_>>();
+            if !indices.is_empty() {
+                writeln!(out, "\tlinkStyle {} {}", indices.join(","), style).ok();
+            }
+        }
+        out
+    }
+
+    /// Renders the graph as a self contained HTML page. Clicking on a node
+    /// highlights everything it depends on and everything that depends on it.
+    pub fn html(&self) -> String {
+        const MARGIN: f64 = 40.0;
+        const NODE_HEIGHT: f64 = 32.0;
+        const NODE_PADDING: f64 = 24.0;
+        const NODE_GAP: f64 = 24.0;
+        const LAYER_GAP: f64 = 80.0;
+        // Approximate width of a character in the monospace font we use
+        const CHAR_WIDTH: f64 = 7.8;
+
+        let max_layer = self.layers.iter().copied().max().unwrap_or_default();
+        let mut rows = vec![Vec::new(); max_layer + 1];
+        for (index, layer) in self.layers.iter().enumerate() {
+            rows[*layer].push(index);
+        }
+        for row in &mut rows {
+            row.sort_by(|a, b| self.names[*a].cmp(&self.names[*b]));
+        }
+
+        let node_width =
+            |index: usize| self.names[index].chars().count() as f64 * CHAR_WIDTH + NODE_PADDING;
+        let row_width = |row: &Vec<usize>| {
+            row.iter().map(|index| node_width(*index)).sum::<f64>()
+                + NODE_GAP * row.len().saturating_sub(1) as f64
+        };
+        let max_row_width = rows.iter().map(row_width).fold(0.0, f64::max);
+
+        // The root is at the bottom, with the nodes that nothing depends on
+        // at the top
+        let mut positions = vec![(0.0, 0.0, 0.0); self.names.len()];
+        for (layer, row) in rows.iter().enumerate() {
+            let y = MARGIN + (max_layer - layer) as f64 * (NODE_HEIGHT + LAYER_GAP);
+            let mut x = MARGIN + (max_row_width - row_width(row)) / 2.0;
+            for index in row {
+                let width = node_width(*index);
+                positions[*index] = (x, y, width);
+                x += width + NODE_GAP;
+            }
+        }
+
+        let mut svg = String::new();
+        for (i, edge) in self.edges.iter().enumerate() {
+            let (from_x, from_y, from_width) = positions[edge.from];
+            let (to_x, to_y, to_width) = positions[edge.to];
+            let (x1, y1) = (from_x + from_width / 2.0, from_y + NODE_HEIGHT);
+            let (x2, y2) = (to_x + to_width / 2.0, to_y);
+            let middle = (y1 + y2) / 2.0;
+            writeln!(
+                svg,
+                r#"<path id="edge-{i}" class="edge {kind}" d="M {x1:.1} {y1:.1} C {x1:.1} {middle:.1}, {x2:.1} {middle:.1}, {x2:.1} {y2:.1}" marker-end="url(#arrow)" />"#,
+                kind = edge.kind.as_str(),
+            )
+            .ok();
+        }
+        for (index, (x, y, width)) in positions.iter().enumerate() {
+            writeln!(
+                svg,
+                r#"<g id="node-{index}" class="node" data-node="{index}"><rect x="{x:.1}" y="{y:.1}" width="{width:.1}" height="{NODE_HEIGHT}" rx="6" /><text x="{text_x:.1}" y="{text_y:.1}">{name}</text></g>"#,
+                text_x = x + width / 2.0,
+                text_y = y + NODE_HEIGHT / 2.0,
+                name = escape_html(&self.names[index]),
+            )
+            .ok();
+        }
+
+        let edges = self
+            .edges
+            .iter()
+            .map(|edge| [edge.from, edge.to])
+            .collect::<Vec<_>>();
+        let edges = serde_json::to_string(&edges).expect("edges are serializable");
+
+        HTML_TEMPLATE
+            .replace("__WIDTH__", &format!("{:.1}", max_row_width + 2.0 * MARGIN))
+            .replace(
+                "__HEIGHT__",
+                &format!(
+                    "{:.1}",
+                    (max_layer + 1) as f64 * (NODE_HEIGHT + LAYER_GAP) - LAYER_GAP + 2.0 * MARGIN
+                ),
+            )
+            .replace("__EDGES__", &edges)
+            .replace("__SVG__", &svg)
+    }
+
+    /// Writes the graph to `output_name` in the repository, picking the format
+    /// from the file extension. Formats other than `.mermaid` and `.html`
+    /// are rendered by Graphviz, falling back to printing the DOT graph if it
+    /// isn't installed.
+    pub fn write_file(
+        &self,
+        repo_root: &AbsoluteSystemPath,
+        output_name: &str,
+        ui: &UI,
+    ) -> Result<(), Error> {
+        let mut output_path = AbsoluteSystemPathBuf::from_unknown(repo_root, output_name);
+        let extension = match output_path
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some(extension) => extension.to_string(),
+            // Default to .jpg if there's no extension
+            None => {
+                output_path =
+                    AbsoluteSystemPathBuf::from_unknown(repo_root, format!("{output_name}.jpg"));
+                "jpg".to_string()
+            }
+        };
+
+        match extension.as_str() {
+            "mermaid" => {
+                output_path
+                    .create_with_contents(&self.mermaid())
+                    .map_err(|err| Error::Io(output_path.clone(), err))?;
+            }
+            "html" => {
+                output_path
+                    .create_with_contents(&self.html())
+                    .map_err(|err| Error::Io(output_path.clone(), err))?;
+                if atty::is(atty::Stream::Stdout) {
+                    open_in_browser(&output_path);
+                }
+            }
+            _ if which::which("dot").is_ok() => {
+                self.render_with_graphviz(&output_path, &extension)?
+            }
+            _ => {
+                println!();
+                // A file was requested, but we can only print the graph
+                eprintln!(
+                    " WARNING  `turbo` uses Graphviz to generate an image of your\ngraph, but \
+                     Graphviz isn't installed on this machine.\n\nYou can download Graphviz from \
+                     https://graphviz.org/download.\n\nIn the meantime, you can use this string \
+                     output with an\nonline Dot graph viewer."
+                );
+                self.print_dot();
+                return Ok(());
+            }
+        }
+
+        println!();
+        println!(
+            "✔ Generated task graph in {}",
+            ui.apply(BOLD.apply_to(&output_path))
+        );
+        Ok(())
+    }
+
+    /// Prints the DOT graph to stdout.
+    pub fn print_dot(&self) {
+        println!();
+        println!("{}", self.dot());
+    }
+
+    fn render_with_graphviz(
+        &self,
+        output_path: &AbsoluteSystemPath,
+        extension: &str,
+    ) -> Result<(), Error> {
+        let graphviz_error =
+            |err: &dyn std::fmt::Display| Error::Graphviz(output_path.to_owned(), err.to_string());
+        let mut child = Command::new("dot")
+            .arg(format!("-T{extension}"))
+            .arg("-o")
+            .arg(output_path.as_path())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| graphviz_error(&err))?;
+        child
+            .stdin
+            .take()
+            .expect("stdin is piped")
+            .write_all(self.dot().as_bytes())
+            .map_err(|err| graphviz_error(&err))?;
+        let output = child
+            .wait_with_output()
+            .map_err(|err| graphviz_error(&err))?;
+        if !output.status.success() {
+            return Err(graphviz_error(&String::from_utf8_lossy(&output.stderr)));
+        }
+        Ok(())
+    }
+}
+
+// Returns the edges of the transitive reduction of `graph`, or `None` if the
+// graph has a cycle.
+fn transitive_reduction<N>(graph: &Graph<N, ()>) -> Option<HashSet<(NodeIndex, NodeIndex)>> {
+    let sorted = toposort(graph, None).ok()?;
+    let (adjacency, _) = dag_to_toposorted_adjacency_list::<_, DefaultIx>(graph, &sorted);
+    let (reduction, _) = dag_transitive_reduction_closure(&adjacency);
+    Some(
+        reduction
+            .edge_indices()
+            .filter_map(|edge| reduction.edge_endpoints(edge))
+            .map(|(from, to)| (sorted[from as usize], sorted[to as usize]))
+            .collect(),
+    )
+}
+
+fn open_in_browser(path: &AbsoluteSystemPath) {
+    if let Err(err) = webbrowser::open(&path.to_string()) {
+        debug!("failed to open browser: {}", err);
+        eprintln!(
+            "failed to open browser. Please navigate to file://{}",
+            path.to_string().replace('\\', "/")
+        );
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const HTML_TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <title>Task Graph</title>
+  <style>
+    body { font-family: ui-monospace, Menlo, Consolas, monospace; font-size: 13px; margin: 0; }
+    header { padding: 12px 16px; border-bottom: 1px solid #ddd; display: flex; gap: 24px; align-items: center; }
+    .legend { display: inline-flex; align-items: center; gap: 6px; }
+    .legend svg { width: 32px; height: 8px; }
+    .node rect { fill: #fff; stroke: #333; cursor: pointer; }
+    .node text { text-anchor: middle; dominant-baseline: central; pointer-events: none; }
+    .edge { fill: none; stroke: #333; stroke-width: 1.2; }
+    .edge.root { stroke: #aaa; }
+    .edge.transitive { stroke-dasharray: 5 5; }
+    body.hide-root .edge.root, body.hide-transitive .edge.transitive { display: none; }
+    .selected .node, .selected .edge { opacity: 0.15; }
+    .selected .node.active, .selected .edge.active { opacity: 1; }
+    .node.current rect { fill: #222; }
+    .node.current text { fill: #fff; }
+    .node.dependency rect { fill: #dbeafe; stroke: #1d4ed8; }
+    .node.dependent rect { fill: #ffedd5; stroke: #c2410c; }
+  </style>
+</head>
+<body>
+  <header>
+    <label><input type="checkbox" id="show-root" checked> Edges to the root</label>
+    <label><input type="checkbox" id="show-transitive" checked> Transitive edges</label>
+    <span class="legend"><svg><line x1="0" y1="4" x2="32" y2="4" class="edge" /></svg>dependency</span>
+    <span class="legend"><svg><line x1="0" y1="4" x2="32" y2="4" class="edge root" /></svg>root</span>
+    <span class="legend"><svg><line x1="0" y1="4" x2="32" y2="4" class="edge transitive" /></svg>transitive</span>
+    <span>Click a task to see what it depends on and what depends on it</span>
+  </header>
+  <svg id="graph" xmlns="http://www.w3.org/2000/svg" width="__WIDTH__" height="__HEIGHT__">
+    <defs>
+      <marker id="arrow" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="6" markerHeight="6" orient="auto-start-reverse">
+        <path d="M 0 0 L 10 5 L 0 10 z" fill="#333" />
+      </marker>
+    </defs>
+__SVG__  </svg>
+  <script>
+    const edges = __EDGES__;
+    const graph = document.getElementById("graph");
+
+    function reachable(start, forward) {
+      const seen = new Set();
+      const stack = [start];
+      while (stack.length > 0) {
+        const node = stack.pop();
+        edges.forEach(([from, to]) => {
+          const [source, target] = forward ? [from, to] : [to, from];
+          if (source === node && !seen.has(target)) {
+            seen.add(target);
+            stack.push(target);
+          }
+        });
+      }
+      return seen;
+    }
+
+    function clearSelection() {
+      graph.classList.remove("selected");
+      graph.querySelectorAll(".active, .current, .dependency, .dependent").forEach((el) =>
+        el.classList.remove("active", "current", "dependency", "dependent")
+      );
+    }
+
+    function select(node) {
+      clearSelection();
+      const dependencies = reachable(node, true);
+      const dependents = reachable(node, false);
+      const active = new Set([node, ...dependencies, ...dependents]);
+      graph.classList.add("selected");
+      document.getElementById(`node-${node}`).classList.add("active", "current");
+      dependencies.forEach((n) => document.getElementById(`node-${n}`).classList.add("active", "dependency"));
+      dependents.forEach((n) => document.getElementById(`node-${n}`).classList.add("active", "dependent"));
+      edges.forEach(([from, to], i) => {
+        const onPath = (from === node || dependents.has(from)) && (to === node || dependencies.has(to))
+          || dependencies.has(from) && dependencies.has(to)
+          || dependents.has(from) && dependents.has(to);
+        if (onPath && active.has(from) && active.has(to)) {
+          document.getElementById(`edge-${i}`).classList.add("active");
+        }
+      });
+    }
+
+    graph.addEventListener("click", (event) => {
+      const node = event.target.closest(".node");
+      if (node) {
+        select(Number(node.dataset.node));
+      } else {
+        clearSelection();
+      }
+    });
+    document.getElementById("show-root").addEventListener("change", (event) =>
+      document.body.classList.toggle("hide-root", !event.target.checked)
+    );
+    document.getElementById("show-transitive").addEventListener("change", (event) =>
+      document.body.classList.toggle("hide-transitive", !event.target.checked)
+    );
+  </script>
+</body>
+</html>
+"##;
+
+#[cfg(test)]
+mod test {
+    use petgraph::Graph;
+
+    use super::*;
+
+    // a -> b -> c -> root, with a redundant edge from a to c
+    fn graph() -> GraphVisualizer {
+        let mut graph = Graph::new();
+        let root = graph.add_node("___ROOT___");
+        let a = graph.add_node("a#build");
+        let b = graph.add_node("b#build");
+        let c = graph.add_node("c#build");
+        graph.add_edge(c, root, ());
+        graph.add_edge(b, c, ());
+        graph.add_edge(a, c, ());
+        graph.add_edge(a, b, ());
+        GraphVisualizer::new(&graph, root, |name| name.to_string())
+    }
+
+    #[test]
+    fn test_dot() {
+        assert_eq!(
+            graph().dot(),
+            "digraph {\n\tcompound = \"true\"\n\tnewrank = \"true\"\n\tsubgraph \"root\" \
+             {\n\t\t\"[root] a#build\" -> \"[root] b#build\"\n\t\t\"[root] a#build\" -> \"[root] \
+             c#build\"\n\t\t\"[root] b#build\" -> \"[root] c#build\"\n\t\t\"[root] c#build\" -> \
+             \"[root] ___ROOT___\"\n\t}\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_mermaid() {
+        assert_eq!(
+            graph().mermaid(),
+            "graph TD\n\tN1(\"a#build\") --> N2(\"b#build\")\n\tN1(\"a#build\") --> \
+             N3(\"c#build\")\n\tN2(\"b#build\") --> N3(\"c#build\")\n\tN3(\"c#build\") --> \
+             N0(\"___ROOT___\")\n\tlinkStyle 3 stroke:#999\n\tlinkStyle 1 stroke-dasharray:5 5\n"
+        );
+    }
+
+    #[test]
+    fn test_edge_kinds_and_layers() {
+        let graph = graph();
+        let kinds = graph
+            .edges
+            .iter()
+            .map(|edge| (edge.from, edge.to, edge.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                (1, 2, EdgeKind::Direct),
+                (1, 3, EdgeKind::Transitive),
+                (2, 3, EdgeKind::Direct),
+                (3, 0, EdgeKind::Root),
+            ]
+        );
+        assert_eq!(graph.layers, vec![0, 3, 2, 1]);
+    }
+
+    #[test]
+    fn test_html_escapes_names() {
+        let mut graph = Graph::new();
+        let root = graph.add_node("___ROOT___");
+        let task = graph.add_node("<a>#build");
+        graph.add_edge(task, root, ());
+        let html = GraphVisualizer::new(&graph, root, |name| name.to_string()).html();
+
+        assert!(html.contains("&lt;a&gt;#build"));
+        assert!(!html.contains("<a>"));
+        assert!(html.contains("const edges = [[1,0]];"));
+        assert!(html.contains(r#"class="edge root""#));
+    }
+}
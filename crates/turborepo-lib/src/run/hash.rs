@@ -0,0 +1,96 @@
+//! Go hashes values by formatting them with `%v` and running the result
+//! through xxHash64 (`fs.HashObject`). Mixed Go and Rust teams need to share
+//! cache artifacts, so we build the exact same representation here.
+
+use std::hash::Hasher;
+
+use turbopath::{PathError, RelativeUnixPathBuf};
+use turborepo_lockfiles::Package;
+use turborepo_scm::package_deps::GitHashes;
+use twox_hash::XxHash64;
+
+/// Hashes the `%v` representation of a value, as created by the helpers in
+/// this module.
+pub fn hash_object(representation: &str) -> String {
+    let mut hasher = XxHash64::with_seed(0);
+    hasher.write(representation.as_bytes());
+    format!("{:016x}", hasher.finish())
+}
+
+/// Formats a slice the way Go does: `[a b c]`. `nil` and empty slices are
+/// both formatted as `[]`.
+pub fn format_slice<S: AsRef<str>>(items: &[S]) -> String {
+    let items: Vec<&str> = items.iter().map(|item| item.as_ref()).collect();
+    format!("[{}]", items.join(" "))
+}
+
+pub fn format_paths(paths: &[RelativeUnixPathBuf]) -> Result<String, PathError> {
+    let paths = paths
+        .iter()
+        .map(|path| path.as_str())
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(format_slice(&paths))
+}
+
+/// Formats file hashes as a Go map, which prints its entries sorted by key:
+/// `map[a:hash b:hash]`.
+pub fn format_file_hashes(hashes: &GitHashes) -> Result<String, PathError> {
+    let mut entries = hashes.iter().collect::<Vec<_>>();
+    entries.sort();
+    let entries = entries
+        .into_iter()
+        .map(|(path, hash)| Ok(format!("{}:{}", path.as_str()?, hash)))
+        .collect::<Result<Vec<_>, PathError>>()?;
+    Ok(format!("map[{}]", entries.join(" ")))
+}
+
+pub fn hash_file_hashes(hashes: &GitHashes) -> Result<String, PathError> {
+    Ok(hash_object(&format_file_hashes(hashes)?))
+}
+
+/// Hashes the external dependencies of a workspace. Go sorts packages by
+/// their concatenated key and version, and every package found in the
+/// lockfile is marked as found.
+pub fn hash_external_dependencies<'a>(packages: impl Iterator<Item = &'a Package>) -> String {
+    let mut packages = packages
+        .map(|package| (format!("{}{}", package.key, package.version), package))
+        .collect::<Vec<_>>();
+    packages.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let packages = packages
+        .into_iter()
+        .map(|(_, package)| format!("{{{} {} true}}", package.key, package.version))
+        .collect::<Vec<_>>();
+    hash_object(&format_slice(&packages))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hash_object() {
+        // xxHash64 test vector for the empty input
+        assert_eq!(hash_object(""), "ef46db3751d8e999");
+    }
+
+    #[test]
+    fn test_format_file_hashes() -> Result<(), PathError> {
+        let hashes: GitHashes = [("package.json", "b"), ("a/b.js", "c"), ("README.md", "a")]
+            .into_iter()
+            .map(|(path, hash)| Ok((RelativeUnixPathBuf::new(path)?, hash.to_string())))
+            .collect::<Result<_, PathError>>()?;
+
+        assert_eq!(
+            format_file_hashes(&hashes)?,
+            "map[README.md:a a/b.js:c package.json:b]"
+        );
+        assert_eq!(format_file_hashes(&GitHashes::new())?, "map[]");
+        Ok(())
+    }
+
+    #[test]
+    fn test_format_slice() {
+        assert_eq!(format_slice::<&str>(&[]), "[]");
+        assert_eq!(format_slice(&["a", "b c"]), "[a b c]");
+    }
+}
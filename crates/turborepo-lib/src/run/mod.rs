@@ -1,35 +1,65 @@
 #![allow(dead_code)]
 
-mod global_hash;
+mod cache;
+mod exec;
+pub(crate) mod global_hash;
 pub mod graph;
-mod scope;
-mod task_id;
+pub(crate) mod graph_visualizer;
+pub(crate) mod hash;
+mod output;
+pub(crate) mod scope;
+pub(crate) mod summary;
+pub(crate) mod task_hash;
+pub(crate) mod task_id;
 
-use anyhow::{Context as ErrorContext, Result};
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+};
+
+use anyhow::{anyhow, Context as ErrorContext, Result};
+use futures::{stream, StreamExt};
 use graph::CompleteGraph;
-use tracing::{debug, info};
+use itertools::Itertools;
+use tracing::{debug, info, warn};
+use turborepo_api_client::APIAuth;
+use turborepo_cache::multiplexer::CacheMultiplexer;
 use turborepo_env::EnvironmentVariableMap;
 
 use crate::{
+    cli::EnvMode,
     commands::CommandBase,
-    daemon::DaemonConnector,
-    manager::Manager,
+    daemon::{self, DaemonConnector},
+    engine::{Engine, EngineBuilder, ExecutionOptions},
+    get_version,
+    manager::{self, Manager},
     opts::Opts,
     package_graph::PackageGraph,
     package_json::PackageJson,
-    run::{global_hash::get_global_hash_inputs, task_id::ROOT_PKG_NAME},
+    run::{
+        cache::RunCache,
+        exec::ExecContext,
+        global_hash::get_global_hash_inputs,
+        output::ColorSelector,
+        summary::{
+            ExecutionTracker, GlobalHashSummary, RunSummary, ScmState, TaskCacheSummary,
+            TaskSummary,
+        },
+        task_hash::{PackageInputsHashes, TaskHasher},
+        task_id::ROOT_PKG_NAME,
+    },
+    task_graph::Pipeline,
+    ui::BOLD_RED,
 };
 
 #[derive(Debug)]
 pub struct Run {
     base: CommandBase,
-    processes: Manager,
 }
 
 impl Run {
     pub fn new(base: CommandBase) -> Self {
-        let processes = Manager::new();
-        Self { base, processes }
+        Self { base }
     }
 
     fn targets(&self) -> &[String] {
@@ -40,23 +70,61 @@ impl Run {
         self.base.args().try_into()
     }
 
-    pub async fn run(&mut self) -> Result<()> {
-        let _start_at = std::time::Instant::now();
+    // Remote caching requires the user to be logged in and linked to a team.
+    fn api_auth(&self) -> Result<Option<APIAuth>> {
+        let repo_config = self.base.repo_config()?;
+        let user_config = self.base.user_config()?;
+        let (Some(team_id), Some(token)) = (repo_config.team_id(), user_config.token()) else {
+            return Ok(None);
+        };
+
+        Ok(Some(APIAuth {
+            team_id: team_id.to_string(),
+            token: token.to_string(),
+            team_slug: repo_config.team_slug().map(|slug| slug.to_string()),
+        }))
+    }
+
+    fn build_engine(
+        &self,
+        package_graph: &PackageGraph,
+        complete_graph: &CompleteGraph,
+        opts: &Opts,
+        pipeline: &Pipeline,
+        filtered_pkgs: &HashSet<String>,
+        workspace_dependencies: bool,
+    ) -> Result<Engine> {
+        let engine = EngineBuilder::new(package_graph, pipeline, opts.run_opts.single_package)
+            .with_workspace_turbo_jsons(complete_graph)
+            .with_workspaces(filtered_pkgs.iter().cloned().sorted().collect())
+            .with_tasks(opts.run_opts.tasks.to_vec())
+            .with_tasks_only(opts.run_opts.only)
+            .with_workspace_dependencies(workspace_dependencies)
+            .build()?;
+
+        // The parallel flag ignores both concurrency and dependencies, so
+        // persistent tasks can't block anything
+        if !opts.run_opts.parallel {
+            engine.validate_persistent_dependencies(package_graph, opts.run_opts.concurrency)?;
+        }
+
+        Ok(engine)
+    }
+
+    /// Runs the requested tasks, returning the exit code for turbo.
+    pub async fn run(&mut self) -> Result<i32> {
+        self.run_to(&mut io::stdout()).await
+    }
+
+    /// Like `run`, but a dry run's report is written to `stdout`.
+    async fn run_to(&mut self, stdout: &mut (impl Write + Send)) -> Result<i32> {
+        let started_at = chrono::Local::now();
         let package_json_path = self.base.repo_root.join_component("package.json");
         let root_package_json =
             PackageJson::load(&package_json_path).context("failed to read package.json")?;
         let targets = self.targets();
         let mut opts = self.opts()?;
 
-        let _is_structured_output = opts.run_opts.graph_dot || opts.run_opts.dry_run_json;
-
-        let pkg_dep_graph = if opts.run_opts.single_package {
-            PackageGraph::build_single_package_graph(&root_package_json)?
-        } else {
-            PackageGraph::build_multi_package_graph(&self.base.repo_root, &root_package_json)?
-        };
-        // There's some warning handling code in Go that I'm ignoring
-
         if self.base.ui.is_ci() && !opts.run_opts.no_daemon {
             info!("skipping turbod since we appear to be in a non-interactive context");
         } else if !opts.run_opts.no_daemon {
@@ -72,6 +140,18 @@ impl Run {
             opts.runcache_opts.output_watcher = Some(client);
         }
 
+        let pkg_dep_graph = if opts.run_opts.single_package {
+            PackageGraph::build_single_package_graph(&root_package_json)?
+        } else {
+            daemon::package_graph(
+                opts.runcache_opts.output_watcher.as_mut(),
+                &self.base.repo_root,
+                &root_package_json,
+            )
+            .await?
+        };
+        // There's some warning handling code in Go that I'm ignoring
+
         pkg_dep_graph
             .validate()
             .context("Invalid package dependency graph")?;
@@ -89,10 +169,15 @@ impl Run {
 
         let pipeline = &turbo_json.pipeline;
 
-        let mut filtered_pkgs =
-            scope::resolve_packages(&opts.scope_opts, &self.base, &pkg_dep_graph)?;
+        let (mut filtered_pkgs, is_all_packages) = scope::resolve_packages(
+            &opts.scope_opts,
+            &self.base,
+            &pkg_dep_graph,
+            opts.runcache_opts.output_watcher.as_mut(),
+        )
+        .await?;
 
-        if filtered_pkgs.len() == pkg_dep_graph.len() {
+        if is_all_packages {
             for target in targets {
                 let key = task_id::root_task_id(target);
                 if pipeline.contains_key(&key) {
@@ -104,23 +189,219 @@ impl Run {
 
         let env_at_execution_start = EnvironmentVariableMap::infer();
 
-        let _global_hash_inputs = get_global_hash_inputs(
+        let root_workspace = pkg_dep_graph
+            .workspace_info(ROOT_PKG_NAME)
+            .context("missing root workspace")?;
+        let global_hash_inputs = get_global_hash_inputs(
             &self.base.ui,
             &self.base.repo_root,
-            &root_package_json,
+            root_workspace,
             pkg_dep_graph.package_manager(),
-            pkg_dep_graph.lockfile(),
-            // TODO: Fill in these vec![] once turbo.json is ported
-            vec![],
+            pkg_dep_graph.has_lockfile(),
+            &turbo_json.global_dependencies,
             &env_at_execution_start,
-            vec![],
-            vec![],
+            &turbo_json.global_env,
+            turbo_json.global_pass_through_env.as_deref(),
             opts.run_opts.env_mode,
             opts.run_opts.framework_inference,
-            vec![],
+            turbo_json.global_dot_env.as_deref(),
         )?;
+        let global_hash = global_hash_inputs.calculate_global_hash()?;
+        debug!("global hash: {}", global_hash);
 
-        Ok(())
+        let cache = CacheMultiplexer::new(
+            &opts.cache_opts,
+            &self.base.repo_root,
+            self.base.api_client()?,
+            self.api_auth()?,
+        )?;
+        let run_cache = RunCache::new(cache, &self.base.repo_root, &opts.runcache_opts);
+
+        let mut engine =
+            self.build_engine(&pkg_dep_graph, &g, &opts, pipeline, &filtered_pkgs, true)?;
+
+        // If we are running in parallel, then we remove the dependencies between
+        // workspaces. We still use dependencies between tasks of the same
+        // workspace as specified by the pipeline configuration.
+        if opts.run_opts.parallel {
+            engine =
+                self.build_engine(&pkg_dep_graph, &g, &opts, pipeline, &filtered_pkgs, false)?;
+        }
+
+        if opts.run_opts.graph_dot {
+            engine.visualizer(is_single_package).print_dot();
+            return Ok(0);
+        }
+        if let Some(graph_file) = opts.run_opts.graph_file {
+            engine.visualizer(is_single_package).write_file(
+                &self.base.repo_root,
+                graph_file,
+                &self.base.ui,
+            )?;
+            return Ok(0);
+        }
+
+        let package_inputs_hashes = PackageInputsHashes::calculate_file_hashes(
+            engine.tasks(),
+            &pkg_dep_graph.workspace_infos(),
+            engine.task_definitions(),
+            &self.base.repo_root,
+            opts.runcache_opts.output_watcher.as_mut(),
+        )
+        .await?;
+        let task_hasher =
+            TaskHasher::new(global_hash, &env_at_execution_start, package_inputs_hashes);
+
+        let mut global_env_mode = opts.run_opts.env_mode;
+        if global_env_mode == EnvMode::Infer && turbo_json.global_pass_through_env.is_some() {
+            global_env_mode = EnvMode::Strict;
+        }
+
+        let build_run_summary = |execution, tasks| -> Result<RunSummary> {
+            let pass_through_env = turbo_json
+                .global_pass_through_env
+                .as_ref()
+                .map(|pass_through_env| env_at_execution_start.from_wildcards(pass_through_env))
+                .transpose()?;
+            Ok(RunSummary::new(
+                get_version(),
+                is_single_package,
+                filtered_pkgs.iter().cloned().collect(),
+                global_env_mode,
+                opts.run_opts.framework_inference,
+                GlobalHashSummary::new(&global_hash_inputs, pass_through_env)?,
+                execution,
+                tasks,
+                summary::get_user(&env_at_execution_start, &self.base.repo_root),
+                ScmState::get(&self.base.repo_root),
+            ))
+        };
+
+        let command = opts.run_opts.synthesize_command();
+        let execution_tracker = ExecutionTracker::new(started_at);
+        let processes = Manager::new().with_grace_period(opts.run_opts.grace_period);
+        let exec_context = ExecContext {
+            engine: &engine,
+            task_hasher: &task_hasher,
+            global_env_mode,
+            framework_inference: opts.run_opts.framework_inference,
+            env_at_execution_start: &env_at_execution_start,
+            run_cache: &run_cache,
+            execution_tracker: &execution_tracker,
+            repo_root: &self.base.repo_root,
+            package_graph: &pkg_dep_graph,
+            ui: &self.base.ui,
+            manager: processes.clone(),
+            targets,
+            passthrough_args: opts.run_opts.passthrough_args,
+            continue_on_error: opts.run_opts.continue_on_error,
+            is_single_package,
+            log_prefix: opts.run_opts.log_prefix,
+            log_order: opts.run_opts.log_order,
+            color_selector: ColorSelector::default(),
+            dry_run: opts.run_opts.dry_run,
+        };
+
+        if opts.run_opts.dry_run {
+            // Dry runs walk the graph one task at a time, since nothing is
+            // executed
+            let errors = engine
+                .execute(ExecutionOptions::new(false, 1), |task_id| {
+                    exec_context.exec(task_id)
+                })
+                .await;
+            if !errors.is_empty() {
+                for err in &errors {
+                    eprintln!("{}", self.base.ui.apply(BOLD_RED.apply_to(err)));
+                }
+                return Err(anyhow!("errors occurred during dry-run graph traversal"));
+            }
+
+            let mut tasks = execution_tracker.finish_dry_run();
+            populate_cache_state(&run_cache, &mut tasks).await;
+            let run_summary = build_run_summary(None, tasks)?;
+            if opts.run_opts.dry_run_json {
+                writeln!(stdout, "{}", run_summary.to_json()?)?;
+            } else {
+                write!(
+                    stdout,
+                    "{}",
+                    run_summary.format_text(&self.base.ui, &pkg_dep_graph)
+                )?;
+            }
+
+            return Ok(0);
+        }
+
+        // Tasks run in their own process groups, so they don't see signals sent
+        // to turbo unless they're passed on
+        let signal_processes = processes.clone();
+        let signal_handler = tokio::spawn(async move {
+            if let Some(signal) = manager::wait_for_signal().await {
+                signal_processes.stop_with_signal(signal);
+            }
+        });
+
+        let errors = engine
+            .execute(
+                ExecutionOptions::new(opts.run_opts.parallel, opts.run_opts.concurrency),
+                |task_id| exec_context.exec(task_id),
+            )
+            .await;
+        signal_handler.abort();
+        // Anything the tasks left running in the background is stopped, rather
+        // than outliving turbo
+        processes.stop();
+        processes.wait_for_shutdown().await;
+
+        // The exit code is the highest exit code of any failed task
+        let mut exit_code = 0;
+        for err in &errors {
+            let error_exit_code = match err.downcast_ref::<manager::Error>() {
+                // A child killed by a signal is reported as -1, take the absolute
+                // value so that it isn't treated as a success
+                Some(manager::Error::ChildExit { exit_code, .. }) => exit_code.abs(),
+                _ => 1,
+            };
+            exit_code = exit_code.max(error_exit_code);
+            eprintln!("{}", self.base.ui.apply(BOLD_RED.apply_to(err)));
+        }
+
+        if opts.run_opts.summarize {
+            let (execution, tasks) = execution_tracker.finish(command, exit_code);
+            let run_summary = build_run_summary(Some(execution), tasks)?;
+            match run_summary.save(&self.base.repo_root) {
+                Ok(path) => debug!("wrote run summary to {}", path),
+                Err(err) => warn!("Error writing run summary: {}", err),
+            }
+        }
+
+        Ok(exit_code)
+    }
+}
+
+// Checks which tasks of a dry run are cached. We make at most 8 requests at a
+// time.
+async fn populate_cache_state(run_cache: &RunCache, tasks: &mut [TaskSummary]) {
+    const MAX_PARALLEL_REQUESTS: usize = 8;
+
+    let cache_summaries = stream::iter(tasks.iter())
+        .map(|task| async move {
+            match run_cache.exists(&task.hash).await {
+                Ok(Some(response)) => TaskCacheSummary::from(response),
+                Ok(None) => TaskCacheSummary::miss(),
+                Err(err) => {
+                    debug!("failed to check cache for {}: {}", task.hash, err);
+                    TaskCacheSummary::miss()
+                }
+            }
+        })
+        .buffered(MAX_PARALLEL_REQUESTS)
+        .collect::<Vec<_>>()
+        .await;
+
+    for (task, cache_summary) in tasks.iter_mut().zip(cache_summaries) {
+        task.cache_summary = cache_summary;
     }
 }
 
@@ -133,7 +414,7 @@ mod test {
     use turbopath::AbsoluteSystemPathBuf;
 
     use crate::{
-        cli::{Command, RunArgs},
+        cli::{Command, DryRunMode, RunArgs},
         commands::CommandBase,
         get_version,
         run::Run,
@@ -156,10 +437,164 @@ mod test {
         let ui = UI::infer();
 
         // Add package.json
-        fs::write(repo_root.join_component("package.json"), "{}")?;
+        fs::write(
+            repo_root.join_component("package.json"),
+            r#"{"packageManager": "npm@8.19.4", "workspaces": ["packages/*"]}"#,
+        )?;
+        fs::write(
+            repo_root.join_component("turbo.json"),
+            r#"{"pipeline": {"build": {"dependsOn": ["^build"]}}}"#,
+        )?;
+        let workspace_dir = repo_root.join_components(&["packages", "a"]);
+        workspace_dir.create_dir_all()?;
+        fs::write(
+            workspace_dir.join_component("package.json"),
+            r#"{"name": "a"}"#,
+        )?;
 
         let base = CommandBase::new(args, repo_root, get_version(), ui)?;
         let mut run = Run::new(base);
-        run.run().await
+        assert_eq!(run.run().await?, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_dry_run() -> Result<()> {
+        let dir = tempdir()?;
+        let repo_root = AbsoluteSystemPathBuf::new(dir.path())?;
+        let mut args = Args::default();
+        let run_args = RunArgs {
+            no_daemon: true,
+            dry_run: Some(DryRunMode::Json),
+            tasks: vec!["build".to_string()],
+            ..Default::default()
+        };
+        args.command = Some(Command::Run(Box::new(run_args)));
+
+        fs::write(
+            repo_root.join_component("package.json"),
+            r#"{"packageManager": "npm@8.19.4", "workspaces": ["packages/*"]}"#,
+        )?;
+        fs::write(
+            repo_root.join_component("turbo.json"),
+            r#"{"pipeline": {"build": {"dependsOn": ["^build"]}}}"#,
+        )?;
+        let workspace_dir = repo_root.join_components(&["packages", "a"]);
+        workspace_dir.create_dir_all()?;
+        // The script would fail if it was executed
+        fs::write(
+            workspace_dir.join_component("package.json"),
+            r#"{"name": "a", "scripts": {"build": "exit 1"}}"#,
+        )?;
+
+        let base = CommandBase::new(args, repo_root.clone(), get_version(), UI::new(true))?;
+        let mut run = Run::new(base);
+        let mut stdout = Vec::new();
+        assert_eq!(run.run_to(&mut stdout).await?, 0);
+
+        let summary: serde_json::Value = serde_json::from_slice(&stdout)?;
+        assert!(summary.get("execution").is_none());
+        let tasks = summary["tasks"].as_array().unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0]["taskId"], "a#build");
+        assert_eq!(tasks[0]["command"], "exit 1");
+        assert_eq!(tasks[0]["cache"]["status"], "MISS");
+        assert!(tasks[0].get("execution").is_none());
+
+        // The text report lists the same task
+        let args = Args {
+            command: Some(Command::Run(Box::new(RunArgs {
+                no_daemon: true,
+                dry_run: Some(DryRunMode::Text),
+                tasks: vec!["build".to_string()],
+                ..Default::default()
+            }))),
+            ..Default::default()
+        };
+        let base = CommandBase::new(args, repo_root, get_version(), UI::new(true))?;
+        let mut run = Run::new(base);
+        let mut stdout = Vec::new();
+        assert_eq!(run.run_to(&mut stdout).await?, 0);
+
+        let report = String::from_utf8(stdout)?;
+        assert!(report.contains("a#build"), "{report}");
+        assert!(report.contains("exit 1"), "{report}");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_run_summary() -> Result<()> {
+        let dir = tempdir()?;
+        let repo_root = AbsoluteSystemPathBuf::new(dir.path())?;
+        let mut args = Args::default();
+        let run_args = RunArgs {
+            no_daemon: true,
+            summarize: Some(Some(true)),
+            tasks: vec!["build".to_string()],
+            ..Default::default()
+        };
+        args.command = Some(Command::Run(Box::new(run_args)));
+
+        fs::write(
+            repo_root.join_component("package.json"),
+            r#"{"packageManager": "npm@8.19.4", "workspaces": ["packages/*"]}"#,
+        )?;
+        fs::write(
+            repo_root.join_component("turbo.json"),
+            r#"{"pipeline": {"build": {"dependsOn": ["^build"]}}}"#,
+        )?;
+        let workspace_dir = repo_root.join_components(&["packages", "a"]);
+        workspace_dir.create_dir_all()?;
+        fs::write(
+            workspace_dir.join_component("package.json"),
+            r#"{"name": "a"}"#,
+        )?;
+        let workspace_dir = repo_root.join_components(&["packages", "b"]);
+        workspace_dir.create_dir_all()?;
+        fs::write(
+            workspace_dir.join_component("package.json"),
+            r#"{"name": "b", "dependencies": {"a": "*"}, "scripts": {"build": "echo building"}}"#,
+        )?;
+
+        let base = CommandBase::new(args, repo_root.clone(), get_version(), UI::infer())?;
+        let mut run = Run::new(base);
+        assert_eq!(run.run().await?, 0);
+
+        let runs_dir = repo_root.join_components(&[".turbo", "runs"]);
+        let summaries = fs::read_dir(runs_dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(
+            summaries[0].extension().and_then(|ext| ext.to_str()),
+            Some("json")
+        );
+
+        let summary: serde_json::Value = serde_json::from_slice(&fs::read(&summaries[0])?)?;
+        assert_eq!(summary["version"], "1");
+        assert_eq!(summary["monorepo"], true);
+        assert_eq!(summary["packages"], serde_json::json!(["a", "b"]));
+        assert_eq!(summary["execution"]["command"], "turbo run build");
+        assert_eq!(summary["execution"]["exitCode"], 0);
+        assert_eq!(summary["execution"]["success"], 1);
+
+        let tasks = summary["tasks"].as_array().unwrap();
+        assert_eq!(tasks.len(), 2);
+        // `a` has no build script, so it's summarized without an execution
+        assert_eq!(tasks[0]["taskId"], "a#build");
+        assert_eq!(tasks[0]["command"], "<NONEXISTENT>");
+        assert_eq!(tasks[0]["dependents"], serde_json::json!(["b#build"]));
+        assert!(tasks[0].get("execution").is_none());
+
+        assert_eq!(tasks[1]["taskId"], "b#build");
+        assert_eq!(tasks[1]["package"], "b");
+        assert_eq!(tasks[1]["command"], "echo building");
+        assert_eq!(tasks[1]["dependencies"], serde_json::json!(["a#build"]));
+        assert_eq!(tasks[1]["cache"]["status"], "MISS");
+        assert_eq!(tasks[1]["execution"]["exitCode"], 0);
+
+        Ok(())
     }
 }
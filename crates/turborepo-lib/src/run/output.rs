@@ -0,0 +1,336 @@
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    sync::{Arc, Mutex},
+};
+
+use console::Style;
+use lazy_static::lazy_static;
+use turbopath::AbsoluteSystemPath;
+
+use crate::{
+    cli::LogOrder,
+    ui::{GREY, UI},
+};
+
+lazy_static! {
+    static ref PREFIX_COLORS: [Style; 5] = [
+        Style::new().cyan(),
+        Style::new().magenta(),
+        Style::new().green(),
+        Style::new().yellow(),
+        Style::new().blue(),
+    ];
+}
+
+/// Hands out a color for each workspace's log prefix. A workspace keeps its
+/// color for the whole run, so that its output is easy to follow.
+#[derive(Debug, Default)]
+pub struct ColorSelector {
+    colors: Mutex<PrefixColors>,
+}
+
+#[derive(Debug, Default)]
+struct PrefixColors {
+    index: usize,
+    by_key: HashMap<String, usize>,
+}
+
+impl ColorSelector {
+    fn color_for_key(&self, key: &str) -> &'static Style {
+        let mut colors = self.colors.lock().expect("color selector lock poisoned");
+        let index = match colors.by_key.get(key) {
+            Some(index) => *index,
+            None => {
+                colors.index += 1;
+                let index = colors.index % PREFIX_COLORS.len();
+                colors.by_key.insert(key.to_string(), index);
+                index
+            }
+        };
+        &PREFIX_COLORS[index]
+    }
+
+    /// Colors `prefix` with the color assigned to `key`.
+    pub fn prefix_with_color(&self, ui: &UI, key: &str, prefix: &str) -> String {
+        if prefix.is_empty() {
+            return String::new();
+        }
+
+        ui.apply(self.color_for_key(key).apply_to(prefix))
+            .to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// The terminal output of a single task. Every line is prefixed with the
+/// task's prefix. When logs are grouped, output is held back until the task
+/// finishes and then printed all at once.
+#[derive(Debug)]
+pub struct TaskOutput {
+    prefix: String,
+    ui: UI,
+    grouped: Option<Mutex<GroupedOutput>>,
+}
+
+#[derive(Debug, Default)]
+struct GroupedOutput {
+    stdout: Vec<u8>,
+    stderr: Vec<u8>,
+}
+
+impl TaskOutput {
+    pub fn new(prefix: String, ui: UI, log_order: LogOrder) -> Self {
+        let grouped = match log_order {
+            LogOrder::Grouped => Some(Mutex::default()),
+            LogOrder::Auto | LogOrder::Stream => None,
+        };
+
+        Self {
+            prefix,
+            ui,
+            grouped,
+        }
+    }
+
+    /// Writes a single line of output, prefixed and terminated by a newline.
+    pub fn line(&self, stream: OutputStream, line: &[u8]) {
+        let mut bytes = Vec::with_capacity(self.prefix.len() + line.len() + 1);
+        bytes.extend_from_slice(self.prefix.as_bytes());
+        bytes.extend_from_slice(line);
+        if !bytes.ends_with(b"\n") {
+            bytes.push(b'\n');
+        }
+        self.write(stream, &bytes);
+    }
+
+    pub fn info(&self, message: impl Display) {
+        self.line(OutputStream::Stdout, message.to_string().as_bytes());
+    }
+
+    pub fn error(&self, message: impl Display) {
+        self.line(OutputStream::Stderr, message.to_string().as_bytes());
+    }
+
+    /// Dims `text` for status messages, such as the task hash.
+    pub fn dim(&self, text: &str) -> String {
+        self.ui.apply(GREY.apply_to(text)).to_string()
+    }
+
+    fn write(&self, stream: OutputStream, bytes: &[u8]) {
+        match &self.grouped {
+            Some(grouped) => {
+                let mut grouped = grouped.lock().expect("task output lock poisoned");
+                match stream {
+                    OutputStream::Stdout => grouped.stdout.extend_from_slice(bytes),
+                    OutputStream::Stderr => grouped.stderr.extend_from_slice(bytes),
+                }
+            }
+            // Lines are written with a single call so that output from tasks
+            // running concurrently doesn't get interleaved mid-line
+            None => {
+                let result = match stream {
+                    OutputStream::Stdout => io::stdout().write_all(bytes),
+                    OutputStream::Stderr => io::stderr().write_all(bytes),
+                };
+                result.ok();
+            }
+        }
+    }
+}
+
+impl Drop for TaskOutput {
+    // Grouped output is printed once nothing else can write to it, which is
+    // when the task has finished
+    fn drop(&mut self) {
+        let Some(grouped) = self.grouped.take() else {
+            return;
+        };
+        let grouped = grouped.into_inner().unwrap_or_else(|err| err.into_inner());
+        if !grouped.stdout.is_empty() {
+            io::stdout().lock().write_all(&grouped.stdout).ok();
+        }
+        if !grouped.stderr.is_empty() {
+            io::stderr().lock().write_all(&grouped.stderr).ok();
+        }
+    }
+}
+
+/// Where the output of a running task goes: the terminal, unless the task's
+/// output mode hides it, and the task's log file when its outputs are cached.
+#[derive(Debug, Default)]
+pub struct OutputWriter {
+    terminal: Option<Arc<TaskOutput>>,
+    log_file: Option<Mutex<BufWriter<File>>>,
+}
+
+impl OutputWriter {
+    pub fn with_terminal(mut self, terminal: Arc<TaskOutput>) -> Self {
+        self.terminal = Some(terminal);
+        self
+    }
+
+    /// Creates the log file, truncating any log from a previous run.
+    pub fn with_log_file(mut self, log_file: &AbsoluteSystemPath) -> io::Result<Self> {
+        log_file.ensure_dir()?;
+        let file = File::create(log_file.as_path())?;
+        self.log_file = Some(Mutex::new(BufWriter::new(file)));
+        Ok(self)
+    }
+
+    /// Writes a single line of output from the task. The log file gets the
+    /// line as the task wrote it, the terminal gets it prefixed.
+    pub fn write_line(&self, stream: OutputStream, line: &[u8]) -> io::Result<()> {
+        if let Some(terminal) = &self.terminal {
+            terminal.line(stream, line);
+        }
+        if let Some(log_file) = &self.log_file {
+            let mut log_file = log_file.lock().expect("log file lock poisoned");
+            log_file.write_all(line)?;
+            if !line.ends_with(b"\n") {
+                log_file.write_all(b"\n")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&self) -> io::Result<()> {
+        match &self.log_file {
+            Some(log_file) => log_file.lock().expect("log file lock poisoned").flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Writes the log of a previous run of a task back out to the terminal.
+pub fn replay_log_file(log_file: &AbsoluteSystemPath, output: &TaskOutput) -> io::Result<()> {
+    let reader = BufReader::new(File::open(log_file.as_path())?);
+    for line in reader.split(b'\n') {
+        output.line(OutputStream::Stdout, &line?);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+
+    use tempfile::tempdir;
+    use turbopath::AbsoluteSystemPathBuf;
+
+    use super::*;
+
+    fn grouped_output(prefix: &str) -> TaskOutput {
+        TaskOutput::new(prefix.to_string(), UI::new(true), LogOrder::Grouped)
+    }
+
+    // Takes the held back output, so that nothing is printed when the task
+    // output is dropped
+    fn take_output(output: &TaskOutput) -> (String, String) {
+        let mut grouped = output.grouped.as_ref().unwrap().lock().unwrap();
+        (
+            String::from_utf8(std::mem::take(&mut grouped.stdout)).unwrap(),
+            String::from_utf8(std::mem::take(&mut grouped.stderr)).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_prefix_colors_are_stable() {
+        let selector = ColorSelector::default();
+        let first = selector.color_for_key("web");
+        assert!(!std::ptr::eq(first, selector.color_for_key("docs")));
+        assert!(std::ptr::eq(first, selector.color_for_key("web")));
+
+        for key in ["a", "b", "c"] {
+            selector.color_for_key(key);
+        }
+        // Colors wrap around once every color has been handed out
+        assert!(std::ptr::eq(first, selector.color_for_key("d")));
+    }
+
+    #[test]
+    fn test_prefix_with_color() {
+        let selector = ColorSelector::default();
+        assert_eq!(
+            selector.prefix_with_color(&UI::new(true), "web", "web:build: "),
+            "web:build: "
+        );
+        assert_eq!(
+            selector.prefix_with_color(&UI::new(false), "web", "web:build: "),
+            "\u{1b}[35mweb:build: \u{1b}[0m"
+        );
+        assert_eq!(selector.prefix_with_color(&UI::new(false), "web", ""), "");
+    }
+
+    #[test]
+    fn test_grouped_output_is_held_back() {
+        let output = grouped_output("web:build: ");
+        output.line(OutputStream::Stdout, b"first\n");
+        output.line(OutputStream::Stderr, b"oops");
+        output.info("second");
+
+        assert_eq!(
+            take_output(&output),
+            (
+                "web:build: first\nweb:build: second\n".to_string(),
+                "web:build: oops\n".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_output_writer() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let log_file =
+            AbsoluteSystemPathBuf::new(dir.path())?.join_components(&[".turbo", "turbo.log"]);
+        let terminal = Arc::new(grouped_output("web:build: "));
+
+        let writer = OutputWriter::default()
+            .with_terminal(terminal.clone())
+            .with_log_file(&log_file)?;
+        writer.write_line(OutputStream::Stdout, b"building\n")?;
+        writer.write_line(OutputStream::Stderr, b"warning")?;
+        writer.flush()?;
+
+        assert_eq!(fs::read_to_string(&log_file)?, "building\nwarning\n");
+        assert_eq!(
+            take_output(&terminal),
+            (
+                "web:build: building\n".to_string(),
+                "web:build: warning\n".to_string()
+            )
+        );
+
+        let writer = OutputWriter::default().with_log_file(&log_file)?;
+        writer.write_line(OutputStream::Stdout, b"hidden\n")?;
+        writer.flush()?;
+        assert_eq!(fs::read_to_string(&log_file)?, "hidden\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_replay_log_file() -> anyhow::Result<()> {
+        let dir = tempdir()?;
+        let log_file = AbsoluteSystemPathBuf::new(dir.path())?.join_component("turbo.log");
+        fs::write(&log_file, "one\n\ntwo\n")?;
+
+        let output = grouped_output("web:build: ");
+        replay_log_file(&log_file, &output)?;
+        assert_eq!(
+            take_output(&output).0,
+            "web:build: one\nweb:build: \nweb:build: two\n"
+        );
+
+        Ok(())
+    }
+}
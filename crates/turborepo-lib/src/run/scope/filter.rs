@@ -0,0 +1,474 @@
+use std::collections::HashSet;
+
+use anyhow::{anyhow, bail, Result};
+use regex::Regex;
+use turbopath::AnchoredSystemPathBuf;
+use wax::{Glob, Pattern};
+
+use super::target_selector::TargetSelector;
+use crate::{package_graph::PackageGraph, run::task_id::ROOT_PKG_NAME};
+
+/// What we know about the workspaces of interest from the directory turbo was
+/// invoked in.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PackageInference {
+    // If set, filters without a workspace name select this workspace
+    package_name: Option<String>,
+    // Filters with a directory are relative to this directory. Filters
+    // without one select every workspace below it, unless `package_name` is
+    // set.
+    directory_root: String,
+}
+
+impl PackageInference {
+    /// Infers the workspaces of interest from `pkg_inference_path`, the
+    /// invocation directory relative to the repo root.
+    pub fn calculate(
+        pkg_inference_path: &AnchoredSystemPathBuf,
+        pkg_graph: &PackageGraph,
+    ) -> Result<Self> {
+        let directory_root = pkg_inference_path.to_unix()?.as_str()?.to_string();
+        for (name, info) in pkg_graph.workspace_infos().iter() {
+            // the inferred path is always below the root workspace
+            if name == ROOT_PKG_NAME {
+                continue;
+            }
+            let package_path = info.package_path().to_unix()?.as_str()?.to_string();
+            if path_contains(&package_path, &directory_root) {
+                // the user may still have set a directory filter, in which
+                // case we should consistently fail to find any workspace
+                return Ok(Self {
+                    package_name: Some(name.clone()),
+                    directory_root,
+                });
+            }
+            if path_contains(&directory_root, &package_path) {
+                // there's a workspace below the inferred directory, so we're
+                // looking for all workspaces below it
+                break;
+            }
+        }
+        Ok(Self {
+            package_name: None,
+            directory_root,
+        })
+    }
+
+    fn apply(&self, selector: &mut TargetSelector) {
+        if !selector.name_pattern.is_empty() {
+            // the selector references a workspace by name, don't infer one
+            return;
+        }
+        if let Some(package_name) = &self.package_name {
+            selector.name_pattern = package_name.clone();
+        }
+        if let Some(parent_dir) = &selector.parent_dir {
+            selector.parent_dir = Some(join(&self.directory_root, parent_dir));
+        } else if self.package_name.is_none() {
+            // we didn't find a single workspace, so select every workspace
+            // below the inferred directory
+            selector.parent_dir = Some(join(&self.directory_root, "**"));
+        }
+    }
+}
+
+/// Selects workspaces from the package graph using parsed `--filter`
+/// selectors. `changed_packages` returns the workspaces that changed between
+/// two git refs.
+pub struct FilterResolver<'a, F> {
+    pkg_graph: &'a PackageGraph,
+    inference: Option<PackageInference>,
+    changed_packages: F,
+}
+
+impl<'a, F> FilterResolver<'a, F>
+where
+    F: Fn(&str, &str) -> Result<HashSet<String>>,
+{
+    pub fn new(
+        pkg_graph: &'a PackageGraph,
+        inference: Option<PackageInference>,
+        changed_packages: F,
+    ) -> Self {
+        Self {
+            pkg_graph,
+            inference,
+            changed_packages,
+        }
+    }
+
+    /// Returns the workspaces selected by any of the include selectors,
+    /// minus those selected by any of the exclude selectors.
+    pub fn get_filtered_packages(
+        &self,
+        mut selectors: Vec<TargetSelector>,
+    ) -> Result<HashSet<String>> {
+        if let Some(inference) = &self.inference {
+            // with no selectors, the inferred workspaces are selected
+            if selectors.is_empty() {
+                selectors.push(TargetSelector::default());
+            }
+            for selector in &mut selectors {
+                inference.apply(selector);
+            }
+        }
+
+        let (exclude, include): (Vec<_>, Vec<_>) =
+            selectors.into_iter().partition(|selector| selector.exclude);
+        let included = if include.is_empty() {
+            self.all_workspaces()
+        } else {
+            self.filter_graph_with_selectors(&include)?
+        };
+        let excluded = self.filter_graph_with_selectors(&exclude)?;
+
+        Ok(&included - &excluded)
+    }
+
+    fn filter_graph_with_selectors(&self, selectors: &[TargetSelector]) -> Result<HashSet<String>> {
+        let mut selected = HashSet::new();
+        for selector in selectors {
+            for workspace in self.filter_graph_with_selector(selector)? {
+                if selector.include_dependencies {
+                    selected.extend(
+                        self.pkg_graph
+                            .transitive_internal_dependencies([workspace.as_str()])
+                            .into_iter()
+                            .filter(|dependency| *dependency != workspace)
+                            .map(str::to_string),
+                    );
+                }
+                if selector.include_dependents {
+                    for dependent in self
+                        .pkg_graph
+                        .transitive_dependents([workspace.as_str()])
+                        .into_iter()
+                        .filter(|dependent| *dependent != workspace)
+                    {
+                        selected.insert(dependent.to_string());
+                        if selector.include_dependencies {
+                            selected.extend(
+                                self.pkg_graph
+                                    .transitive_internal_dependencies([dependent])
+                                    .into_iter()
+                                    .filter(|dependency| *dependency != dependent)
+                                    .map(str::to_string),
+                            );
+                        }
+                    }
+                }
+                let walked = selector.include_dependencies || selector.include_dependents;
+                if !walked || !selector.exclude_self {
+                    selected.insert(workspace);
+                }
+            }
+        }
+        Ok(selected)
+    }
+
+    fn filter_graph_with_selector(&self, selector: &TargetSelector) -> Result<HashSet<String>> {
+        if selector.match_dependencies {
+            self.filter_subtrees_with_selector(selector)
+        } else {
+            self.filter_nodes_with_selector(selector)
+        }
+    }
+
+    /// Returns the workspaces that match the selector
+    fn filter_nodes_with_selector(&self, selector: &TargetSelector) -> Result<HashSet<String>> {
+        if !selector.is_valid() {
+            bail!("invalid selector: {}", selector.raw);
+        }
+
+        let entry_packages = if let Some(from_ref) = &selector.from_ref {
+            let changed = (self.changed_packages)(from_ref, selector.to_ref())?;
+            match &selector.parent_dir {
+                Some(parent_dir) => self.filter_by_directory(changed, parent_dir)?,
+                None => changed,
+            }
+        } else if let Some(parent_dir) = &selector.parent_dir {
+            self.filter_by_directory(self.all_workspaces(), parent_dir)?
+        } else {
+            return match_package_names(&selector.name_pattern, self.all_workspaces());
+        };
+
+        if selector.name_pattern.is_empty() {
+            Ok(entry_packages)
+        } else {
+            match_package_names(&selector.name_pattern, entry_packages)
+        }
+    }
+
+    /// Returns the workspaces that match the selector, and have changed
+    /// themselves or have a dependency that changed
+    fn filter_subtrees_with_selector(&self, selector: &TargetSelector) -> Result<HashSet<String>> {
+        let from_ref = selector
+            .from_ref
+            .as_deref()
+            .ok_or_else(|| anyhow!("invalid selector: {}", selector.raw))?;
+        let changed = (self.changed_packages)(from_ref, selector.to_ref())?;
+
+        let mut entry_packages = self.all_workspaces();
+        if let Some(parent_dir) = &selector.parent_dir {
+            entry_packages = self.filter_by_directory(entry_packages, parent_dir)?;
+        }
+        if !selector.name_pattern.is_empty() {
+            entry_packages = match_package_names(&selector.name_pattern, entry_packages)?;
+        }
+
+        Ok(entry_packages
+            .into_iter()
+            .filter(|workspace| {
+                self.pkg_graph
+                    .transitive_internal_dependencies([workspace.as_str()])
+                    .into_iter()
+                    .any(|dependency| {
+                        (dependency != workspace || !selector.exclude_self)
+                            && changed.contains(dependency)
+                    })
+            })
+            .collect())
+    }
+
+    fn all_workspaces(&self) -> HashSet<String> {
+        self.pkg_graph
+            .workspace_infos()
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    fn filter_by_directory(
+        &self,
+        workspaces: HashSet<String>,
+        parent_dir: &str,
+    ) -> Result<HashSet<String>> {
+        // directories outside of the repo can't contain any workspaces
+        if parent_dir == ".." || parent_dir.starts_with("../") {
+            return Ok(HashSet::new());
+        }
+        let glob = match parent_dir {
+            "." => None,
+            parent_dir => Some(
+                Glob::new(parent_dir)
+                    .map_err(|e| anyhow!("invalid directory filter {parent_dir}: {e}"))?,
+            ),
+        };
+
+        let workspace_infos = self.pkg_graph.workspace_infos();
+        let mut matched = HashSet::new();
+        for workspace in workspaces {
+            let info = workspace_infos
+                .get(&workspace)
+                .ok_or_else(|| anyhow!("missing info for package {workspace}"))?;
+            let package_path = info.package_path().to_unix()?;
+            let package_path = package_path.as_str()?;
+            let is_match = match &glob {
+                Some(glob) => !package_path.is_empty() && glob.is_match(package_path),
+                None => package_path.is_empty(),
+            };
+            if is_match {
+                matched.insert(workspace);
+            }
+        }
+        Ok(matched)
+    }
+}
+
+fn match_package_names(pattern: &str, workspaces: HashSet<String>) -> Result<HashSet<String>> {
+    let matcher = matcher_from_pattern(pattern)?;
+    let matched: HashSet<String> = workspaces
+        .iter()
+        .filter(|workspace| matcher(workspace))
+        .cloned()
+        .collect();
+    if !matched.is_empty() || pattern.starts_with('@') || pattern.contains('/') {
+        return Ok(matched);
+    }
+
+    // the pattern isn't scoped, so check if it matches exactly one scoped
+    // workspace
+    let matcher = matcher_from_pattern(&format!("@*/{pattern}"))?;
+    let mut scoped = workspaces
+        .into_iter()
+        .filter(|workspace| matcher(workspace));
+    match (scoped.next(), scoped.next()) {
+        (Some(workspace), None) => Ok(HashSet::from([workspace])),
+        // we can't disambiguate between several scoped workspaces
+        _ => Ok(HashSet::new()),
+    }
+}
+
+type Matcher = Box<dyn Fn(&str) -> bool>;
+
+// `*` matches any sequence of characters, everything else is literal
+fn matcher_from_pattern(pattern: &str) -> Result<Matcher> {
+    if pattern == "*" {
+        return Ok(Box::new(|_| true));
+    }
+    if !pattern.contains('*') {
+        let pattern = pattern.to_string();
+        return Ok(Box::new(move |name| name == pattern));
+    }
+    let regex = Regex::new(&format!(
+        "^{}$",
+        regex::escape(pattern).replace("\\*", ".*")
+    ))
+    .map_err(|e| anyhow!("failed to compile filter pattern to regex: {pattern}: {e}"))?;
+    Ok(Box::new(move |name| regex.is_match(name)))
+}
+
+// Whether `path` is `parent` or below it. Both are unix paths relative to the
+// repo root, where the root itself is "".
+fn path_contains(parent: &str, path: &str) -> bool {
+    parent.is_empty()
+        || path
+            .strip_prefix(parent)
+            .map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+fn join(directory: &str, path: &str) -> String {
+    match (directory, path) {
+        ("", path) => path.to_string(),
+        (directory, ".") => directory.to_string(),
+        (directory, path) => format!("{directory}/{path}"),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+    use anyhow::Result;
+    use test_case::test_case;
+    use turbopath::AnchoredSystemPathBuf;
+
+    use super::{FilterResolver, PackageInference};
+    use crate::{
+        package_graph::{PackageGraph, WorkspaceInfo},
+        package_json::PackageJson,
+        package_manager::PackageManager,
+        run::{scope::target_selector::TargetSelector, task_id::ROOT_PKG_NAME},
+    };
+
+    fn workspace(name: &str, dir: &str, dependencies: &[&str]) -> (String, WorkspaceInfo) {
+        let package_json_path = match dir {
+            "" => "package.json".to_string(),
+            dir => format!("{dir}/package.json"),
+        };
+        let info = WorkspaceInfo {
+            package_json: PackageJson {
+                name: Some(name.to_string()),
+                ..Default::default()
+            },
+            package_json_path: AnchoredSystemPathBuf::from_raw(package_json_path).unwrap(),
+            internal_dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+            unresolved_external_dependencies: BTreeMap::new(),
+            transitive_dependencies: None,
+        };
+        (name.to_string(), info)
+    }
+
+    // project-0 depends on project-1 and project-5, and project-1 depends on
+    // project-2 and project-4
+    fn graph() -> PackageGraph {
+        let workspaces = BTreeMap::from([
+            workspace(ROOT_PKG_NAME, "", &[]),
+            workspace(
+                "project-0",
+                "packages/project-0",
+                &["project-1", "project-5"],
+            ),
+            workspace(
+                "project-1",
+                "packages/project-1",
+                &["project-2", "project-4"],
+            ),
+            workspace("project-2", "project-2", &[]),
+            workspace("project-3", "project-3", &[]),
+            workspace("project-4", "project-4", &[]),
+            workspace("project-5", "project-5", &[]),
+            workspace("project-6", "project-5/packages/project-6", &[]),
+        ]);
+        PackageGraph::from_resolved_workspaces(workspaces, PackageManager::Npm, false)
+    }
+
+    fn resolve(
+        graph: &PackageGraph,
+        inference: Option<PackageInference>,
+        selectors: &[&str],
+    ) -> BTreeSet<String> {
+        let resolver = FilterResolver::new(graph, inference, |from_ref: &str, to_ref: &str| {
+            let changed: &[&str] = match (from_ref, to_ref) {
+                ("HEAD~1", "HEAD") => &["project-1", "project-2", ROOT_PKG_NAME],
+                ("HEAD~2", "HEAD") => &["project-1", "project-2", "project-3", ROOT_PKG_NAME],
+                ("HEAD~2", "HEAD~1") => &["project-3"],
+                _ => panic!("unsupported commit range {from_ref}...{to_ref}"),
+            };
+            Ok::<_, anyhow::Error>(changed.iter().map(|s| s.to_string()).collect())
+        });
+        let selectors = selectors
+            .iter()
+            .map(|selector| selector.parse())
+            .collect::<Result<Vec<TargetSelector>, _>>()
+            .unwrap();
+        resolver
+            .get_filtered_packages(selectors)
+            .unwrap()
+            .into_iter()
+            .collect()
+    }
+
+    #[test_case(&["//"], &["//"] ; "root package")]
+    #[test_case(&["project-1^..."], &["project-2", "project-4"] ; "only dependencies")]
+    #[test_case(&["project-1..."], &["project-1", "project-2", "project-4"] ; "with dependencies")]
+    #[test_case(&["...^project-1..."], &["project-0", "project-1", "project-2", "project-4", "project-5"] ; "dependents and their dependencies")]
+    #[test_case(&["...project-2"], &["project-0", "project-1", "project-2"] ; "with dependents")]
+    #[test_case(&["...^project-2"], &["project-0", "project-1"] ; "only dependents")]
+    #[test_case(&["...^project-2", "project-1^..."], &["project-0", "project-1", "project-2", "project-4"] ; "two selectors")]
+    #[test_case(&["project-2"], &["project-2"] ; "by name")]
+    #[test_case(&["{packages/*}"], &["project-0", "project-1"] ; "by directory glob")]
+    #[test_case(&["{project-5/**}"], &["project-5", "project-6"] ; "by directory globstar")]
+    #[test_case(&["./project-5"], &["project-5"] ; "by directory")]
+    #[test_case(&["."], &["//"] ; "root by directory")]
+    #[test_case(&["!project-1"], &["//", "project-0", "project-2", "project-3", "project-4", "project-5", "project-6"] ; "exclude")]
+    #[test_case(&["{packages/*}", "!*-1"], &["project-0"] ; "exclude by pattern")]
+    #[test_case(&["[HEAD~1]"], &["//", "project-1", "project-2"] ; "changed")]
+    #[test_case(&["{.}[HEAD~1]"], &["//"] ; "changed in root")]
+    #[test_case(&["{project-2}[HEAD~1]"], &["project-2"] ; "changed in directory")]
+    #[test_case(&["project-2*[HEAD~1]"], &["project-2"] ; "changed matching pattern")]
+    #[test_case(&["[HEAD~2...HEAD~1]"], &["project-3"] ; "changed in range")]
+    #[test_case(&["project-1...[HEAD~1]"], &["project-1"] ; "changed with dependencies")]
+    #[test_case(&["{packages/*}...[HEAD~1]"], &["project-0", "project-1"] ; "dependency changed")]
+    #[test_case(&["project-0...[HEAD~2...HEAD~1]"], &[] ; "unrelated change")]
+    fn test_filter(selectors: &[&str], expected: &[&str]) {
+        let expected: BTreeSet<String> = expected.iter().map(|s| s.to_string()).collect();
+        assert_eq!(resolve(&graph(), None, selectors), expected);
+    }
+
+    #[test_case("packages", &[], &["project-0", "project-1"] ; "directory")]
+    #[test_case("packages/project-0", &[], &["project-0"] ; "workspace")]
+    #[test_case("packages/project-0/src", &[], &["project-0"] ; "workspace subdirectory")]
+    #[test_case("packages", &["{./project-1}"], &["project-1"] ; "relative directory")]
+    #[test_case("packages/project-0", &["project-2"], &["project-2"] ; "explicit name")]
+    fn test_inference(root: &str, selectors: &[&str], expected: &[&str]) {
+        let graph = graph();
+        let inference =
+            PackageInference::calculate(&AnchoredSystemPathBuf::from_raw(root).unwrap(), &graph)
+                .unwrap();
+        let expected: BTreeSet<String> = expected.iter().map(|s| s.to_string()).collect();
+        assert_eq!(resolve(&graph, Some(inference), selectors), expected);
+    }
+
+    #[test_case("bar", &["@foo/bar"], &["@foo/bar"] ; "single scoped match")]
+    #[test_case("bar", &["@foo/bar", "bar"], &["bar"] ; "exact match first")]
+    #[test_case("bar", &["@foo/bar", "@types/bar"], &[] ; "ambiguous scoped match")]
+    fn test_scoped_names(pattern: &str, workspaces: &[&str], expected: &[&str]) {
+        let workspaces: HashSet<String> = workspaces.iter().map(|s| s.to_string()).collect();
+        let expected: HashSet<String> = expected.iter().map(|s| s.to_string()).collect();
+        assert_eq!(
+            super::match_package_names(pattern, workspaces).unwrap(),
+            expected
+        );
+    }
+}
@@ -0,0 +1,416 @@
+mod filter;
+mod target_selector;
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{anyhow, Context, Result};
+use tracing::{debug, warn};
+use turbopath::AbsoluteSystemPath;
+use turborepo_scm::{
+    git::{changed_files_with_mode, previous_content, ShallowCloneMode},
+    package_deps::find_git_root,
+};
+use wax::{Glob, Pattern};
+
+use self::{
+    filter::{FilterResolver, PackageInference},
+    target_selector::TargetSelector,
+};
+use crate::{
+    commands::CommandBase,
+    daemon::{self, DaemonClient, DaemonConnector},
+    opts::ScopeOpts,
+    package_graph::{self, PackageGraph},
+    run::task_id::ROOT_PKG_NAME,
+};
+
+/// Returns the workspaces in scope for this run, and whether that is every
+/// workspace in the repository.
+pub async fn resolve_packages(
+    opts: &ScopeOpts,
+    base: &CommandBase,
+    pkg_graph: &PackageGraph,
+    mut daemon: Option<&mut DaemonClient<DaemonConnector>>,
+) -> Result<(HashSet<String>, bool)> {
+    let mut patterns = opts.filter_patterns.clone();
+    patterns.extend(opts.legacy_filter.as_filter_patterns());
+
+    if patterns.is_empty() && opts.pkg_inference_root.is_none() {
+        // no filters specified, run every workspace
+        let all_pkgs = pkg_graph
+            .workspace_infos()
+            .iter()
+            .map(|(name, _)| name)
+            .filter(|name| name.as_str() != ROOT_PKG_NAME)
+            .cloned()
+            .collect();
+        return Ok((all_pkgs, true));
+    }
+
+    let selectors = patterns
+        .iter()
+        .map(|pattern| pattern.parse())
+        .collect::<Result<Vec<TargetSelector>, _>>()?;
+    let inference = opts
+        .pkg_inference_root
+        .as_ref()
+        .map(|root| {
+            debug!("Using {:?} as a basis for selecting packages", root);
+            PackageInference::calculate(root, pkg_graph)
+        })
+        .transpose()?;
+
+    // The daemon can only be queried asynchronously, so look up the changes
+    // for every range up front
+    let mut changes = HashMap::new();
+    for selector in &selectors {
+        let Some(from_ref) = &selector.from_ref else {
+            continue;
+        };
+        let range = (from_ref.clone(), selector.to_ref().to_string());
+        if changes.contains_key(&range) {
+            continue;
+        }
+        let changed = daemon::changed_packages(
+            daemon.as_deref_mut(),
+            &base.repo_root,
+            pkg_graph,
+            &range.0,
+            &range.1,
+            &opts.global_deps,
+            &opts.ignore_patterns,
+            opts.shallow_clone_mode,
+        )
+        .await?;
+        changes.insert(range, changed);
+    }
+
+    let resolver = FilterResolver::new(pkg_graph, inference, |from_ref: &str, to_ref: &str| {
+        changes
+            .get(&(from_ref.to_string(), to_ref.to_string()))
+            .cloned()
+            .ok_or_else(|| anyhow!("changes for {from_ref}...{to_ref} weren't calculated"))
+    });
+    let filtered_pkgs = resolver.get_filtered_packages(selectors)?;
+    Ok((filtered_pkgs, false))
+}
+
+// turbo.json and the root package.json are always global dependencies
+const DEFAULT_GLOBAL_DEPS: &[&str] = &["turbo.json", "package.json"];
+
+/// Returns the workspaces with changes between `from_ref` and `to_ref`,
+/// including uncommitted changes. Files that aren't in any workspace are
+/// attributed to the root workspace, unless they match `ignore_patterns`.
+/// If a global dependency changed every workspace is returned, as it is if
+/// the refs can't be compared in a shallow clone and `shallow_clone_mode`
+/// allows that.
+pub(crate) fn changed_packages(
+    repo_root: &AbsoluteSystemPath,
+    package_graph: &PackageGraph,
+    from_ref: &str,
+    to_ref: &str,
+    global_deps: &[String],
+    ignore_patterns: &[String],
+    shallow_clone_mode: ShallowCloneMode,
+) -> Result<HashSet<String>> {
+    let all_workspaces = || {
+        package_graph
+            .workspace_infos()
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    };
+
+    let git_root = find_git_root(repo_root)?;
+    // CI commonly uses shallow clones, which may not have the history needed
+    // to compare the refs
+    let Some(changed_files) = changed_files_with_mode(
+        git_root.into(),
+        repo_root.to_owned().into(),
+        Some(from_ref),
+        to_ref,
+        shallow_clone_mode,
+    )? else {
+        warn!(
+            "{from_ref} and {to_ref} have no common history in this shallow clone, considering \
+             every workspace changed"
+        );
+        return Ok(all_workspaces());
+    };
+
+    let global_deps = compile_globs(
+        global_deps
+            .iter()
+            .map(String::as_str)
+            .chain(DEFAULT_GLOBAL_DEPS.iter().copied()),
+    )
+    .context("invalid global deps glob")?;
+    if changed_files
+        .iter()
+        .any(|file| global_deps.iter().any(|glob| glob.is_match(file.as_str())))
+    {
+        return Ok(all_workspaces());
+    }
+
+    let ignore_patterns = compile_globs(ignore_patterns.iter().map(String::as_str))
+        .context("invalid ignore globs")?;
+    let workspace_paths = package_graph
+        .workspace_infos()
+        .iter()
+        .filter(|(name, _)| name.as_str() != ROOT_PKG_NAME)
+        .map(|(name, info)| Ok((name.clone(), info.lockfile_path()?)))
+        .collect::<Result<Vec<_>, package_graph::Error>>()?;
+
+    let mut changed = HashSet::new();
+    for file in changed_files.iter().filter(|file| {
+        !ignore_patterns
+            .iter()
+            .any(|glob| glob.is_match(file.as_str()))
+    }) {
+        let workspace = workspace_paths
+            .iter()
+            .find(|(_, path)| file_in_package(file, path))
+            .map_or(ROOT_PKG_NAME, |(name, _)| name.as_str());
+        changed.insert(workspace.to_string());
+    }
+
+    match lockfile_changed_packages(repo_root, package_graph, &changed_files, from_ref) {
+        Some(lockfile_changes) => changed.extend(lockfile_changes),
+        None => return Ok(all_workspaces()),
+    }
+
+    Ok(changed)
+}
+
+fn compile_globs<'a>(globs: impl Iterator<Item = &'a str>) -> Result<Vec<Glob<'static>>> {
+    Ok(globs
+        .map(|glob| Glob::new(glob).map(Glob::into_owned))
+        .collect::<Result<_, _>>()?)
+}
+
+// Whether `file` is `package_path` or inside of it. Both are unix paths
+// relative to the repo root.
+fn file_in_package(file: &str, package_path: &str) -> bool {
+    file.strip_prefix(package_path)
+        .map_or(false, |rest| rest.is_empty() || rest.starts_with('/'))
+}
+
+/// Returns the workspaces whose external dependencies changed because of
+/// edits to the lockfile since `from_ref`. `changed_files` are the files
+/// changed since `from_ref`, relative to the repo root.
+///
+/// Returns `None` if every workspace should be considered changed, either
+/// because the lockfile change affects every workspace or because the
+/// previous lockfile couldn't be read.
+pub(crate) fn lockfile_changed_packages(
+    repo_root: &AbsoluteSystemPath,
+    package_graph: &PackageGraph,
+    changed_files: &HashSet<String>,
+    from_ref: &str,
+) -> Option<Vec<String>> {
+    let package_manager = package_graph.package_manager();
+    if !changed_files.contains(package_manager.lockfile_name()) {
+        return Some(Vec::new());
+    }
+
+    let root_package_json = &package_graph.workspace_info(ROOT_PKG_NAME)?.package_json;
+    // Graphs received from the daemon don't include the lockfile, so the graph
+    // is rebuilt locally to compare against the previous lockfile
+    let local_graph;
+    let package_graph = match package_graph.lockfile() {
+        Some(_) => package_graph,
+        None => {
+            local_graph =
+                PackageGraph::build_multi_package_graph(repo_root, root_package_json).ok()?;
+            &local_graph
+        }
+    };
+    // If we can't reconstruct or parse the old lockfile we assume everything
+    // changed
+    let git_root = find_git_root(repo_root).ok()?;
+    let previous_contents = previous_content(
+        git_root.into(),
+        from_ref,
+        package_manager.lockfile_path(repo_root).into(),
+    )
+    .ok()?;
+    let previous_lockfile = package_manager
+        .parse_lockfile(root_package_json, &previous_contents)
+        .ok()?;
+
+    package_graph
+        .changed_packages(previous_lockfile.as_ref())
+        .ok()
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashSet, fs, process::Command};
+
+    use serde_json::json;
+    use tempfile::tempdir;
+    use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf};
+    use turborepo_scm::git::ShallowCloneMode;
+
+    use super::{changed_packages, lockfile_changed_packages};
+    use crate::{
+        package_graph::PackageGraph, package_json::PackageJson, package_manager::PackageManager,
+    };
+
+    fn write_json(path: &AbsoluteSystemPath, value: serde_json::Value) {
+        fs::create_dir_all(path.as_path().parent().unwrap()).unwrap();
+        fs::write(
+            path.as_path(),
+            serde_json::to_string_pretty(&value).unwrap(),
+        )
+        .unwrap();
+    }
+
+    fn write_lockfile(repo_root: &AbsoluteSystemPath, lodash: &str, react: &str) {
+        write_json(
+            &repo_root.join_component("package-lock.json"),
+            json!({
+                "lockfileVersion": 3,
+                "packages": {
+                    "": {"workspaces": ["packages/*"]},
+                    "packages/a": {"dependencies": {"lodash": "^4.0.0"}},
+                    "packages/b": {"dependencies": {"react": "^18.0.0"}},
+                    "node_modules/lodash": {"version": lodash},
+                    "node_modules/react": {"version": react},
+                }
+            }),
+        );
+    }
+
+    fn git(repo_root: &AbsoluteSystemPath, args: &[&str]) {
+        let output = Command::new("git")
+            .args(args)
+            .current_dir(repo_root)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?} failed", args);
+    }
+
+    fn setup_repo(repo_root: &AbsoluteSystemPath) -> PackageJson {
+        let root_package_json = json!({
+            "name": "monorepo",
+            "packageManager": "npm@8.19.4",
+            "workspaces": ["packages/*"],
+        });
+        write_json(
+            &repo_root.join_component("package.json"),
+            root_package_json.clone(),
+        );
+        write_json(
+            &repo_root.join_components(&["packages", "a", "package.json"]),
+            json!({"name": "a", "dependencies": {"lodash": "^4.0.0"}}),
+        );
+        write_json(
+            &repo_root.join_components(&["packages", "b", "package.json"]),
+            json!({"name": "b", "dependencies": {"react": "^18.0.0"}}),
+        );
+        write_lockfile(repo_root, "4.17.20", "18.2.0");
+
+        git(repo_root, &["init", "--quiet"]);
+        git(repo_root, &["config", "user.email", "turbo@vercel.com"]);
+        git(repo_root, &["config", "user.name", "Turbobot"]);
+        git(repo_root, &["add", "."]);
+        git(repo_root, &["commit", "--quiet", "-m", "initial"]);
+
+        serde_json::from_value(root_package_json).unwrap()
+    }
+
+    #[test]
+    fn test_lockfile_changed_packages() {
+        let tmp = tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::new(tmp.path()).unwrap();
+        let root_package_json = setup_repo(&repo_root);
+        write_lockfile(&repo_root, "4.17.21", "18.2.0");
+
+        let graph =
+            PackageGraph::build_multi_package_graph(&repo_root, &root_package_json).unwrap();
+        let changed_files = HashSet::from(["package-lock.json".to_string()]);
+        assert_eq!(
+            lockfile_changed_packages(&repo_root, &graph, &changed_files, "HEAD"),
+            Some(vec!["a".to_string()])
+        );
+        assert_eq!(
+            lockfile_changed_packages(&repo_root, &graph, &HashSet::new(), "HEAD"),
+            Some(vec![])
+        );
+
+        // A graph from the daemon doesn't have the lockfile, which is read
+        // from the repository instead
+        let daemon_graph = PackageGraph::from_resolved_workspaces(
+            graph
+                .workspace_infos()
+                .iter()
+                .map(|(name, info)| (name.clone(), info.clone()))
+                .collect(),
+            PackageManager::Npm,
+            true,
+        );
+        assert!(daemon_graph.lockfile().is_none());
+        assert_eq!(
+            lockfile_changed_packages(&repo_root, &daemon_graph, &changed_files, "HEAD"),
+            Some(vec!["a".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_lockfile_changed_packages_unknown_ref() {
+        let tmp = tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::new(tmp.path()).unwrap();
+        let root_package_json = setup_repo(&repo_root);
+
+        let graph =
+            PackageGraph::build_multi_package_graph(&repo_root, &root_package_json).unwrap();
+        let changed_files = HashSet::from(["package-lock.json".to_string()]);
+        assert_eq!(
+            lockfile_changed_packages(&repo_root, &graph, &changed_files, "not-a-ref"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_changed_packages() {
+        let tmp = tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::new(tmp.path()).unwrap();
+        let root_package_json = setup_repo(&repo_root);
+        let changed = |global_deps: &[String], ignore_patterns: &[String]| {
+            let graph =
+                PackageGraph::build_multi_package_graph(&repo_root, &root_package_json).unwrap();
+            let mut changed: Vec<_> = changed_packages(
+                &repo_root,
+                &graph,
+                "HEAD",
+                "HEAD",
+                global_deps,
+                ignore_patterns,
+                ShallowCloneMode::Fail,
+            )
+            .unwrap()
+            .into_iter()
+            .collect();
+            changed.sort();
+            changed
+        };
+
+        assert!(changed(&[], &[]).is_empty());
+
+        fs::write(
+            repo_root.join_components(&["packages", "b", "index.js"]),
+            "",
+        )
+        .unwrap();
+        fs::write(repo_root.join_component("README.md"), "").unwrap();
+        assert_eq!(changed(&[], &[]), vec!["//", "b"]);
+        assert_eq!(changed(&[], &["*.md".to_string()]), vec!["b"]);
+        assert_eq!(changed(&["*.md".to_string()], &[]), vec!["//", "a", "b"]);
+
+        // lockfile changes are attributed to the workspaces they affect, as
+        // well as the root since the lockfile isn't in any workspace
+        write_lockfile(&repo_root, "4.17.21", "18.2.0");
+        assert_eq!(changed(&[], &["*.md".to_string()]), vec!["//", "a", "b"]);
+    }
+}
@@ -0,0 +1,315 @@
+use std::str::FromStr;
+
+use thiserror::Error;
+
+/// A parsed `--filter` selector. The syntax mirrors pnpm's:
+///
+/// ```text
+/// [!][...[^]]<name>[{<directory>}][[...][<from>[...<to>]]][[^]...]
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TargetSelector {
+    pub include_dependencies: bool,
+    // `...[ref]`: select workspaces if they or any of their dependencies
+    // changed
+    pub match_dependencies: bool,
+    pub include_dependents: bool,
+    pub exclude: bool,
+    pub exclude_self: bool,
+    // A unix style glob relative to the repo root, "." is the root itself
+    pub parent_dir: Option<String>,
+    pub name_pattern: String,
+    pub from_ref: Option<String>,
+    pub to_ref_override: Option<String>,
+    pub raw: String,
+}
+
+impl TargetSelector {
+    /// Whether the selector specifies which workspaces to select at all
+    pub fn is_valid(&self) -> bool {
+        self.from_ref.is_some() || self.parent_dir.is_some() || !self.name_pattern.is_empty()
+    }
+
+    /// The upper bound of the range of commits to look for changes in
+    pub fn to_ref(&self) -> &str {
+        self.to_ref_override.as_deref().unwrap_or("HEAD")
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("invalid filter \"{selector}\": {reason}\n  {selector}\n  {}^", " ".repeat(*.column))]
+pub struct InvalidSelectorError {
+    selector: String,
+    // the column of the offending character
+    column: usize,
+    reason: InvalidSelectorReason,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum InvalidSelectorReason {
+    #[error("empty selector")]
+    Empty,
+    #[error("unexpected '{0}'")]
+    UnexpectedCharacter(char),
+    #[error("missing closing '{0}'")]
+    Unclosed(char),
+    #[error("empty path specification")]
+    EmptyPath,
+    #[error("path specifications must be relative to the repository root")]
+    AbsolutePath,
+    #[error("empty git range")]
+    EmptyGitRange,
+    #[error("cannot use match dependencies without specifying either a directory or package")]
+    CantMatchDependencies,
+}
+
+impl FromStr for TargetSelector {
+    type Err = InvalidSelectorError;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        // `offset` tracks where `selector` starts in `raw`, so that errors can
+        // point at the offending character
+        let error = |offset: usize, reason| InvalidSelectorError {
+            selector: raw.to_string(),
+            column: raw[..offset].chars().count(),
+            reason,
+        };
+        let mut selector = raw;
+        let mut offset = 0;
+
+        let exclude = match selector.strip_prefix('!') {
+            Some(rest) => {
+                selector = rest;
+                offset += 1;
+                true
+            }
+            None => false,
+        };
+        if selector.is_empty() {
+            return Err(error(offset, InvalidSelectorReason::Empty));
+        }
+
+        let mut exclude_self = false;
+        let include_dependencies = match selector.strip_suffix("...") {
+            Some(rest) => {
+                selector = rest;
+                if let Some(rest) = selector.strip_suffix('^') {
+                    exclude_self = true;
+                    selector = rest;
+                }
+                true
+            }
+            None => false,
+        };
+        let include_dependents = match selector.strip_prefix("...") {
+            Some(rest) => {
+                selector = rest;
+                offset += 3;
+                if let Some(rest) = selector.strip_prefix('^') {
+                    exclude_self = true;
+                    selector = rest;
+                    offset += 1;
+                }
+                true
+            }
+            None => false,
+        };
+
+        let mut target_selector = TargetSelector {
+            include_dependencies,
+            include_dependents,
+            exclude,
+            exclude_self,
+            raw: raw.to_string(),
+            ..Default::default()
+        };
+
+        if !selector.contains(['{', '}', '[', ']']) {
+            match selector_by_location(selector) {
+                Some(parent_dir) => target_selector.parent_dir = Some(parent_dir),
+                None => target_selector.name_pattern = selector.to_string(),
+            }
+            return Ok(target_selector);
+        }
+
+        let mut index = selector.find(['{', '[']).unwrap_or(selector.len());
+        let mut name = &selector[..index];
+        // the name can't end with a `.`, so `name...[ref]` is a name followed
+        // by a change range
+        if selector[index..].starts_with('[') {
+            if let Some(rest) = name.strip_suffix("...") {
+                name = rest;
+                index -= 3;
+            }
+        }
+        if let Some(position) = name.find(['}', ']']) {
+            let character = name[position..].chars().next().unwrap_or_default();
+            return Err(error(
+                offset + position,
+                InvalidSelectorReason::UnexpectedCharacter(character),
+            ));
+        }
+        target_selector.name_pattern = name.to_string();
+
+        if selector[index..].starts_with('{') {
+            let Some(length) = selector[index..].find('}') else {
+                return Err(error(offset + index, InvalidSelectorReason::Unclosed('}')));
+            };
+            let parent_dir = &selector[index + 1..index + length];
+            if parent_dir.is_empty() {
+                return Err(error(offset + index, InvalidSelectorReason::EmptyPath));
+            }
+            if is_absolute(parent_dir) {
+                return Err(error(
+                    offset + index + 1,
+                    InvalidSelectorReason::AbsolutePath,
+                ));
+            }
+            target_selector.parent_dir = Some(clean_path(parent_dir));
+            index += length + 1;
+        }
+
+        if index == selector.len() {
+            return Ok(target_selector);
+        }
+
+        let start = if selector[index..].starts_with("...[") {
+            if target_selector.parent_dir.is_none() && target_selector.name_pattern.is_empty() {
+                return Err(error(
+                    offset + index,
+                    InvalidSelectorReason::CantMatchDependencies,
+                ));
+            }
+            target_selector.match_dependencies = true;
+            index + 3
+        } else if selector[index..].starts_with('[') {
+            index
+        } else {
+            let character = selector[index..].chars().next().unwrap_or_default();
+            return Err(error(
+                offset + index,
+                InvalidSelectorReason::UnexpectedCharacter(character),
+            ));
+        };
+
+        let Some(length) = selector[start..].find(']') else {
+            return Err(error(offset + start, InvalidSelectorReason::Unclosed(']')));
+        };
+        let commits = &selector[start + 1..start + length];
+        if commits.is_empty() {
+            return Err(error(offset + start, InvalidSelectorReason::EmptyGitRange));
+        }
+        let end = start + length + 1;
+        if let Some(character) = selector[end..].chars().next() {
+            return Err(error(
+                offset + end,
+                InvalidSelectorReason::UnexpectedCharacter(character),
+            ));
+        }
+
+        match commits.split("...").collect::<Vec<_>>().as_slice() {
+            [from_ref, to_ref] => {
+                target_selector.from_ref = Some(from_ref.to_string());
+                target_selector.to_ref_override = Some(to_ref.to_string());
+            }
+            _ => target_selector.from_ref = Some(commits.to_string()),
+        }
+
+        Ok(target_selector)
+    }
+}
+
+/// Returns the directory if the selector is a path, i.e. `.`, `..` or starts
+/// with `./` or `../`
+fn selector_by_location(selector: &str) -> Option<String> {
+    let rest = selector
+        .strip_prefix("..")
+        .or_else(|| selector.strip_prefix('.'))?;
+    (rest.is_empty() || rest.starts_with(['/', '\\'])).then(|| clean_path(selector))
+}
+
+fn is_absolute(path: &str) -> bool {
+    path.starts_with(['/', '\\']) || path.chars().nth(1) == Some(':')
+}
+
+// Normalizes a relative path to use unix separators and resolves `.` and `..`
+// segments where possible. The repo root itself is ".".
+fn clean_path(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split(['/', '\\']) {
+        match segment {
+            "" | "." => {}
+            ".." if segments.last().map_or(false, |last| *last != "..") => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    if segments.is_empty() {
+        ".".to_string()
+    } else {
+        segments.join("/")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use test_case::test_case;
+
+    use super::{InvalidSelectorReason, TargetSelector};
+
+    #[test_case("foo", TargetSelector { name_pattern: "foo".into(), ..Default::default() } ; "name")]
+    #[test_case("foo...", TargetSelector { name_pattern: "foo".into(), include_dependencies: true, ..Default::default() } ; "dependencies")]
+    #[test_case("...foo", TargetSelector { name_pattern: "foo".into(), include_dependents: true, ..Default::default() } ; "dependents")]
+    #[test_case("...foo...", TargetSelector { name_pattern: "foo".into(), include_dependents: true, include_dependencies: true, ..Default::default() } ; "dependents and dependencies")]
+    #[test_case("foo^...", TargetSelector { name_pattern: "foo".into(), include_dependencies: true, exclude_self: true, ..Default::default() } ; "only dependencies")]
+    #[test_case("...^foo", TargetSelector { name_pattern: "foo".into(), include_dependents: true, exclude_self: true, ..Default::default() } ; "only dependents")]
+    #[test_case("!foo", TargetSelector { name_pattern: "foo".into(), exclude: true, ..Default::default() } ; "exclude")]
+    #[test_case("./foo", TargetSelector { parent_dir: Some("foo".into()), ..Default::default() } ; "location")]
+    #[test_case("../foo", TargetSelector { parent_dir: Some("../foo".into()), ..Default::default() } ; "parent location")]
+    #[test_case(".", TargetSelector { parent_dir: Some(".".into()), ..Default::default() } ; "root")]
+    #[test_case("..", TargetSelector { parent_dir: Some("..".into()), ..Default::default() } ; "parent")]
+    #[test_case("...{./foo}", TargetSelector { parent_dir: Some("foo".into()), include_dependents: true, ..Default::default() } ; "directory dependents")]
+    #[test_case("[main]", TargetSelector { from_ref: Some("main".into()), ..Default::default() } ; "changed")]
+    #[test_case("[from...to]", TargetSelector { from_ref: Some("from".into()), to_ref_override: Some("to".into()), ..Default::default() } ; "range")]
+    #[test_case("{foo}[main]", TargetSelector { parent_dir: Some("foo".into()), from_ref: Some("main".into()), ..Default::default() } ; "directory changed")]
+    #[test_case("pattern{foo}[main]", TargetSelector { name_pattern: "pattern".into(), parent_dir: Some("foo".into()), from_ref: Some("main".into()), ..Default::default() } ; "name directory changed")]
+    #[test_case("...[from...to]...", TargetSelector { from_ref: Some("from".into()), to_ref_override: Some("to".into()), include_dependents: true, include_dependencies: true, ..Default::default() } ; "range dependents and dependencies")]
+    #[test_case("foo...[main]", TargetSelector { name_pattern: "foo".into(), from_ref: Some("main".into()), match_dependencies: true, ..Default::default() } ; "match dependencies")]
+    #[test_case("foo...[main]...", TargetSelector { name_pattern: "foo".into(), from_ref: Some("main".into()), match_dependencies: true, include_dependencies: true, ..Default::default() } ; "match dependencies with dependencies")]
+    #[test_case("{foo}...[main]", TargetSelector { parent_dir: Some("foo".into()), from_ref: Some("main".into()), match_dependencies: true, ..Default::default() } ; "directory match dependencies")]
+    fn test_parse_target_selector(raw: &str, expected: TargetSelector) {
+        let expected = TargetSelector {
+            raw: raw.to_string(),
+            ..expected
+        };
+        assert_eq!(TargetSelector::from_str(raw), Ok(expected));
+    }
+
+    #[test_case("", InvalidSelectorReason::Empty, 0 ; "empty")]
+    #[test_case("{}", InvalidSelectorReason::EmptyPath, 0 ; "empty path")]
+    #[test_case("foo{/bar}", InvalidSelectorReason::AbsolutePath, 4 ; "absolute path")]
+    #[test_case("......[main]", InvalidSelectorReason::CantMatchDependencies, 3 ; "match dependencies without target")]
+    #[test_case("foo{bar", InvalidSelectorReason::Unclosed('}'), 3 ; "unclosed directory")]
+    #[test_case("!foo[main", InvalidSelectorReason::Unclosed(']'), 4 ; "unclosed range")]
+    #[test_case("foo[]", InvalidSelectorReason::EmptyGitRange, 3 ; "empty range")]
+    #[test_case("foo]", InvalidSelectorReason::UnexpectedCharacter(']'), 3 ; "unexpected bracket")]
+    #[test_case("{foo}bar", InvalidSelectorReason::UnexpectedCharacter('b'), 5 ; "name after directory")]
+    #[test_case("[main]{foo}", InvalidSelectorReason::UnexpectedCharacter('{'), 6 ; "directory after range")]
+    fn test_invalid_target_selector(raw: &str, reason: InvalidSelectorReason, column: usize) {
+        let error = TargetSelector::from_str(raw).unwrap_err();
+        assert_eq!(error.reason, reason);
+        assert_eq!(error.column, column);
+    }
+
+    #[test]
+    fn test_error_points_at_character() {
+        let error = TargetSelector::from_str("...foo{bar").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "invalid filter \"...foo{bar\": missing closing '}'\n  ...foo{bar\n        ^"
+        );
+    }
+}
@@ -0,0 +1,152 @@
+use std::sync::Mutex;
+
+use chrono::{DateTime, Local};
+use serde::Serialize;
+
+use crate::run::summary::TaskSummary;
+
+/// Tracks the outcome of every task that runs, so that they can be
+/// summarized at the end of the run.
+#[derive(Debug)]
+pub struct ExecutionTracker {
+    started_at: DateTime<Local>,
+    state: Mutex<ExecutionState>,
+}
+
+#[derive(Debug, Default)]
+struct ExecutionState {
+    // Number of tasks that exited successfully, not including cache hits
+    success: usize,
+    failed: usize,
+    cached: usize,
+    // Number of tasks that started
+    attempted: usize,
+    tasks: Vec<TaskSummary>,
+}
+
+impl ExecutionTracker {
+    pub fn new(started_at: DateTime<Local>) -> Self {
+        Self {
+            started_at,
+            state: Mutex::default(),
+        }
+    }
+
+    /// Starts timing a task. The returned tracker records the outcome.
+    pub fn track_task(&self) -> TaskTracker<'_> {
+        self.state().attempted += 1;
+        TaskTracker {
+            tracker: self,
+            started_at: Local::now(),
+        }
+    }
+
+    /// Records the summary of a task that was executed.
+    pub fn add_task(&self, task: TaskSummary) {
+        self.state().tasks.push(task);
+    }
+
+    /// Ends the run, returning the summary of its execution and of every task
+    /// that was executed.
+    pub fn finish(self, command: String, exit_code: i32) -> (ExecutionSummary, Vec<TaskSummary>) {
+        let state = self
+            .state
+            .into_inner()
+            .expect("execution tracker lock poisoned");
+        let summary = ExecutionSummary {
+            command,
+            // The path from the repo root to where turbo was invoked, which is
+            // always the root until package inference is supported
+            repo_path: String::new(),
+            success: state.success,
+            failed: state.failed,
+            cached: state.cached,
+            attempted: state.attempted,
+            start_time: self.started_at.timestamp_millis(),
+            end_time: Local::now().timestamp_millis(),
+            exit_code,
+        };
+        (summary, state.tasks)
+    }
+
+    /// Ends a dry run, returning the summary of every task. Nothing was
+    /// executed, so there is no execution summary.
+    pub fn finish_dry_run(self) -> Vec<TaskSummary> {
+        self.state
+            .into_inner()
+            .expect("execution tracker lock poisoned")
+            .tasks
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, ExecutionState> {
+        self.state.lock().expect("execution tracker lock poisoned")
+    }
+}
+
+/// Records the outcome of a single task.
+pub struct TaskTracker<'a> {
+    tracker: &'a ExecutionTracker,
+    started_at: DateTime<Local>,
+}
+
+impl TaskTracker<'_> {
+    pub fn cached(self) -> TaskExecutionSummary {
+        self.tracker.state().cached += 1;
+        self.finish(Some(0), None)
+    }
+
+    pub fn built(self) -> TaskExecutionSummary {
+        self.tracker.state().success += 1;
+        self.finish(Some(0), None)
+    }
+
+    pub fn failed(self, exit_code: Option<i32>, error: String) -> TaskExecutionSummary {
+        self.tracker.state().failed += 1;
+        self.finish(exit_code, Some(error))
+    }
+
+    /// The task was stopped because the run is shutting down.
+    pub fn stopped(self) -> TaskExecutionSummary {
+        self.finish(None, None)
+    }
+
+    fn finish(self, exit_code: Option<i32>, error: Option<String>) -> TaskExecutionSummary {
+        TaskExecutionSummary {
+            start_time: self.started_at.timestamp_millis(),
+            end_time: Local::now().timestamp_millis(),
+            error,
+            exit_code,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutionSummary {
+    command: String,
+    repo_path: String,
+    success: usize,
+    failed: usize,
+    cached: usize,
+    attempted: usize,
+    start_time: i64,
+    end_time: i64,
+    exit_code: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskExecutionSummary {
+    start_time: i64,
+    end_time: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    // None if the task never exited, e.g. because it was stopped
+    exit_code: Option<i32>,
+}
+
+impl TaskExecutionSummary {
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+}
@@ -0,0 +1,214 @@
+use std::fmt::Write;
+
+use crate::{
+    package_graph::PackageGraph,
+    run::summary::RunSummary,
+    ui::{BOLD, BOLD_CYAN, GREY, UI},
+};
+
+impl RunSummary {
+    /// Renders the summary for `--dry=text`, in the same format as Go.
+    pub fn format_text(&self, ui: &UI, package_graph: &PackageGraph) -> String {
+        let mut out = String::new();
+        let single_package = !self.monorepo;
+
+        if !single_package {
+            let mut rows = vec!["Name\tPath\t".to_string()];
+            rows.extend(self.packages.iter().flatten().map(|package| {
+                let path = package_graph
+                    .workspace_info(package)
+                    .and_then(|info| info.package_path().to_str().map(str::to_string).ok())
+                    .unwrap_or_default();
+                format!("{package}\t{path}\t")
+            }));
+            out.push('\n');
+            writeln!(out, "{}", ui.apply(BOLD_CYAN.apply_to("Packages in Scope"))).ok();
+            for row in align_columns(&rows) {
+                writeln!(out, "{row}").ok();
+            }
+        }
+
+        let global = &self.global_hash_summary;
+        let global_env = &global.env_vars;
+        let rows = [
+            format!("Global Files\t=\t{}", global.global_file_hash_map.len()),
+            format!(
+                "External Dependencies Hash\t=\t{}",
+                global.root_external_deps_hash
+            ),
+            format!("Global Cache Key\t=\t{}", global.global_cache_key),
+            format!(
+                "Global .env Files Considered\t=\t{}",
+                global.dot_env.as_ref().map_or(0, |dot_env| dot_env.len())
+            ),
+            format!(
+                "Global Env Vars\t=\t{}",
+                global_env.specified.env.join(", ")
+            ),
+            format!(
+                "Global Env Vars Values\t=\t{}",
+                global_env.configured.join(", ")
+            ),
+            format!(
+                "Inferred Global Env Vars Values\t=\t{}",
+                global_env.inferred.join(", ")
+            ),
+            format!(
+                "Global Passed Through Env Vars\t=\t{}",
+                join(global_env.specified.pass_through_env.as_deref())
+            ),
+            format!(
+                "Global Passed Through Env Vars Values\t=\t{}",
+                join(global_env.passthrough.as_deref())
+            ),
+        ];
+        out.push('\n');
+        writeln!(
+            out,
+            "{}",
+            ui.apply(BOLD_CYAN.apply_to("Global Hash Inputs"))
+        )
+        .ok();
+        for row in align_columns(&rows) {
+            writeln!(out, "  {}", ui.apply(GREY.apply_to(row))).ok();
+        }
+
+        out.push('\n');
+        writeln!(out, "{}", ui.apply(BOLD_CYAN.apply_to("Tasks to Run"))).ok();
+        for task in &self.tasks {
+            let task_name = if single_package {
+                &task.task
+            } else {
+                &task.task_id
+            };
+            writeln!(out, "{}", ui.apply(BOLD.apply_to(task_name))).ok();
+
+            let env_vars = &task.env_vars;
+            let mut rows = vec![format!("Task\t=\t{}\t", task.task)];
+            if !single_package {
+                rows.push(format!(
+                    "Package\t=\t{}\t",
+                    task.package.as_deref().unwrap_or_default()
+                ));
+            }
+            rows.push(format!("Hash\t=\t{}\t", task.hash));
+            rows.push(format!("Cached (Local)\t=\t{}\t", task.cache_summary.local));
+            rows.push(format!(
+                "Cached (Remote)\t=\t{}\t",
+                task.cache_summary.remote
+            ));
+            if !single_package {
+                rows.push(format!(
+                    "Directory\t=\t{}\t",
+                    task.dir.as_deref().unwrap_or_default()
+                ));
+            }
+            rows.extend([
+                format!("Command\t=\t{}\t", task.command),
+                format!("Outputs\t=\t{}\t", join(task.outputs.as_deref())),
+                format!("Log File\t=\t{}\t", task.log_file_relative_path),
+                format!("Dependencies\t=\t{}\t", task.dependencies.join(", ")),
+                // The typo is kept for compatibility with the Go output
+                format!("Dependendents\t=\t{}\t", task.dependents.join(", ")),
+                format!(
+                    "Inputs Files Considered\t=\t{}\t",
+                    task.expanded_inputs.len()
+                ),
+                format!(
+                    ".env Files Considered\t=\t{}\t",
+                    task.dot_env.as_ref().map_or(0, |dot_env| dot_env.len())
+                ),
+                format!("Env Vars\t=\t{}\t", env_vars.specified.env.join(", ")),
+                format!("Env Vars Values\t=\t{}\t", env_vars.configured.join(", ")),
+                format!(
+                    "Inferred Env Vars Values\t=\t{}\t",
+                    env_vars.inferred.join(", ")
+                ),
+                format!(
+                    "Passed Through Env Vars\t=\t{}\t",
+                    join(env_vars.specified.pass_through_env.as_deref())
+                ),
+                format!(
+                    "Passed Through Env Vars Values\t=\t{}\t",
+                    join(env_vars.passthrough.as_deref())
+                ),
+            ]);
+            if let Ok(task_definition) = serde_json::to_string(&task.resolved_task_definition) {
+                rows.push(format!("ResolvedTaskDefinition\t=\t{task_definition}\t"));
+            }
+            rows.push(format!("Framework\t=\t{}\t", task.framework));
+
+            for row in align_columns(&rows) {
+                writeln!(out, "  {}", ui.apply(GREY.apply_to(row))).ok();
+            }
+        }
+
+        out
+    }
+}
+
+fn join(values: Option<&[String]>) -> String {
+    values.map(|values| values.join(", ")).unwrap_or_default()
+}
+
+// Aligns tab terminated cells into columns, padding every cell to the width of
+// the widest cell in its column plus one space. Text after the last tab isn't
+// part of a column. This is how Go's tabwriter formats the dry run output.
+fn align_columns(rows: &[String]) -> Vec<String> {
+    let mut widths: Vec<usize> = Vec::new();
+    for row in rows {
+        let mut cells: Vec<&str> = row.split('\t').collect();
+        cells.pop();
+        for (i, cell) in cells.iter().enumerate() {
+            let width = cell.chars().count();
+            match widths.get_mut(i) {
+                Some(max) => *max = (*max).max(width),
+                None => widths.push(width),
+            }
+        }
+    }
+
+    rows.iter()
+        .map(|row| {
+            let mut cells: Vec<&str> = row.split('\t').collect();
+            let last = cells.pop().unwrap_or_default();
+            let mut aligned = String::new();
+            for (cell, width) in cells.iter().zip(&widths) {
+                write!(aligned, "{cell:width$} ", width = width).ok();
+            }
+            aligned.push_str(last);
+            aligned
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::align_columns;
+
+    #[test]
+    fn test_align_columns() {
+        let rows = [
+            "Name\tPath\t".to_string(),
+            "another\tpackages/another\t".to_string(),
+            "util\tpackages/util\t".to_string(),
+        ];
+        assert_eq!(
+            align_columns(&rows),
+            vec![
+                "Name    Path             ",
+                "another packages/another ",
+                "util    packages/util    ",
+            ]
+        );
+
+        let rows = [
+            "Global Files\t=\t1".to_string(),
+            "Global Cache Key\t=\tkey".to_string(),
+        ];
+        assert_eq!(
+            align_columns(&rows),
+            vec!["Global Files     = 1", "Global Cache Key = key"]
+        );
+    }
+}
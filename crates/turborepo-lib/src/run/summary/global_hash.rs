@@ -0,0 +1,88 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use turbopath::PathError;
+use turborepo_env::EnvironmentVariableMap;
+
+use crate::run::global_hash::GlobalHashableInputs;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlobalEnvConfiguration {
+    pub env: Vec<String>,
+    pub pass_through_env: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GlobalEnvVarSummary {
+    pub specified: GlobalEnvConfiguration,
+    pub configured: Vec<String>,
+    pub inferred: Vec<String>,
+    pub passthrough: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GlobalHashSummary {
+    #[serde(rename = "rootKey")]
+    pub global_cache_key: &'static str,
+    #[serde(rename = "files")]
+    pub global_file_hash_map: BTreeMap<String, String>,
+    #[serde(rename = "hashOfExternalDependencies")]
+    pub root_external_deps_hash: String,
+    #[serde(rename = "globalDotEnv")]
+    pub dot_env: Option<Vec<String>>,
+    #[serde(rename = "environmentVariables")]
+    pub env_vars: GlobalEnvVarSummary,
+}
+
+impl GlobalHashSummary {
+    /// Summarizes the global hash inputs. Env var values are hashed so that
+    /// secrets don't end up in the summary.
+    pub fn new(
+        inputs: &GlobalHashableInputs,
+        resolved_pass_through_env_vars: Option<EnvironmentVariableMap>,
+    ) -> Result<Self, PathError> {
+        let global_file_hash_map = inputs
+            .global_file_hash_map
+            .iter()
+            .map(|(path, hash)| Ok((path.as_str()?.to_string(), hash.clone())))
+            .collect::<Result<_, PathError>>()?;
+        let dot_env = inputs
+            .dot_env
+            .as_ref()
+            .map(|dot_env| {
+                dot_env
+                    .iter()
+                    .map(|path| Ok(path.as_str()?.to_string()))
+                    .collect::<Result<Vec<_>, PathError>>()
+            })
+            .transpose()?;
+        let (configured, inferred) = inputs
+            .resolved_env_vars
+            .as_ref()
+            .map(|env_vars| {
+                (
+                    env_vars.by_source.explicit.to_secret_hashable(),
+                    env_vars.by_source.matching.to_secret_hashable(),
+                )
+            })
+            .unwrap_or_default();
+
+        Ok(Self {
+            global_cache_key: inputs.global_cache_key,
+            global_file_hash_map,
+            root_external_deps_hash: inputs.root_external_deps_hash.clone(),
+            dot_env,
+            env_vars: GlobalEnvVarSummary {
+                specified: GlobalEnvConfiguration {
+                    env: inputs.env.clone(),
+                    pass_through_env: inputs.pass_through_env.clone(),
+                },
+                configured,
+                inferred,
+                passthrough: resolved_pass_through_env_vars
+                    .map(|env_vars| env_vars.to_secret_hashable()),
+            },
+        })
+    }
+}
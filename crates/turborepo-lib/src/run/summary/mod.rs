@@ -0,0 +1,315 @@
+//! Run summaries describe everything that happened during a run: the inputs
+//! to the global hash, and the hash, cache status and outcome of every task.
+//! The JSON layout matches the Go `runsummary` package, since tooling is built
+//! on top of the summaries in `.turbo/runs`.
+
+mod execution;
+mod format_text;
+mod global_hash;
+mod task;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub use execution::{ExecutionSummary, ExecutionTracker, TaskExecutionSummary, TaskTracker};
+pub use global_hash::GlobalHashSummary;
+use rand::RngCore;
+use serde::{Serialize, Serializer};
+pub use task::{
+    TaskCacheSummary, TaskEnvConfiguration, TaskEnvVarSummary, TaskSummary,
+    TaskSummaryTaskDefinition,
+};
+use thiserror::Error;
+use tracing::debug;
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf};
+use turborepo_env::EnvironmentVariableMap;
+
+use crate::cli::EnvMode;
+
+const RUN_SUMMARY_SCHEMA_VERSION: &str = "1";
+
+/// Shown in dry runs instead of the command when a workspace doesn't have a
+/// script for the task.
+pub const MISSING_TASK_LABEL: &str = "<NONEXISTENT>";
+/// Shown in dry runs when no framework was detected for a workspace.
+pub const NO_FRAMEWORK_DETECTED: &str = "<NO FRAMEWORK DETECTED>";
+/// Shown in dry runs when framework inference is disabled.
+pub const FRAMEWORK_DETECTION_SKIPPED: &str = "<FRAMEWORK DETECTION SKIPPED>";
+
+// The CI vendors that record who started a build, as in Go's ci package
+const CI_USER_ENV_VARS: &[&str] = &["GITHUB_ACTOR", "VERCEL_GIT_COMMIT_AUTHOR_LOGIN"];
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("failed to render run summary: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("failed to write run summary: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunSummary {
+    id: String,
+    version: &'static str,
+    turbo_version: &'static str,
+    monorepo: bool,
+    #[serde(rename = "globalCacheInputs")]
+    global_hash_summary: GlobalHashSummary,
+    // Single package repos don't have any packages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    packages: Option<Vec<String>>,
+    #[serde(serialize_with = "serialize_env_mode")]
+    env_mode: EnvMode,
+    framework_inference: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    execution: Option<ExecutionSummary>,
+    tasks: Vec<TaskSummary>,
+    user: String,
+    scm: ScmState,
+}
+
+impl RunSummary {
+    pub fn new(
+        turbo_version: &'static str,
+        single_package: bool,
+        mut packages: Vec<String>,
+        env_mode: EnvMode,
+        framework_inference: bool,
+        global_hash_summary: GlobalHashSummary,
+        execution: Option<ExecutionSummary>,
+        mut tasks: Vec<TaskSummary>,
+        user: String,
+        scm: ScmState,
+    ) -> Self {
+        packages.sort();
+        if single_package {
+            for task in &mut tasks {
+                task.clean_for_single_package();
+            }
+        }
+        tasks.sort_by(|a, b| a.task_id.cmp(&b.task_id));
+
+        Self {
+            id: ksuid(),
+            version: RUN_SUMMARY_SCHEMA_VERSION,
+            turbo_version,
+            monorepo: !single_package,
+            global_hash_summary,
+            packages: (!single_package).then_some(packages),
+            env_mode,
+            framework_inference,
+            execution,
+            tasks,
+            user,
+            scm,
+        }
+    }
+
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn to_json(&self) -> Result<String, Error> {
+        let json = serde_json::to_string_pretty(self)?;
+        // Go escapes HTML characters in JSON strings. These characters can only
+        // appear inside of strings, so escaping the output matches it exactly.
+        Ok(json
+            .replace('<', "\\u003c")
+            .replace('>', "\\u003e")
+            .replace('&', "\\u0026")
+            .replace('\u{2028}', "\\u2028")
+            .replace('\u{2029}', "\\u2029"))
+    }
+
+    /// Writes the summary to `.turbo/runs/<id>.json`. The file is written
+    /// atomically, so readers never see a partially written summary.
+    pub fn save(&self, repo_root: &AbsoluteSystemPath) -> Result<AbsoluteSystemPathBuf, Error> {
+        let json = self.to_json()?;
+        let runs_dir = repo_root.join_components(&[".turbo", "runs"]);
+        runs_dir.create_dir_all()?;
+
+        let summary_path = runs_dir.join_component(&format!("{}.json", self.id));
+        let tmp_path = runs_dir.join_component(&format!(".{}.json.tmp", self.id));
+        tmp_path.create_with_contents(&json)?;
+        if let Err(err) = std::fs::rename(tmp_path.as_path(), summary_path.as_path()) {
+            tmp_path.remove_file().ok();
+            return Err(err.into());
+        }
+
+        Ok(summary_path)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScmState {
+    #[serde(rename = "type")]
+    scm_type: &'static str,
+    sha: String,
+    branch: String,
+}
+
+impl ScmState {
+    /// Gets the current sha and branch. Both are empty when we aren't in a
+    /// git repository.
+    pub fn get(repo_root: &AbsoluteSystemPath) -> Self {
+        let sha = turborepo_scm::git::current_sha(repo_root).unwrap_or_else(|err| {
+            debug!("failed to get current sha: {}", err);
+            String::new()
+        });
+        let branch = turborepo_scm::git::current_branch(repo_root).unwrap_or_else(|err| {
+            debug!("failed to get current branch: {}", err);
+            String::new()
+        });
+        Self {
+            scm_type: "git",
+            sha,
+            branch,
+        }
+    }
+}
+
+/// Gets the user that started the run. In CI this is the user that triggered
+/// the build, otherwise it's the git user. It's empty if neither is known.
+pub fn get_user(env: &EnvironmentVariableMap, repo_root: &AbsoluteSystemPath) -> String {
+    let ci_user = CI_USER_ENV_VARS
+        .iter()
+        .filter_map(|var| env.get(*var))
+        .find(|user| !user.is_empty());
+    if let Some(user) = ci_user {
+        return user.clone();
+    }
+
+    turborepo_scm::git::user_name(repo_root).unwrap_or_else(|err| {
+        debug!("failed to get git user: {}", err);
+        String::new()
+    })
+}
+
+// Go marshals env modes in lowercase
+fn serialize_env_mode<S: Serializer>(env_mode: &EnvMode, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(match env_mode {
+        EnvMode::Infer => "infer",
+        EnvMode::Loose => "loose",
+        EnvMode::Strict => "strict",
+    })
+}
+
+// KSUIDs are sortable by creation time, which makes it easy to find the most
+// recent run. They're made up of a timestamp and a random payload.
+fn ksuid() -> String {
+    // KSUID timestamps are seconds since 2014-05-13
+    const EPOCH: u64 = 1_400_000_000;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default();
+    let timestamp = now.saturating_sub(EPOCH) as u32;
+    let mut payload = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut payload);
+    encode_ksuid(timestamp, payload)
+}
+
+fn encode_ksuid(timestamp: u32, payload: [u8; 16]) -> String {
+    const ALPHABET: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+    const ENCODED_LENGTH: usize = 27;
+
+    let mut number = [0u8; 20];
+    number[..4].copy_from_slice(&timestamp.to_be_bytes());
+    number[4..].copy_from_slice(&payload);
+
+    // Base62 encode the 160 bit number by repeatedly dividing it by 62
+    let mut digits = Vec::with_capacity(ENCODED_LENGTH);
+    while number.iter().any(|byte| *byte != 0) {
+        let mut remainder = 0u32;
+        for byte in number.iter_mut() {
+            let value = (remainder << 8) | u32::from(*byte);
+            *byte = (value / 62) as u8;
+            remainder = value % 62;
+        }
+        digits.push(ALPHABET[remainder as usize]);
+    }
+    digits.resize(ENCODED_LENGTH, b'0');
+    digits.reverse();
+
+    String::from_utf8(digits).expect("base62 alphabet is ascii")
+}
+
+#[cfg(test)]
+mod test {
+    use std::{collections::HashMap, process::Command};
+
+    use super::*;
+
+    #[test]
+    fn test_encode_ksuid() {
+        // Example from the KSUID spec
+        let payload: [u8; 16] = hex::decode("B5A1CD34B5F99D1154FB6853345C9735")
+            .unwrap()
+            .try_into()
+            .unwrap();
+        assert_eq!(
+            encode_ksuid(0x0669F7EF, payload),
+            "0ujtsYcgvSTl8PAuAdqWYSMnLOv"
+        );
+        assert_eq!(encode_ksuid(0, [0; 16]), "000000000000000000000000000");
+    }
+
+    #[test]
+    fn test_json_escapes_html() {
+        let summary = RunSummary::new(
+            "1.0.0",
+            false,
+            Vec::new(),
+            EnvMode::Infer,
+            true,
+            GlobalHashSummary::new(&Default::default(), None).unwrap(),
+            None,
+            Vec::new(),
+            String::new(),
+            ScmState {
+                scm_type: "git",
+                sha: String::new(),
+                branch: "a&b".to_string(),
+            },
+        );
+        let json = summary.to_json().unwrap();
+        assert!(json.contains(r#""branch": "a\u0026b""#));
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&json).unwrap()["scm"]["branch"],
+            "a&b"
+        );
+    }
+
+    #[test]
+    fn test_get_user() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo_root = AbsoluteSystemPathBuf::new(dir.path()).unwrap();
+        for args in [
+            &["init", "--quiet"][..],
+            &["config", "user.name", "Turbobot"],
+        ] {
+            let output = Command::new("git")
+                .args(args)
+                .current_dir(&repo_root)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "git {:?} failed", args);
+        }
+
+        let env = EnvironmentVariableMap::default();
+        assert_eq!(get_user(&env, &repo_root), "Turbobot");
+
+        let env = EnvironmentVariableMap::from(HashMap::from([(
+            "GITHUB_ACTOR".to_string(),
+            "octocat".to_string(),
+        )]));
+        assert_eq!(get_user(&env, &repo_root), "octocat");
+    }
+
+    #[test]
+    fn test_ksuids_are_unique() {
+        let id = ksuid();
+        assert_eq!(id.len(), 27);
+        assert_ne!(id, ksuid());
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use turbopath::AnchoredSystemPathBuf;
+use turborepo_cache::{CacheResponse, CacheSource};
+
+use crate::{
+    cli::EnvMode,
+    run::{
+        summary::{execution::TaskExecutionSummary, serialize_env_mode},
+        task_id::strip_package_name,
+    },
+    task_graph::{TaskDefinition, TaskOutputMode},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum CacheStatus {
+    #[serde(rename = "HIT")]
+    Hit,
+    #[serde(rename = "MISS")]
+    Miss,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskCacheSummary {
+    // Deprecated, but kept around for --dry=json
+    pub(super) local: bool,
+    // Deprecated, but kept around for --dry=json
+    pub(super) remote: bool,
+    status: CacheStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<&'static str>,
+    time_saved: u32,
+}
+
+impl TaskCacheSummary {
+    pub fn miss() -> Self {
+        Self {
+            local: false,
+            remote: false,
+            status: CacheStatus::Miss,
+            source: None,
+            time_saved: 0,
+        }
+    }
+}
+
+impl From<CacheResponse> for TaskCacheSummary {
+    fn from(response: CacheResponse) -> Self {
+        let source = match response.source {
+            CacheSource::Local => "LOCAL",
+            CacheSource::Remote => "REMOTE",
+        };
+        Self {
+            local: response.source == CacheSource::Local,
+            remote: response.source == CacheSource::Remote,
+            status: CacheStatus::Hit,
+            source: Some(source),
+            time_saved: response.time_saved,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskSummary {
+    pub task_id: String,
+    pub task: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    pub hash: String,
+    #[serde(rename = "inputs")]
+    pub expanded_inputs: BTreeMap<String, String>,
+    #[serde(rename = "hashOfExternalDependencies")]
+    pub external_deps_hash: String,
+    #[serde(rename = "cache")]
+    pub cache_summary: TaskCacheSummary,
+    pub command: String,
+    #[serde(rename = "cliArguments")]
+    pub command_arguments: Vec<String>,
+    pub outputs: Option<Vec<String>>,
+    pub excluded_outputs: Option<Vec<String>>,
+    #[serde(rename = "logFile")]
+    pub log_file_relative_path: String,
+    #[serde(rename = "directory", skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+    pub dependencies: Vec<String>,
+    pub dependents: Vec<String>,
+    pub resolved_task_definition: TaskSummaryTaskDefinition,
+    pub expanded_outputs: Vec<AnchoredSystemPathBuf>,
+    pub framework: String,
+    #[serde(serialize_with = "serialize_env_mode")]
+    pub env_mode: EnvMode,
+    #[serde(rename = "environmentVariables")]
+    pub env_vars: TaskEnvVarSummary,
+    pub dot_env: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execution: Option<TaskExecutionSummary>,
+}
+
+impl TaskSummary {
+    /// Single package repos don't have a concept of packages, so we drop the
+    /// package names from task IDs.
+    pub fn clean_for_single_package(&mut self) {
+        let task = strip_package_name(&self.task_id);
+        for task_id in self
+            .dependencies
+            .iter_mut()
+            .chain(self.dependents.iter_mut())
+        {
+            *task_id = strip_package_name(task_id);
+        }
+        self.task_id = task.clone();
+        self.task = task;
+        self.dir = None;
+        self.package = None;
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskEnvConfiguration {
+    pub env: Vec<String>,
+    pub pass_through_env: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TaskEnvVarSummary {
+    pub specified: TaskEnvConfiguration,
+    pub configured: Vec<String>,
+    pub inferred: Vec<String>,
+    pub passthrough: Option<Vec<String>>,
+}
+
+// The task definition as it was resolved for this task. Unlike turbo.json,
+// every field is present so that defaults are visible.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TaskSummaryTaskDefinition {
+    outputs: Vec<String>,
+    cache: bool,
+    depends_on: Vec<String>,
+    inputs: Vec<String>,
+    output_mode: TaskOutputMode,
+    persistent: bool,
+    env: Vec<String>,
+    pass_through_env: Option<Vec<String>>,
+    dot_env: Option<Vec<String>>,
+}
+
+impl From<&TaskDefinition> for TaskSummaryTaskDefinition {
+    fn from(task_definition: &TaskDefinition) -> Self {
+        let mut outputs = task_definition.outputs.inclusions.clone();
+        outputs.extend(
+            task_definition
+                .outputs
+                .exclusions
+                .iter()
+                .map(|exclusion| format!("!{exclusion}")),
+        );
+        outputs.sort();
+
+        let mut depends_on = task_definition.task_dependencies.clone();
+        depends_on.extend(
+            task_definition
+                .topological_dependencies
+                .iter()
+                .map(|dependency| format!("^{dependency}")),
+        );
+        depends_on.sort();
+
+        let pass_through_env = task_definition.pass_through_env.clone().map(|mut env| {
+            env.sort();
+            env
+        });
+
+        // The dotEnv files are _not_ sorted, the order is the load order
+        let dot_env = task_definition.dot_env.as_ref().map(|dot_env| {
+            dot_env
+                .iter()
+                .map(|path| path.as_str().unwrap_or_default().to_string())
+                .collect()
+        });
+
+        Self {
+            outputs,
+            cache: task_definition.should_cache,
+            depends_on,
+            inputs: task_definition.inputs.clone(),
+            output_mode: task_definition.output_mode,
+            persistent: task_definition.persistent,
+            env: task_definition.env_var_dependencies.clone(),
+            pass_through_env,
+            dot_env,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+    use crate::task_graph::TaskOutputs;
+
+    #[test]
+    fn test_cache_summary() {
+        assert_eq!(
+            serde_json::to_value(TaskCacheSummary::miss()).unwrap(),
+            json!({"local": false, "remote": false, "status": "MISS", "timeSaved": 0})
+        );
+        let hit = TaskCacheSummary::from(CacheResponse {
+            source: CacheSource::Remote,
+            time_saved: 42,
+        });
+        assert_eq!(
+            serde_json::to_value(hit).unwrap(),
+            json!({
+                "local": false,
+                "remote": true,
+                "status": "HIT",
+                "source": "REMOTE",
+                "timeSaved": 42
+            })
+        );
+    }
+
+    #[test]
+    fn test_resolved_task_definition() {
+        let task_definition = TaskDefinition {
+            outputs: TaskOutputs {
+                inclusions: vec!["dist/**".to_string()],
+                exclusions: vec!["dist/cache/**".to_string()],
+            },
+            should_cache: true,
+            topological_dependencies: vec!["build".to_string()],
+            task_dependencies: vec!["codegen".to_string()],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            serde_json::to_value(TaskSummaryTaskDefinition::from(&task_definition)).unwrap(),
+            json!({
+                "outputs": ["!dist/cache/**", "dist/**"],
+                "cache": true,
+                "dependsOn": ["^build", "codegen"],
+                "inputs": [],
+                "outputMode": "full",
+                "persistent": false,
+                "env": [],
+                "passThroughEnv": null,
+                "dotEnv": null
+            })
+        );
+    }
+}
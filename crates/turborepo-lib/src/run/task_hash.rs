@@ -0,0 +1,519 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Mutex,
+};
+
+use thiserror::Error;
+use tracing::debug;
+use turbopath::{AbsoluteSystemPath, AnchoredSystemPathBuf, PathError, RelativeUnixPathBuf};
+use turborepo_env::{BySource, DetailedMap, EnvironmentVariableMap};
+use turborepo_scm::{
+    manual::hash_files,
+    package_deps::{get_package_file_hashes, GitHashes},
+};
+
+use crate::{
+    cli::EnvMode,
+    daemon::{DaemonClient, DaemonConnector, DaemonError},
+    engine::TaskNode,
+    framework::infer_framework,
+    package_graph::{WorkspaceCatalog, WorkspaceInfo},
+    run::{
+        hash::{format_paths, format_slice, hash_file_hashes, hash_object},
+        task_id::{get_package_task_from_id, ROOT_PKG_NAME},
+    },
+    task_graph::{TaskDefinition, TaskOutputs},
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("missing pipeline entry {0}")]
+    MissingPipelineEntry(String),
+    #[error("cannot find package {0}")]
+    MissingPackage(String),
+    #[error("cannot find package-file hash for {0}")]
+    MissingPackageFileHash(String),
+    #[error("missing hash for dependent task: {0}")]
+    MissingDependencyTaskHash(String),
+    #[error(transparent)]
+    Scm(#[from] turborepo_scm::Error),
+    #[error(transparent)]
+    Env(#[from] regex::Error),
+    #[error(transparent)]
+    Path(#[from] PathError),
+    #[error(transparent)]
+    Daemon(#[from] DaemonError),
+}
+
+/// The hashes of the input files for every task, keyed by task ID. These
+/// must be calculated before any task hashes.
+#[derive(Debug, Default)]
+pub struct PackageInputsHashes {
+    hashes: HashMap<String, String>,
+    expanded_hashes: HashMap<String, GitHashes>,
+}
+
+impl PackageInputsHashes {
+    /// When a daemon is available, file hashes are requested from it so
+    /// that packages which haven't changed since it last hashed them aren't
+    /// hashed again. If the daemon fails, hashing falls back to doing it
+    /// locally.
+    pub async fn calculate_file_hashes<'a>(
+        all_tasks: impl Iterator<Item = &'a str>,
+        workspaces: &WorkspaceCatalog,
+        task_definitions: &HashMap<String, TaskDefinition>,
+        repo_root: &AbsoluteSystemPath,
+        mut daemon: Option<&mut DaemonClient<DaemonConnector>>,
+    ) -> Result<PackageInputsHashes, Error> {
+        let mut hashes = HashMap::new();
+        let mut expanded_hashes = HashMap::new();
+
+        for task_id in all_tasks {
+            let (package_name, _) = get_package_task_from_id(task_id);
+            let task_definition = task_definitions
+                .get(task_id)
+                .ok_or_else(|| Error::MissingPipelineEntry(task_id.to_string()))?;
+            let workspace = workspaces
+                .get(&package_name)
+                .ok_or_else(|| Error::MissingPackage(package_name.clone()))?;
+
+            let package_path = workspace.package_path();
+            // Get the hashes of each file, keyed by the path.
+            let daemon_hashes = match daemon.as_deref_mut() {
+                Some(daemon) => {
+                    Some(daemon_file_hashes(daemon, &package_path, &task_definition.inputs).await)
+                }
+                None => None,
+            };
+            let mut hash_object = match daemon_hashes {
+                Some(Ok(hash_object)) => hash_object,
+                Some(Err(err)) => {
+                    debug!("failed to get file hashes from daemon, hashing locally: {err}");
+                    daemon = None;
+                    get_package_file_hashes(repo_root, &package_path, &task_definition.inputs)?
+                }
+                None => get_package_file_hashes(repo_root, &package_path, &task_definition.inputs)?,
+            };
+
+            // Make sure we include specified .env files in the file hash.
+            // Handled separately because these are not globs!
+            if let Some(dot_env) = &task_definition.dot_env {
+                let dot_env_files = dot_env
+                    .iter()
+                    .map(|path| AnchoredSystemPathBuf::from_raw(path.as_str()?))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let dot_env_object = hash_files(
+                    &repo_root.resolve(&package_path),
+                    dot_env_files.into_iter(),
+                    true,
+                )?;
+                hash_object.extend(dot_env_object);
+            }
+
+            hashes.insert(task_id.to_string(), hash_file_hashes(&hash_object)?);
+            expanded_hashes.insert(task_id.to_string(), hash_object);
+        }
+
+        Ok(PackageInputsHashes {
+            hashes,
+            expanded_hashes,
+        })
+    }
+}
+
+async fn daemon_file_hashes(
+    daemon: &mut DaemonClient<DaemonConnector>,
+    package_path: &AnchoredSystemPathBuf,
+    inputs: &[String],
+) -> Result<GitHashes, Error> {
+    let package_path = package_path.to_unix()?.as_str()?.to_string();
+    daemon
+        .get_package_file_hashes(package_path, inputs.to_vec())
+        .await?
+        .into_iter()
+        .map(|(path, hash)| Ok((RelativeUnixPathBuf::new(path)?, hash)))
+        .collect()
+}
+
+/// The env mode of a single task. In infer mode, tasks that configure
+/// `passThroughEnv` are strict and all other tasks are loose.
+pub fn task_env_mode(global_env_mode: EnvMode, task_definition: &TaskDefinition) -> EnvMode {
+    match global_env_mode {
+        EnvMode::Infer if task_definition.pass_through_env.is_some() => EnvMode::Strict,
+        EnvMode::Infer => EnvMode::Loose,
+        env_mode => env_mode,
+    }
+}
+
+// Everything that goes into a task hash. The fields and their order must
+// match Go's `taskHashable` exactly.
+struct TaskHashable<'a> {
+    global_hash: &'a str,
+    task_dependency_hashes: Vec<String>,
+    package_dir: String,
+    hash_of_files: &'a str,
+    external_deps_hash: String,
+    task: &'a str,
+    outputs: TaskOutputs,
+    pass_thru_args: &'a [String],
+    env: &'a [String],
+    resolved_env_vars: Vec<String>,
+    pass_through_env: &'a [String],
+    env_mode: EnvMode,
+    dot_env: String,
+}
+
+impl TaskHashable<'_> {
+    fn calculate_task_hash(self) -> String {
+        let env_mode = match self.env_mode {
+            EnvMode::Loose => "Loose",
+            EnvMode::Strict => "Strict",
+            EnvMode::Infer => unreachable!("task env mode should have already been resolved"),
+        };
+        // Go hashes a pointer to the struct, hence the leading `&`. In loose mode
+        // pass through env vars aren't considered, but they're formatted the
+        // same as an empty list either way.
+        let pass_through_env = match self.env_mode {
+            EnvMode::Loose => "[]".to_string(),
+            _ => format_slice(self.pass_through_env),
+        };
+        hash_object(&format!(
+            "&{{{} {} {} {} {} {} {{{} {}}} {} {} {} {} {} {}}}",
+            self.global_hash,
+            format_slice(&self.task_dependency_hashes),
+            self.package_dir,
+            self.hash_of_files,
+            self.external_deps_hash,
+            self.task,
+            format_slice(&self.outputs.inclusions),
+            format_slice(&self.outputs.exclusions),
+            format_slice(self.pass_thru_args),
+            format_slice(self.env),
+            format_slice(&self.resolved_env_vars),
+            pass_through_env,
+            env_mode,
+            self.dot_env,
+        ))
+    }
+}
+
+/// Calculates task hashes. Hashes have to be calculated in topological order,
+/// since a task's hash includes the hashes of its dependencies.
+pub struct TaskHasher<'a> {
+    global_hash: String,
+    env_at_execution_start: &'a EnvironmentVariableMap,
+    package_inputs_hashes: PackageInputsHashes,
+    state: Mutex<TaskHashState>,
+}
+
+#[derive(Debug, Default)]
+struct TaskHashState {
+    task_hashes: HashMap<String, String>,
+    task_env_vars: HashMap<String, DetailedMap>,
+    task_frameworks: HashMap<String, &'static str>,
+}
+
+impl<'a> TaskHasher<'a> {
+    pub fn new(
+        global_hash: String,
+        env_at_execution_start: &'a EnvironmentVariableMap,
+        package_inputs_hashes: PackageInputsHashes,
+    ) -> Self {
+        Self {
+            global_hash,
+            env_at_execution_start,
+            package_inputs_hashes,
+            state: Mutex::default(),
+        }
+    }
+
+    pub fn calculate_task_hash(
+        &self,
+        task_id: &str,
+        task_definition: &TaskDefinition,
+        workspace: &WorkspaceInfo,
+        env_mode: EnvMode,
+        dependency_set: HashSet<&TaskNode>,
+        framework_inference: bool,
+        args: &[String],
+    ) -> Result<String, Error> {
+        let (package_name, task) = get_package_task_from_id(task_id);
+        let hash_of_files = self
+            .package_inputs_hashes
+            .hashes
+            .get(task_id)
+            .ok_or_else(|| Error::MissingPackageFileHash(task_id.to_string()))?;
+
+        let framework = framework_inference
+            .then(|| infer_framework(workspace))
+            .flatten();
+        let env_vars = match framework {
+            Some(framework) => {
+                debug!(
+                    "auto detected framework for {}: {} (env prefix {:?})",
+                    package_name, framework.slug, framework.env_wildcards
+                );
+                let mut computed_wildcards = framework
+                    .env_wildcards
+                    .iter()
+                    .map(|wildcard| wildcard.to_string())
+                    .collect::<Vec<_>>();
+
+                // Vendor excludes are only applied against inferred includes.
+                if let Some(exclude_prefix) = self
+                    .env_at_execution_start
+                    .get("TURBO_CI_VENDOR_ENV_KEY")
+                    .filter(|prefix| !prefix.is_empty())
+                {
+                    let computed_exclude = format!("!{exclude_prefix}*");
+                    debug!(
+                        "excluding environment variables matching wildcard {}",
+                        computed_exclude
+                    );
+                    computed_wildcards.push(computed_exclude);
+                }
+
+                let inference_env_var_map = self
+                    .env_at_execution_start
+                    .from_wildcards(&computed_wildcards)?;
+                let user_env_var_set = self
+                    .env_at_execution_start
+                    .wildcard_map_from_wildcards_unresolved(
+                        &task_definition.env_var_dependencies,
+                    )?;
+
+                let mut all_env_var_map = EnvironmentVariableMap::default();
+                all_env_var_map.union(&user_env_var_set.inclusions);
+                all_env_var_map.union(&inference_env_var_map);
+                all_env_var_map.difference(&user_env_var_set.exclusions);
+
+                let mut explicit_env_var_map = EnvironmentVariableMap::default();
+                explicit_env_var_map.union(&user_env_var_set.inclusions);
+                explicit_env_var_map.difference(&user_env_var_set.exclusions);
+
+                let mut matching_env_var_map = EnvironmentVariableMap::default();
+                matching_env_var_map.union(&inference_env_var_map);
+                matching_env_var_map.difference(&user_env_var_set.exclusions);
+
+                DetailedMap {
+                    all: all_env_var_map,
+                    by_source: BySource {
+                        explicit: explicit_env_var_map,
+                        matching: matching_env_var_map,
+                    },
+                }
+            }
+            None => {
+                let all_env_var_map = self
+                    .env_at_execution_start
+                    .from_wildcards(&task_definition.env_var_dependencies)?;
+
+                DetailedMap {
+                    all: all_env_var_map.clone(),
+                    by_source: BySource {
+                        explicit: all_env_var_map,
+                        matching: EnvironmentVariableMap::default(),
+                    },
+                }
+            }
+        };
+
+        let hashable_env_pairs = env_vars.all.to_hashable();
+        let outputs = hashable_outputs(task_definition, &task);
+        let task_dependency_hashes = self.calculate_dependency_hashes(dependency_set)?;
+        debug!(
+            "task hash env vars for {}:{} vars: {:?}",
+            package_name, task, hashable_env_pairs
+        );
+
+        let dot_env = task_definition.dot_env.as_deref().unwrap_or_default();
+        let hash = TaskHashable {
+            global_hash: &self.global_hash,
+            task_dependency_hashes,
+            package_dir: workspace.package_path().to_unix()?.as_str()?.to_string(),
+            hash_of_files,
+            external_deps_hash: workspace.external_deps_hash(),
+            task: &task,
+            outputs,
+            pass_thru_args: args,
+            env: &task_definition.env_var_dependencies,
+            resolved_env_vars: hashable_env_pairs,
+            pass_through_env: task_definition
+                .pass_through_env
+                .as_deref()
+                .unwrap_or_default(),
+            env_mode,
+            dot_env: format_paths(dot_env)?,
+        }
+        .calculate_task_hash();
+
+        let mut state = self.state.lock().expect("task hasher lock poisoned");
+        state.task_env_vars.insert(task_id.to_string(), env_vars);
+        state.task_hashes.insert(task_id.to_string(), hash.clone());
+        if let Some(framework) = framework {
+            state
+                .task_frameworks
+                .insert(task_id.to_string(), framework.slug);
+        }
+
+        Ok(hash)
+    }
+
+    fn calculate_dependency_hashes(
+        &self,
+        dependency_set: HashSet<&TaskNode>,
+    ) -> Result<Vec<String>, Error> {
+        let state = self.state.lock().expect("task hasher lock poisoned");
+        let root_prefix = format!("{ROOT_PKG_NAME}#");
+        let mut dependency_hashes = HashSet::new();
+        for dependency in dependency_set {
+            let TaskNode::Task(dependency_task) = dependency else {
+                continue;
+            };
+            if dependency_task.starts_with(&root_prefix) {
+                continue;
+            }
+            let dependency_hash = state
+                .task_hashes
+                .get(dependency_task)
+                .ok_or_else(|| Error::MissingDependencyTaskHash(dependency_task.clone()))?;
+            dependency_hashes.insert(dependency_hash.clone());
+        }
+
+        let mut dependency_hashes = dependency_hashes.into_iter().collect::<Vec<_>>();
+        dependency_hashes.sort();
+        Ok(dependency_hashes)
+    }
+
+    /// The file hashes that went into the hash of `task_id`.
+    pub fn expanded_inputs(&self, task_id: &str) -> Option<&GitHashes> {
+        self.package_inputs_hashes.expanded_hashes.get(task_id)
+    }
+
+    pub fn task_hash(&self, task_id: &str) -> Option<String> {
+        let state = self.state.lock().expect("task hasher lock poisoned");
+        state.task_hashes.get(task_id).cloned()
+    }
+
+    /// The env vars that affected the hash of `task_id`.
+    pub fn env_vars(&self, task_id: &str) -> Option<DetailedMap> {
+        let state = self.state.lock().expect("task hasher lock poisoned");
+        state.task_env_vars.get(task_id).cloned()
+    }
+
+    /// The framework inferred for the workspace of `task_id`.
+    pub fn framework(&self, task_id: &str) -> Option<&'static str> {
+        let state = self.state.lock().expect("task hasher lock poisoned");
+        state.task_frameworks.get(task_id).copied()
+    }
+}
+
+// The package-relative globs for files to be considered outputs of a task.
+// The log file is always an output.
+pub(crate) fn hashable_outputs(task_definition: &TaskDefinition, task: &str) -> TaskOutputs {
+    let mut inclusions = vec![format!(".turbo/turbo-{task}.log")];
+    inclusions.extend(task_definition.outputs.inclusions.iter().cloned());
+    inclusions.sort();
+
+    let mut exclusions = task_definition.outputs.exclusions.clone();
+    exclusions.sort();
+
+    TaskOutputs {
+        inclusions,
+        exclusions,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use turbopath::RelativeUnixPathBuf;
+
+    use super::*;
+    use crate::run::global_hash::{GlobalHashableInputs, GLOBAL_CACHE_KEY};
+
+    fn file_hashes(hashes: &[(&str, &str)]) -> GitHashes {
+        hashes
+            .iter()
+            .map(|(path, hash)| (RelativeUnixPathBuf::new(*path).unwrap(), hash.to_string()))
+            .collect()
+    }
+
+    // Taken from the `single_package` dry run integration test, which is
+    // hashed by the Go implementation
+    #[test]
+    fn test_matches_go_task_hash() {
+        let global_hash = GlobalHashableInputs {
+            global_cache_key: GLOBAL_CACHE_KEY,
+            global_file_hash_map: file_hashes(&[
+                (
+                    "package-lock.json",
+                    "1c117cce37347befafe3a9cba1b8a609b3600021",
+                ),
+                ("package.json", "185771929d92c3865ce06c863c07d357500d3364"),
+                ("somefile.txt", "45b983be36b73c0788dc9cbcb76cbb80fc7bb057"),
+            ]),
+            env_mode: EnvMode::Infer,
+            framework_inference: true,
+            ..Default::default()
+        }
+        .calculate_global_hash()
+        .unwrap();
+
+        let hash_of_files = hash_file_hashes(&file_hashes(&[
+            (".gitignore", "6f23ff6842b5526da43ab38f4a5bf3b0158eeb42"),
+            (
+                "package-lock.json",
+                "1c117cce37347befafe3a9cba1b8a609b3600021",
+            ),
+            ("package.json", "185771929d92c3865ce06c863c07d357500d3364"),
+            ("somefile.txt", "45b983be36b73c0788dc9cbcb76cbb80fc7bb057"),
+            ("turbo.json", "505752e75c10f9e7a0d2538cf8b6f0fcfb8980a0"),
+        ]))
+        .unwrap();
+
+        let task_definition = TaskDefinition {
+            outputs: TaskOutputs {
+                inclusions: vec!["foo".to_string()],
+                exclusions: vec![],
+            },
+            ..Default::default()
+        };
+
+        let hash = TaskHashable {
+            global_hash: &global_hash,
+            task_dependency_hashes: vec![],
+            package_dir: String::new(),
+            hash_of_files: &hash_of_files,
+            external_deps_hash: String::new(),
+            task: "build",
+            outputs: hashable_outputs(&task_definition, "build"),
+            pass_thru_args: &[],
+            env: &[],
+            resolved_env_vars: vec![],
+            pass_through_env: &[],
+            env_mode: EnvMode::Loose,
+            dot_env: format_paths(&[]).unwrap(),
+        }
+        .calculate_task_hash();
+
+        assert_eq!(hash, "d2295def33764d46");
+    }
+
+    #[test]
+    fn test_hashable_outputs() {
+        let task_definition = TaskDefinition {
+            outputs: TaskOutputs {
+                inclusions: vec!["dist/**".to_string(), ".next/**".to_string()],
+                exclusions: vec!["dist/b".to_string(), "dist/a".to_string()],
+            },
+            ..Default::default()
+        };
+
+        let outputs = hashable_outputs(&task_definition, "build");
+        assert_eq!(
+            outputs.inclusions,
+            vec![".next/**", ".turbo/turbo-build.log", "dist/**"]
+        );
+        assert_eq!(outputs.exclusions, vec!["dist/a", "dist/b"]);
+    }
+}
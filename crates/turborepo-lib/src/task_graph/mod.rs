@@ -1,10 +1,34 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use thiserror::Error;
+use tracing::warn;
+use turbopath::RelativeUnixPathBuf;
+
+use crate::{
+    cli::OutputLogsMode,
+    config::{object_schema, properties, JsonSchema},
+};
 
 pub type Pipeline = HashMap<String, BookkeepingTaskDefinition>;
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) const ENV_PIPELINE_DELIMITER: &str = "$";
+const TOPOLOGICAL_PIPELINE_DELIMITER: &str = "^";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(
+        "You specified \"{value}\" in the \"{key}\" key. You should not prefix your environment \
+         variables with \"{ENV_PIPELINE_DELIMITER}\""
+    )]
+    InvalidEnvPrefix { value: String, key: &'static str },
+    #[error(transparent)]
+    Path(#[from] turbopath::PathError),
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "RawTaskDefinition", into = "RawTaskDefinition")]
 pub struct BookkeepingTaskDefinition {
     defined_fields: HashSet<String>,
     experimental_fields: HashSet<String>,
@@ -15,42 +39,83 @@ pub struct BookkeepingTaskDefinition {
 // A list of config fields in a task definition that are considered
 // experimental. We keep these separated so we can compute a global hash without
 // these.
-#[derive(Debug, Default, Serialize, Deserialize)]
-struct TaskDefinitionExperiments {
-    passthrough_env: Vec<String>,
-}
+#[derive(Debug, Default, Clone, PartialEq)]
+struct TaskDefinitionExperiments {}
 
 // TaskOutputs represents the patterns for including and excluding files from
 // outputs
-#[derive(Debug, Default, Serialize, Deserialize)]
-struct TaskOutputs {
-    inclusions: Vec<String>,
-    exclusions: Vec<String>,
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TaskOutputs {
+    pub inclusions: Vec<String>,
+    pub exclusions: Vec<String>,
 }
 
 // TaskOutputMode defines the ways turbo can display task output during a run
-#[derive(Debug, Default, Serialize, Deserialize)]
-enum TaskOutputMode {
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TaskOutputMode {
     // FullTaskOutput will show all task output
     #[default]
+    #[serde(rename = "full")]
     Full,
     // None will hide all task output
+    #[serde(rename = "none")]
     None,
     // Hash will display turbo-computed task hashes
+    #[serde(rename = "hash-only")]
     Hash,
     // New will show all new task output and turbo-computed task hashes for cached
     // output
+    #[serde(rename = "new-only")]
     New,
     // Error will show task output for failures only; no cache miss/hit messages are
     // emitted
+    #[serde(rename = "errors-only")]
     Error,
 }
 
+impl From<OutputLogsMode> for TaskOutputMode {
+    fn from(mode: OutputLogsMode) -> Self {
+        match mode {
+            OutputLogsMode::Full => TaskOutputMode::Full,
+            OutputLogsMode::None => TaskOutputMode::None,
+            OutputLogsMode::HashOnly => TaskOutputMode::Hash,
+            OutputLogsMode::NewOnly => TaskOutputMode::New,
+            OutputLogsMode::ErrorsOnly => TaskOutputMode::Error,
+        }
+    }
+}
+
+// RawTaskDefinition is the shape of a task definition in turbo.json. When
+// fields are omitted we _want_ them to be missing, so that we can distinguish
+// a missing value from an empty one.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawTaskDefinition {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outputs: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cache: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    depends_on: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    inputs: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output_mode: Option<TaskOutputMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    persistent: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    env: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pass_through_env: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dot_env: Option<Vec<String>>,
+}
+
 // taskDefinitionHashable exists as a definition for PristinePipeline, which is
 // used downstream for calculating the global hash. We want to exclude
 // experimental fields here because we don't want experimental fields to be part
 // of the global hash.
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq)]
 struct TaskDefinitionHashable {
     outputs: TaskOutputs,
     should_cache: bool,
@@ -60,41 +125,508 @@ struct TaskDefinitionHashable {
     inputs: Vec<String>,
     output_mode: TaskOutputMode,
     persistent: bool,
+    pass_through_env: Option<Vec<String>>,
+    dot_env: Option<Vec<RelativeUnixPathBuf>>,
+}
+
+impl Default for TaskDefinitionHashable {
+    fn default() -> Self {
+        Self {
+            outputs: TaskOutputs::default(),
+            // Tasks are cached unless they opt out
+            should_cache: true,
+            env_var_dependencies: Vec::new(),
+            topological_dependencies: Vec::new(),
+            task_dependencies: Vec::new(),
+            inputs: Vec::new(),
+            output_mode: TaskOutputMode::default(),
+            persistent: false,
+            pass_through_env: None,
+            dot_env: None,
+        }
+    }
 }
 
 // task_definition is a representation of the configFile pipeline for further
 // computation.
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct TaskDefinition {
-    outputs: TaskOutputs,
-    should_cache: bool,
+    pub outputs: TaskOutputs,
+    pub should_cache: bool,
 
     // This field is custom-marshalled from rawTask.Env and rawTask.DependsOn
-    env_var_dependencies: Vec<String>,
+    pub env_var_dependencies: Vec<String>,
+
+    // rawTask.PassThroughEnv
+    pub pass_through_env: Option<Vec<String>>,
 
-    // rawTask.PassthroughEnv
-    passthrough_env: Vec<String>,
+    // rawTask.DotEnv
+    pub dot_env: Option<Vec<RelativeUnixPathBuf>>,
 
     // TopologicalDependencies are tasks from package dependencies.
     // E.g. "build" is a topological dependency in:
     // dependsOn: ['^build'].
     // This field is custom-marshalled from rawTask.DependsOn
-    topological_dependencies: Vec<String>,
+    pub topological_dependencies: Vec<String>,
 
     // TaskDependencies are anything that is not a topological dependency
     // E.g. both something and //whatever are TaskDependencies in:
     // dependsOn: ['something', '//whatever']
     // This field is custom-marshalled from rawTask.DependsOn
-    task_dependencies: Vec<String>,
+    pub task_dependencies: Vec<String>,
 
     // Inputs indicate the list of files this Task depends on. If any of those files change
     // we can conclude that any cached outputs or logs for this Task should be invalidated.
-    inputs: Vec<String>,
+    pub inputs: Vec<String>,
 
     // OutputMode determines how we should log the output.
-    output_mode: TaskOutputMode,
+    pub output_mode: TaskOutputMode,
 
     // Persistent indicates whether the Task is expected to exit or not
     // Tasks marked Persistent do not exit (e.g. --watch mode or dev servers)
-    persistent: bool,
+    pub persistent: bool,
+}
+
+impl BookkeepingTaskDefinition {
+    // Definition for a script synthesized from the root package.json in single
+    // package mode. `cache` is marked as defined so that downstream we can
+    // pretend it was set on purpose in a config file.
+    pub fn synthesized() -> Self {
+        Self {
+            defined_fields: ["ShouldCache".to_string()].into_iter().collect(),
+            task_definition: TaskDefinitionHashable {
+                should_cache: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    pub fn has_field(&self, field: &str) -> bool {
+        self.defined_fields.contains(field) || self.experimental_fields.contains(field)
+    }
+
+    pub fn task_definition(&self) -> TaskDefinition {
+        let TaskDefinitionHashable {
+            outputs,
+            should_cache,
+            env_var_dependencies,
+            topological_dependencies,
+            task_dependencies,
+            inputs,
+            output_mode,
+            persistent,
+            pass_through_env,
+            dot_env,
+        } = self.task_definition.clone();
+
+        TaskDefinition {
+            outputs,
+            should_cache,
+            env_var_dependencies,
+            pass_through_env,
+            dot_env,
+            topological_dependencies,
+            task_dependencies,
+            inputs,
+            output_mode,
+            persistent,
+        }
+    }
+
+    // Merges a chain of definitions for the same task, e.g. from the root and
+    // a workspace turbo.json. Later definitions replace only the fields they
+    // define, so an unset field never overrides a value set earlier.
+    pub fn merge<'a>(definitions: impl IntoIterator<Item = &'a Self>) -> TaskDefinition {
+        let mut merged = TaskDefinitionHashable::default();
+        for definition in definitions {
+            let task = &definition.task_definition;
+            if definition.has_field("Outputs") {
+                merged.outputs = task.outputs.clone();
+            }
+            if definition.has_field("ShouldCache") {
+                merged.should_cache = task.should_cache;
+            }
+            if definition.has_field("DependsOn") {
+                merged.topological_dependencies = task.topological_dependencies.clone();
+                merged.task_dependencies = task.task_dependencies.clone();
+            }
+            if definition.has_field("Inputs") {
+                merged.inputs = task.inputs.clone();
+            }
+            if definition.has_field("OutputMode") {
+                merged.output_mode = task.output_mode;
+            }
+            if definition.has_field("Persistent") {
+                merged.persistent = task.persistent;
+            }
+            if definition.has_field("Env") {
+                merged.env_var_dependencies = task.env_var_dependencies.clone();
+            }
+            if definition.has_field("PassThroughEnv") {
+                merged.pass_through_env = task.pass_through_env.clone();
+            }
+            if definition.has_field("DotEnv") {
+                merged.dot_env = task.dot_env.clone();
+            }
+        }
+
+        BookkeepingTaskDefinition {
+            task_definition: merged,
+            ..Default::default()
+        }
+        .task_definition()
+    }
+}
+
+pub(crate) fn gather_env_vars(
+    vars: Vec<String>,
+    key: &'static str,
+    into: &mut BTreeSet<String>,
+) -> Result<(), Error> {
+    for value in vars {
+        if value.starts_with(ENV_PIPELINE_DELIMITER) {
+            // Hard error to help people specify this correctly during migration.
+            return Err(Error::InvalidEnvPrefix { value, key });
+        }
+        into.insert(value);
+    }
+
+    Ok(())
+}
+
+fn warn_on_absolute_path(key: &str, glob: &str) {
+    if std::path::Path::new(glob).is_absolute() {
+        warn!(
+            "Using an absolute path in \"{}\" ({}) will not work and will be an error in a future \
+             version",
+            key, glob
+        );
+    }
+}
+
+impl TryFrom<RawTaskDefinition> for BookkeepingTaskDefinition {
+    type Error = Error;
+
+    fn try_from(raw_task: RawTaskDefinition) -> Result<Self, Error> {
+        let mut defined_fields = HashSet::new();
+        let mut task_definition = TaskDefinitionHashable::default();
+
+        if let Some(outputs) = raw_task.outputs {
+            // Record that outputs were really configured in the underlying config file
+            defined_fields.insert("Outputs".to_string());
+            for glob in outputs {
+                if let Some(exclusion) = glob.strip_prefix('!') {
+                    warn_on_absolute_path("outputs", exclusion);
+                    task_definition
+                        .outputs
+                        .exclusions
+                        .push(exclusion.to_string());
+                } else {
+                    warn_on_absolute_path("outputs", &glob);
+                    task_definition.outputs.inclusions.push(glob);
+                }
+            }
+            task_definition.outputs.inclusions.sort();
+            task_definition.outputs.exclusions.sort();
+        }
+
+        if let Some(cache) = raw_task.cache {
+            defined_fields.insert("ShouldCache".to_string());
+            task_definition.should_cache = cache;
+        }
+
+        let mut env_var_dependencies = BTreeSet::new();
+
+        // We don't care what's in dependsOn, just that it was there. We use this
+        // marker to overwrite while merging task definitions.
+        if let Some(depends_on) = raw_task.depends_on {
+            defined_fields.insert("DependsOn".to_string());
+            for dependency in depends_on {
+                if let Some(env_var) = dependency.strip_prefix(ENV_PIPELINE_DELIMITER) {
+                    warn!(
+                        "[DEPRECATED] Declaring an environment variable in \"dependsOn\" is \
+                         deprecated, found {}. Use the \"env\" key or use `npx @turbo/codemod \
+                         migrate-env-var-dependencies`.",
+                        dependency
+                    );
+                    defined_fields.insert("Env".to_string());
+                    env_var_dependencies.insert(env_var.to_string());
+                } else if let Some(topo_dependency) =
+                    dependency.strip_prefix(TOPOLOGICAL_PIPELINE_DELIMITER)
+                {
+                    task_definition
+                        .topological_dependencies
+                        .push(topo_dependency.to_string());
+                } else {
+                    task_definition.task_dependencies.push(dependency);
+                }
+            }
+            task_definition.topological_dependencies.sort();
+            task_definition.task_dependencies.sort();
+        }
+
+        if let Some(env) = raw_task.env {
+            defined_fields.insert("Env".to_string());
+            gather_env_vars(env, "env", &mut env_var_dependencies)?;
+        }
+        task_definition.env_var_dependencies = env_var_dependencies.into_iter().collect();
+
+        if let Some(pass_through_env) = raw_task.pass_through_env {
+            defined_fields.insert("PassThroughEnv".to_string());
+            let mut env_var_pass_throughs = BTreeSet::new();
+            gather_env_vars(
+                pass_through_env,
+                "passThroughEnv",
+                &mut env_var_pass_throughs,
+            )?;
+            task_definition.pass_through_env = Some(env_var_pass_throughs.into_iter().collect());
+        }
+
+        if let Some(dot_env) = raw_task.dot_env {
+            defined_fields.insert("DotEnv".to_string());
+            // These are _explicitly_ not sorted, the order is the load order.
+            task_definition.dot_env = Some(
+                dot_env
+                    .into_iter()
+                    .map(RelativeUnixPathBuf::new)
+                    .collect::<Result<_, _>>()?,
+            );
+        }
+
+        if let Some(inputs) = raw_task.inputs {
+            // Inputs don't need to be sorted, the resulting file hashes are
+            defined_fields.insert("Inputs".to_string());
+            for input in &inputs {
+                warn_on_absolute_path("inputs", input);
+            }
+            task_definition.inputs = inputs;
+        }
+
+        if let Some(output_mode) = raw_task.output_mode {
+            defined_fields.insert("OutputMode".to_string());
+            task_definition.output_mode = output_mode;
+        }
+
+        if let Some(persistent) = raw_task.persistent {
+            defined_fields.insert("Persistent".to_string());
+            task_definition.persistent = persistent;
+        }
+
+        Ok(BookkeepingTaskDefinition {
+            defined_fields,
+            experimental_fields: HashSet::new(),
+            experimental: TaskDefinitionExperiments::default(),
+            task_definition,
+        })
+    }
+}
+
+impl JsonSchema for TaskOutputMode {
+    fn json_schema() -> Value {
+        let modes = [Self::Full, Self::None, Self::Hash, Self::New, Self::Error]
+            .into_iter()
+            .map(|mode| serde_json::to_value(mode).expect("output modes serialize to strings"))
+            .collect::<Vec<_>>();
+        json!({"type": "string", "enum": modes})
+    }
+}
+
+// Unknown task keys are rejected when turbo.json is loaded, so the schema
+// doesn't allow them either
+impl JsonSchema for RawTaskDefinition {
+    fn json_schema() -> Value {
+        object_schema(
+            properties!(RawTaskDefinition {
+                outputs => "Globs of files produced by the task that are cached. Prefix a glob \
+                            with `!` to exclude files.",
+                cache => "Whether the task's outputs and logs are cached. Defaults to true.",
+                depends_on => "Tasks that must complete before this one. Prefix a task with `^` \
+                               to depend on it in the workspace's dependencies.",
+                inputs => "Globs of files that the task's hash depends on. Defaults to every \
+                           file in the workspace.",
+                output_mode => "How the task's logs are shown while running.",
+                persistent => "Marks a long-running task, such as a dev server, that other \
+                               tasks can't depend on.",
+                env => "Environment variables that the task's hash depends on.",
+                pass_through_env => "Environment variables that are available to the task \
+                                     without affecting its hash.",
+                dot_env => "`.env` files that the task's hash depends on, in the order they are \
+                            loaded.",
+            }),
+            true,
+        )
+    }
+}
+
+// Task definitions are read from turbo.json as a `RawTaskDefinition`
+impl JsonSchema for BookkeepingTaskDefinition {
+    fn json_schema() -> Value {
+        RawTaskDefinition::json_schema()
+    }
+}
+
+// Only fields that were defined are written back out, so that reading and
+// writing a turbo.json doesn't fill in defaults the user never set.
+impl From<BookkeepingTaskDefinition> for RawTaskDefinition {
+    fn from(definition: BookkeepingTaskDefinition) -> Self {
+        let has_field = |field: &str| definition.has_field(field);
+        let task = &definition.task_definition;
+
+        let outputs = has_field("Outputs").then(|| {
+            task.outputs
+                .inclusions
+                .iter()
+                .cloned()
+                .chain(
+                    task.outputs
+                        .exclusions
+                        .iter()
+                        .map(|exclusion| format!("!{exclusion}")),
+                )
+                .collect()
+        });
+        let depends_on = has_field("DependsOn").then(|| {
+            task.topological_dependencies
+                .iter()
+                .map(|dependency| format!("{TOPOLOGICAL_PIPELINE_DELIMITER}{dependency}"))
+                .chain(task.task_dependencies.iter().cloned())
+                .collect()
+        });
+        let dot_env = task.dot_env.as_ref().map(|dot_env| {
+            dot_env
+                .iter()
+                .map(|path| path.as_str().unwrap_or_default().to_string())
+                .collect()
+        });
+
+        RawTaskDefinition {
+            outputs,
+            cache: has_field("ShouldCache").then_some(task.should_cache),
+            depends_on,
+            inputs: has_field("Inputs").then(|| task.inputs.clone()),
+            output_mode: has_field("OutputMode").then_some(task.output_mode),
+            persistent: has_field("Persistent").then_some(task.persistent),
+            env: has_field("Env").then(|| task.env_var_dependencies.clone()),
+            pass_through_env: task.pass_through_env.clone(),
+            dot_env,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config::assert_matches_schema;
+
+    #[test]
+    fn test_deserialize_task_definition() {
+        let definition: BookkeepingTaskDefinition = serde_json::from_str(
+            r#"{
+                "dependsOn": ["^build", "prebuild", "$FOO", "b#codegen"],
+                "outputs": ["dist/**", "!dist/cache/**"],
+                "env": ["BAR"],
+                "persistent": true,
+                "outputMode": "new-only"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            definition.task_definition(),
+            TaskDefinition {
+                outputs: TaskOutputs {
+                    inclusions: vec!["dist/**".to_string()],
+                    exclusions: vec!["dist/cache/**".to_string()],
+                },
+                should_cache: true,
+                env_var_dependencies: vec!["BAR".to_string(), "FOO".to_string()],
+                pass_through_env: None,
+                dot_env: None,
+                topological_dependencies: vec!["build".to_string()],
+                task_dependencies: vec!["b#codegen".to_string(), "prebuild".to_string()],
+                inputs: vec![],
+                output_mode: TaskOutputMode::New,
+                persistent: true,
+            }
+        );
+        assert!(definition.has_field("DependsOn"));
+        assert!(!definition.has_field("ShouldCache"));
+    }
+
+    #[test]
+    fn test_env_prefix_is_rejected() {
+        let err =
+            serde_json::from_str::<BookkeepingTaskDefinition>(r#"{"env": ["$FOO"]}"#).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("You should not prefix your environment variables"));
+    }
+
+    #[test]
+    fn test_merge_task_definitions() {
+        let root: BookkeepingTaskDefinition = serde_json::from_str(
+            r#"{"dependsOn": ["^build"], "outputs": ["dist/**"], "env": ["FOO"]}"#,
+        )
+        .unwrap();
+        let workspace: BookkeepingTaskDefinition =
+            serde_json::from_str(r#"{"outputs": [".next/**"], "cache": false, "env": []}"#)
+                .unwrap();
+
+        let merged = BookkeepingTaskDefinition::merge([&root, &workspace]);
+        assert_eq!(merged.outputs.inclusions, vec![".next/**".to_string()]);
+        assert_eq!(merged.topological_dependencies, vec!["build".to_string()]);
+        assert!(!merged.should_cache);
+        // Explicitly empty fields still override
+        assert!(merged.env_var_dependencies.is_empty());
+
+        // Nothing defined falls back to the defaults
+        let merged = BookkeepingTaskDefinition::merge([]);
+        assert!(merged.should_cache);
+    }
+
+    #[test]
+    fn test_schema_describes_every_key() {
+        let all_fields = RawTaskDefinition {
+            outputs: Some(vec!["a".to_string()]),
+            cache: Some(true),
+            depends_on: Some(vec!["a".to_string()]),
+            inputs: Some(vec!["a".to_string()]),
+            output_mode: Some(TaskOutputMode::Full),
+            persistent: Some(true),
+            env: Some(vec!["a".to_string()]),
+            pass_through_env: Some(vec!["a".to_string()]),
+            dot_env: Some(vec!["a".to_string()]),
+        };
+        let value = serde_json::to_value(all_fields).unwrap();
+        let keys = value
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        let schema = BookkeepingTaskDefinition::json_schema();
+        let properties = schema["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<BTreeSet<_>>();
+        assert_eq!(keys, properties);
+        assert_matches_schema(&value, &schema);
+
+        for mode in TaskOutputMode::json_schema()["enum"].as_array().unwrap() {
+            serde_json::from_value::<TaskOutputMode>(mode.clone()).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_serialize_only_defined_fields() {
+        let definition: BookkeepingTaskDefinition =
+            serde_json::from_str(r#"{"dependsOn": ["^build"], "cache": false}"#).unwrap();
+        assert_eq!(
+            serde_json::to_value(&definition).unwrap(),
+            serde_json::json!({"dependsOn": ["^build"], "cache": false})
+        );
+    }
 }
@@ -29,7 +29,7 @@ pub fn start_spinner(message: &str) -> ProgressBar {
 }
 
 /// Helper struct to apply any necessary formatting to UI output
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct UI {
     pub should_strip_ansi: bool,
 }
@@ -111,6 +111,8 @@ lazy_static! {
     pub static ref BOLD: Style = Style::new().bold();
     pub static ref MAGENTA: Style = Style::new().magenta();
     pub static ref UNDERLINE: Style = Style::new().underlined();
+    pub static ref BOLD_RED: Style = Style::new().bold().red();
+    pub static ref BOLD_CYAN: Style = Style::new().bold().cyan();
 }
 
 pub const RESET: &str = "\x1b[0m";
@@ -148,15 +148,6 @@ impl<'a> Descriptor<'a> {
         self.range.split_once(':').map(|(protocol, _)| protocol)
     }
 
-    /// Access the range based on the lifetime of the underlying string slice
-    /// this will return None if the underlying range is owned.
-    pub(crate) fn range(&self) -> Option<&'a str> {
-        match self.range {
-            Cow::Borrowed(s) => Some(s),
-            _ => None,
-        }
-    }
-
     /// If the descriptor is a patch returns the version that the patch targets
     pub fn primary_version(&self) -> Option<String> {
         let Locator { reference, .. } = Locator::from_patch_reference(&self.range)?;
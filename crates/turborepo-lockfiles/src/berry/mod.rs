@@ -5,9 +5,10 @@ mod resolution;
 mod ser;
 
 use std::{
+    any::Any,
     collections::{HashMap, HashSet},
     iter,
-    path::Path,
+    sync::Arc,
 };
 
 use de::SemverString;
@@ -38,18 +39,18 @@ pub enum Error {
 // We depend on BTree iteration being sorted for correct serialization
 type Map<K, V> = std::collections::BTreeMap<K, V>;
 
-pub struct BerryLockfile<'a> {
-    data: &'a LockfileData,
-    resolutions: Map<Descriptor<'a>, Locator<'a>>,
+pub struct BerryLockfile {
+    metadata: Metadata,
+    resolutions: Map<Descriptor<'static>, Locator<'static>>,
     // A mapping from descriptors without protocols to a range with a protocol
-    resolver: DescriptorResolver<'a>,
-    locator_package: Map<Locator<'a>, &'a BerryPackage>,
+    resolver: DescriptorResolver,
+    locator_package: Map<Locator<'static>, Arc<BerryPackage>>,
     // Map of regular locators to patch locators that apply to them
-    patches: Map<Locator<'static>, Locator<'a>>,
+    patches: Map<Locator<'static>, Locator<'static>>,
     // Descriptors that come from default package extensions that ship with berry
     extensions: HashSet<Descriptor<'static>>,
     // Package overrides
-    overrides: Map<Resolution<'a>, &'a str>,
+    overrides: Map<Resolution, String>,
 }
 
 // This is the direct representation of the lockfile as it appears on disk.
@@ -97,11 +98,8 @@ pub struct BerryManifest {
     resolutions: Option<Map<String, String>>,
 }
 
-impl<'a> BerryLockfile<'a> {
-    pub fn new(
-        lockfile: &'a LockfileData,
-        manifest: Option<&'a BerryManifest>,
-    ) -> Result<Self, Error> {
+impl BerryLockfile {
+    pub fn new(lockfile: &LockfileData, manifest: Option<&BerryManifest>) -> Result<Self, Error> {
         let mut patches = Map::new();
         let mut locator_package = Map::new();
         let mut descriptor_locator = Map::new();
@@ -113,27 +111,30 @@ impl<'a> BerryLockfile<'a> {
                 let original_locator = locator
                     .patched_locator()
                     .ok_or_else(|| Error::PatchMissingOriginalLocator(locator.as_owned()))?;
-                patches.insert(original_locator.as_owned(), locator.clone());
+                patches.insert(original_locator.as_owned(), locator.as_owned());
             }
 
-            locator_package.insert(locator.clone(), package);
+            locator_package.insert(locator.as_owned(), Arc::new(package.clone()));
 
             for descriptor in Descriptor::from_lockfile_key(key) {
                 let descriptor = descriptor?;
                 if let Some(other) = resolver.insert(&descriptor) {
                     panic!("Descriptor collision {descriptor} and {other}");
                 }
-                descriptor_locator.insert(descriptor, locator.clone());
+                descriptor_locator.insert(descriptor.into_owned(), locator.as_owned());
             }
         }
 
         let overrides = manifest
             .and_then(|manifest| manifest.resolutions())
             .transpose()?
-            .unwrap_or_default();
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(resolution, reference)| (resolution, reference.to_string()))
+            .collect();
 
         let mut this = Self {
-            data: lockfile,
+            metadata: lockfile.metadata.clone(),
             resolutions: descriptor_locator,
             locator_package,
             resolver,
@@ -173,18 +174,8 @@ impl<'a> BerryLockfile<'a> {
         Ok(())
     }
 
-    /// All patch files referenced in the lockfile
-    pub fn patches(&self) -> Vec<&Path> {
-        self.patches
-            .values()
-            .filter_map(|patch| patch.patch_file())
-            .filter(|path| !Locator::is_patch_builtin(path))
-            .map(Path::new)
-            .collect()
-    }
-
     // Helper function for inverting the resolution map
-    fn locator_to_descriptors(&self) -> HashMap<&Locator<'a>, HashSet<&Descriptor<'a>>> {
+    fn locator_to_descriptors(&self) -> HashMap<&Locator<'static>, HashSet<&Descriptor<'static>>> {
         let mut reverse_lookup: HashMap<&Locator, HashSet<&Descriptor>> =
             HashMap::with_capacity(self.locator_package.len());
 
@@ -201,7 +192,7 @@ impl<'a> BerryLockfile<'a> {
     /// Constructs a new lockfile data ready to be serialized
     pub fn lockfile(&self) -> Result<LockfileData, Error> {
         let mut packages: std::collections::BTreeMap<String, BerryPackage> = Map::new();
-        let mut metadata = self.data.metadata.clone();
+        let mut metadata = self.metadata.clone();
         let reverse_lookup = self.locator_to_descriptors();
 
         for (locator, descriptors) in reverse_lookup {
@@ -216,7 +207,7 @@ impl<'a> BerryLockfile<'a> {
                 .locator_package
                 .get(locator)
                 .ok_or_else(|| Error::MissingPackageForLocator(locator.as_owned()))?;
-            packages.insert(key, (*package).clone());
+            packages.insert(key, package.as_ref().clone());
         }
 
         // If there aren't any checksums in the lockfile, then cache key is omitted
@@ -242,7 +233,7 @@ impl<'a> BerryLockfile<'a> {
         &self,
         workspace_packages: &[String],
         packages: &[String],
-    ) -> Result<BerryLockfile<'a>, Error> {
+    ) -> Result<BerryLockfile, Error> {
         let reverse_lookup = self.locator_to_descriptors();
 
         let mut resolutions = Map::new();
@@ -263,7 +254,7 @@ impl<'a> BerryLockfile<'a> {
                         .resolutions
                         .get(&dependency)
                         .unwrap_or_else(|| panic!("No locator found for {dependency}"));
-                    resolutions.insert(dependency, dep_locator.clone());
+                    resolutions.insert(dependency.into_owned(), dep_locator.clone());
                 }
 
                 // Included workspaces will always have their locator listed as a descriptor.
@@ -287,7 +278,7 @@ impl<'a> BerryLockfile<'a> {
                     .resolutions
                     .get(&dependency)
                     .ok_or_else(|| Error::MissingLocator(dependency.clone().into_owned()))?;
-                resolutions.insert(dependency, dep_locator.clone());
+                resolutions.insert(dependency.into_owned(), dep_locator.clone());
             }
 
             // If the package has an associated patch we include it in the subgraph
@@ -327,7 +318,7 @@ impl<'a> BerryLockfile<'a> {
         }
 
         Ok(Self {
-            data: self.data,
+            metadata: self.metadata.clone(),
             resolutions,
             patches,
             // We clone the following structures without any alterations and
@@ -339,12 +330,12 @@ impl<'a> BerryLockfile<'a> {
         })
     }
 
-    fn resolve_dependency(
+    fn resolve_dependency<'b>(
         &self,
         locator: &Locator,
-        name: &'a str,
-        range: &'a str,
-    ) -> Result<Descriptor<'a>, Error> {
+        name: &'b str,
+        range: &'b str,
+    ) -> Result<Descriptor<'b>, Error> {
         let mut dependency = Descriptor::new(name, range)?;
         // If there's no protocol we attempt to find a known one
         if dependency.protocol().is_none() {
@@ -366,7 +357,7 @@ impl<'a> BerryLockfile<'a> {
     }
 }
 
-impl<'a> Lockfile for BerryLockfile<'a> {
+impl Lockfile for BerryLockfile {
     fn resolve_package(
         &self,
         workspace_path: &str,
@@ -432,6 +423,56 @@ impl<'a> Lockfile for BerryLockfile<'a> {
         // For each dependency we need to check if there's an override
         Ok(Some(map))
     }
+
+    fn all_packages(&self) -> Vec<String> {
+        self.locator_package
+            .keys()
+            // Patched packages are always resolved to the patch locator, but
+            // the original package must stay in the lockfile
+            .filter(|locator| {
+                !locator.reference.starts_with("workspace:") && !self.patches.contains_key(locator)
+            })
+            .map(|locator| locator.to_string())
+            .collect()
+    }
+
+    fn subgraph(
+        &self,
+        workspace_packages: &[String],
+        packages: &[String],
+    ) -> Result<Box<dyn Lockfile>, crate::Error> {
+        let subgraph = self.subgraph(workspace_packages, packages)?;
+        Ok(Box::new(subgraph))
+    }
+
+    fn encode(&self) -> Result<Vec<u8>, crate::Error> {
+        Ok(self.lockfile()?.to_string().into_bytes())
+    }
+
+    fn patches(&self) -> Vec<String> {
+        let mut patches = self
+            .patches
+            .values()
+            .filter_map(|patch| patch.patch_file())
+            .filter(|path| !Locator::is_patch_builtin(path))
+            .map(|path| path.to_string())
+            .collect::<Vec<_>>();
+        patches.sort();
+        patches
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn global_change(&self, other: &dyn Lockfile) -> bool {
+        let Some(other) = other.as_any().downcast_ref::<Self>() else {
+            return true;
+        };
+
+        self.metadata.cache_key != other.metadata.cache_key
+            || self.metadata.version != other.metadata.version
+    }
 }
 
 impl LockfileData {
@@ -604,10 +645,27 @@ mod test {
             LockfileData::from_bytes(include_bytes!("../../fixtures/minimal-berry.lock")).unwrap();
         let lockfile = BerryLockfile::new(&data, None).unwrap();
 
-        let empty_vec: Vec<&Path> = Vec::new();
+        let empty_vec: Vec<String> = Vec::new();
         assert_eq!(lockfile.patches(), empty_vec);
     }
 
+    #[test]
+    fn test_subgraph_trait_object() {
+        let contents = include_bytes!("../../fixtures/minimal-berry.lock");
+        let workspaces = ["packages/a".to_string(), "packages/c".to_string()];
+        let packages = ["lodash@npm:4.17.21".to_string()];
+        let lockfile: Box<dyn Lockfile> = Box::new(
+            BerryLockfile::new(&LockfileData::from_bytes(contents).unwrap(), None).unwrap(),
+        );
+
+        let pruned = Lockfile::subgraph(lockfile.as_ref(), &workspaces, &packages).unwrap();
+        assert_eq!(
+            pruned.encode().unwrap(),
+            berry_subgraph(contents, &workspaces, &packages, None).unwrap()
+        );
+        assert!(!lockfile.global_change(pruned.as_ref()));
+    }
+
     #[test]
     fn test_basic_descriptor_prune() {
         let data: LockfileData =
@@ -26,17 +26,17 @@ pub enum Error {
 
 /// A resolution that can appear in the resolutions field of the top level
 /// package.json
-#[derive(Debug, PartialEq, Clone, Copy, Eq, Default, PartialOrd, Ord, Hash)]
-pub struct Resolution<'a> {
-    from: Option<Specifier<'a>>,
-    descriptor: Specifier<'a>,
+#[derive(Debug, PartialEq, Clone, Eq, Default, PartialOrd, Ord, Hash)]
+pub struct Resolution {
+    from: Option<Specifier>,
+    descriptor: Specifier,
 }
 
 // This is essentially an Ident with an optional semver range
-#[derive(Debug, PartialEq, Clone, Copy, Eq, Default, PartialOrd, Ord, Hash)]
-struct Specifier<'a> {
-    full_name: &'a str,
-    description: Option<&'a str>,
+#[derive(Debug, PartialEq, Clone, Eq, Default, PartialOrd, Ord, Hash)]
+struct Specifier {
+    full_name: String,
+    description: Option<String>,
 }
 
 #[derive(Parser)]
@@ -80,8 +80,12 @@ fn parse_specifier(specifier: Pair<'_, Rule>) -> Result<Option<Specifier>, Error
     match specifier.as_rule() {
         Rule::specifier => {
             let mut parts = specifier.into_inner();
-            let full_name = parts.next().ok_or(Error::UnexpectedEOI)?.as_str();
-            let description = parts.next().map(|p| p.as_str());
+            let full_name = parts
+                .next()
+                .ok_or(Error::UnexpectedEOI)?
+                .as_str()
+                .to_string();
+            let description = parts.next().map(|p| p.as_str().to_string());
             Ok(Some(Specifier {
                 full_name,
                 description,
@@ -92,7 +96,7 @@ fn parse_specifier(specifier: Pair<'_, Rule>) -> Result<Option<Specifier>, Error
     }
 }
 
-impl<'a> Resolution<'a> {
+impl Resolution {
     /// Returns a new descriptor if an override is applicable
     // reference: version that this resolution resolves to
     // locator: package that depends on the dependency
@@ -116,6 +120,7 @@ impl<'a> Resolution<'a> {
                 ident: from_ident,
                 reference: from
                     .description
+                    .as_deref()
                     .map_or_else(|| locator.reference.to_string(), |desc| desc.to_string())
                     .into(),
             };
@@ -146,6 +151,7 @@ impl<'a> Resolution<'a> {
             range: self
                 .descriptor
                 .description
+                .as_deref()
                 .map_or_else(|| dependency.range.to_string(), |range| range.to_string())
                 .into(),
         };
@@ -179,13 +185,13 @@ impl<'a> Resolution<'a> {
     }
 }
 
-impl<'a> Specifier<'a> {
-    fn ident(&self) -> Ident<'a> {
-        Ident::try_from(self.full_name).expect("Invalid identifier in resolution")
+impl Specifier {
+    fn ident(&self) -> Ident {
+        Ident::try_from(self.full_name.as_str()).expect("Invalid identifier in resolution")
     }
 }
 
-impl fmt::Display for Resolution<'_> {
+impl fmt::Display for Resolution {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if let Some(from) = &self.from {
             f.write_fmt(format_args!("{from}/"))?;
@@ -195,10 +201,10 @@ impl fmt::Display for Resolution<'_> {
     }
 }
 
-impl fmt::Display for Specifier<'_> {
+impl fmt::Display for Specifier {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(self.full_name)?;
-        if let Some(descriptor) = self.description {
+        f.write_str(&self.full_name)?;
+        if let Some(descriptor) = &self.description {
             f.write_fmt(format_args!("@{descriptor}"))?;
         }
         Ok(())
@@ -248,7 +254,7 @@ mod test {
             Resolution {
                 from: None,
                 descriptor: Specifier {
-                    full_name: "relay-compiler",
+                    full_name: "relay-compiler".into(),
                     description: None
                 }
             }
@@ -262,7 +268,7 @@ mod test {
             Resolution {
                 from: None,
                 descriptor: Specifier {
-                    full_name: "@babel/core",
+                    full_name: "@babel/core".into(),
                     description: None
                 }
             }
@@ -275,11 +281,11 @@ mod test {
             parse_resolution("webpack/memory-fs").unwrap(),
             Resolution {
                 from: Some(Specifier {
-                    full_name: "webpack",
+                    full_name: "webpack".into(),
                     description: None
                 }),
                 descriptor: Specifier {
-                    full_name: "memory-fs",
+                    full_name: "memory-fs".into(),
                     description: None
                 }
             }
@@ -289,11 +295,11 @@ mod test {
             parse_resolution("is-even/is-odd").unwrap(),
             Resolution {
                 from: Some(Specifier {
-                    full_name: "is-even",
+                    full_name: "is-even".into(),
                     description: None
                 }),
                 descriptor: Specifier {
-                    full_name: "is-odd",
+                    full_name: "is-odd".into(),
                     description: None
                 }
             }
@@ -306,11 +312,11 @@ mod test {
             parse_resolution("@babel/core@npm:7.0.0/@babel/generator").unwrap(),
             Resolution {
                 from: Some(Specifier {
-                    full_name: "@babel/core",
-                    description: Some("npm:7.0.0"),
+                    full_name: "@babel/core".into(),
+                    description: Some("npm:7.0.0".into()),
                 }),
                 descriptor: Specifier {
-                    full_name: "@babel/generator",
+                    full_name: "@babel/generator".into(),
                     description: None
                 }
             }
@@ -0,0 +1,112 @@
+use std::any::Any;
+
+use crate::{Error, Lockfile, Package, Yarn1Lockfile};
+
+/// A Bun lockfile.
+///
+/// `bun.lockb` is a binary format, but Bun can print it in the yarn v1
+/// format by running `bun bun.lockb`. This reads that output.
+pub struct BunLockfile {
+    data: Yarn1Lockfile,
+}
+
+impl BunLockfile {
+    pub fn from_bytes(input: &[u8]) -> Result<Self, Error> {
+        let data = Yarn1Lockfile::from_bytes(input)?;
+        Ok(Self { data })
+    }
+}
+
+impl Lockfile for BunLockfile {
+    fn resolve_package(
+        &self,
+        workspace_path: &str,
+        name: &str,
+        version: &str,
+    ) -> Result<Option<Package>, Error> {
+        self.data.resolve_package(workspace_path, name, version)
+    }
+
+    fn all_dependencies(
+        &self,
+        key: &str,
+    ) -> Result<Option<std::collections::HashMap<String, String>>, Error> {
+        self.data.all_dependencies(key)
+    }
+
+    fn all_packages(&self) -> Vec<String> {
+        self.data.all_packages()
+    }
+
+    fn subgraph(
+        &self,
+        _workspace_packages: &[String],
+        packages: &[String],
+    ) -> Result<Box<dyn Lockfile>, Error> {
+        let data = self.data.subgraph(packages)?;
+        Ok(Box::new(Self { data }))
+    }
+
+    // A pruned bun.lockb can't be produced without running Bun, so the lockfile
+    // is encoded in the yarn v1 format that Bun prints it in.
+    fn encode(&self) -> Result<Vec<u8>, Error> {
+        self.data.encode()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn global_change(&self, other: &dyn Lockfile) -> bool {
+        other.as_any().downcast_ref::<Self>().is_none()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    const LOCKFILE: &[u8] = include_bytes!("../fixtures/bun.lockb.txt");
+
+    #[test]
+    fn test_resolve_package() {
+        let lockfile = BunLockfile::from_bytes(LOCKFILE).unwrap();
+        assert_eq!(
+            lockfile
+                .resolve_package("apps/web", "is-odd", "^3.0.1")
+                .unwrap(),
+            Some(Package::new("is-odd@^3.0.1", "3.0.1"))
+        );
+        let closure = crate::transitive_closure(
+            &lockfile,
+            "apps/web",
+            [("is-odd".to_string(), "^3.0.1".to_string())]
+                .into_iter()
+                .collect(),
+        )
+        .unwrap();
+        assert_eq!(
+            closure,
+            [
+                Package::new("is-odd@^3.0.1", "3.0.1"),
+                Package::new("is-number@^6.0.0", "6.0.0"),
+            ]
+            .into_iter()
+            .collect()
+        );
+    }
+
+    #[test]
+    fn test_subgraph() {
+        let lockfile = BunLockfile::from_bytes(LOCKFILE).unwrap();
+        let pruned = lockfile
+            .subgraph(&["apps/web".into()], &["turbo@^1.10.0".into()])
+            .unwrap();
+        let contents = String::from_utf8(pruned.encode().unwrap()).unwrap();
+        assert!(contents.contains("turbo@^1.10.0"));
+        assert!(!contents.contains("is-odd"));
+        assert!(!lockfile.global_change(pruned.as_ref()));
+    }
+}
@@ -0,0 +1,218 @@
+use std::collections::{BTreeMap, HashSet};
+
+use crate::{transitive_closure, Error, Lockfile};
+
+/// Problems found when comparing workspace manifests against a lockfile
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// Dependencies declared by a workspace that the lockfile can't resolve
+    pub unresolved: Vec<Unresolved>,
+    /// Packages that workspaces depend on directly at more than one version
+    pub duplicates: Vec<Duplicate>,
+    /// Lockfile entries that aren't reachable from any workspace
+    pub stale: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Unresolved {
+    pub workspace: String,
+    pub name: String,
+    pub specifier: String,
+    /// Set if the lockfile reported an error instead of a missing entry
+    pub error: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Duplicate {
+    pub name: String,
+    /// Maps each resolved version to the workspaces that depend on it
+    pub versions: BTreeMap<String, Vec<String>>,
+}
+
+impl Report {
+    /// Whether the lockfile is out of sync with the workspaces. Duplicate
+    /// versions are often intentional so they aren't considered drift.
+    pub fn has_drift(&self) -> bool {
+        !self.unresolved.is_empty() || !self.stale.is_empty()
+    }
+}
+
+/// Checks that every dependency of every workspace resolves in the lockfile
+/// and that every package in the lockfile is used by some workspace.
+/// Workspaces are keyed by their path in the lockfile and map to the
+/// dependencies declared in their package.json.
+pub fn check<L: Lockfile + ?Sized>(
+    lockfile: &L,
+    workspaces: &BTreeMap<String, BTreeMap<String, String>>,
+) -> Result<Report, Error> {
+    let mut report = Report::default();
+    let mut reachable = HashSet::new();
+    let mut versions: BTreeMap<&str, BTreeMap<String, Vec<String>>> = BTreeMap::new();
+
+    for (workspace, dependencies) in workspaces {
+        for (name, specifier) in dependencies {
+            let package = match lockfile.resolve_package(workspace, name, specifier) {
+                Ok(Some(package)) => package,
+                result => {
+                    report.unresolved.push(Unresolved {
+                        workspace: workspace.clone(),
+                        name: name.clone(),
+                        specifier: specifier.clone(),
+                        error: result.err().map(|err| err.to_string()),
+                    });
+                    continue;
+                }
+            };
+
+            versions
+                .entry(name)
+                .or_default()
+                .entry(package.version.clone())
+                .or_default()
+                .push(workspace.clone());
+
+            if reachable.contains(&package) {
+                continue;
+            }
+            let dependencies = lockfile.all_dependencies(&package.key)?;
+            reachable.insert(package);
+            if let Some(dependencies) = dependencies {
+                reachable.extend(transitive_closure(lockfile, workspace, dependencies)?);
+            }
+        }
+    }
+
+    report.duplicates = versions
+        .into_iter()
+        .filter(|(_, versions)| versions.len() > 1)
+        .map(|(name, versions)| Duplicate {
+            name: name.to_string(),
+            versions,
+        })
+        .collect();
+
+    let reachable_keys = reachable
+        .iter()
+        .map(|package| package.key.as_str())
+        .collect::<HashSet<_>>();
+    report.stale = lockfile
+        .all_packages()
+        .into_iter()
+        .filter(|key| !reachable_keys.contains(key.as_str()))
+        .collect();
+    report.stale.sort();
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::{PnpmLockfile, Yarn1Lockfile};
+
+    fn workspaces(
+        workspaces: &[(&str, &[(&str, &str)])],
+    ) -> BTreeMap<String, BTreeMap<String, String>> {
+        workspaces
+            .iter()
+            .map(|(path, deps)| {
+                (
+                    path.to_string(),
+                    deps.iter()
+                        .map(|(name, specifier)| (name.to_string(), specifier.to_string()))
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_check_yarn() {
+        let lockfile = Yarn1Lockfile::from_bytes(
+            br#"# THIS IS AN AUTOGENERATED FILE. DO NOT EDIT THIS FILE DIRECTLY.
+# yarn lockfile v1
+
+
+left-pad@^1.0.0:
+  version "1.3.0"
+
+lodash@^3.0.0:
+  version "3.10.1"
+
+lodash@^4.17.21:
+  version "4.17.21"
+"#,
+        )
+        .unwrap();
+        let report = check(
+            &lockfile,
+            &workspaces(&[
+                ("apps/a", &[("lodash", "^3.0.0")]),
+                ("apps/b", &[("lodash", "^4.17.21"), ("react", "^18.0.0")]),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            report,
+            Report {
+                unresolved: vec![Unresolved {
+                    workspace: "apps/b".into(),
+                    name: "react".into(),
+                    specifier: "^18.0.0".into(),
+                    error: None,
+                }],
+                duplicates: vec![Duplicate {
+                    name: "lodash".into(),
+                    versions: [
+                        ("3.10.1".to_string(), vec!["apps/a".to_string()]),
+                        ("4.17.21".to_string(), vec!["apps/b".to_string()]),
+                    ]
+                    .into_iter()
+                    .collect(),
+                }],
+                stale: vec!["left-pad@^1.0.0".into()],
+            }
+        );
+        assert!(report.has_drift());
+    }
+
+    #[test]
+    fn test_check_pnpm() {
+        let lockfile =
+            PnpmLockfile::from_bytes(include_bytes!("../fixtures/pnpm-injected-v6.yaml")).unwrap();
+        let report = check(
+            &lockfile,
+            &workspaces(&[
+                ("", &[]),
+                ("apps/docs", &[("is-odd", "^3.0.1")]),
+                ("apps/web", &[("lodash", "^4.17.21")]),
+                ("packages/ui", &[("react", "^18.2.0")]),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(report, Report::default());
+        assert!(!report.has_drift());
+
+        let report = check(
+            &lockfile,
+            &workspaces(&[
+                ("apps/web", &[("lodash", "^4.17.21"), ("react", "^17.0.0")]),
+                ("packages/ui", &[("react", "^18.2.0")]),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(report.unresolved.len(), 1);
+        assert_eq!(report.unresolved[0].name, "react");
+        assert!(report.unresolved[0].error.is_some());
+        assert_eq!(
+            report.stale,
+            vec![
+                "/is-number@6.0.0",
+                "/is-odd@3.0.1(patch_hash=nrrwwz7lemethtlvvm75r5bmhq)"
+            ]
+        );
+    }
+}
@@ -1,14 +1,21 @@
 #![feature(once_cell)]
 
 mod berry;
+mod bun;
+mod check;
 mod error;
 mod npm;
 mod pnpm;
 mod yarn1;
 
-use std::collections::{HashMap, HashSet};
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+};
 
 pub use berry::{Error as BerryError, *};
+pub use bun::BunLockfile;
+pub use check::{check, Duplicate, Report, Unresolved};
 pub use error::Error;
 pub use npm::*;
 pub use pnpm::{pnpm_global_change, pnpm_subgraph, PnpmLockfile};
@@ -24,7 +31,7 @@ pub struct Package {
 // This trait will only be used when migrating the Go lockfile implementations
 // to Rust. Once the migration is complete we will leverage petgraph for doing
 // our graph calculations.
-pub trait Lockfile {
+pub trait Lockfile: Send + Sync + Any {
     // Given a workspace, a package it imports and version returns the key, resolved
     // version, and if it was found
     fn resolve_package(
@@ -36,9 +43,30 @@ pub trait Lockfile {
     // Given a lockfile key return all (prod/dev/optional) dependencies of that
     // package
     fn all_dependencies(&self, key: &str) -> Result<Option<HashMap<String, String>>, Error>;
+    // Keys of every external package in the lockfile, in the same format that
+    // `resolve_package` returns them
+    fn all_packages(&self) -> Vec<String>;
+    // Given a list of workspace paths and lockfile keys, produce a lockfile that
+    // only contains those workspaces and packages
+    fn subgraph(
+        &self,
+        workspace_packages: &[String],
+        packages: &[String],
+    ) -> Result<Box<dyn Lockfile>, Error>;
+    // Serialize the lockfile into the format the package manager expects on disk
+    fn encode(&self) -> Result<Vec<u8>, Error>;
+    // All patch files referenced by the lockfile, sorted
+    fn patches(&self) -> Vec<String> {
+        Vec::new()
+    }
+    // Whether the differences between this lockfile and another should be
+    // treated as affecting every package in the workspace
+    fn global_change(&self, other: &dyn Lockfile) -> bool;
+    // Allows downcasting to the concrete lockfile type
+    fn as_any(&self) -> &dyn Any;
 }
 
-pub fn all_transitive_closures<L: Lockfile + Sync>(
+pub fn all_transitive_closures<L: Lockfile + ?Sized>(
     lockfile: &L,
     workspaces: HashMap<String, HashMap<String, String>>,
 ) -> Result<HashMap<String, HashSet<Package>>, Error> {
@@ -52,7 +80,7 @@ pub fn all_transitive_closures<L: Lockfile + Sync>(
 }
 
 // this should get replaced by petgraph in the future :)
-pub fn transitive_closure<L: Lockfile>(
+pub fn transitive_closure<L: Lockfile + ?Sized>(
     lockfile: &L,
     workspace_path: &str,
     unresolved_deps: HashMap<String, String>,
@@ -68,7 +96,7 @@ pub fn transitive_closure<L: Lockfile>(
     Ok(transitive_deps)
 }
 
-fn transitive_closure_helper<L: Lockfile>(
+fn transitive_closure_helper<L: Lockfile + ?Sized>(
     lockfile: &L,
     workspace_path: &str,
     unresolved_deps: HashMap<String, impl AsRef<str>>,
@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::{any::Any, collections::HashMap};
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -99,6 +99,42 @@ impl Lockfile for NpmLockfile {
             })
             .transpose()
     }
+
+    fn all_packages(&self) -> Vec<String> {
+        // Workspaces are stored under their path and are symlinked into
+        // node_modules with an entry that has no version
+        self.packages
+            .iter()
+            .filter(|(key, pkg)| key.contains("node_modules/") && pkg.version.is_some())
+            .map(|(key, _)| key.clone())
+            .collect()
+    }
+
+    fn subgraph(
+        &self,
+        workspace_packages: &[String],
+        packages: &[String],
+    ) -> Result<Box<dyn Lockfile>, Error> {
+        let subgraph = self.subgraph(workspace_packages, packages)?;
+        Ok(Box::new(subgraph))
+    }
+
+    fn encode(&self) -> Result<Vec<u8>, Error> {
+        Ok(serde_json::to_vec_pretty(&self)?)
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn global_change(&self, other: &dyn Lockfile) -> bool {
+        let Some(other) = other.as_any().downcast_ref::<Self>() else {
+            return true;
+        };
+
+        self.lockfile_version != other.lockfile_version
+            || self.other.get("requires") != other.other.get("requires")
+    }
 }
 
 impl NpmLockfile {
@@ -200,19 +236,14 @@ pub fn npm_subgraph(
 ) -> Result<Vec<u8>, Error> {
     let lockfile = NpmLockfile::load(contents)?;
     let pruned_lockfile = lockfile.subgraph(workspace_packages, packages)?;
-    let new_contents = serde_json::to_vec_pretty(&pruned_lockfile)?;
-
-    Ok(new_contents)
+    pruned_lockfile.encode()
 }
 
 pub fn npm_global_change(prev_contents: &[u8], curr_contents: &[u8]) -> Result<bool, Error> {
     let prev_lockfile = NpmLockfile::load(prev_contents)?;
     let curr_lockfile = NpmLockfile::load(curr_contents)?;
 
-    Ok(
-        prev_lockfile.lockfile_version != curr_lockfile.lockfile_version
-            || prev_lockfile.other.get("requires") != curr_lockfile.other.get("requires"),
-    )
+    Ok(prev_lockfile.global_change(&curr_lockfile))
 }
 #[cfg(test)]
 mod test {
@@ -433,4 +464,15 @@ mod test {
         assert!(closures.get("packages/c").unwrap().is_empty());
         Ok(())
     }
+
+    #[test]
+    fn test_global_change() -> Result<(), Error> {
+        let lockfile = NpmLockfile::load(include_bytes!("../fixtures/npm-lock.json"))?;
+        let same = NpmLockfile::load(include_bytes!("../fixtures/npm-lock.json"))?;
+        let yarn = crate::Yarn1Lockfile::from_bytes(include_bytes!("../fixtures/yarn1.lock"))?;
+        assert!(!lockfile.global_change(&same));
+        assert!(lockfile.global_change(&yarn));
+        assert!(yarn.global_change(&lockfile));
+        Ok(())
+    }
 }
@@ -1,4 +1,4 @@
-use std::borrow::Cow;
+use std::{any::Any, borrow::Cow, collections::HashSet};
 
 use serde::{Deserialize, Serialize};
 
@@ -17,6 +17,8 @@ pub struct PnpmLockfile {
     #[serde(skip_serializing_if = "Option::is_none")]
     only_built_dependencies: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    catalogs: Option<Map<String, Map<String, Dependency>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     overrides: Option<Map<String, String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     package_extensions_checksum: Option<String>,
@@ -122,6 +124,8 @@ pub struct PackageResolution {
     #[serde(skip_serializing_if = "Option::is_none")]
     dir: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    directory: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     repo: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     commit: Option<String>,
@@ -151,6 +155,13 @@ impl PnpmLockfile {
         patches
     }
 
+    // Returns the keys of the overrides section, if the lockfile has one
+    pub fn overrides(&self) -> Option<Vec<String>> {
+        self.overrides
+            .as_ref()
+            .map(|overrides| overrides.keys().cloned().collect())
+    }
+
     fn get_packages(&self, key: &str) -> Option<&PackageSnapshot> {
         self.packages
             .as_ref()
@@ -258,7 +269,7 @@ impl PnpmLockfile {
                 .ok_or_else(|| crate::Error::MissingPackage(package.clone()))?;
             pruned_packages.insert(package.clone(), entry.clone());
         }
-        for importer in importers.values() {
+        for (importer_path, importer) in &importers {
             // Find all injected packages in each workspace and include it in
             // the pruned lockfile
             for dependency in
@@ -280,6 +291,18 @@ impl PnpmLockfile {
                     .get_packages(version)
                     .ok_or_else(|| crate::Error::MissingPackage(version.into()))?;
                 pruned_packages.insert(version.to_string(), entry.clone());
+
+                // Injected workspaces are copied instead of symlinked so their
+                // external dependencies are only reachable through the
+                // `file:` entry and not through the workspace's own closure
+                let dependencies =
+                    crate::Lockfile::all_dependencies(self, version)?.unwrap_or_default();
+                for package in crate::transitive_closure(self, importer_path, dependencies)? {
+                    let entry = self
+                        .get_packages(&package.key)
+                        .ok_or_else(|| crate::Error::MissingPackage(package.key.clone()))?;
+                    pruned_packages.insert(package.key, entry.clone());
+                }
             }
         }
 
@@ -288,6 +311,16 @@ impl PnpmLockfile {
             .as_ref()
             .map(|patches| Self::prune_patches(patches, &pruned_packages))
             .transpose()?;
+        let overrides = self
+            .overrides
+            .as_ref()
+            .map(|overrides| Self::prune_overrides(overrides, &importers, &pruned_packages))
+            .filter(|overrides| !overrides.is_empty());
+        let catalogs = self
+            .catalogs
+            .as_ref()
+            .map(|catalogs| Self::prune_catalogs(catalogs, &importers))
+            .filter(|catalogs| !catalogs.is_empty());
 
         Ok(Self {
             importers,
@@ -298,7 +331,8 @@ impl PnpmLockfile {
             lockfile_version: self.lockfile_version.clone(),
             never_built_dependencies: self.never_built_dependencies.clone(),
             only_built_dependencies: self.only_built_dependencies.clone(),
-            overrides: self.overrides.clone(),
+            catalogs,
+            overrides,
             package_extensions_checksum: self.package_extensions_checksum.clone(),
             patched_dependencies: patches,
             time: None,
@@ -311,7 +345,11 @@ impl PnpmLockfile {
         pruned_packages: &Map<String, PackageSnapshot>,
     ) -> Result<Map<String, PatchFile>, Error> {
         let mut pruned_patches = Map::new();
-        for dependency in pruned_packages.keys() {
+        // Packages installed from a local directory can't be patched
+        for dependency in pruned_packages
+            .keys()
+            .filter(|key| !key.starts_with("file:"))
+        {
             let dp = DepPath::try_from(dependency.as_str())?;
             let patch_key = format!("{}@{}", dp.name, dp.version);
             if let Some(patch) = patches
@@ -323,6 +361,75 @@ impl PnpmLockfile {
         }
         Ok(pruned_patches)
     }
+
+    // Only keeps overrides that target a package that is still present in the
+    // pruned lockfile
+    fn prune_overrides(
+        overrides: &Map<String, String>,
+        importers: &Map<String, ProjectSnapshot>,
+        pruned_packages: &Map<String, PackageSnapshot>,
+    ) -> Map<String, String> {
+        let mut names = importers
+            .values()
+            .flat_map(|importer| importer.dependencies.specifiers())
+            .map(|(name, _)| name)
+            .collect::<HashSet<_>>();
+        for (key, entry) in pruned_packages {
+            match DepPath::try_from(key.as_str()) {
+                Ok(dp) => {
+                    names.insert(dp.name);
+                }
+                Err(_) => names.extend(entry.name.as_deref()),
+            }
+        }
+
+        overrides
+            .iter()
+            .filter(|(selector, _)| names.contains(override_target(selector)))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect()
+    }
+
+    // Only keeps catalog entries that are still referenced by one of the
+    // pruned importers
+    fn prune_catalogs(
+        catalogs: &Map<String, Map<String, Dependency>>,
+        importers: &Map<String, ProjectSnapshot>,
+    ) -> Map<String, Map<String, Dependency>> {
+        let mut pruned_catalogs: Map<String, Map<String, Dependency>> = Map::new();
+        for (name, specifier) in importers
+            .values()
+            .flat_map(|importer| importer.dependencies.specifiers())
+        {
+            let Some(catalog) = specifier.strip_prefix("catalog:") else {
+                continue;
+            };
+            let catalog = match catalog {
+                "" => "default",
+                catalog => catalog,
+            };
+            if let Some(entry) = catalogs.get(catalog).and_then(|entries| entries.get(name)) {
+                pruned_catalogs
+                    .entry(catalog.to_string())
+                    .or_default()
+                    .insert(name.to_string(), entry.clone());
+            }
+        }
+        pruned_catalogs
+    }
+}
+
+// Extracts the name of the package an override selector applies to e.g.
+// `foo@1>@scope/bar@^2` targets `@scope/bar`
+fn override_target(selector: &str) -> &str {
+    let target = selector
+        .rsplit_once('>')
+        .map_or(selector, |(_, target)| target);
+    let version_start = match target.strip_prefix('@') {
+        Some(scoped) => scoped.find('@').map(|idx| idx + 1),
+        None => target.find('@'),
+    };
+    version_start.map_or(target, |idx| &target[..idx])
 }
 
 impl crate::Lockfile for PnpmLockfile {
@@ -389,6 +496,51 @@ impl crate::Lockfile for PnpmLockfile {
                 .collect(),
         ))
     }
+
+    fn all_packages(&self) -> Vec<String> {
+        // Local directories are copies of workspaces rather than external
+        // packages
+        self.packages
+            .iter()
+            .flatten()
+            .map(|(key, _)| key)
+            .filter(|key| !key.starts_with("file:"))
+            .cloned()
+            .collect()
+    }
+
+    fn subgraph(
+        &self,
+        workspace_packages: &[String],
+        packages: &[String],
+    ) -> Result<Box<dyn crate::Lockfile>, crate::Error> {
+        let subgraph = self.subgraph(workspace_packages, packages)?;
+        Ok(Box::new(subgraph))
+    }
+
+    fn encode(&self) -> Result<Vec<u8>, crate::Error> {
+        Ok(serde_yaml::to_string(&self)?.into_bytes())
+    }
+
+    fn patches(&self) -> Vec<String> {
+        self.patches()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn global_change(&self, other: &dyn crate::Lockfile) -> bool {
+        let Some(other) = other.as_any().downcast_ref::<Self>() else {
+            return true;
+        };
+
+        self.lockfile_version != other.lockfile_version
+            || self.package_extensions_checksum != other.package_extensions_checksum
+            || self.overrides != other.overrides
+            || self.patched_dependencies != other.patched_dependencies
+            || self.settings != other.settings
+    }
 }
 
 impl DependencyInfo {
@@ -419,6 +571,30 @@ impl DependencyInfo {
         }
     }
 
+    // All dependencies of the importer along with their specifiers
+    fn specifiers(&self) -> Box<dyn Iterator<Item = (&str, &str)> + '_> {
+        match self {
+            DependencyInfo::PreV6 { specifiers, .. } => Box::new(
+                specifiers
+                    .iter()
+                    .flatten()
+                    .map(|(name, specifier)| (name.as_str(), specifier.as_str())),
+            ),
+            DependencyInfo::V6 {
+                dependencies,
+                optional_dependencies,
+                dev_dependencies,
+            } => Box::new(
+                dependencies
+                    .iter()
+                    .chain(optional_dependencies)
+                    .chain(dev_dependencies)
+                    .flatten()
+                    .map(|(name, dependency)| (name.as_str(), dependency.specifier.as_str())),
+            ),
+        }
+    }
+
     fn get_resolution<'a, V>(maybe_map: &'a Option<Map<String, V>>, key: &str) -> Option<&'a V> {
         maybe_map.as_ref().and_then(|maybe_map| maybe_map.get(key))
     }
@@ -437,11 +613,7 @@ pub fn pnpm_global_change(
 ) -> Result<bool, crate::Error> {
     let prev_data = PnpmLockfile::from_bytes(prev_contents)?;
     let curr_data = PnpmLockfile::from_bytes(curr_contents)?;
-    Ok(prev_data.lockfile_version != curr_data.lockfile_version
-        || prev_data.package_extensions_checksum != curr_data.package_extensions_checksum
-        || prev_data.overrides != curr_data.overrides
-        || prev_data.patched_dependencies != curr_data.patched_dependencies
-        || prev_data.settings != curr_data.settings)
+    Ok(crate::Lockfile::global_change(&prev_data, &curr_data))
 }
 
 #[cfg(test)]
@@ -462,13 +634,23 @@ mod tests {
     const PNPM_OVERRIDE: &[u8] = include_bytes!("../../fixtures/pnpm-override.yaml").as_slice();
     const PNPM_PATCH: &[u8] = include_bytes!("../../fixtures/pnpm-patch.yaml").as_slice();
     const PNPM_PATCH_V6: &[u8] = include_bytes!("../../fixtures/pnpm-patch-v6.yaml").as_slice();
+    const PNPM_INJECTED: &[u8] = include_bytes!("../../fixtures/pnpm-injected.yaml").as_slice();
+    const PNPM_INJECTED_V6: &[u8] =
+        include_bytes!("../../fixtures/pnpm-injected-v6.yaml").as_slice();
 
     use super::*;
     use crate::{Lockfile, Package};
 
     #[test]
     fn test_roundtrip() {
-        for fixture in &[PNPM6, PNPM7, PNPM8, PNPM8_6] {
+        for fixture in &[
+            PNPM6,
+            PNPM7,
+            PNPM8,
+            PNPM8_6,
+            PNPM_INJECTED,
+            PNPM_INJECTED_V6,
+        ] {
             let lockfile = PnpmLockfile::from_bytes(fixture).unwrap();
             let serialized_lockfile = serde_yaml::to_string(&lockfile).unwrap();
             let lockfile_from_serialized =
@@ -751,6 +933,125 @@ mod tests {
         )
     }
 
+    #[test_case(
+        PNPM_INJECTED,
+        &["/lodash/4.17.21"],
+        "file:packages/ui_react@18.2.0",
+        &["/js-tokens/4.0.0", "/loose-envify/1.4.0", "/react/18.2.0"] ;
+        "v5"
+    )]
+    #[test_case(
+        PNPM_INJECTED_V6,
+        &["/lodash@4.17.21"],
+        "file:packages/ui(react@18.2.0)",
+        &["/js-tokens@4.0.0", "/loose-envify@1.4.0", "/react@18.2.0"] ;
+        "v6"
+    )]
+    fn test_prune_injected(
+        lockfile: &[u8],
+        packages: &[&str],
+        injected: &str,
+        injected_dependencies: &[&str],
+    ) {
+        let lockfile = PnpmLockfile::from_bytes(lockfile).unwrap();
+        let pruned = lockfile
+            .subgraph(
+                &["apps/web".into(), "packages/ui".into()],
+                &packages.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+            )
+            .unwrap();
+
+        let mut expected = packages
+            .iter()
+            .chain(injected_dependencies)
+            .chain(std::iter::once(&injected))
+            .copied()
+            .collect::<Vec<_>>();
+        expected.sort();
+        assert_eq!(
+            pruned
+                .packages
+                .as_ref()
+                .unwrap()
+                .keys()
+                .map(|k| k.as_str())
+                .collect::<Vec<_>>(),
+            expected
+        );
+        let injected_entry = pruned.get_packages(injected).unwrap();
+        assert_eq!(
+            injected_entry.resolution.directory.as_deref(),
+            Some("packages/ui")
+        );
+        // The injected package's directory resolution must survive encoding
+        let encoded = String::from_utf8(crate::Lockfile::encode(&pruned).unwrap()).unwrap();
+        assert!(encoded.contains("directory: packages/ui"));
+
+        assert_eq!(pruned.overrides(), Some(vec!["js-tokens".to_string()]));
+        assert!(pruned.patches().is_empty());
+    }
+
+    #[test_case(PNPM_INJECTED, "/is-odd/3.0.1_nrrwwz7lemethtlvvm75r5bmhq", "/is-number/6.0.0" ; "v5")]
+    #[test_case(
+        PNPM_INJECTED_V6,
+        "/is-odd@3.0.1(patch_hash=nrrwwz7lemethtlvvm75r5bmhq)",
+        "/is-number@6.0.0" ;
+        "v6"
+    )]
+    fn test_prune_overrides_and_patches(lockfile: &[u8], is_odd: &str, is_number: &str) {
+        let lockfile = PnpmLockfile::from_bytes(lockfile).unwrap();
+        let pruned = lockfile
+            .subgraph(&["apps/docs".into()], &[is_odd.into(), is_number.into()])
+            .unwrap();
+        assert_eq!(
+            pruned.overrides(),
+            Some(vec!["is-odd>is-number".to_string()])
+        );
+        assert_eq!(pruned.patches(), vec!["patches/is-odd@3.0.1.patch"]);
+    }
+
+    #[test]
+    fn test_prune_catalogs() {
+        let lockfile = PnpmLockfile::from_bytes(
+            br#"lockfileVersion: "6.0"
+catalogs:
+  default:
+    lodash:
+      specifier: ^4.17.21
+      version: 4.17.21
+  legacy:
+    react:
+      specifier: ^17.0.0
+      version: 17.0.2
+importers:
+  .: {}
+  apps/a:
+    dependencies:
+      lodash:
+        specifier: "catalog:"
+        version: 4.17.21
+  apps/b:
+    dependencies:
+      react:
+        specifier: catalog:legacy
+        version: 17.0.2
+"#,
+        )
+        .unwrap();
+        let pruned = lockfile.subgraph(&["apps/a".into()], &[]).unwrap();
+        let catalogs = pruned.catalogs.unwrap();
+        assert_eq!(catalogs.keys().collect::<Vec<_>>(), vec!["default"]);
+        assert!(catalogs["default"].contains_key("lodash"));
+    }
+
+    #[test_case("foo", "foo" ; "bare")]
+    #[test_case("foo@1", "foo" ; "versioned")]
+    #[test_case("@scope/foo@^1", "@scope/foo" ; "scoped")]
+    #[test_case("bar@2>@scope/foo", "@scope/foo" ; "parent selector")]
+    fn test_override_target(selector: &str, expected: &str) {
+        assert_eq!(override_target(selector), expected);
+    }
+
     #[test]
     fn test_pnpm_alias_overlap() {
         let lockfile = PnpmLockfile::from_bytes(PNPM_ABSOLUTE).unwrap();
@@ -38,6 +38,5 @@ pub fn pnpm_subgraph(
 ) -> Result<Vec<u8>, crate::Error> {
     let lockfile = PnpmLockfile::from_bytes(contents)?;
     let pruned_lockfile = lockfile.subgraph(workspace_packages, packages)?;
-    let new_contents = serde_yaml::to_string(&pruned_lockfile)?.into_bytes();
-    Ok(new_contents)
+    crate::Lockfile::encode(&pruned_lockfile)
 }
@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{any::Any, str::FromStr};
 
 use serde::Deserialize;
 
@@ -99,12 +99,39 @@ impl Lockfile for Yarn1Lockfile {
             true => None,
         })
     }
+
+    fn all_packages(&self) -> Vec<String> {
+        self.inner.keys().cloned().collect()
+    }
+
+    fn subgraph(
+        &self,
+        _workspace_packages: &[String],
+        packages: &[String],
+    ) -> Result<Box<dyn Lockfile>, crate::Error> {
+        let subgraph = self.subgraph(packages)?;
+        Ok(Box::new(subgraph))
+    }
+
+    fn encode(&self) -> Result<Vec<u8>, crate::Error> {
+        Ok(self.to_string().into_bytes())
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn global_change(&self, other: &dyn Lockfile) -> bool {
+        // yarn1 has no lockfile level settings so only a package manager change is
+        // global
+        other.as_any().downcast_ref::<Self>().is_none()
+    }
 }
 
 pub fn yarn_subgraph(contents: &[u8], packages: &[String]) -> Result<Vec<u8>, crate::Error> {
     let lockfile = Yarn1Lockfile::from_bytes(contents)?;
     let pruned_lockfile = lockfile.subgraph(packages)?;
-    Ok(pruned_lockfile.to_string().into_bytes())
+    pruned_lockfile.encode()
 }
 
 impl Entry {
@@ -4,18 +4,37 @@ use std::{
 
 use turbopath::{
     AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPathBuf, RelativeUnixPath,
+    RelativeUnixPathBuf,
 };
 use which::which;
 
-use crate::Error;
+use crate::{in_process, with_fallback, Error};
+
+/// How many commits `ShallowCloneMode::Deepen` fetches on each attempt
+const DEEPEN_STEPS: &[u32] = &[50, 200, 1000];
+
+/// What to do when the merge base of two commits is missing because the
+/// repository is a shallow clone, as is common in CI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShallowCloneMode {
+    /// Return `Error::ShallowClone`
+    #[default]
+    Fail,
+    /// Fetch more history from the default remote until the merge base is
+    /// found
+    Deepen,
+    /// Report that the comparison can't be made so that the caller can treat
+    /// everything as changed
+    AllChanged,
+}
 
 /// Finds the changed files in a repository between index and working directory
 /// (unstaged changes) and between two commits. Includes untracked files,
 /// i.e. files not yet in git.
 ///
-/// We shell out to git instead of using a git2 library because git2 doesn't
-/// support shallow clones, and therefore errors on repositories that
-/// are shallow cloned.
+/// The repository is read in-process unless the subprocess backend is
+/// selected. libgit2 doesn't support shallow clones, so if it fails we shell
+/// out to git instead.
 ///
 /// # Arguments
 ///
@@ -32,24 +51,63 @@ pub fn changed_files(
     from_commit: Option<&str>,
     to_commit: &str,
 ) -> Result<HashSet<String>, Error> {
+    // `Fail` errors rather than skipping the comparison, so there are always
+    // files
+    changed_files_with_mode(
+        git_root,
+        turbo_root,
+        from_commit,
+        to_commit,
+        ShallowCloneMode::Fail,
+    )
+    .map(Option::unwrap_or_default)
+}
+
+/// Like `changed_files`, but `shallow_clone_mode` determines what happens if
+/// the merge base of `from_commit` and `to_commit` is missing from a shallow
+/// clone. Returns `None` if the commits can't be compared and the caller
+/// should consider everything changed.
+pub fn changed_files_with_mode(
+    git_root: PathBuf,
+    turbo_root: PathBuf,
+    from_commit: Option<&str>,
+    to_commit: &str,
+    shallow_clone_mode: ShallowCloneMode,
+) -> Result<Option<HashSet<String>>, Error> {
     let git_root = AbsoluteSystemPathBuf::new(git_root)?;
     let turbo_root = AbsoluteSystemPathBuf::new(turbo_root)?;
-    let turbo_root_relative_to_git_root = git_root.anchor(&turbo_root)?;
+    if let Some(from_commit) = from_commit {
+        // Surface a missing merge base directly instead of as an opaque diff
+        // failure
+        if !ensure_merge_base(&git_root, from_commit, to_commit, shallow_clone_mode)? {
+            return Ok(None);
+        }
+    }
+    with_fallback(
+        || in_process::changed_files(&git_root, &turbo_root, from_commit, to_commit),
+        || changed_files_with_git(&git_root, &turbo_root, from_commit, to_commit),
+    )
+    .map(Some)
+}
+
+pub(crate) fn changed_files_with_git(
+    git_root: &AbsoluteSystemPath,
+    turbo_root: &AbsoluteSystemPath,
+    from_commit: Option<&str>,
+    to_commit: &str,
+) -> Result<HashSet<String>, Error> {
+    let turbo_root_relative_to_git_root = git_root.anchor(turbo_root)?;
     let pathspec = turbo_root_relative_to_git_root.to_str()?;
 
     let mut files = HashSet::new();
 
-    let output = execute_git_command(
-        git_root.borrow(),
-        &["diff", "--name-only", to_commit],
-        pathspec,
-    )?;
+    let output = execute_git_command(git_root, &["diff", "--name-only", to_commit], pathspec)?;
 
-    add_files_from_stdout(&mut files, &git_root, &turbo_root, output);
+    add_files_from_stdout(&mut files, git_root, turbo_root, output);
 
     if let Some(from_commit) = from_commit {
         let output = execute_git_command(
-            git_root.borrow(),
+            git_root,
             &[
                 "diff",
                 "--name-only",
@@ -58,20 +116,124 @@ pub fn changed_files(
             pathspec,
         )?;
 
-        add_files_from_stdout(&mut files, &git_root, &turbo_root, output);
+        add_files_from_stdout(&mut files, git_root, turbo_root, output);
     }
 
     let output = execute_git_command(
-        git_root.borrow(),
+        git_root,
         &["ls-files", "--others", "--exclude-standard"],
         pathspec,
     )?;
 
-    add_files_from_stdout(&mut files, &git_root, &turbo_root, output);
+    add_files_from_stdout(&mut files, git_root, turbo_root, output);
 
     Ok(files)
 }
 
+/// Returns whether the repository at `git_root` is a shallow clone.
+pub fn is_shallow(git_root: &AbsoluteSystemPath) -> Result<bool, Error> {
+    with_fallback(
+        || in_process::is_shallow(git_root),
+        || {
+            let output =
+                execute_git_command(git_root, &["rev-parse", "--is-shallow-repository"], "")?;
+            Ok(String::from_utf8(output)?.trim() == "true")
+        },
+    )
+}
+
+/// Returns the best common ancestor of two commits, or `None` if they don't
+/// share any history that is present in the repository.
+pub fn merge_base(
+    git_root: &AbsoluteSystemPath,
+    first: &str,
+    second: &str,
+) -> Result<Option<String>, Error> {
+    with_fallback(
+        || in_process::merge_base(git_root, first, second),
+        || {
+            let output = Command::new(which("git")?)
+                .args(["merge-base", first, second])
+                .current_dir(git_root)
+                .output()?;
+            match output.status.code() {
+                Some(0) => Ok(Some(String::from_utf8(output.stdout)?.trim().to_string())),
+                // git exits with 1 and no output if there isn't a merge base
+                Some(1) if output.stdout.is_empty() && output.stderr.is_empty() => Ok(None),
+                _ => Err(Error::Git(
+                    String::from_utf8_lossy(&output.stderr).to_string(),
+                    Backtrace::capture(),
+                )),
+            }
+        },
+    )
+}
+
+/// Checks that the merge base of `from_commit` and `to_commit` is available,
+/// which is required to compare them. If it's missing because the repository
+/// is a shallow clone, `mode` determines whether to fetch more history, to
+/// error or to return `false` so that the caller can consider everything
+/// changed.
+pub fn ensure_merge_base(
+    git_root: &AbsoluteSystemPath,
+    from_commit: &str,
+    to_commit: &str,
+    mode: ShallowCloneMode,
+) -> Result<bool, Error> {
+    if merge_base(git_root, from_commit, to_commit)?.is_some() {
+        return Ok(true);
+    }
+    let no_merge_base = || Error::NoMergeBase {
+        from_commit: from_commit.to_string(),
+        to_commit: to_commit.to_string(),
+    };
+    if !is_shallow(git_root)? {
+        return Err(no_merge_base());
+    }
+
+    match mode {
+        ShallowCloneMode::AllChanged => return Ok(false),
+        ShallowCloneMode::Fail => {}
+        ShallowCloneMode::Deepen => {
+            for depth in DEEPEN_STEPS {
+                execute_git_command(git_root, &["fetch", &format!("--deepen={depth}")], "")?;
+                if merge_base(git_root, from_commit, to_commit)?.is_some() {
+                    return Ok(true);
+                }
+                if !is_shallow(git_root)? {
+                    return Err(no_merge_base());
+                }
+            }
+        }
+    }
+
+    Err(Error::ShallowClone {
+        from_commit: from_commit.to_string(),
+        to_commit: to_commit.to_string(),
+    })
+}
+
+/// Returns the name of the branch that is currently checked out in the
+/// repository containing `path`.
+pub fn current_branch(path: &AbsoluteSystemPath) -> Result<String, Error> {
+    let output = execute_git_command(path, &["branch", "--show-current"], "")?;
+    Ok(String::from_utf8(output)?.trim().to_string())
+}
+
+/// Returns the SHA of the commit that is currently checked out in the
+/// repository containing `path`.
+pub fn current_sha(path: &AbsoluteSystemPath) -> Result<String, Error> {
+    let output = execute_git_command(path, &["rev-parse", "HEAD"], "")?;
+    Ok(String::from_utf8(output)?.trim().to_string())
+}
+
+/// Returns the `user.name` from the git config of the repository containing
+/// `path`.
+pub fn user_name(path: &AbsoluteSystemPath) -> Result<String, Error> {
+    let output = execute_git_command(path, &["config", "user.name"], "")?;
+    Ok(String::from_utf8(output)?.trim().to_string())
+}
+
 fn execute_git_command(
     git_root: &AbsoluteSystemPath,
     args: &[&str],
@@ -121,7 +283,7 @@ fn add_files_from_stdout(
     }
 }
 
-fn reanchor_path_from_git_root_to_turbo_root(
+pub(crate) fn reanchor_path_from_git_root_to_turbo_root(
     git_root: &AbsoluteSystemPath,
     turbo_root: &AbsoluteSystemPath,
     path: &RelativeUnixPath,
@@ -157,17 +319,25 @@ pub fn previous_content(
     } else {
         file_path.as_path().try_into()?
     };
+    let anchored_file_path = anchored_file_path.to_unix()?;
 
+    with_fallback(
+        || in_process::previous_content(&git_root, from_commit, &anchored_file_path),
+        || previous_content_with_git(&git_root, from_commit, &anchored_file_path),
+    )
+}
+
+fn previous_content_with_git(
+    git_root: &AbsoluteSystemPath,
+    from_commit: &str,
+    file_path: &RelativeUnixPathBuf,
+) -> Result<Vec<u8>, Error> {
     let git_binary = which("git")?;
     let mut command = Command::new(git_binary);
     let command = command
         .arg("show")
-        .arg(format!(
-            "{}:{}",
-            from_commit,
-            anchored_file_path.to_str().unwrap()
-        ))
-        .current_dir(&git_root);
+        .arg(format!("{}:{}", from_commit, file_path.as_str()?))
+        .current_dir(git_root);
 
     let output = command.output()?;
     if output.status.success() {
@@ -192,11 +362,17 @@ mod tests {
 
     use git2::{Oid, Repository};
     use tempfile::TempDir;
-    use turbopath::PathError;
+    use turbopath::{AbsoluteSystemPathBuf, PathError};
     use which::which;
 
-    use super::previous_content;
-    use crate::{git::changed_files, Error};
+    use super::{previous_content, ShallowCloneMode};
+    use crate::{
+        git::{
+            changed_files, changed_files_with_mode, current_branch, current_sha, ensure_merge_base,
+            is_shallow,
+        },
+        Error,
+    };
 
     fn setup_repository() -> Result<(TempDir, Repository), Error> {
         let repo_root = tempfile::tempdir()?;
@@ -289,6 +465,140 @@ mod tests {
         Ok(())
     }
 
+    fn git(cwd: &Path, args: &[&str]) -> Result<(), Error> {
+        let output = Command::new(which("git")?)
+            .args(args)
+            .current_dir(cwd)
+            .output()?;
+        assert!(
+            output.status.success(),
+            "git {args:?} failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        Ok(())
+    }
+
+    // Creates a repository where `feature` branched off of `main` a few commits
+    // ago, and a clone of it that only contains the tip of each branch.
+    fn setup_shallow_clone() -> Result<(TempDir, TempDir), Error> {
+        let (origin, _repo) = setup_repository()?;
+        let commit = |file: &str, contents: &str| -> Result<(), Error> {
+            fs::write(origin.path().join(file), contents)?;
+            git(origin.path(), &["add", file])?;
+            git(origin.path(), &["commit", "-m", contents])
+        };
+        git(origin.path(), &["checkout", "-b", "main"])?;
+        commit("base.js", "base")?;
+        git(origin.path(), &["branch", "feature"])?;
+        for i in 0..3 {
+            commit("main.js", &format!("main {i}"))?;
+        }
+        git(origin.path(), &["checkout", "feature"])?;
+        for i in 0..3 {
+            commit("feature.js", &format!("feature {i}"))?;
+        }
+
+        let clone = tempfile::tempdir()?;
+        git(
+            clone.path(),
+            &[
+                "clone",
+                "--depth=1",
+                "--no-single-branch",
+                "--branch=feature",
+                &format!("file://{}", origin.path().display()),
+                ".",
+            ],
+        )?;
+        Ok((origin, clone))
+    }
+
+    #[test]
+    fn test_shallow_clone_modes() -> Result<(), Error> {
+        let (_origin, clone) = setup_shallow_clone()?;
+        let root = AbsoluteSystemPathBuf::new(clone.path())?;
+        assert!(is_shallow(&root)?);
+
+        assert_matches!(
+            changed_files(
+                clone.path().to_path_buf(),
+                clone.path().to_path_buf(),
+                Some("origin/main"),
+                "HEAD",
+            ),
+            Err(Error::ShallowClone { .. })
+        );
+        assert_eq!(
+            changed_files_with_mode(
+                clone.path().to_path_buf(),
+                clone.path().to_path_buf(),
+                Some("origin/main"),
+                "HEAD",
+                ShallowCloneMode::AllChanged,
+            )?,
+            None
+        );
+
+        let files = changed_files_with_mode(
+            clone.path().to_path_buf(),
+            clone.path().to_path_buf(),
+            Some("origin/main"),
+            "HEAD",
+            ShallowCloneMode::Deepen,
+        )?;
+        assert_eq!(files, Some(HashSet::from(["feature.js".to_string()])));
+        assert!(ensure_merge_base(
+            &root,
+            "origin/main",
+            "HEAD",
+            ShallowCloneMode::Fail
+        )?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_no_merge_base() -> Result<(), Error> {
+        let (repo_root, repo) = setup_repository()?;
+        fs::write(repo_root.path().join("foo.js"), "let z = 0;")?;
+        let first = commit_file(&repo, Path::new("foo.js"), None)?;
+        // A second root commit that shares no history with the first
+        repo.set_head("refs/heads/orphan")?;
+        fs::write(repo_root.path().join("bar.js"), "let y = 1;")?;
+        let second = commit_file(&repo, Path::new("bar.js"), None)?;
+
+        let root = AbsoluteSystemPathBuf::new(repo_root.path())?;
+        assert!(!is_shallow(&root)?);
+        assert_matches!(
+            ensure_merge_base(
+                &root,
+                &first.to_string(),
+                &second.to_string(),
+                ShallowCloneMode::AllChanged
+            ),
+            Err(Error::NoMergeBase { .. })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_current_branch_and_sha() -> Result<(), Error> {
+        let (repo_root, repo) = setup_repository()?;
+
+        let file = repo_root.path().join("foo.js");
+        fs::write(file, "let z = 0;")?;
+        let commit_oid = commit_file(&repo, Path::new("foo.js"), None)?;
+
+        let root = AbsoluteSystemPathBuf::new(repo_root.path())?;
+        assert_eq!(current_sha(&root)?, commit_oid.to_string());
+        assert_eq!(
+            current_branch(&root)?,
+            repo.head()?.shorthand().unwrap_or_default()
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_deleted_files() -> Result<(), Error> {
         let (repo_root, repo) = setup_repository()?;
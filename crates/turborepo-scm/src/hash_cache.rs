@@ -0,0 +1,250 @@
+use std::{
+    collections::HashMap,
+    fs::{self, Metadata},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hex::ToHex;
+use sha1::{Digest, Sha1};
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+
+use crate::Error;
+
+// Bump if the hashing scheme or file format changes
+const HEADER: &str = "turbo-file-hashes-v1";
+
+/// Hashes of the files in a single package from a previous run, stored in
+/// `node_modules/.cache/turbo/file-hashes`. A file is only re-read if its
+/// size, modification time or inode changed since it was hashed.
+pub(crate) struct FileHashCache {
+    path: AbsoluteSystemPathBuf,
+    entries: HashMap<String, Entry>,
+    dirty: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    key: FileKey,
+    hash: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FileKey {
+    size: u64,
+    mtime: u128,
+    inode: u64,
+}
+
+impl FileKey {
+    fn new(metadata: &Metadata) -> Result<Self, Error> {
+        let mtime = metadata
+            .modified()?
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |mtime| mtime.as_nanos());
+        Ok(Self {
+            size: metadata.len(),
+            mtime,
+            inode: inode(metadata),
+        })
+    }
+}
+
+#[cfg(unix)]
+fn inode(metadata: &Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::ino(metadata)
+}
+
+#[cfg(not(unix))]
+fn inode(_metadata: &Metadata) -> u64 {
+    0
+}
+
+impl FileHashCache {
+    /// Loads the cache for the package at `package_path`. A missing or
+    /// unreadable cache is treated as empty.
+    pub(crate) fn load(
+        turbo_root: &AbsoluteSystemPath,
+        package_path: &AnchoredSystemPathBuf,
+    ) -> Self {
+        let mut hasher = Sha1::new();
+        hasher.update(package_path.to_str().unwrap_or_default().as_bytes());
+        let path = Self::dir(turbo_root).join_component(&hasher.finalize().encode_hex::<String>());
+        let entries = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| parse(&contents))
+            .unwrap_or_default();
+        Self {
+            path,
+            entries,
+            dirty: false,
+        }
+    }
+
+    /// The directory the caches are stored in, which must not be hashed itself
+    pub(crate) fn dir(turbo_root: &AbsoluteSystemPath) -> AbsoluteSystemPathBuf {
+        turbo_root.join_components(&["node_modules", ".cache", "turbo", "file-hashes"])
+    }
+
+    /// Returns the cached hash of `path` if its metadata is unchanged,
+    /// otherwise hashes it with `hash_file` and caches the result.
+    pub(crate) fn get_or_hash(
+        &mut self,
+        relative_path: &str,
+        metadata: &Metadata,
+        hash_file: impl FnOnce() -> Result<String, Error>,
+    ) -> Result<String, Error> {
+        let key = FileKey::new(metadata)?;
+        if let Some(entry) = self.entries.get(relative_path) {
+            if entry.key == key {
+                return Ok(entry.hash.clone());
+            }
+        }
+        let hash = hash_file()?;
+        self.entries.insert(
+            relative_path.to_string(),
+            Entry {
+                key,
+                hash: hash.clone(),
+            },
+        );
+        self.dirty = true;
+        Ok(hash)
+    }
+
+    /// Drops entries for files that no longer exist.
+    pub(crate) fn retain(&mut self, keep: impl Fn(&str) -> bool) {
+        let len = self.entries.len();
+        self.entries.retain(|path, _| keep(path));
+        self.dirty |= self.entries.len() != len;
+    }
+
+    pub(crate) fn save(&self) -> Result<(), Error> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let dir = self
+            .path
+            .as_path()
+            .parent()
+            .expect("cache file is always in a directory");
+        fs::create_dir_all(dir)?;
+
+        // Entries are only trusted if they were modified before the cache was
+        // written, otherwise a write in the same instant could go unnoticed.
+        let written_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_nanos());
+        let mut contents = format!("{HEADER} {written_at}\n");
+        for (path, Entry { key, hash }) in &self.entries {
+            contents.push_str(&format!(
+                "{}\t{}\t{}\t{hash}\t{path}\n",
+                key.size, key.mtime, key.inode
+            ));
+        }
+
+        // Write to a temporary file first so that concurrent readers never see
+        // a partially written cache
+        let tmp = dir.join(format!(
+            "{}.{}",
+            self.path.as_path().file_name().unwrap().to_string_lossy(),
+            std::process::id()
+        ));
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+fn parse(contents: &str) -> Option<HashMap<String, Entry>> {
+    let mut lines = contents.lines();
+    let (header, written_at) = lines.next()?.split_once(' ')?;
+    if header != HEADER {
+        return None;
+    }
+    let written_at: u128 = written_at.parse().ok()?;
+
+    let mut entries = HashMap::new();
+    for line in lines {
+        let mut fields = line.splitn(5, '\t');
+        let size = fields.next()?.parse().ok()?;
+        let mtime = fields.next()?.parse().ok()?;
+        let inode = fields.next()?.parse().ok()?;
+        let hash = fields.next()?.to_string();
+        let path = fields.next()?.to_string();
+        if mtime >= written_at {
+            continue;
+        }
+        entries.insert(
+            path,
+            Entry {
+                key: FileKey { size, mtime, inode },
+                hash,
+            },
+        );
+    }
+    Some(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use turbopath::{AbsoluteSystemPathBuf, AnchoredSystemPathBuf};
+
+    use super::FileHashCache;
+    use crate::Error;
+
+    #[test]
+    fn test_cache_roundtrip() -> Result<(), Error> {
+        let tmp = tempfile::tempdir()?;
+        let root = AbsoluteSystemPathBuf::new(tmp.path())?;
+        let package = AnchoredSystemPathBuf::from_raw("packages/a")?;
+        let file = root.join_components(&["packages", "a", "index.js"]);
+        fs::create_dir_all(file.as_path().parent().unwrap())?;
+        fs::write(&file, "let a = 1;")?;
+
+        let mut cache = FileHashCache::load(&root, &package);
+        let hash = cache.get_or_hash("index.js", &fs::metadata(&file)?, || Ok("a".into()))?;
+        assert_eq!(hash, "a");
+        cache.save()?;
+
+        // Unchanged files aren't hashed again
+        let mut cache = FileHashCache::load(&root, &package);
+        let hash = cache.get_or_hash("index.js", &fs::metadata(&file)?, || {
+            panic!("file should not be rehashed")
+        })?;
+        assert_eq!(hash, "a");
+
+        // A different size invalidates the entry
+        fs::write(&file, "let a = 12;")?;
+        let hash = cache.get_or_hash("index.js", &fs::metadata(&file)?, || Ok("b".into()))?;
+        assert_eq!(hash, "b");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_racy_entries_are_ignored() -> Result<(), Error> {
+        let tmp = tempfile::tempdir()?;
+        let root = AbsoluteSystemPathBuf::new(tmp.path())?;
+        let package = AnchoredSystemPathBuf::from_raw("")?;
+        let file = root.join_component("index.js");
+        fs::write(&file, "let a = 1;")?;
+
+        let mut cache = FileHashCache::load(&root, &package);
+        cache.get_or_hash("index.js", &fs::metadata(&file)?, || Ok("a".into()))?;
+        cache.save()?;
+
+        // Rewrite the cache as if it was written before the file was modified
+        let contents = fs::read_to_string(&cache.path)?;
+        let (header, rest) = contents.split_once('\n').unwrap();
+        let (name, _) = header.split_once(' ').unwrap();
+        fs::write(&cache.path, format!("{name} 0\n{rest}"))?;
+
+        let mut cache = FileHashCache::load(&root, &package);
+        let hash = cache.get_or_hash("index.js", &fs::metadata(&file)?, || Ok("b".into()))?;
+        assert_eq!(hash, "b");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,372 @@
+//! In-process implementations of the git operations used for change detection
+//! and file hashing, backed by libgit2. These avoid spawning a git process per
+//! query, but libgit2 doesn't support every repository layout (e.g. shallow
+//! clones), so callers fall back to the git binary if they fail.
+
+use std::{collections::HashSet, path::Path};
+
+use git2::{
+    Delta, DiffOptions, ObjectType, Repository, StatusOptions, TreeWalkMode, TreeWalkResult,
+};
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, RelativeUnixPathBuf};
+
+use crate::{git::reanchor_path_from_git_root_to_turbo_root, package_deps::GitHashes, Error};
+
+pub(crate) fn changed_files(
+    git_root: &AbsoluteSystemPath,
+    turbo_root: &AbsoluteSystemPath,
+    from_commit: Option<&str>,
+    to_commit: &str,
+) -> Result<HashSet<String>, Error> {
+    let repo = Repository::open(git_root)?;
+    let pathspec = git_root.anchor(turbo_root)?;
+    let pathspec = pathspec.to_str()?;
+    let diff_options = || {
+        let mut options = DiffOptions::new();
+        if !pathspec.is_empty() {
+            options.pathspec(pathspec);
+        }
+        options
+    };
+
+    let mut files = HashSet::new();
+    let to_commit = repo.revparse_single(to_commit)?.peel_to_commit()?;
+    let to_tree = to_commit.tree()?;
+
+    // Staged and unstaged changes relative to `to_commit`
+    let diff = repo.diff_tree_to_workdir_with_index(Some(&to_tree), Some(&mut diff_options()))?;
+    add_files_from_diff(&mut files, git_root, turbo_root, &diff)?;
+
+    if let Some(from_commit) = from_commit {
+        // Equivalent to `git diff from...to`, which compares against the merge base
+        let from_commit = repo.revparse_single(from_commit)?.peel_to_commit()?;
+        let merge_base = repo.merge_base(from_commit.id(), to_commit.id())?;
+        let base_tree = repo.find_commit(merge_base)?.tree()?;
+        let diff =
+            repo.diff_tree_to_tree(Some(&base_tree), Some(&to_tree), Some(&mut diff_options()))?;
+        add_files_from_diff(&mut files, git_root, turbo_root, &diff)?;
+    }
+
+    let mut status_options = StatusOptions::new();
+    status_options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(false);
+    if !pathspec.is_empty() {
+        status_options.pathspec(pathspec);
+    }
+    for entry in repo.statuses(Some(&mut status_options))?.iter() {
+        if entry.status().is_wt_new() {
+            add_file(&mut files, git_root, turbo_root, entry.path_bytes())?;
+        }
+    }
+
+    Ok(files)
+}
+
+fn add_files_from_diff(
+    files: &mut HashSet<String>,
+    git_root: &AbsoluteSystemPath,
+    turbo_root: &AbsoluteSystemPath,
+    diff: &git2::Diff,
+) -> Result<(), Error> {
+    for delta in diff.deltas() {
+        // Without rename detection both sides of a delta refer to the same path
+        // unless the file was added or deleted, in which case one side is empty
+        let file = match delta.status() {
+            Delta::Deleted => delta.old_file(),
+            _ => delta.new_file(),
+        };
+        if let Some(path) = file.path_bytes() {
+            add_file(files, git_root, turbo_root, path)?;
+        }
+    }
+    Ok(())
+}
+
+fn add_file(
+    files: &mut HashSet<String>,
+    git_root: &AbsoluteSystemPath,
+    turbo_root: &AbsoluteSystemPath,
+    path: &[u8],
+) -> Result<(), Error> {
+    let path = RelativeUnixPathBuf::new(path)?;
+    let path = reanchor_path_from_git_root_to_turbo_root(git_root, turbo_root, &path)?;
+    files.insert(path.to_str()?.to_string());
+    Ok(())
+}
+
+pub(crate) fn previous_content(
+    git_root: &AbsoluteSystemPath,
+    from_commit: &str,
+    file_path: &RelativeUnixPathBuf,
+) -> Result<Vec<u8>, Error> {
+    let repo = Repository::open(git_root)?;
+    let tree = repo.revparse_single(from_commit)?.peel_to_tree()?;
+    let entry = tree.get_path(Path::new(file_path.as_str()?))?;
+    let blob = entry.to_object(&repo)?.peel_to_blob()?;
+    Ok(blob.content().to_vec())
+}
+
+pub(crate) fn is_shallow(git_root: &AbsoluteSystemPath) -> Result<bool, Error> {
+    Ok(Repository::open(git_root)?.is_shallow())
+}
+
+pub(crate) fn merge_base(
+    git_root: &AbsoluteSystemPath,
+    first: &str,
+    second: &str,
+) -> Result<Option<String>, Error> {
+    let repo = Repository::open(git_root)?;
+    let first = repo.revparse_single(first)?.peel_to_commit()?;
+    let second = repo.revparse_single(second)?.peel_to_commit()?;
+    match repo.merge_base(first.id(), second.id()) {
+        Ok(oid) => Ok(Some(oid.to_string())),
+        Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+pub(crate) fn find_git_root(
+    turbo_root: &AbsoluteSystemPath,
+) -> Result<AbsoluteSystemPathBuf, Error> {
+    let repo = Repository::discover(turbo_root)?;
+    let prefix = repo_relative_path(&repo, turbo_root)?;
+    // Walk up from `turbo_root` rather than using the repository's path so
+    // that the result doesn't have symlinks resolved, matching the git binary
+    let depth = prefix
+        .as_str()?
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .count();
+    turbo_root
+        .ancestors()
+        .nth(depth)
+        .map(|root| root.to_owned())
+        .ok_or_else(|| {
+            Error::git_error(format!("unable to find repository root above {turbo_root}"))
+        })
+}
+
+/// Lists the files committed at HEAD below `root_path`, keyed by their path
+/// relative to `root_path`.
+pub(crate) fn git_ls_tree(root_path: &AbsoluteSystemPath) -> Result<GitHashes, Error> {
+    let repo = Repository::discover(root_path)?;
+    let prefix = repo_relative_path(&repo, root_path)?;
+
+    let head = repo.head()?.peel_to_tree()?;
+    let tree = if prefix.as_str()?.is_empty() {
+        head
+    } else {
+        match head.get_path(Path::new(prefix.as_str()?)) {
+            Ok(entry) => entry.to_object(&repo)?.peel_to_tree()?,
+            // Nothing below `root_path` has been committed yet
+            Err(err) if err.code() == git2::ErrorCode::NotFound => return Ok(GitHashes::new()),
+            Err(err) => return Err(err.into()),
+        }
+    };
+
+    let mut hashes = GitHashes::new();
+    let mut result: Result<(), Error> = Ok(());
+    tree.walk(TreeWalkMode::PreOrder, |dir, entry| {
+        if entry.kind() == Some(ObjectType::Tree) {
+            return TreeWalkResult::Ok;
+        }
+        let mut path = dir.as_bytes().to_vec();
+        path.extend_from_slice(entry.name_bytes());
+        match RelativeUnixPathBuf::new(path) {
+            Ok(path) => {
+                hashes.insert(path, entry.id().to_string());
+                TreeWalkResult::Ok
+            }
+            Err(err) => {
+                result = Err(err.into());
+                TreeWalkResult::Abort
+            }
+        }
+    })?;
+    result?;
+
+    Ok(hashes)
+}
+
+/// Removes deleted files below `root_path` from `hashes` and returns the
+/// repository-relative paths of files that were added or modified.
+pub(crate) fn append_git_status(
+    root_path: &AbsoluteSystemPath,
+    pkg_prefix: &RelativeUnixPathBuf,
+    hashes: &mut GitHashes,
+) -> Result<Vec<RelativeUnixPathBuf>, Error> {
+    let repo = Repository::discover(root_path)?;
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(true)
+        .include_ignored(false)
+        .renames_head_to_index(false)
+        .renames_index_to_workdir(false);
+    if !pkg_prefix.as_str()?.is_empty() {
+        options.pathspec(pkg_prefix.as_str()?);
+    }
+
+    let mut to_hash = Vec::new();
+    for entry in repo.statuses(Some(&mut options))?.iter() {
+        let path = RelativeUnixPathBuf::new(entry.path_bytes())?;
+        // Pathspecs are fuzzier than a directory prefix, so double check
+        let Ok(package_relative_path) = path.strip_prefix(pkg_prefix) else {
+            continue;
+        };
+        let status = entry.status();
+        if status.is_index_deleted() || status.is_wt_deleted() {
+            hashes.remove(&package_relative_path);
+        } else {
+            to_hash.push(path);
+        }
+    }
+    Ok(to_hash)
+}
+
+// The repository's working directory might be reported with symlinks
+// resolved, so both sides are canonicalized before comparing them.
+fn repo_relative_path(
+    repo: &Repository,
+    path: &AbsoluteSystemPath,
+) -> Result<RelativeUnixPathBuf, Error> {
+    let workdir = repo
+        .workdir()
+        .ok_or_else(|| Error::git_error("repository has no working directory"))?;
+    let workdir = AbsoluteSystemPathBuf::new(workdir.canonicalize()?)?;
+    let path = AbsoluteSystemPathBuf::new(path.as_path().canonicalize()?)?;
+    Ok(workdir.anchor(path)?.to_unix()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::Path};
+
+    use git2::Repository;
+    use tempfile::TempDir;
+    use turbopath::{AbsoluteSystemPathBuf, RelativeUnixPathBuf};
+
+    use crate::{hash_object::hash_objects, ls_tree, package_deps::GitHashes, status, Error};
+
+    fn commit_all(repo: &Repository) -> Result<(), Error> {
+        let mut index = repo.index()?;
+        index.add_all(["*"], git2::IndexAddOption::DEFAULT, None)?;
+        index.write()?;
+        let tree = repo.find_tree(index.write_tree()?)?;
+        let parent = repo.head().ok().and_then(|head| head.peel_to_commit().ok());
+        let signature = git2::Signature::now("test", "test@example.com")?;
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Commit",
+            &tree,
+            &parent.iter().collect::<Vec<_>>(),
+        )?;
+        Ok(())
+    }
+
+    fn setup() -> Result<(TempDir, Repository), Error> {
+        let tmp = tempfile::tempdir()?;
+        let repo = Repository::init(tmp.path())?;
+        for (path, contents) in [
+            ("package.json", "{}"),
+            (".gitignore", "dist/\n"),
+            ("packages/a/package.json", "{}"),
+            ("packages/a/src/index.js", "let a = 1;"),
+            ("packages/a/removed.js", "let removed = true;"),
+            ("packages/ab/package.json", "{}"),
+        ] {
+            let path = tmp.path().join(path);
+            fs::create_dir_all(path.parent().unwrap())?;
+            fs::write(path, contents)?;
+        }
+        commit_all(&repo)?;
+
+        let package = tmp.path().join("packages/a");
+        fs::write(package.join("src/index.js"), "let a = 2;")?;
+        fs::write(package.join("src/new.js"), "let b = 1;")?;
+        fs::remove_file(package.join("removed.js"))?;
+        fs::create_dir_all(package.join("dist"))?;
+        fs::write(package.join("dist/index.js"), "ignored")?;
+        fs::write(tmp.path().join("packages/ab/new.js"), "other package")?;
+
+        Ok((tmp, repo))
+    }
+
+    #[test]
+    fn test_changed_files_matches_git() -> Result<(), Error> {
+        let (tmp, _repo) = setup()?;
+        let root = AbsoluteSystemPathBuf::new(tmp.path().canonicalize()?)?;
+        let turbo_root = root.join_components(&["packages", "a"]);
+
+        let expected =
+            crate::git::changed_files_with_git(&root, &turbo_root, Some("HEAD"), "HEAD")?;
+        let actual = super::changed_files(&root, &turbo_root, Some("HEAD"), "HEAD")?;
+        assert_eq!(actual, expected);
+        assert_eq!(actual.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_previous_content() -> Result<(), Error> {
+        let (tmp, _repo) = setup()?;
+        let root = AbsoluteSystemPathBuf::new(tmp.path())?;
+        let path = RelativeUnixPathBuf::new("packages/a/src/index.js")?;
+        assert_eq!(
+            super::previous_content(&root, "HEAD", &path)?,
+            b"let a = 1;".to_vec()
+        );
+        assert!(
+            super::previous_content(&root, "HEAD", &RelativeUnixPathBuf::new("nope")?).is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_package_hashes_match_git() -> Result<(), Error> {
+        let (tmp, _repo) = setup()?;
+        let root = AbsoluteSystemPathBuf::new(tmp.path().canonicalize()?)?;
+        let package = root.join_components(&["packages", "a"]);
+        let prefix = RelativeUnixPathBuf::new("packages/a")?;
+
+        let hash_package = |in_process: bool| -> Result<GitHashes, Error> {
+            let (mut hashes, to_hash) = if in_process {
+                let mut hashes = super::git_ls_tree(&package)?;
+                let to_hash = super::append_git_status(&package, &prefix, &mut hashes)?;
+                (hashes, to_hash)
+            } else {
+                let mut hashes = ls_tree::git_ls_tree(&package)?;
+                let to_hash = status::append_git_status(&package, &prefix, &mut hashes)?;
+                (hashes, to_hash)
+            };
+            hash_objects(&root, &package, to_hash, &mut hashes)?;
+            Ok(hashes)
+        };
+
+        let expected = hash_package(false)?;
+        let actual = hash_package(true)?;
+        assert_eq!(actual, expected);
+        let mut files = actual
+            .keys()
+            .map(|path| path.as_str().map(|s| s.to_string()))
+            .collect::<Result<Vec<_>, _>>()?;
+        files.sort();
+        assert_eq!(files, vec!["package.json", "src/index.js", "src/new.js"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ls_tree_uncommitted_directory() -> Result<(), Error> {
+        let (tmp, _repo) = setup()?;
+        let package = tmp.path().join("packages/c");
+        fs::create_dir_all(&package)?;
+        let package = AbsoluteSystemPathBuf::new(Path::new(&package))?;
+        assert!(super::git_ls_tree(&package)?.is_empty());
+        Ok(())
+    }
+}
@@ -9,10 +9,13 @@ use std::{
 };
 
 use thiserror::Error;
+use tracing::debug;
 use turbopath::{AbsoluteSystemPath, PathError};
 
 pub mod git;
+mod hash_cache;
 mod hash_object;
+mod in_process;
 mod ls_tree;
 pub mod manual;
 pub mod package_deps;
@@ -41,6 +44,19 @@ pub enum Error {
     Glob(Box<wax::BuildError>, backtrace::Backtrace),
     #[error(transparent)]
     Walk(#[from] globwalk::WalkError),
+    #[error(
+        "{from_commit} and {to_commit} have no common history in this shallow clone. Fetch more \
+         history with `git fetch --deepen=<depth>` or `git fetch --unshallow`"
+    )]
+    ShallowClone {
+        from_commit: String,
+        to_commit: String,
+    },
+    #[error("{from_commit} and {to_commit} have no common ancestor")]
+    NoMergeBase {
+        from_commit: String,
+        to_commit: String,
+    },
 }
 
 impl From<wax::BuildError> for Error {
@@ -55,6 +71,43 @@ impl Error {
     }
 }
 
+/// The implementation used to query git repositories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GitBackend {
+    /// Query the repository in-process with libgit2, falling back to the git
+    /// binary if that fails.
+    InProcess,
+    /// Always shell out to the git binary.
+    Subprocess,
+}
+
+impl GitBackend {
+    /// Returns the backend selected by the environment.
+    /// `TURBO_GIT_BACKEND=subprocess` selects the git binary, otherwise the
+    /// in-process backend is used.
+    pub fn current() -> Self {
+        match std::env::var("TURBO_GIT_BACKEND").as_deref() {
+            Ok("subprocess") | Ok("git") => GitBackend::Subprocess,
+            _ => GitBackend::InProcess,
+        }
+    }
+}
+
+// Runs `in_process` if the in-process backend is enabled, and `subprocess` if
+// it isn't or if `in_process` fails.
+pub(crate) fn with_fallback<T>(
+    in_process: impl FnOnce() -> Result<T, Error>,
+    subprocess: impl FnOnce() -> Result<T, Error>,
+) -> Result<T, Error> {
+    match GitBackend::current() {
+        GitBackend::InProcess => in_process().or_else(|err| {
+            debug!("falling back to the git binary: {}", err);
+            subprocess()
+        }),
+        GitBackend::Subprocess => subprocess(),
+    }
+}
+
 fn read_git_error_to_string<R: Read>(stderr: &mut R) -> Option<String> {
     let mut buf = String::new();
     let bytes_read = stderr.read_to_string(&mut buf).ok()?;
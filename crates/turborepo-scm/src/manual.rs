@@ -1,13 +1,18 @@
-use std::{fs::Metadata, io::Read, path::Path};
+use std::{
+    collections::HashSet,
+    fs::Metadata,
+    io::{ErrorKind, Read},
+    path::Path,
+};
 
 use hex::ToHex;
 use ignore::WalkBuilder;
 use path_slash::PathExt;
 use sha1::{Digest, Sha1};
-use turbopath::{AbsoluteSystemPathBuf, AnchoredSystemPathBuf, PathError};
+use turbopath::{AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPathBuf, PathError};
 use wax::{any, Glob, Pattern};
 
-use crate::{package_deps::GitHashes, Error};
+use crate::{hash_cache::FileHashCache, package_deps::GitHashes, Error};
 
 fn git_like_hash_file(path: &AbsoluteSystemPathBuf, metadata: &Metadata) -> Result<String, Error> {
     let mut hasher = Sha1::new();
@@ -22,6 +27,32 @@ fn git_like_hash_file(path: &AbsoluteSystemPathBuf, metadata: &Metadata) -> Resu
     Ok(result.encode_hex::<String>())
 }
 
+/// Hashes each of `files` the same way git would, keyed by their unix path
+/// relative to `turbo_root`. When `allow_missing` is set, files that don't
+/// exist are skipped rather than being an error.
+pub fn hash_files(
+    turbo_root: &AbsoluteSystemPath,
+    files: impl Iterator<Item = AnchoredSystemPathBuf>,
+    allow_missing: bool,
+) -> Result<GitHashes, Error> {
+    let mut hashes = GitHashes::new();
+    for file in files {
+        let path = turbo_root.resolve(&file);
+        let metadata = match std::fs::metadata(&path) {
+            Ok(metadata) => metadata,
+            Err(err) if allow_missing && err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        let hash = git_like_hash_file(&path, &metadata)?;
+        hashes.insert(file.to_unix()?, hash);
+    }
+    Ok(hashes)
+}
+
+/// Hashes the files in `package_path` that aren't gitignored and match
+/// `inputs`, for repositories that aren't git checkouts. Hashes are cached
+/// on disk so that files which haven't changed since the previous run aren't
+/// read again.
 pub fn get_package_file_hashes_from_processing_gitignore<S: AsRef<str>>(
     turbo_root: &AbsoluteSystemPathBuf,
     package_path: &AnchoredSystemPathBuf,
@@ -29,6 +60,9 @@ pub fn get_package_file_hashes_from_processing_gitignore<S: AsRef<str>>(
 ) -> Result<GitHashes, Error> {
     let full_package_path = turbo_root.resolve(package_path);
     let mut hashes = GitHashes::new();
+    let mut cache = FileHashCache::load(turbo_root, package_path);
+    let mut existing_files = HashSet::new();
+    let cache_dir = FileHashCache::dir(turbo_root);
 
     let mut walker_builder = WalkBuilder::new(&full_package_path);
     let mut includes = Vec::new();
@@ -66,6 +100,7 @@ pub fn get_package_file_hashes_from_processing_gitignore<S: AsRef<str>>(
         .git_ignore(true)
         .require_git(false)
         .hidden(false) // this results in yielding hidden files (e.g. .gitignore)
+        .filter_entry(move |dirent| dirent.path() != cache_dir.as_path())
         .build();
     for dirent in walker {
         let dirent = dirent?;
@@ -78,6 +113,7 @@ pub fn get_package_file_hashes_from_processing_gitignore<S: AsRef<str>>(
         let path = AbsoluteSystemPathBuf::new(dirent.path())?;
         let relative_path = full_package_path.anchor(&path)?;
         let relative_path = relative_path.to_unix()?;
+        existing_files.insert(relative_path.as_str()?.to_string());
         if let Some(include_pattern) = include_pattern.as_ref() {
             if !include_pattern.is_match(relative_path.as_str()?) {
                 continue;
@@ -88,9 +124,21 @@ pub fn get_package_file_hashes_from_processing_gitignore<S: AsRef<str>>(
                 continue;
             }
         }
-        let hash = git_like_hash_file(&path, &metadata)?;
+        // Symlinks are hashed by their target's contents, which their own
+        // metadata doesn't reflect
+        let hash = if metadata.is_symlink() {
+            git_like_hash_file(&path, &metadata)?
+        } else {
+            cache.get_or_hash(relative_path.as_str()?, &metadata, || {
+                git_like_hash_file(&path, &metadata)
+            })?
+        };
         hashes.insert(relative_path, hash);
     }
+    cache.retain(|path| existing_files.contains(path));
+    // The cache only speeds up future runs, so failing to write it (e.g. on a
+    // read-only filesystem) shouldn't fail hashing
+    let _ = cache.save();
     Ok(hashes)
 }
 
@@ -203,4 +251,30 @@ mod tests {
         .unwrap();
         assert_eq!(hashes, expected);
     }
+
+    #[test]
+    fn test_hash_files() {
+        let (_tmp, turbo_root) = tmp_dir();
+        let file_path = turbo_root.join_components(&["some-dir", "some-file"]);
+        file_path.ensure_dir().unwrap();
+        file_path
+            .create_with_contents("some-file-contents")
+            .unwrap();
+
+        let files = || {
+            ["some-dir/some-file", "missing-file"]
+                .into_iter()
+                .map(|file| AnchoredSystemPathBuf::from_raw(file).unwrap())
+        };
+
+        let hashes = hash_files(&turbo_root, files(), true).unwrap();
+        let mut expected = GitHashes::new();
+        expected.insert(
+            RelativeUnixPathBuf::new("some-dir/some-file").unwrap(),
+            "7e59c6a6ea9098c6d3beb00e753e2c54ea502311".to_owned(),
+        );
+        assert_eq!(hashes, expected);
+
+        assert!(hash_files(&turbo_root, files(), false).is_err());
+    }
 }
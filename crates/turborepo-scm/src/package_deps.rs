@@ -6,10 +6,37 @@ use turbopath::{
     AbsoluteSystemPath, AbsoluteSystemPathBuf, AnchoredSystemPathBuf, RelativeUnixPathBuf,
 };
 
-use crate::{hash_object::hash_objects, ls_tree::git_ls_tree, status::append_git_status, Error};
+use crate::{
+    hash_object::hash_objects, in_process, ls_tree::git_ls_tree,
+    manual::get_package_file_hashes_from_processing_gitignore, status::append_git_status,
+    with_fallback, Error,
+};
 
 pub type GitHashes = HashMap<RelativeUnixPathBuf, String>;
 
+/// Hashes the files in the package at `package_path`, keyed by their path
+/// relative to the package. Without any `inputs`, every file that isn't
+/// ignored by git is hashed. If git can't be used, the files are found by
+/// walking the package directory and respecting .gitignore files.
+pub fn get_package_file_hashes<S: AsRef<str>>(
+    turbo_root: &AbsoluteSystemPath,
+    package_path: &AnchoredSystemPathBuf,
+    inputs: &[S],
+) -> Result<GitHashes, Error> {
+    let result = if inputs.is_empty() {
+        get_package_file_hashes_from_git_index(turbo_root, package_path)
+    } else {
+        get_package_file_hashes_from_inputs(turbo_root, package_path, inputs)
+    };
+    result.or_else(|_| {
+        get_package_file_hashes_from_processing_gitignore(
+            &turbo_root.to_owned(),
+            package_path,
+            inputs,
+        )
+    })
+}
+
 pub fn get_package_file_hashes_from_git_index(
     turbo_root: &AbsoluteSystemPath,
     package_path: &AnchoredSystemPathBuf,
@@ -19,9 +46,19 @@ pub fn get_package_file_hashes_from_git_index(
     let full_pkg_path = turbo_root.resolve(package_path);
     let git_to_pkg_path = git_root.anchor(&full_pkg_path)?;
     let pkg_prefix = git_to_pkg_path.to_unix()?;
-    let mut hashes = git_ls_tree(&full_pkg_path)?;
+    let (mut hashes, to_hash) = with_fallback(
+        || {
+            let mut hashes = in_process::git_ls_tree(&full_pkg_path)?;
+            let to_hash = in_process::append_git_status(&full_pkg_path, &pkg_prefix, &mut hashes)?;
+            Ok((hashes, to_hash))
+        },
+        || {
+            let mut hashes = git_ls_tree(&full_pkg_path)?;
+            let to_hash = append_git_status(&full_pkg_path, &pkg_prefix, &mut hashes)?;
+            Ok((hashes, to_hash))
+        },
+    )?;
     // Note: to_hash is *git repo relative*
-    let to_hash = append_git_status(&full_pkg_path, &pkg_prefix, &mut hashes)?;
     hash_objects(&git_root, &full_pkg_path, to_hash, &mut hashes)?;
     Ok(hashes)
 }
@@ -83,9 +120,14 @@ pub fn get_package_file_hashes_from_inputs<S: AsRef<str>>(
     Ok(hashes)
 }
 
-pub(crate) fn find_git_root(
-    turbo_root: &AbsoluteSystemPath,
-) -> Result<AbsoluteSystemPathBuf, Error> {
+pub fn find_git_root(turbo_root: &AbsoluteSystemPath) -> Result<AbsoluteSystemPathBuf, Error> {
+    with_fallback(
+        || in_process::find_git_root(turbo_root),
+        || find_git_root_with_git(turbo_root),
+    )
+}
+
+fn find_git_root_with_git(turbo_root: &AbsoluteSystemPath) -> Result<AbsoluteSystemPathBuf, Error> {
     let rev_parse = Command::new("git")
         .args(["rev-parse", "--show-cdup"])
         .current_dir(turbo_root)
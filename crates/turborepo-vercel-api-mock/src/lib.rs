@@ -1,7 +1,18 @@
-use std::net::SocketAddr;
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
 
 use anyhow::Result;
-use axum::{routing::get, Json, Router};
+use axum::{
+    body::Bytes,
+    extract::Path,
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::IntoResponse,
+    routing::get,
+    Json, Router,
+};
 use turborepo_api_client::{
     CachingStatus, CachingStatusResponse, Membership, Role, Space, SpacesResponse, Team,
     TeamsResponse, User, UserResponse, VerificationResponse,
@@ -24,7 +35,21 @@ pub const EXPECTED_SPACE_NAME: &str = "expected_space_name";
 pub const EXPECTED_SSO_TEAM_ID: &str = "expected_sso_team_id";
 pub const EXPECTED_SSO_TEAM_SLUG: &str = "expected_sso_team_slug";
 
+#[derive(Clone)]
+struct StoredArtifact {
+    body: Bytes,
+    duration: Option<HeaderValue>,
+    tag: Option<HeaderValue>,
+}
+
+type ArtifactStore = Arc<Mutex<HashMap<String, StoredArtifact>>>;
+
 pub async fn start_test_server(port: u16) -> Result<()> {
+    // Artifacts are kept in memory for the lifetime of the server
+    let artifacts = ArtifactStore::default();
+    let get_artifacts = artifacts.clone();
+    let put_artifacts = artifacts;
+
     let app = Router::new()
         .route(
             "/v2/user",
@@ -74,6 +99,37 @@ pub async fn start_test_server(port: u16) -> Result<()> {
                 })
             }),
         )
+        .route(
+            "/v8/artifacts/:hash",
+            get(move |Path(hash): Path<String>| async move {
+                let artifact = get_artifacts.lock().unwrap().get(&hash).cloned();
+                let Some(artifact) = artifact else {
+                    return StatusCode::NOT_FOUND.into_response();
+                };
+
+                let mut headers = HeaderMap::new();
+                if let Some(duration) = artifact.duration {
+                    headers.insert("x-artifact-duration", duration);
+                }
+                if let Some(tag) = artifact.tag {
+                    headers.insert("x-artifact-tag", tag);
+                }
+
+                (StatusCode::OK, headers, artifact.body).into_response()
+            })
+            .put(
+                move |Path(hash): Path<String>, headers: HeaderMap, body: Bytes| async move {
+                    let artifact = StoredArtifact {
+                        body,
+                        duration: headers.get("x-artifact-duration").cloned(),
+                        tag: headers.get("x-artifact-tag").cloned(),
+                    };
+                    put_artifacts.lock().unwrap().insert(hash, artifact);
+
+                    StatusCode::OK
+                },
+            ),
+        )
         .route(
             "/registration/verify",
             get(|| async move {